@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
 use std::path::Path;
 use std::time::Instant;
 use std::{cell::RefCell, rc::Rc, sync::Arc};
@@ -22,13 +23,15 @@ use criterion::*;
 
 use deepflow_agent::{
     _FlowPerfCounter as FlowPerfCounter, _L7PerfCache as L7PerfCache,
-    _PacketDirection as PacketDirection, _TcpPerf as TcpPerf,
-    _benchmark_report as benchmark_report,
+    _L7ProtocolChecker as L7ProtocolChecker, _PacketDirection as PacketDirection,
+    _TcpPerf as TcpPerf, _benchmark_report as benchmark_report,
     _benchmark_session_peer_seq_no_assert as benchmark_session_peer_seq_no_assert,
     _meta_flow_perf_update as meta_flow_perf_update,
-    common::l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    common::l7_protocol_log::{
+        get_all_protocol, L7ProtocolBitmap, L7ProtocolParserInterface, ParseParam,
+    },
     utils::test::Capture,
-    HttpLog,
+    HttpLog, MysqlLog,
 };
 
 pub(super) fn bench(c: &mut Criterion) {
@@ -95,4 +98,82 @@ pub(super) fn bench(c: &mut Criterion) {
             start.elapsed()
         })
     });
+    c.bench_function("check_mysql_payload", |b| {
+        b.iter_custom(|iters| {
+            let capture = Capture::load_pcap(
+                Path::new("./resources/test/flow_generator/mysql/mysql-table-select.pcap"),
+                None,
+            );
+            let packets = capture.as_meta_packets();
+            let rrt_cache = Rc::new(RefCell::new(L7PerfCache::new(8)));
+            let mut parser = MysqlLog::default();
+            let (payload, param) = packets
+                .iter()
+                .filter_map(|p| {
+                    let param = ParseParam::from((p, rrt_cache.clone(), false));
+                    let payload = p.get_l4_payload()?;
+                    if parser.check_payload(payload, &param) {
+                        Some((payload, param))
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .expect("no mysql query in pcap file");
+
+            let start = Instant::now();
+            for _ in 0..iters {
+                let _ = parser.check_payload(payload, &param);
+            }
+            start.elapsed()
+        })
+    });
+    // 默认端口的协议最先检查, 未知端口需要依次检查 MySQL 之前的所有协议
+    // protocol of default port is checked first, all protocols before MySQL are checked on unknown port
+    for (name, port) in [
+        ("check_l7_protocol_default_port", 3306),
+        ("check_l7_protocol_unknown_port", 13306),
+    ] {
+        c.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let all_protocols = get_all_protocol()
+                    .iter()
+                    .map(|p| p.as_str().to_owned())
+                    .collect::<Vec<_>>();
+                let checker = L7ProtocolChecker::new(
+                    &L7ProtocolBitmap::from(&all_protocols),
+                    &HashMap::new(),
+                    Default::default(),
+                );
+                let capture = Capture::load_pcap(
+                    Path::new("./resources/test/flow_generator/mysql/mysql-table-select.pcap"),
+                    None,
+                );
+                let packets = capture.as_meta_packets();
+                let rrt_cache = Rc::new(RefCell::new(L7PerfCache::new(8)));
+                let mut parser = MysqlLog::default();
+                let (payload, mut param) = packets
+                    .iter()
+                    .filter_map(|p| {
+                        let param = ParseParam::from((p, rrt_cache.clone(), false));
+                        let payload = p.get_l4_payload()?;
+                        if parser.check_payload(payload, &param) {
+                            Some((payload, param))
+                        } else {
+                            None
+                        }
+                    })
+                    .next()
+                    .expect("no mysql query in pcap file");
+                param.direction = PacketDirection::ClientToServer;
+                param.port_dst = port;
+
+                let start = Instant::now();
+                for _ in 0..iters {
+                    let _ = checker.check_payload(payload, &param, &[]);
+                }
+                start.elapsed()
+            })
+        });
+    }
 }
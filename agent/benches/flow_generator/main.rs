@@ -18,6 +18,12 @@ use criterion::*;
 
 mod flow_map;
 mod l7_log;
+mod protocol_logs;
 
-criterion_group!(benches, flow_map::bench, l7_log::bench);
+criterion_group!(
+    benches,
+    flow_map::bench,
+    l7_log::bench,
+    protocol_logs::bench
+);
 criterion_main!(benches);
@@ -0,0 +1,121 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{cell::RefCell, rc::Rc};
+
+use criterion::*;
+
+use deepflow_agent::{
+    _L7PerfCache as L7PerfCache, _PacketDirection as PacketDirection,
+    common::l7_protocol_log::{get_parser, L7ProtocolParserInterface, ParseParam},
+    utils::test::Capture,
+};
+use public::l7_protocol::{L7Protocol, L7ProtocolEnum};
+
+const FILE_DIR: &str = "./resources/test/flow_generator";
+
+// (名称, 协议, pcap 文件)
+// (name, protocol, pcap file)
+const CASES: [(&str, L7Protocol, &str); 4] = [
+    ("mysql", L7Protocol::MySQL, "mysql/mysql.pcap"),
+    ("http", L7Protocol::Http1, "http/httpv1.pcap"),
+    ("dns", L7Protocol::DNS, "dns/dns.pcap"),
+    ("redis", L7Protocol::Redis, "redis/redis.pcap"),
+];
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+// 统计内存分配次数, 用于报告每个包的分配次数
+// counts memory allocations to report allocations per packet
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// 每次迭代处理一个包, 所以报告的时间即每个包的耗时
+// every iteration handles one packet, so the reported time is per packet
+pub(super) fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("protocol_logs");
+    for (name, protocol, pcap) in CASES {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(pcap), None);
+        let mut packets = capture.as_meta_packets();
+        packets.retain(|p| matches!(p.get_l4_payload(), Some(p) if !p.is_empty()));
+        if packets.is_empty() {
+            panic!("no payload in pcap file {}", pcap);
+        }
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+        }
+
+        let rrt_cache = Rc::new(RefCell::new(L7PerfCache::new(100)));
+        let params = packets
+            .iter()
+            .map(|p| ParseParam::from((p, rrt_cache.clone(), false)))
+            .collect::<Vec<_>>();
+        let mut parser = get_parser(L7ProtocolEnum::L7Protocol(protocol)).unwrap();
+        let mut run = |i: usize| {
+            let payload = packets[i].get_l4_payload().unwrap();
+            black_box(parser.check_payload(payload, &params[i]));
+            let _ = black_box(parser.parse_payload(payload, &params[i]));
+            parser.reset();
+        };
+
+        // 第一轮会初始化统计数据等, 不计入分配次数
+        // the first round initializes perf stats and so on, which is not counted
+        (0..packets.len()).for_each(&mut run);
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        (0..packets.len()).for_each(&mut run);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!(
+            "protocol_logs/{}: {:.2} allocations/packet",
+            name,
+            allocations as f64 / packets.len() as f64
+        );
+
+        let mut i = 0;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                run(i);
+                i = (i + 1) % packets.len();
+            })
+        });
+    }
+    group.finish();
+}
@@ -49,6 +49,33 @@ pub fn read_u64_le(bs: &[u8]) -> u64 {
     u64::from_le_bytes(bs[..8].try_into().unwrap())
 }
 
+// 与 read_xxx 相同, 但长度不足时返回 None 而不是 panic, 用于解析可能被截断的报文
+// same as read_xxx, but returns None instead of panic when length is insufficient,
+// used for parsing payloads which may be truncated
+pub fn try_read_u16_be(bs: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(bs.get(..2)?.try_into().unwrap()))
+}
+
+pub fn try_read_u16_le(bs: &[u8]) -> Option<u16> {
+    Some(u16::from_le_bytes(bs.get(..2)?.try_into().unwrap()))
+}
+
+pub fn try_read_u32_be(bs: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bs.get(..4)?.try_into().unwrap()))
+}
+
+pub fn try_read_u32_le(bs: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(bs.get(..4)?.try_into().unwrap()))
+}
+
+pub fn try_read_u64_be(bs: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(bs.get(..8)?.try_into().unwrap()))
+}
+
+pub fn try_read_u64_le(bs: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(bs.get(..8)?.try_into().unwrap()))
+}
+
 pub fn write_u16_be(bs: &mut [u8], v: u16) {
     assert!(bs.len() >= 2);
     bs[0..2].copy_from_slice(v.to_be_bytes().as_slice())
@@ -63,3 +90,16 @@ pub fn write_u64_be(bs: &mut [u8], v: u64) {
     assert!(bs.len() >= 8);
     bs[0..8].copy_from_slice(v.to_be_bytes().as_slice())
 }
+
+// 无符号 LEB128 变长整数, 返回值和占用的字节数
+// unsigned LEB128 varint, returns the value and the number of bytes consumed
+pub fn read_varint(bs: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, b) in bs.iter().take(10).enumerate() {
+        value |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
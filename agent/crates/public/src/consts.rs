@@ -400,6 +400,11 @@ pub const MIN_MASK_LEN: u8 = 0;
 // 静态配置项默认值
 pub const L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT: usize = 50;
 pub const L7_PROTOCOL_INFERENCE_TTL: usize = 60;
+pub const L7_LOG_DNS_MAX_ANSWERS: usize = 16;
+// MySQL 客户端错误码, 其余错误码为服务端错误
+// MySQL client error codes, other error codes are server errors
+pub const L7_LOG_MYSQL_ERROR_MESSAGE_MAX_LENGTH: usize = 256;
+pub const L7_LOG_MYSQL_CLIENT_ERROR_CODES: &str = "1044-1046,1048-1052,1054,1060-1069,1071-1072,1091,1109-1110,1136,1142-1143,1146,1149,1216-1217,1264,1292,1364,1366,1406,1451-1452,1690,2000-2999";
 
 // RawPcap
 pub const PCAP_MAGIC: u32 = 0xa1b2c3d4;
@@ -122,6 +122,7 @@ pub enum IpProtocol {
     NoNextHeader,
     Ipv6Destination,
     Ipip,
+    Sctp,
     Unknown(u8),
 }
 
@@ -141,6 +142,7 @@ impl IpProtocol {
     const NO_NEXT_HEADER: u8 = 59;
     const IPV6_DESTINATION: u8 = 60;
     const IPIP: u8 = 94;
+    const SCTP: u8 = 132;
 }
 
 impl Default for IpProtocol {
@@ -167,6 +169,7 @@ impl From<u8> for IpProtocol {
             Self::NO_NEXT_HEADER => Self::NoNextHeader,
             Self::IPV6_DESTINATION => Self::Ipv6Destination,
             Self::IPIP => Self::Ipip,
+            Self::SCTP => Self::Sctp,
             p => Self::Unknown(p),
         }
     }
@@ -190,6 +193,7 @@ impl From<IpProtocol> for u8 {
             IpProtocol::NoNextHeader => IpProtocol::NO_NEXT_HEADER,
             IpProtocol::Ipv6Destination => IpProtocol::IPV6_DESTINATION,
             IpProtocol::Ipip => IpProtocol::IPIP,
+            IpProtocol::Sctp => IpProtocol::SCTP,
             IpProtocol::Unknown(p) => p,
         }
     }
@@ -29,16 +29,22 @@ pub enum L7Protocol {
     Http2 = 21,
     Http1TLS = 22,
     Http2TLS = 23,
+    Coap = 24,
+    Websocket = 25,
 
     // RPC
     Dubbo = 40,
     Grpc = 41,
     ProtobufRPC = 42,
     SofaRPC = 43,
+    Thrift = 44,
 
     // SQL
     MySQL = 60,
     PostgreSQL = 61,
+    ClickHouse = 62,
+    Oracle = 63,
+    Mssql = 64,
 
     // NoSQL
     Redis = 80,
@@ -46,9 +52,21 @@ pub enum L7Protocol {
     // MQ
     Kafka = 100,
     MQTT = 101,
+    Nats = 102,
+    RocketMq = 103,
 
     // INFRA
     DNS = 120,
+    Tls = 121,
+    Smtp = 122,
+    Zookeeper = 123,
+    Ftp = 124,
+    Statsd = 125,
+    Unrecognized = 126,
+
+    // 保留给运行时加载的插件, 由于 bitmap 使用 u128, 只剩下 127 可用
+    // reserved for plugins loaded at runtime, only 127 is left because bitmap uses u128
+    Custom = 127,
 
     Max = 255,
 }
@@ -60,16 +78,31 @@ impl From<String> for L7Protocol {
         match l7_protocol_str.as_str() {
             "http" => Self::Http1,
             "https" => Self::Http1TLS,
+            "coap" => Self::Coap,
+            "websocket" => Self::Websocket,
             "dubbo" => Self::Dubbo,
             "grpc" => Self::Grpc,
             "protobufrpc" => Self::ProtobufRPC,
             "sofarpc" => Self::SofaRPC,
+            "thrift" => Self::Thrift,
             "mysql" => Self::MySQL,
             "postgresql" => Self::PostgreSQL,
+            "clickhouse" => Self::ClickHouse,
+            "oracle" => Self::Oracle,
+            "mssql" => Self::Mssql,
             "redis" => Self::Redis,
             "kafka" => Self::Kafka,
             "mqtt" => Self::MQTT,
+            "nats" => Self::Nats,
+            "rocketmq" => Self::RocketMq,
             "dns" => Self::DNS,
+            "tls" => Self::Tls,
+            "smtp" => Self::Smtp,
+            "zookeeper" => Self::Zookeeper,
+            "ftp" => Self::Ftp,
+            "statsd" => Self::Statsd,
+            "unrecognized" => Self::Unrecognized,
+            "custom" => Self::Custom,
             _ => Self::Other,
         }
     }
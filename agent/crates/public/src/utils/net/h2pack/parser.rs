@@ -44,6 +44,10 @@ bitflags! {
 
 pub struct Parser<'a> {
     decoder: Decoder<'a>,
+    // 是否使用动态表解析索引, 需要从连接开始按顺序解析该方向所有的头部块
+    // resolve index in dynamic table, requires all header blocks of the direction
+    // to be parsed in order since the start of connection
+    dynamic_table: bool,
 }
 
 fn parse_int(buf: &[u8], prefix: u8) -> Result<(usize, usize), ParseError> {
@@ -94,6 +98,17 @@ impl Parser<'_> {
     pub fn new() -> Parser<'static> {
         Parser {
             decoder: Decoder::new(),
+            dynamic_table: false,
+        }
+    }
+
+    // 跨头部块维护动态表 (包括大小更新和淘汰), 动态表中不存在的索引返回 HeaderIndexOutOfBounds
+    // dynamic table (including size update and eviction) is maintained across header blocks,
+    // index not found in dynamic table returns HeaderIndexOutOfBounds
+    pub fn with_dynamic_table() -> Parser<'static> {
+        Parser {
+            decoder: Decoder::new(),
+            dynamic_table: true,
         }
     }
 
@@ -116,7 +131,9 @@ impl Parser<'_> {
             // RFC7541附录A(https://datatracker.ietf.org/doc/html/rfc7541#appendix-A)规定：
             // 静态表index从1到61，共60项。如果index大于61, 意味着这是一个dynamic table的
             // index，我们无法解出index对应的value，应该跳过对应的字节继续解析。
-            if index > STATIC_INDEX_MAX {
+            // 使用动态表时由 decoder 解析
+            // resolved by decoder when dynamic table is used
+            if index > STATIC_INDEX_MAX && !self.dynamic_table {
                 return Ok((None, val_len));
             }
         } else {
@@ -157,6 +174,11 @@ impl Parser<'_> {
                 Ok(rst) => Ok((Some(rst), index_len)),
                 Err(_) => Err(ParseError::InvalidHuffmanCode),
             }
+        } else if index > STATIC_INDEX_MAX && self.dynamic_table {
+            match self.decoder.decode(&buf[..index_len]) {
+                Ok(rst) => Ok((Some(rst), index_len)),
+                Err(_) => Err(ParseError::HeaderIndexOutOfBounds),
+            }
         } else {
             Ok((None, index_len))
         }
@@ -171,6 +193,11 @@ impl Parser<'_> {
 
     fn parse_sizeup(&mut self, buf: &[u8]) -> Result<usize, ParseError> {
         let (_, consumed) = parse_int(buf, 5)?;
+        // 调整动态表大小, 超出的表项被淘汰
+        // resize dynamic table, entries exceeding the size are evicted
+        if self.dynamic_table && self.decoder.decode(&buf[..consumed]).is_err() {
+            return Err(ParseError::InvalidInput);
+        }
         Ok(consumed)
     }
 
@@ -261,6 +288,43 @@ mod tests {
         assert_eq!(0, r2.len());
     }
 
+    #[test]
+    fn parse_field_dynamic_table() {
+        let mut parser = Parser::with_dynamic_table();
+
+        // literal with incremental indexing, new name: x-tenant: blue
+        let buffer1 = [
+            0x40, 0x08, b'x', b'-', b't', b'e', b'n', b'a', b'n', b't', 0x04, b'b', b'l', b'u',
+            b'e',
+        ];
+        let r1 = parser.parse(&buffer1).unwrap();
+        assert_eq!(b"x-tenant", r1[0].0.as_slice());
+
+        let buffer2 = [0xbe]; // dynamic table index 62
+        let r2 = parser.parse(&buffer2).unwrap();
+        assert_eq!(1, r2.len());
+        assert_eq!(b"x-tenant", r2[0].0.as_slice());
+        assert_eq!(b"blue", r2[0].1.as_slice());
+
+        // literal with incremental indexing, indexed name: :authority: example.com
+        let buffer3 = [
+            0x41, 0x0b, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+        ];
+        parser.parse(&buffer3).unwrap();
+        let r3 = parser.parse(&[0xbf]).unwrap();
+        assert_eq!(b"x-tenant", r3[0].0.as_slice());
+
+        // size update to 64 evicts x-tenant, only :authority is left
+        let buffer4 = [0x3f, 0x21, 0xbe];
+        let r4 = parser.parse(&buffer4).unwrap();
+        assert_eq!(b":authority", r4[0].0.as_slice());
+        assert_eq!(b"example.com", r4[0].1.as_slice());
+        assert_eq!(
+            Err(ParseError::HeaderIndexOutOfBounds),
+            parser.parse(&[0xbf])
+        );
+    }
+
     #[test]
     fn parse_field_incremental_indexing() {
         let mut parser = Parser::new();
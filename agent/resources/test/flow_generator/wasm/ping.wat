@@ -0,0 +1,48 @@
+;; 示例插件: 载荷以 PING 开头为请求, 以 PONG 开头为响应
+;; sample plugin: payload starting with PING is request, starting with PONG is response
+(module
+  (memory (export "memory") 1)
+
+  ;; 解析结果, 即 json 序列化的 CustomInfo
+  ;; results of parsing, which are CustomInfo serialized in json
+  (data (i32.const 0) "{\"msg_type\":\"Request\",\"request_type\":\"PING\"}")
+  (data (i32.const 64) "{\"msg_type\":\"Response\",\"response_status\":\"Ok\",\"response_code\":0}")
+
+  ;; 载荷总是写到 1024, 每次调用复用
+  ;; payload is always written at 1024, reused by every call
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 1024))
+
+  ;; 0 为 PING, 1 为 PONG, -1 为其他
+  ;; 0 for PING, 1 for PONG, -1 for others
+  (func $kind (param $ptr i32) (param $len i32) (result i32)
+    (if (i32.lt_u (local.get $len) (i32.const 4))
+      (then (return (i32.const -1))))
+    ;; "PING" in little endian
+    (if (i32.eq (i32.load (local.get $ptr)) (i32.const 0x474e4950))
+      (then (return (i32.const 0))))
+    ;; "PONG" in little endian
+    (if (i32.eq (i32.load (local.get $ptr)) (i32.const 0x474e4f50))
+      (then (return (i32.const 1))))
+    (i32.const -1))
+
+  (func (export "check_payload")
+    (param $ptr i32) (param $len i32)
+    (param $l4_protocol i32) (param $direction i32) (param $port_src i32) (param $port_dst i32)
+    (result i32)
+    (i32.ne (call $kind (local.get $ptr) (local.get $len)) (i32.const -1)))
+
+  (func (export "parse_payload")
+    (param $ptr i32) (param $len i32)
+    (param $l4_protocol i32) (param $direction i32) (param $port_src i32) (param $port_dst i32)
+    (result i64)
+    (local $kind i32)
+    (local.set $kind (call $kind (local.get $ptr) (local.get $len)))
+    ;; (0 << 32) | 44
+    (if (i32.eqz (local.get $kind))
+      (then (return (i64.const 44))))
+    ;; (64 << 32) | 64
+    (if (i32.eq (local.get $kind) (i32.const 1))
+      (then (return (i64.const 274877907008))))
+    (i64.const 0))
+)
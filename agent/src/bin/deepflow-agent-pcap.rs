@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    path::PathBuf,
+    process,
+    rc::Rc,
+};
+
+use clap::Parser;
+
+use deepflow_agent::{
+    _L7PerfCache as L7PerfCache, _L7ProtocolChecker as L7ProtocolChecker,
+    _ModuleConfig as ModuleConfig, _PacketDirection as PacketDirection,
+    common::l7_protocol_log::{
+        get_all_protocol, L7ProtocolBitmap, L7ProtocolParser, L7ProtocolParserInterface,
+        ParseParam, ProtocolParserRegistry,
+    },
+    utils::test::Capture,
+};
+
+const L7_PERF_CACHE_CAPACITY: usize = 1024;
+
+/// parse pcap file with l7 protocol parsers and print the logs of each packet
+///
+/// eg: deepflow-agent-pcap --protocol MySQL mysql.pcap
+#[derive(Parser)]
+#[clap(name = "deepflow-agent-pcap")]
+struct Cmd {
+    /// pcap file
+    pcap: PathBuf,
+    /// only use the parser of this protocol, such as HTTP, HTTP2, MySQL, Redis
+    #[clap(short, long)]
+    protocol: Option<String>,
+    /// print logs in json
+    #[clap(long)]
+    json: bool,
+    /// max length of each packet to parse
+    #[clap(long, default_value_t = 65535)]
+    parse_len: usize,
+}
+
+// 流中第一个包的目的端作为服务端, 协议识别成功后固定使用该解析器
+// destination of the first packet in flow is the server,
+// the parser is fixed after protocol is identified
+struct FlowState {
+    server: (IpAddr, u16),
+    parser: Option<L7ProtocolParser>,
+}
+
+fn parsers(protocol: Option<&str>) -> Vec<L7ProtocolParser> {
+    get_all_protocol()
+        .into_iter()
+        .filter(|p| protocol.map_or(true, |name| p.as_str().eq_ignore_ascii_case(name)))
+        .collect()
+}
+
+// 与 agent 相同, 按默认端口和检查分数识别协议, 并遵循配置的协议端口
+// identify protocols by default port and check score the same as agent,
+// with the configured protocol ports respected
+fn checker(config: &ModuleConfig, protocol: Option<&str>) -> L7ProtocolChecker {
+    let enabled = parsers(protocol)
+        .iter()
+        .map(|p| p.as_str().to_owned())
+        .collect::<Vec<_>>();
+    let port_bitmap = config
+        .flow
+        .l7_protocol_parse_port_bitmap
+        .iter()
+        .filter_map(|(name, bitmap)| {
+            L7ProtocolParser::try_from(name.as_ref())
+                .ok()
+                .map(|p| (p.protocol(), bitmap.clone()))
+        })
+        .collect::<HashMap<_, _>>();
+    L7ProtocolChecker::new(
+        &L7ProtocolBitmap::from(&enabled),
+        &port_bitmap,
+        ProtocolParserRegistry::global(),
+    )
+}
+
+fn main() {
+    let cmd = Cmd::parse();
+    if parsers(cmd.protocol.as_deref()).is_empty() {
+        let names = get_all_protocol()
+            .iter()
+            .map(|p| p.as_str())
+            .collect::<Vec<_>>();
+        eprintln!(
+            "unknown protocol {}, supported: {}",
+            cmd.protocol.unwrap_or_default(),
+            names.join(", ")
+        );
+        process::exit(1);
+    }
+    if !cmd.pcap.is_file() {
+        eprintln!("pcap file {} not found", cmd.pcap.display());
+        process::exit(1);
+    }
+
+    let config = ModuleConfig::default();
+    let checker = checker(&config, cmd.protocol.as_deref());
+    let perf_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_PERF_CACHE_CAPACITY)));
+    let capture = Capture::load_pcap(&cmd.pcap, Some(cmd.parse_len));
+    let mut packets = capture.as_meta_packets();
+    let mut flows = BTreeMap::new();
+
+    for (i, packet) in packets.iter_mut().enumerate() {
+        let key = &packet.lookup_key;
+        let (src, dst) = ((key.src_ip, key.src_port), (key.dst_ip, key.dst_port));
+        let flow = flows
+            .entry((key.proto, src.min(dst), src.max(dst)))
+            .or_insert(FlowState {
+                server: dst,
+                parser: None,
+            });
+        packet.lookup_key.direction = if flow.server == dst {
+            PacketDirection::ClientToServer
+        } else {
+            PacketDirection::ServerToClient
+        };
+        let Some(payload) = packet.get_l4_payload() else {
+            continue;
+        };
+        if payload.is_empty() {
+            continue;
+        }
+
+        let param = ParseParam::from((&*packet, perf_cache.clone(), false, &config.log_parser));
+        if flow.parser.is_none() {
+            flow.parser = checker.check_payload(payload, &param, &[]);
+        }
+        let Some(parser) = flow.parser.as_mut() else {
+            continue;
+        };
+
+        match parser.parse_payload(payload, &param) {
+            Ok(infos) => {
+                for info in infos {
+                    if cmd.json {
+                        println!("{}", serde_json::to_string(&info).unwrap());
+                    } else {
+                        println!(
+                            "#{} {:?} {} {:?}",
+                            i,
+                            param.direction,
+                            parser.as_str(),
+                            info
+                        );
+                    }
+                }
+            }
+            Err(e) if !cmd.json => {
+                println!("#{} {:?} {} {}", i, param.direction, parser.as_str(), e)
+            }
+            Err(_) => (),
+        }
+        parser.reset();
+    }
+}
@@ -14,15 +14,17 @@
  * limitations under the License.
  */
 
-use super::flow::PacketDirection;
+use super::flow::{L7Protocol, PacketDirection};
 use enum_dispatch::enum_dispatch;
 use log::{debug, error};
 use serde::Serialize;
 
 use crate::flow_generator::{
     protocol_logs::{
-        pb_adapter::L7ProtocolSendLog, DnsInfo, DubboInfo, HttpInfo, KafkaInfo, MqttInfo,
-        MysqlInfo, PostgreInfo, ProtobufRpcInfo, RedisInfo, SofaRpcInfo,
+        pb_adapter::L7ProtocolSendLog, ClickHouseInfo, CoapInfo, CustomInfo, DnsInfo, DubboInfo,
+        FtpInfo, HttpInfo, KafkaInfo, L7ResponseStatus, MqttInfo, MssqlInfo, MysqlInfo, NatsInfo,
+        OracleInfo, PostgreInfo, ProtobufRpcInfo, RedisInfo, RocketMqInfo, SmtpInfo, SofaRpcInfo,
+        StatsdInfo, ThriftInfo, TlsInfo, UnknownInfo, WebsocketInfo, ZookeeperInfo,
     },
     AppProtoHead, LogMessageType, Result,
 };
@@ -49,6 +51,19 @@ macro_rules! all_protocol_info {
                 }
             }
         }
+
+        impl L7ProtocolInfo {
+            // 不带枚举名的 Debug 输出, 用于测试结果比较
+            // Debug output without enum name, used to compare test results
+            #[cfg(test)]
+            pub fn inner_debug(&self) -> String {
+                match self {
+                    $(
+                        L7ProtocolInfo::$name(info) => format!("{:?}", info),
+                    )+
+                }
+            }
+        }
     };
 }
 
@@ -63,9 +78,36 @@ all_protocol_info!(
     PostgreInfo(PostgreInfo),
     ProtobufRpcInfo(ProtobufRpcInfo),
     SofaRpcInfo(SofaRpcInfo),
+    NatsInfo(NatsInfo),
+    ClickHouseInfo(ClickHouseInfo),
+    OracleInfo(OracleInfo),
+    MssqlInfo(MssqlInfo),
+    RocketMqInfo(RocketMqInfo),
+    CoapInfo(CoapInfo),
+    TlsInfo(TlsInfo),
+    WebsocketInfo(WebsocketInfo),
+    ThriftInfo(ThriftInfo),
+    SmtpInfo(SmtpInfo),
+    ZookeeperInfo(ZookeeperInfo),
+    FtpInfo(FtpInfo),
+    StatsdInfo(StatsdInfo),
+    UnknownInfo(UnknownInfo),
+    CustomInfo(CustomInfo),
     // add new protocol info below
 );
 
+impl L7ProtocolInfo {
+    // 是否为错误日志, 需要转换为发送格式, 开销较大
+    // whether it is an error log, it is converted to send log so the cost is high
+    pub fn is_error(&self) -> bool {
+        let log = L7ProtocolSendLog::from(self.clone());
+        matches!(
+            log.resp.status,
+            L7ResponseStatus::ClientError | L7ResponseStatus::ServerError
+        )
+    }
+}
+
 #[enum_dispatch(L7ProtocolInfo)]
 pub trait L7ProtocolInfoInterface: Into<L7ProtocolSendLog> {
     // 个别协议一个连接可能有子流，这里需要返回流标识，例如http2的stream id
@@ -102,6 +144,13 @@ pub trait L7ProtocolInfoInterface: Into<L7ProtocolSendLog> {
         (false, false)
     }
 
+    // 协议升级后流需要交给的协议, 例如 HTTP 升级为 WebSocket
+    // =========================================================
+    // the protocol which the flow is handed off to after upgrade, such as HTTP upgrades to WebSocket
+    fn upgrade_protocol(&self) -> Option<L7Protocol> {
+        None
+    }
+
     fn cal_cache_key(&self, param: &ParseParam) -> u128 {
         /*
             if session id is some: flow id 64bit | 0 32bit | session id 32bit
@@ -146,8 +195,12 @@ pub trait L7ProtocolInfoInterface: Into<L7ProtocolSendLog> {
 
         if time != 0 {
             let Some(previous_log_info) = previous_log_info else {
-                perf_cache.rrt_cache.put(cache_key, (param.direction.into(), param.time));
-                let timeout_count = perf_cache.timeout_cache.get_or_insert_mut(param.flow_id, ||0);
+                perf_cache
+                    .rrt_cache
+                    .put(cache_key, (param.direction.into(), param.time));
+                let timeout_count = perf_cache
+                    .timeout_cache
+                    .get_or_insert_mut(param.flow_id, || 0);
                 *timeout_count += 1;
                 return None;
             };
@@ -18,6 +18,7 @@ use std::cell::RefCell;
 use std::fmt::Debug;
 use std::net::IpAddr;
 use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
 
 use enum_dispatch::enum_dispatch;
 
@@ -30,13 +31,16 @@ use super::MetaPacket;
 
 use crate::config::handler::LogParserConfig;
 use crate::flow_generator::protocol_logs::{
-    get_protobuf_rpc_parser, DnsLog, DubboLog, HttpLog, KafkaLog, MqttLog, MysqlLog, PostgresqlLog,
-    ProtobufRpcWrapLog, RedisLog, SofaRpcLog,
+    get_protobuf_rpc_parser, ClickHouseLog, CoapLog, DnsLog, DubboLog, FtpLog, HttpLog, KafkaLog,
+    MqttLog, MssqlLog, MysqlLog, NatsLog, OracleLog, PostgresqlLog, ProtobufRpcWrapLog, RedisLog,
+    RocketMqLog, SmtpLog, SofaRpcLog, StatsdLog, ThriftLog, TlsLog, UnknownLog, WebsocketLog,
+    ZookeeperLog,
 };
-use crate::flow_generator::{LogMessageType, Result};
+use crate::flow_generator::{Error, LogMessageType, Result};
 
 use public::enums::IpProtocol;
 use public::l7_protocol::{L7Protocol, L7ProtocolEnum, ProtobufRpcProtocol};
+use public::utils::net::MacAddr;
 
 /*
  所有协议都需要实现L7ProtocolLogInterface这个接口.
@@ -62,6 +66,9 @@ macro_rules! impl_protocol_parser {
     (pub enum $name:ident { $($proto:ident($log_type:ty)),* $(,)? }) => {
         pub enum $name {
             Http(Box<HttpLog>),
+            // 运行时注册的解析器, 见 ProtocolParserRegistry
+            // parsers registered at runtime, see ProtocolParserRegistry
+            Registered(Box<dyn L7ProtocolParserInterface>),
             $($proto($log_type)),*
         }
 
@@ -69,13 +76,23 @@ macro_rules! impl_protocol_parser {
             fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
                 match self {
                     Self::Http(p) => p.check_payload(payload, param),
+                    Self::Registered(p) => p.check_payload(payload, param),
                     $(Self::$proto(p) => p.check_payload(payload, param)),*
                 }
             }
 
+            fn check_payload_score(&mut self, payload: &[u8], param: &ParseParam) -> u8 {
+                match self {
+                    Self::Http(p) => p.check_payload_score(payload, param),
+                    Self::Registered(p) => p.check_payload_score(payload, param),
+                    $(Self::$proto(p) => p.check_payload_score(payload, param)),*
+                }
+            }
+
             fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
                 match self {
                     Self::Http(p) => p.parse_payload(payload, param),
+                    Self::Registered(p) => p.parse_payload(payload, param),
                     $(Self::$proto(p) => p.parse_payload(payload, param)),*
                 }
             }
@@ -83,6 +100,7 @@ macro_rules! impl_protocol_parser {
             fn protocol(&self) -> L7Protocol {
                 match self {
                     Self::Http(p) => p.protocol(),
+                    Self::Registered(p) => p.protocol(),
                     $(Self::$proto(p) => p.protocol()),*
                 }
             }
@@ -90,6 +108,7 @@ macro_rules! impl_protocol_parser {
             fn protobuf_rpc_protocol(&self) -> Option<ProtobufRpcProtocol> {
                 match self {
                     Self::Http(p) => p.protobuf_rpc_protocol(),
+                    Self::Registered(p) => p.protobuf_rpc_protocol(),
                     $(Self::$proto(p) => p.protobuf_rpc_protocol()),*
                 }
             }
@@ -97,6 +116,7 @@ macro_rules! impl_protocol_parser {
             fn l7_protocl_enum(&self) -> L7ProtocolEnum {
                 match self {
                     Self::Http(p) => p.l7_protocl_enum(),
+                    Self::Registered(p) => p.l7_protocl_enum(),
                     $(Self::$proto(p) => p.l7_protocl_enum()),*
                 }
             }
@@ -104,6 +124,7 @@ macro_rules! impl_protocol_parser {
             fn parsable_on_tcp(&self) -> bool {
                 match self {
                     Self::Http(p) => p.parsable_on_tcp(),
+                    Self::Registered(p) => p.parsable_on_tcp(),
                     $(Self::$proto(p) => p.parsable_on_tcp()),*
                 }
             }
@@ -111,20 +132,55 @@ macro_rules! impl_protocol_parser {
             fn parsable_on_udp(&self) -> bool {
                 match self {
                     Self::Http(p) => p.parsable_on_udp(),
+                    Self::Registered(p) => p.parsable_on_udp(),
                     $(Self::$proto(p) => p.parsable_on_udp()),*
                 }
             }
 
+            fn parsable_on(&self, proto: IpProtocol) -> bool {
+                match self {
+                    Self::Http(p) => p.parsable_on(proto),
+                    Self::Registered(p) => p.parsable_on(proto),
+                    $(Self::$proto(p) => p.parsable_on(proto)),*
+                }
+            }
+
+            fn default_ports(&self) -> &[u16] {
+                match self {
+                    Self::Http(p) => p.default_ports(),
+                    Self::Registered(p) => p.default_ports(),
+                    $(Self::$proto(p) => p.default_ports()),*
+                }
+            }
+
             fn parse_default(&self) -> bool {
                 match self {
                     Self::Http(p) => p.parse_default(),
+                    Self::Registered(p) => p.parse_default(),
                     $(Self::$proto(p) => p.parse_default()),*
                 }
             }
 
+            fn needs_bidirectional(&self) -> bool {
+                match self {
+                    Self::Http(p) => p.needs_bidirectional(),
+                    Self::Registered(p) => p.needs_bidirectional(),
+                    $(Self::$proto(p) => p.needs_bidirectional()),*
+                }
+            }
+
+            fn parse_retransmission(&self) -> bool {
+                match self {
+                    Self::Http(p) => p.parse_retransmission(),
+                    Self::Registered(p) => p.parse_retransmission(),
+                    $(Self::$proto(p) => p.parse_retransmission()),*
+                }
+            }
+
             fn reset(&mut self) {
                 match self {
                     Self::Http(p) => p.reset(),
+                    Self::Registered(p) => p.reset(),
                     $(Self::$proto(p) => p.reset()),*
                 }
             }
@@ -132,6 +188,7 @@ macro_rules! impl_protocol_parser {
             fn perf_stats(&mut self) -> Option<L7PerfStats> {
                 match self {
                     Self::Http(p) => p.perf_stats(),
+                    Self::Registered(p) => p.perf_stats(),
                     $(Self::$proto(p) => p.perf_stats()),*
                 }
             }
@@ -147,6 +204,7 @@ macro_rules! impl_protocol_parser {
                             _ => unreachable!()
                         }
                     },
+                    Self::Registered(_) => "Registered",
                     $(
                         Self::$proto(_) => stringify!($proto),
                     )*
@@ -245,19 +303,57 @@ impl_protocol_parser! {
         DNS(DnsLog),
         ProtobufRPC(Box<ProtobufRpcWrapLog>),
         SofaRPC(Box<SofaRpcLog>),
-        MySQL(MysqlLog),
-        Kafka(KafkaLog),
+        MySQL(Box<MysqlLog>),
+        Kafka(Box<KafkaLog>),
         Redis(Box<RedisLog>),
         PostgreSQL(Box<PostgresqlLog>),
         Dubbo(Box<DubboLog>),
         MQTT(MqttLog),
+        Nats(NatsLog),
+        ClickHouse(Box<ClickHouseLog>),
+        Oracle(Box<OracleLog>),
+        Mssql(Box<MssqlLog>),
+        RocketMq(Box<RocketMqLog>),
+        Coap(Box<CoapLog>),
+        Tls(Box<TlsLog>),
+        Websocket(Box<WebsocketLog>),
+        Thrift(Box<ThriftLog>),
+        Smtp(Box<SmtpLog>),
+        Zookeeper(Box<ZookeeperLog>),
+        Ftp(Box<FtpLog>),
+        Statsd(Box<StatsdLog>),
         // add protocol below
+
+        // 兜底的解析器，必须放在最后，避免遮蔽其他协议
+        // fallback parser, must be the last one so that it never shadows other protocols
+        Unrecognized(Box<UnknownLog>),
     }
 }
 
+// check_payload_score 的返回值: 不匹配, 宽松的匹配 (如仅检查 ASCII 格式), 默认, 确定的匹配
+// return values of check_payload_score: no match, loose match (such as ASCII format check only),
+// default and certain match
+pub const CHECK_SCORE_NONE: u8 = 0;
+pub const CHECK_SCORE_LOW: u8 = 25;
+pub const CHECK_SCORE_DEFAULT: u8 = 50;
+pub const CHECK_SCORE_MAX: u8 = 100;
+
 #[enum_dispatch]
 pub trait L7ProtocolParserInterface {
     fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool;
+    // 协议识别的置信度, 多个协议都匹配时选择分数最高的, 分数相同时按协议顺序选择.
+    // 默认由 check_payload 得出, 覆盖时分数不为 CHECK_SCORE_NONE 当且仅当 check_payload 返回 true
+    // ===========================================================================================
+    // confidence of protocol detection, the highest one is chosen when several protocols match,
+    // and protocol order decides when scores are equal. derived from check_payload by default,
+    // when overridden, the score must be non-zero if and only if check_payload returns true
+    fn check_payload_score(&mut self, payload: &[u8], param: &ParseParam) -> u8 {
+        if self.check_payload(payload, param) {
+            CHECK_SCORE_DEFAULT
+        } else {
+            CHECK_SCORE_NONE
+        }
+    }
     // 协议解析
     fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>>;
     // 返回协议号和协议名称，由于的bitmap使用u128，所以协议号不能超过128.
@@ -292,12 +388,48 @@ pub trait L7ProtocolParserInterface {
     fn parsable_on_udp(&self) -> bool {
         true
     }
+    // l4 是 proto 时是否解析, 默认只支持 tcp 和 udp, 其他 l4 协议(如 sctp)的解析器需要重写
+    // ==============================
+    // whether l4 is parsed when proto, only tcp and udp are supported by default,
+    // parsers of other l4 protocols (such as sctp) need to override it
+    fn parsable_on(&self, proto: IpProtocol) -> bool {
+        match proto {
+            IpProtocol::Tcp => self.parsable_on_tcp(),
+            IpProtocol::Udp => self.parsable_on_udp(),
+            _ => false,
+        }
+    }
+
+    // 协议的默认端口，仅用于调整协议检查的顺序，端口不匹配时协议依然会被检查
+    // ==============================
+    // default ports of the protocol, only a hint to reorder protocol checking,
+    // the protocol is still checked when port does not match
+    fn default_ports(&self) -> &[u16] {
+        &[]
+    }
 
     // is parse default? use for config init.
     fn parse_default(&self) -> bool {
         true
     }
 
+    // 协议是否依赖两个方向的数据 (例如服务端的握手包), 仅供参考, 协议识别目前不使用
+    // ==============================
+    // whether the protocol depends on payloads of both directions (server handshake for example),
+    // advisory only, it is not consulted by protocol identification for now
+    fn needs_bidirectional(&self) -> bool {
+        false
+    }
+
+    // 是否解析 TcpPerf 判断的重传包, 此时 ParseParam.is_retransmission 为 true, 由解析器自行去重,
+    // 否则重传包不会进入应用层解析
+    // ==============================
+    // whether retransmissions determined by TcpPerf are parsed, ParseParam.is_retransmission is true
+    // for them and the parser should dedupe by itself, otherwise retransmissions never reach l7 parsing
+    fn parse_retransmission(&self) -> bool {
+        false
+    }
+
     fn reset(&mut self) {}
 
     // return perf data
@@ -351,6 +483,7 @@ impl L7PerfCache {
     }
 }
 
+#[derive(Clone)]
 pub struct ParseParam<'a> {
     // l3/l4 info
     pub l4_protocol: IpProtocol,
@@ -360,6 +493,12 @@ pub struct ParseParam<'a> {
     pub port_dst: u16,
     pub flow_id: u64,
 
+    // l2 info, 可用于区分不同 VLAN 中 IP 地址重复的会话
+    // l2 info, can be used to distinguish sessions with the same ip in different vlan
+    pub vlan: Option<u16>,
+    pub src_mac: MacAddr,
+    pub dst_mac: MacAddr,
+
     // parse info
     pub direction: PacketDirection,
     pub ebpf_type: EbpfType,
@@ -369,6 +508,13 @@ pub struct ParseParam<'a> {
     pub ebpf_param: Option<EbpfParam>,
     // calculate from cap_seq, req and correspond resp may have same packet seq, non ebpf always 0
     pub packet_seq: u64,
+    // 用于 TCP 分段重组, UDP 和 eBPF 的数据为 0 和 false,
+    // 只有 parse_retransmission 的解析器会收到重传包
+    // ===================================================
+    // used for reassembly of tcp segments, 0 and false for udp and ebpf payload,
+    // only parsers with parse_retransmission receive retransmissions
+    pub tcp_seq: u32,
+    pub is_retransmission: bool,
     pub time: u64, // micro second
     pub perf_only: bool,
 
@@ -390,9 +536,19 @@ impl From<(&MetaPacket<'_>, Rc<RefCell<L7PerfCache>>, bool)> for ParseParam<'_>
             port_dst: packet.lookup_key.dst_port,
             flow_id: packet.flow_id,
 
+            vlan: if packet.vlan_tag_size > 0 {
+                Some(packet.vlan)
+            } else {
+                None
+            },
+            src_mac: packet.lookup_key.src_mac,
+            dst_mac: packet.lookup_key.dst_mac,
+
             direction: packet.lookup_key.direction,
             ebpf_type: packet.ebpf_type,
             packet_seq: packet.cap_seq,
+            tcp_seq: 0,
+            is_retransmission: false,
             ebpf_param: None,
             time: packet.lookup_key.timestamp.as_micros() as u64,
             perf_only,
@@ -414,6 +570,9 @@ impl From<(&MetaPacket<'_>, Rc<RefCell<L7PerfCache>>, bool)> for ParseParam<'_>
                 is_resp_end: packet.is_response_end,
                 cap_seq: packet.cap_seq,
             });
+        } else if packet.lookup_key.proto == IpProtocol::Tcp {
+            param.tcp_seq = packet.tcp_data.seq;
+            param.is_retransmission = packet.is_retransmission;
         }
 
         param
@@ -452,6 +611,22 @@ impl ParseParam<'_> {
     }
 }
 
+// 协议号必须唯一且小于128, 否则 bitmap 会把不同的协议混在一起, 返回第一个不满足的协议
+// ==========================================================================================
+// protocol number must be unique and less than 128, otherwise the bitmap will mix up protocols,
+// return the first protocol which does not satisfy
+pub fn check_protocol_number(parsers: &[L7ProtocolParser]) -> std::result::Result<(), L7Protocol> {
+    let mut bitmap = 0u128;
+    for p in parsers {
+        let protocol = p.protocol();
+        if protocol as u8 >= u128::BITS as u8 || bitmap & (1 << protocol as u128) != 0 {
+            return Err(protocol);
+        }
+        bitmap |= 1 << protocol as u128;
+    }
+    Ok(())
+}
+
 /*
     param:
         protocol: the protocol which should check
@@ -463,22 +638,287 @@ impl ParseParam<'_> {
     return the protocol bitmap indicate which protocol should check and parse.
 */
 pub fn get_parse_bitmap(protocol: IpProtocol, l7_enabled: L7ProtocolBitmap) -> L7ProtocolBitmap {
+    get_parse_bitmap_with_disabled(protocol, l7_enabled, &[])
+}
+
+/*
+    same as get_parse_bitmap, but the protocols in disabled are always skipped.
+*/
+pub fn get_parse_bitmap_with_disabled(
+    protocol: IpProtocol,
+    l7_enabled: L7ProtocolBitmap,
+    disabled: &[L7Protocol],
+) -> L7ProtocolBitmap {
+    let parsers = get_all_protocol();
+    parse_bitmap(
+        parsers.iter().map(|p| p as &dyn L7ProtocolParserInterface),
+        protocol,
+        l7_enabled,
+        disabled,
+    )
+}
+
+fn parse_bitmap<'a>(
+    parsers: impl Iterator<Item = &'a dyn L7ProtocolParserInterface>,
+    protocol: IpProtocol,
+    l7_enabled: L7ProtocolBitmap,
+    disabled: &[L7Protocol],
+) -> L7ProtocolBitmap {
     let mut bitmap = L7ProtocolBitmap(0);
-    for i in get_all_protocol().iter() {
-        if l7_enabled.is_enabled(i.protocol()) {
-            match protocol {
-                IpProtocol::Tcp if i.parsable_on_tcp() => {
-                    bitmap.set_enabled(i.protocol());
-                }
-                IpProtocol::Udp if i.parsable_on_udp() => {
-                    bitmap.set_enabled(i.protocol());
+    for i in parsers {
+        if l7_enabled.is_enabled(i.protocol()) && i.parsable_on(protocol) {
+            bitmap.set_enabled(i.protocol());
+        }
+    }
+    for p in disabled {
+        bitmap.set_disabled(*p);
+    }
+
+    bitmap
+}
+
+/*
+    运行时注册的协议解析器, 用于在不重新编译 agent 的情况下支持私有协议.
+    注册的协议号不能和内置协议或已注册的协议冲突, 且由于 bitmap 使用 u128, 协议号不能超过 127.
+    注册的协议总是开启, 不受 l7-protocol-enabled 配置影响.
+
+    parsers registered at runtime, used to support private protocols without recompiling agent.
+    the protocol number must not conflict with inner or registered protocols,
+    and because of bitmap use u128, the protocol number can not exceed 127.
+    registered protocols are always enabled regardless of l7-protocol-enabled config.
+*/
+pub type L7ProtocolParserBuilder =
+    Box<dyn Fn() -> Box<dyn L7ProtocolParserInterface> + Send + Sync>;
+
+static GLOBAL_REGISTRY: OnceLock<Arc<ProtocolParserRegistry>> = OnceLock::new();
+
+// 内置协议的协议号和是否可以在 TCP/UDP 上解析, 只在第一次使用时创建所有解析器
+// protocol numbers of inner protocols and whether they are parsable on TCP/UDP,
+// all parsers are created only on first use
+fn inner_protocols() -> &'static [(L7Protocol, bool, bool)] {
+    static PROTOCOLS: OnceLock<Vec<(L7Protocol, bool, bool)>> = OnceLock::new();
+    PROTOCOLS.get_or_init(|| {
+        get_all_protocol()
+            .iter()
+            .map(|p| (p.protocol(), p.parsable_on_tcp(), p.parsable_on_udp()))
+            .collect()
+    })
+}
+
+#[derive(Default)]
+pub struct ProtocolParserRegistry {
+    parsers: Vec<(L7Protocol, L7ProtocolParserBuilder)>,
+}
+
+impl ProtocolParserRegistry {
+    // 安装进程的全局注册表, 需要在创建 FlowMap 之前调用, 只能安装一次, 安装时检查协议号
+    // install the global registry of process, should be called before FlowMap is created,
+    // and can be installed only once, protocol numbers are checked on install
+    pub fn install(registry: ProtocolParserRegistry) -> Result<()> {
+        check_protocol_number(&registry.get_all_protocol()).map_err(Error::L7ProtocolConflict)?;
+        GLOBAL_REGISTRY
+            .set(Arc::new(registry))
+            .map_err(|_| Error::L7ProtocolRegistryInstalled)
+    }
+
+    // 全局注册表, 未安装时为空
+    // the global registry, empty if not installed
+    pub fn global() -> Arc<ProtocolParserRegistry> {
+        GLOBAL_REGISTRY.get_or_init(Default::default).clone()
+    }
+
+    pub fn register(&mut self, builder: L7ProtocolParserBuilder) -> Result<()> {
+        let protocol = builder().protocol();
+        if protocol as u8 >= u128::BITS as u8
+            || protocol == L7Protocol::Unknown
+            || inner_protocols().iter().any(|(p, _, _)| *p == protocol)
+            || self.is_registered(protocol)
+        {
+            return Err(Error::L7ProtocolConflict(protocol));
+        }
+        self.parsers.push((protocol, builder));
+        Ok(())
+    }
+
+    pub fn is_registered(&self, protocol: L7Protocol) -> bool {
+        self.parsers.iter().any(|(p, _)| *p == protocol)
+    }
+
+    pub fn get_parser(&self, protocol: L7ProtocolEnum) -> Option<L7ProtocolParser> {
+        if let Some(p) = get_parser(protocol) {
+            return Some(p);
+        }
+        let L7ProtocolEnum::L7Protocol(protocol) = protocol else {
+            return None;
+        };
+        self.parsers
+            .iter()
+            .find(|(p, _)| *p == protocol)
+            .map(|(_, builder)| L7ProtocolParser::Registered(builder()))
+    }
+
+    // 内置协议和注册协议的解析器, 内置协议在前
+    // parsers of inner and registered protocols, inner protocols first
+    pub fn get_all_protocol(&self) -> Vec<L7ProtocolParser> {
+        let mut parsers = get_all_protocol();
+        parsers.extend(
+            self.parsers
+                .iter()
+                .map(|(_, builder)| L7ProtocolParser::Registered(builder())),
+        );
+        parsers
+    }
+
+    // 在 bitmap 中开启所有注册的协议
+    // enable all registered protocols in bitmap
+    pub fn enabled_bitmap(&self, l7_enabled: L7ProtocolBitmap) -> L7ProtocolBitmap {
+        let mut bitmap = l7_enabled;
+        for (p, _) in self.parsers.iter() {
+            bitmap.set_enabled(*p);
+        }
+        bitmap
+    }
+
+    // 同 get_parse_bitmap, 包含注册的协议
+    // same as get_parse_bitmap, registered protocols included
+    pub fn get_parse_bitmap(
+        &self,
+        protocol: IpProtocol,
+        l7_enabled: L7ProtocolBitmap,
+    ) -> L7ProtocolBitmap {
+        let parsers = self.get_all_protocol();
+        parse_bitmap(
+            parsers.iter().map(|p| p as &dyn L7ProtocolParserInterface),
+            protocol,
+            self.enabled_bitmap(l7_enabled),
+            &[],
+        )
+    }
+
+    // 返回 check_payload_score 分数最高的解析器, 分数相同时返回靠前的, 只创建需要检查的解析器
+    // return the parser with the highest check_payload_score, the former one if scores are equal,
+    // only parsers to be checked are created
+    pub fn check_payload(&self, payload: &[u8], param: &ParseParam) -> Option<L7ProtocolParser> {
+        let inner = inner_protocols()
+            .iter()
+            .filter(|(_, tcp, udp)| match param.l4_protocol {
+                IpProtocol::Tcp => *tcp,
+                IpProtocol::Udp => *udp,
+                _ => false,
+            })
+            .filter_map(|(p, _, _)| get_parser(L7ProtocolEnum::L7Protocol(*p)));
+        let registered = self
+            .parsers
+            .iter()
+            .map(|(_, builder)| L7ProtocolParser::Registered(builder()))
+            .filter(|p| p.parsable_on(param.l4_protocol));
+        let mut best: Option<(u8, L7ProtocolParser)> = None;
+        for mut parser in inner.chain(registered) {
+            let score = parser.check_payload_score(payload, param);
+            if score > best.as_ref().map(|(s, _)| *s).unwrap_or(CHECK_SCORE_NONE) {
+                best = Some((score, parser));
+                if score >= CHECK_SCORE_MAX {
+                    break;
                 }
-                _ => {}
             }
         }
+        best.map(|(_, parser)| parser)
     }
+}
 
-    bitmap
+/*
+    解析成功后观察 payload 的钩子, 参数为 (协议, payload, 解析参数, 解析结果),
+    每个解析结果调用一次, 用于把数据输出到外部.
+
+    hook to observe payload after successful parse, arguments are (protocol, payload, parse param, result),
+    called once for each result, used to tee data to external sinks.
+*/
+pub type L7PayloadInspector = Box<dyn Fn(L7Protocol, &[u8], &ParseParam, &L7ProtocolInfo)>;
+
+// 带有 L7PayloadInspector 的解析器, 不影响解析结果, 没有设置钩子时没有额外开销
+// parser with L7PayloadInspector, results are not affected, no extra cost without inspector
+pub struct InspectedParser<P: L7ProtocolParserInterface> {
+    parser: P,
+    inspector: Option<L7PayloadInspector>,
+}
+
+impl<P: L7ProtocolParserInterface> InspectedParser<P> {
+    pub fn new(parser: P) -> Self {
+        Self {
+            parser,
+            inspector: None,
+        }
+    }
+
+    pub fn inspector(mut self, inspector: L7PayloadInspector) -> Self {
+        self.inspector = Some(inspector);
+        self
+    }
+}
+
+impl<P: L7ProtocolParserInterface> L7ProtocolParserInterface for InspectedParser<P> {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        self.parser.check_payload(payload, param)
+    }
+
+    fn check_payload_score(&mut self, payload: &[u8], param: &ParseParam) -> u8 {
+        self.parser.check_payload_score(payload, param)
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        let infos = self.parser.parse_payload(payload, param)?;
+        if let Some(inspector) = self.inspector.as_ref() {
+            let protocol = self.parser.protocol();
+            for info in infos.iter() {
+                inspector(protocol, payload, param, info);
+            }
+        }
+        Ok(infos)
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        self.parser.protocol()
+    }
+
+    fn protobuf_rpc_protocol(&self) -> Option<ProtobufRpcProtocol> {
+        self.parser.protobuf_rpc_protocol()
+    }
+
+    fn parsable_on_tcp(&self) -> bool {
+        self.parser.parsable_on_tcp()
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        self.parser.parsable_on_udp()
+    }
+
+    fn parsable_on(&self, proto: IpProtocol) -> bool {
+        self.parser.parsable_on(proto)
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        self.parser.default_ports()
+    }
+
+    fn parse_default(&self) -> bool {
+        self.parser.parse_default()
+    }
+
+    fn needs_bidirectional(&self) -> bool {
+        self.parser.needs_bidirectional()
+    }
+
+    fn parse_retransmission(&self) -> bool {
+        self.parser.parse_retransmission()
+    }
+
+    fn reset(&mut self) {
+        self.parser.reset()
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.parser.perf_stats()
+    }
 }
 
 /*
@@ -505,6 +945,15 @@ impl L7ProtocolBitmap {
     pub fn is_enabled(&self, p: L7Protocol) -> bool {
         !self.is_disabled(p)
     }
+
+    // 返回bitmap中已开启的协议
+    // return the protocols enabled in bitmap
+    pub fn protocols(&self) -> Vec<L7Protocol> {
+        (0..u128::BITS as u8)
+            .map(L7Protocol::from)
+            .filter(|p| *p != L7Protocol::Unknown && self.is_enabled(*p))
+            .collect()
+    }
 }
 
 impl From<&Vec<String>> for L7ProtocolBitmap {
@@ -521,12 +970,493 @@ impl From<&Vec<String>> for L7ProtocolBitmap {
 
 impl Debug for L7ProtocolBitmap {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut p = vec![];
-        for i in get_all_protocol() {
-            if self.is_enabled(i.protocol()) {
-                p.push(i.protocol());
+        f.write_str(format!("{:#?}", self.protocols()).as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::net::Ipv4Addr;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator";
+
+    struct DummyLog;
+
+    impl L7ProtocolParserInterface for DummyLog {
+        fn check_payload(&mut self, payload: &[u8], _: &ParseParam) -> bool {
+            payload.starts_with(b"DUMMY")
+        }
+
+        fn parse_payload(&mut self, _: &[u8], _: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+            Ok(vec![])
+        }
+
+        fn protocol(&self) -> L7Protocol {
+            L7Protocol::Other
+        }
+
+        fn parsable_on_udp(&self) -> bool {
+            false
+        }
+
+        fn perf_stats(&mut self) -> Option<L7PerfStats> {
+            None
+        }
+    }
+
+    struct SctpDummyLog;
+
+    impl L7ProtocolParserInterface for SctpDummyLog {
+        fn check_payload(&mut self, _: &[u8], _: &ParseParam) -> bool {
+            false
+        }
+
+        fn parse_payload(&mut self, _: &[u8], _: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+            Ok(vec![])
+        }
+
+        fn protocol(&self) -> L7Protocol {
+            L7Protocol::Other
+        }
+
+        fn parsable_on(&self, proto: IpProtocol) -> bool {
+            proto == IpProtocol::Sctp
+        }
+
+        fn perf_stats(&mut self) -> Option<L7PerfStats> {
+            None
+        }
+    }
+
+    // 匹配 "GET " 开头的 payload, 分数由参数指定
+    // matches payload starting with "GET ", score is given by the parameter
+    struct ScoredLog(u8);
+
+    impl L7ProtocolParserInterface for ScoredLog {
+        fn check_payload(&mut self, payload: &[u8], _: &ParseParam) -> bool {
+            payload.starts_with(b"GET ")
+        }
+
+        fn check_payload_score(&mut self, payload: &[u8], param: &ParseParam) -> u8 {
+            if self.check_payload(payload, param) {
+                self.0
+            } else {
+                CHECK_SCORE_NONE
+            }
+        }
+
+        fn parse_payload(&mut self, _: &[u8], _: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+            Ok(vec![])
+        }
+
+        fn protocol(&self) -> L7Protocol {
+            L7Protocol::Other
+        }
+
+        fn perf_stats(&mut self) -> Option<L7PerfStats> {
+            None
+        }
+    }
+
+    fn new_param(l4_protocol: IpProtocol) -> ParseParam<'static> {
+        ParseParam {
+            l4_protocol,
+            ip_src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            ip_dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            port_src: 12345,
+            port_dst: 8000,
+            flow_id: 0,
+            vlan: None,
+            src_mac: MacAddr::ZERO,
+            dst_mac: MacAddr::ZERO,
+            direction: PacketDirection::ClientToServer,
+            ebpf_type: EbpfType::None,
+            ebpf_param: None,
+            packet_seq: 0,
+            tcp_seq: 0,
+            is_retransmission: false,
+            time: 0,
+            perf_only: false,
+            parse_config: None,
+            l7_perf_cache: Rc::new(RefCell::new(L7PerfCache::new(1))),
+        }
+    }
+
+    #[test]
+    fn check_registry() {
+        let mut registry = ProtocolParserRegistry::default();
+        assert!(registry.register(Box::new(|| Box::new(DummyLog))).is_ok());
+        // 协议号冲突
+        // protocol number conflict
+        assert!(registry.register(Box::new(|| Box::new(DummyLog))).is_err());
+        assert!(registry
+            .register(Box::new(|| Box::new(MysqlLog::default())))
+            .is_err());
+
+        let all = registry.get_all_protocol();
+        assert_eq!(all.len(), get_all_protocol().len() + 1);
+        assert_eq!(all.last().unwrap().protocol(), L7Protocol::Other);
+        assert!(matches!(
+            registry.get_parser(L7ProtocolEnum::L7Protocol(L7Protocol::Other)),
+            Some(L7ProtocolParser::Registered(_))
+        ));
+        assert!(matches!(
+            registry.get_parser(L7ProtocolEnum::L7Protocol(L7Protocol::MySQL)),
+            Some(L7ProtocolParser::MySQL(_))
+        ));
+
+        let parser = registry.check_payload(b"DUMMY payload", &new_param(IpProtocol::Tcp));
+        assert_eq!(parser.map(|p| p.protocol()), Some(L7Protocol::Other));
+        assert!(registry
+            .check_payload(b"DUMMY payload", &new_param(IpProtocol::Udp))
+            .is_none());
+    }
+
+    #[test]
+    fn check_payload_score() {
+        let payload = b"GET /index.html HTTP/1.1\r\n\r\n";
+        let param = new_param(IpProtocol::Tcp);
+        assert_eq!(
+            DummyLog.check_payload_score(b"DUMMY payload", &param),
+            CHECK_SCORE_DEFAULT
+        );
+        assert_eq!(
+            DummyLog.check_payload_score(payload, &param),
+            CHECK_SCORE_NONE
+        );
+
+        // 分数相同时靠前的内置协议优先, 分数更高时选择注册的协议
+        // the former inner protocol is preferred when scores are equal, the registered protocol
+        // is chosen when its score is higher
+        for (score, protocol) in [
+            (CHECK_SCORE_LOW, L7Protocol::Http1),
+            (CHECK_SCORE_DEFAULT, L7Protocol::Http1),
+            (CHECK_SCORE_MAX, L7Protocol::Other),
+        ] {
+            let mut registry = ProtocolParserRegistry::default();
+            registry
+                .register(Box::new(move || Box::new(ScoredLog(score))))
+                .unwrap();
+            let parser = registry.check_payload(payload, &param);
+            assert_eq!(parser.map(|p| p.protocol()), Some(protocol), "{}", score);
+        }
+    }
+
+    #[test]
+    fn check_all_protocol_number() {
+        assert_eq!(check_protocol_number(&get_all_protocol()), Ok(()));
+
+        let mut parsers = get_all_protocol();
+        parsers.push(L7ProtocolParser::MySQL(Default::default()));
+        assert_eq!(check_protocol_number(&parsers), Err(L7Protocol::MySQL));
+    }
+
+    #[test]
+    fn check_parse_bitmap_with_disabled() {
+        let all_protocols = get_all_protocol()
+            .iter()
+            .map(|p| p.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let l7_enabled = L7ProtocolBitmap::from(&all_protocols);
+
+        let bitmap = get_parse_bitmap(IpProtocol::Tcp, l7_enabled);
+        assert!(bitmap.0 & (1 << 60) != 0);
+        assert!(bitmap.0 & (1 << 80) != 0);
+
+        let bitmap =
+            get_parse_bitmap_with_disabled(IpProtocol::Tcp, l7_enabled, &[L7Protocol::MySQL]);
+        assert!(bitmap.0 & (1 << 60) == 0);
+        assert!(bitmap.0 & (1 << 80) != 0);
+        assert!(bitmap.is_disabled(L7Protocol::MySQL));
+        assert!(bitmap.is_enabled(L7Protocol::Redis));
+    }
+
+    #[test]
+    fn check_bitmap_protocols() {
+        let all_protocols = get_all_protocol()
+            .iter()
+            .map(|p| p.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let l7_enabled = L7ProtocolBitmap::from(&all_protocols);
+
+        let protocols = get_parse_bitmap(IpProtocol::Tcp, l7_enabled).protocols();
+        assert!(protocols.contains(&L7Protocol::Http1));
+        assert!(protocols.contains(&L7Protocol::Http2));
+        assert!(protocols.contains(&L7Protocol::MySQL));
+        assert!(protocols.contains(&L7Protocol::Redis));
+        assert!(protocols.contains(&L7Protocol::Kafka));
+        assert!(!protocols.contains(&L7Protocol::Coap));
+
+        let protocols = get_parse_bitmap(IpProtocol::Udp, l7_enabled).protocols();
+        assert!(protocols.contains(&L7Protocol::DNS));
+        assert!(protocols.contains(&L7Protocol::Coap));
+        assert!(!protocols.contains(&L7Protocol::MySQL));
+
+        assert!(L7ProtocolBitmap(0).protocols().is_empty());
+    }
+
+    #[test]
+    fn check_sctp_bitmap() {
+        let l7_enabled = L7ProtocolBitmap(u128::MAX);
+        assert!(get_parse_bitmap(IpProtocol::Sctp, l7_enabled)
+            .protocols()
+            .is_empty());
+
+        let mut registry = ProtocolParserRegistry::default();
+        assert!(registry
+            .register(Box::new(|| Box::new(SctpDummyLog)))
+            .is_ok());
+        let bitmap = registry.get_parse_bitmap(IpProtocol::Sctp, l7_enabled);
+        assert!(bitmap.is_enabled(L7Protocol::Other));
+        assert_eq!(bitmap.protocols(), vec![L7Protocol::Other]);
+        assert!(registry
+            .get_parse_bitmap(IpProtocol::Tcp, l7_enabled)
+            .is_disabled(L7Protocol::Other));
+        // 注册的协议不受 l7_enabled 影响
+        // registered protocols are not affected by l7_enabled
+        assert!(registry
+            .get_parse_bitmap(IpProtocol::Sctp, L7ProtocolBitmap(0))
+            .is_enabled(L7Protocol::Other));
+        assert!(registry
+            .get_parse_bitmap(IpProtocol::Tcp, l7_enabled)
+            .is_enabled(L7Protocol::MySQL));
+    }
+
+    fn udp_packet(vlan: Option<u16>) -> Vec<u8> {
+        let mut packet = vec![];
+        // dst mac, src mac
+        packet.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x02]);
+        packet.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x01]);
+        if let Some(vlan) = vlan {
+            packet.extend_from_slice(&[0x81, 0x00]);
+            packet.extend_from_slice(&vlan.to_be_bytes());
+        }
+        packet.extend_from_slice(&[0x08, 0x00]);
+        // ipv4, total length 32, ttl 64, udp, 10.0.0.1 -> 10.0.0.2
+        packet.extend_from_slice(&[
+            0x45, 0, 0, 32, 0, 0, 0x40, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+        ]);
+        // udp 12345 -> 53, length 12
+        packet.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 12, 0, 0]);
+        packet.extend_from_slice(b"ping");
+        packet
+    }
+
+    #[test]
+    fn check_param_l2_info() {
+        let tagged = udp_packet(Some(100));
+        let mut packet = MetaPacket::default();
+        packet
+            .update_without_raw_copy(&tagged, true, true, Duration::ZERO, tagged.len())
+            .unwrap();
+        let param = ParseParam::from((&packet, Rc::new(RefCell::new(L7PerfCache::new(1))), false));
+        assert_eq!(param.vlan, Some(100));
+        assert_eq!(param.src_mac, MacAddr::from([0x02, 0, 0, 0, 0, 0x01]));
+        assert_eq!(param.dst_mac, MacAddr::from([0x02, 0, 0, 0, 0, 0x02]));
+        assert_eq!(param.port_dst, 53);
+
+        let untagged = udp_packet(None);
+        let mut packet = MetaPacket::default();
+        packet
+            .update_without_raw_copy(&untagged, true, true, Duration::ZERO, untagged.len())
+            .unwrap();
+        let param = ParseParam::from((&packet, Rc::new(RefCell::new(L7PerfCache::new(1))), false));
+        assert_eq!(param.vlan, None);
+        assert_eq!(param.port_dst, 53);
+    }
+
+    #[test]
+    fn check_param_tcp_seq() {
+        let cache = Rc::new(RefCell::new(L7PerfCache::new(1)));
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.tcp_data.seq = 1000;
+        packet.is_retransmission = true;
+        let param = ParseParam::from((&packet, cache.clone(), false));
+        assert_eq!(param.tcp_seq, 1000);
+        assert!(param.is_retransmission);
+
+        // eBPF 和 UDP 的数据没有序列号
+        // no sequence number for ebpf and udp payload
+        packet.ebpf_type = EbpfType::TracePoint;
+        let param = ParseParam::from((&packet, cache.clone(), false));
+        assert_eq!(param.tcp_seq, 0);
+        assert!(!param.is_retransmission);
+
+        let untagged = udp_packet(None);
+        let mut packet = MetaPacket::default();
+        packet
+            .update_without_raw_copy(&untagged, true, true, Duration::ZERO, untagged.len())
+            .unwrap();
+        let param = ParseParam::from((&packet, cache, false));
+        assert_eq!(param.tcp_seq, 0);
+        assert!(!param.is_retransmission);
+    }
+
+    #[test]
+    fn check_inspected_parser() {
+        let capture = Capture::load_pcap(
+            Path::new(FILE_DIR).join("mysql/mysql-multi-response.pcap"),
+            None,
+        );
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+
+        let inspected_infos = Rc::new(RefCell::new(vec![]));
+        let infos = inspected_infos.clone();
+        let mut parser = InspectedParser::new(MysqlLog::default()).inspector(Box::new(
+            move |protocol, payload, param, info| {
+                assert!(!payload.is_empty());
+                infos
+                    .borrow_mut()
+                    .push((protocol, param.direction, format!("{:?}", info)));
+            },
+        ));
+        let mut plain_parser = MysqlLog::default();
+        let cache = Rc::new(RefCell::new(L7PerfCache::new(100)));
+        let plain_cache = Rc::new(RefCell::new(L7PerfCache::new(100)));
+
+        let mut expected = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = ParseParam::from((&*packet, cache.clone(), false));
+            let infos = parser.parse_payload(payload, &param).unwrap_or_default();
+            let param = ParseParam::from((&*packet, plain_cache.clone(), false));
+            let plain_infos = plain_parser
+                .parse_payload(payload, &param)
+                .unwrap_or_default();
+            // 钩子不影响解析结果
+            // results are not affected by inspector
+            assert_eq!(format!("{:?}", infos), format!("{:?}", plain_infos));
+            for info in infos.iter() {
+                expected.push((L7Protocol::MySQL, param.direction, format!("{:?}", info)));
+            }
+            parser.reset();
+            plain_parser.reset();
+        }
+        // 每个 MySQL 消息调用一次钩子
+        // inspector is called once for each MySQL message
+        assert!(!expected.is_empty());
+        assert_eq!(*inspected_infos.borrow(), expected);
+    }
+
+    // 测试目录中的 pcap 应被识别为的协议
+    // protocols that pcaps in the test directory should be identified as
+    fn owners(dir: &str) -> &'static [L7Protocol] {
+        match dir {
+            "clickhouse" => &[L7Protocol::ClickHouse],
+            "coap" => &[L7Protocol::Coap],
+            "dns" => &[L7Protocol::DNS],
+            "dubbo" => &[L7Protocol::Dubbo],
+            "ftp" => &[L7Protocol::Ftp],
+            "http" => &[L7Protocol::Http1, L7Protocol::Http2],
+            "kafka" => &[L7Protocol::Kafka],
+            "krpc" => &[L7Protocol::ProtobufRPC],
+            "mqtt" => &[L7Protocol::MQTT],
+            "mssql" => &[L7Protocol::Mssql],
+            "mysql" => &[L7Protocol::MySQL],
+            "nats" => &[L7Protocol::Nats],
+            "oracle" => &[L7Protocol::Oracle],
+            "postgre" => &[L7Protocol::PostgreSQL],
+            "redis" => &[L7Protocol::Redis],
+            "rocketmq" => &[L7Protocol::RocketMq],
+            "smtp" => &[L7Protocol::Smtp],
+            "sofarpc" => &[L7Protocol::SofaRPC],
+            "statsd" => &[L7Protocol::Statsd],
+            "thrift" => &[L7Protocol::Thrift],
+            "tls" => &[L7Protocol::Tls],
+            // 握手阶段是 HTTP Upgrade 请求
+            // the handshake is an HTTP Upgrade request
+            "websocket" => &[L7Protocol::Websocket, L7Protocol::Http1],
+            "zookeeper" => &[L7Protocol::Zookeeper],
+            _ => &[],
+        }
+    }
+
+    // 每个协议的 check_payload 都不应识别其他协议的 pcap
+    // check_payload of every protocol should not identify pcaps of other protocols
+    #[test]
+    fn check_cross_detection() {
+        let config = LogParserConfig::default();
+        let cache = Rc::new(RefCell::new(L7PerfCache::new(100)));
+
+        let mut dirs = fs::read_dir(FILE_DIR)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.is_dir())
+            .collect::<Vec<_>>();
+        dirs.sort();
+
+        let mut hits = vec![];
+        for dir in dirs {
+            let name = dir.file_name().unwrap().to_str().unwrap().to_owned();
+            let mut pcaps = fs::read_dir(&dir)
+                .unwrap()
+                .map(|e| e.unwrap().path())
+                .filter(|p| p.extension().map_or(false, |e| e == "pcap"))
+                .collect::<Vec<_>>();
+            if pcaps.is_empty() {
+                continue;
+            }
+            pcaps.sort();
+            let owners = owners(&name);
+            assert!(!owners.is_empty(), "no protocol for directory {}", name);
+
+            for pcap in pcaps {
+                let capture = Capture::load_pcap(&pcap, None);
+                let mut packets = capture.as_meta_packets();
+                if packets.is_empty() {
+                    continue;
+                }
+                let first_dst_port = packets[0].lookup_key.dst_port;
+                for (i, packet) in packets.iter_mut().enumerate() {
+                    packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                        PacketDirection::ClientToServer
+                    } else {
+                        PacketDirection::ServerToClient
+                    };
+                    let Some(payload) = packet.get_l4_payload() else {
+                        continue;
+                    };
+                    if payload.is_empty() {
+                        continue;
+                    }
+                    let param = ParseParam::from((&*packet, cache.clone(), false, &config));
+                    for mut parser in get_all_protocol() {
+                        let protocol = parser.protocol();
+                        if protocol == L7Protocol::Unrecognized
+                            || owners.contains(&protocol)
+                            || !parser.parsable_on(param.l4_protocol)
+                        {
+                            continue;
+                        }
+                        if parser.check_payload(payload, &param) {
+                            hits.push(format!(
+                                "{}#{} {:?}: {:?}",
+                                Path::new(&name).join(pcap.file_name().unwrap()).display(),
+                                i,
+                                param.direction,
+                                protocol
+                            ));
+                        }
+                    }
+                }
             }
         }
-        f.write_str(format!("{:#?}", p).as_str())
+        assert!(hits.is_empty(), "cross detections:\n{}", hits.join("\n"));
     }
 }
@@ -89,6 +89,9 @@ pub struct MetaPacket<'a> {
     tcp_options_flag: u8,
 
     pub tcp_data: MetaPacketTcpHeader,
+    // 由 TcpPerf 判断的重传包
+    // retransmission determined by TcpPerf
+    pub is_retransmission: bool,
     pub tap_port: TapPort, // packet与xflow复用
     pub signal_source: SignalSource,
     pub payload_len: u16,
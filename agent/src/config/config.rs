@@ -19,6 +19,7 @@ use std::fs;
 use std::io;
 use std::net::{IpAddr, ToSocketAddrs};
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
 use log::{error, info, warn};
@@ -34,10 +35,12 @@ use thiserror::Error;
 use tokio::runtime::Runtime;
 
 use crate::common::decapsulate::TunnelType;
+use crate::common::flow::L7Protocol;
 use crate::common::l7_protocol_log::get_all_protocol;
-use crate::common::l7_protocol_log::L7ProtocolParserInterface;
+use crate::common::l7_protocol_log::{L7ProtocolParser, L7ProtocolParserInterface};
 use crate::common::{
-    enums::TapType, DEFAULT_LOG_FILE, L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT,
+    enums::TapType, DEFAULT_LOG_FILE, L7_LOG_DNS_MAX_ANSWERS, L7_LOG_MYSQL_CLIENT_ERROR_CODES,
+    L7_LOG_MYSQL_ERROR_MESSAGE_MAX_LENGTH, L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT,
     L7_PROTOCOL_INFERENCE_TTL,
 };
 use crate::rpc::Session;
@@ -79,6 +82,7 @@ pub struct Config {
     pub agent_mode: RunningMode,
     pub override_os_hostname: Option<String>,
     pub async_worker_thread_number: u16,
+    pub wasm_plugin: Option<String>,
 }
 
 impl Config {
@@ -217,6 +221,7 @@ impl Default for Config {
             agent_mode: Default::default(),
             override_os_hostname: None,
             async_worker_thread_number: 16,
+            wasm_plugin: None,
         }
     }
 }
@@ -350,6 +355,15 @@ pub struct YamlConfig {
     pub grpc_buffer_size: usize,
     #[serde(with = "humantime_serde")]
     pub l7_log_session_aggr_timeout: Duration,
+    #[serde(with = "humantime_serde")]
+    pub l7_log_session_slot_width: Duration,
+    pub l7_log_dns_max_answers: usize,
+    pub l7_log_sql_query_offset: bool,
+    pub l7_log_sql_mask_literals: bool,
+    pub l7_log_mysql_client_error_codes: String,
+    pub l7_log_mysql_ignored_schemas: Vec<String>,
+    pub l7_log_mysql_error_message_max_length: usize,
+    pub l7_log_http_decompress_max_size: usize,
     pub tap_mac_script: String,
     pub cloud_gateway_traffic: bool,
     pub kubernetes_namespace: String,
@@ -365,6 +379,9 @@ pub struct YamlConfig {
     pub packet_sequence_flag: u8,          // Enterprise Edition Feature: packet-sequence
     pub feature_flags: Vec<String>,
     pub l7_protocol_enabled: Vec<String>,
+    pub l7_protocol_disabled: Vec<String>,
+    // hashmap<protocolName, sampling>
+    pub l7_log_sampling: HashMap<String, L7LogSampling>,
     pub ebpf: EbpfYamlConfig,
     pub external_agent_http_proxy_compressed: bool,
     pub standalone_data_file_size: u32,
@@ -453,9 +470,12 @@ impl YamlConfig {
             c.first_path_level = 8;
         }
 
-        // L7Log Session timeout must more than or equal 10s to keep window
-        if c.l7_log_session_aggr_timeout.as_secs() < 10 {
-            c.l7_log_session_aggr_timeout = Duration::from_secs(10);
+        if c.l7_log_session_slot_width.as_secs() < 1 {
+            c.l7_log_session_slot_width = Duration::from_secs(10);
+        }
+        // L7Log Session timeout must contain at least 2 slots to keep window
+        if c.l7_log_session_aggr_timeout < 2 * c.l7_log_session_slot_width {
+            c.l7_log_session_aggr_timeout = 2 * c.l7_log_session_slot_width;
         }
 
         if c.external_metrics_sender_queue_size == 0 {
@@ -569,6 +589,50 @@ impl YamlConfig {
         port_bitmap.sort_unstable_by_key(|p| p.0.clone());
         port_bitmap
     }
+
+    // 运行时关闭的协议，无法识别的协议名会被忽略
+    // protocols disabled at runtime, unknown protocol names are ignored
+    pub fn get_l7_protocol_disabled(&self) -> Vec<L7Protocol> {
+        let mut protocols = vec![];
+        for name in self.l7_protocol_disabled.iter() {
+            match L7ProtocolParser::try_from(name.as_str()) {
+                Ok(p) => protocols.push(p.protocol()),
+                Err(_) => warn!("invalid l7 protocol in l7-protocol-disabled: {}", name),
+            }
+        }
+        protocols
+    }
+
+    // 按协议的日志采样配置，无法识别的协议名会被忽略
+    // per protocol log sampling config, unknown protocol names are ignored
+    pub fn get_l7_log_sampling(&self) -> HashMap<L7Protocol, L7LogSampling> {
+        let mut sampling = HashMap::new();
+        for (name, s) in self.l7_log_sampling.iter() {
+            match L7ProtocolParser::try_from(name.as_str()) {
+                Ok(p) if s.is_enabled() => {
+                    sampling.insert(p.protocol(), *s);
+                }
+                Ok(_) => (),
+                Err(_) => warn!("invalid l7 protocol in l7-log-sampling: {}", name),
+            }
+        }
+        sampling
+    }
+
+    // 格式错误时使用默认值
+    // default value is used if the format is invalid
+    pub fn get_l7_log_mysql_client_error_codes(&self) -> Arc<Bitmap> {
+        let codes = parse_u16_range_list_to_bitmap(&self.l7_log_mysql_client_error_codes, true)
+            .unwrap_or_else(|| {
+                warn!(
+                    "invalid l7-log-mysql-client-error-codes: {}",
+                    self.l7_log_mysql_client_error_codes
+                );
+                parse_u16_range_list_to_bitmap(&L7_LOG_MYSQL_CLIENT_ERROR_CODES.to_string(), false)
+                    .unwrap()
+            });
+        Arc::new(codes)
+    }
 }
 
 impl Default for YamlConfig {
@@ -615,6 +679,14 @@ impl Default for YamlConfig {
             ingress_flavour: IngressFlavour::Kubernetes,
             grpc_buffer_size: 5,
             l7_log_session_aggr_timeout: Duration::from_secs(120),
+            l7_log_session_slot_width: Duration::from_secs(10),
+            l7_log_dns_max_answers: L7_LOG_DNS_MAX_ANSWERS,
+            l7_log_sql_query_offset: false,
+            l7_log_sql_mask_literals: false,
+            l7_log_mysql_client_error_codes: L7_LOG_MYSQL_CLIENT_ERROR_CODES.into(),
+            l7_log_mysql_ignored_schemas: vec![],
+            l7_log_mysql_error_message_max_length: L7_LOG_MYSQL_ERROR_MESSAGE_MAX_LENGTH,
+            l7_log_http_decompress_max_size: 0,
             tap_mac_script: "".into(),
             cloud_gateway_traffic: false,
             kubernetes_namespace: "".into(),
@@ -637,6 +709,8 @@ impl Default for YamlConfig {
                 }
                 protos
             },
+            l7_protocol_disabled: vec![],
+            l7_log_sampling: HashMap::new(),
             external_agent_http_proxy_compressed: false,
             standalone_data_file_size: 200,
             standalone_data_file_dir: Path::new(DEFAULT_LOG_FILE)
@@ -808,6 +882,21 @@ impl Default for TripleMapConfig {
     }
 }
 
+// 应用日志采样, 保留 1/interval 的请求, 每个流每秒最多保留 max-per-second 条日志, 为 0 时不限制
+// l7 log sampling, keeps 1 in interval requests and at most max-per-second logs per flow, unlimited if 0
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7LogSampling {
+    pub interval: u32,
+    pub max_per_second: u32,
+}
+
+impl L7LogSampling {
+    pub fn is_enabled(&self) -> bool {
+        self.interval > 1 || self.max_per_second > 0
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
 #[serde(rename_all = "kebab-case")]
 pub enum KubernetesPollerType {
@@ -1381,4 +1470,50 @@ mod tests {
         assert_eq!(c.controller_ips.len(), 1);
         assert_eq!(&c.controller_ips[0], "127.0.0.1");
     }
+
+    #[test]
+    fn l7_protocol_disabled() {
+        let c = YamlConfig {
+            l7_protocol_disabled: vec!["Kafka".into(), "MySQL".into(), "NoSuchProtocol".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            c.get_l7_protocol_disabled(),
+            vec![L7Protocol::Kafka, L7Protocol::MySQL]
+        );
+        assert!(YamlConfig::default().get_l7_protocol_disabled().is_empty());
+    }
+
+    #[test]
+    fn l7_log_sampling() {
+        let c: YamlConfig = serde_yaml::from_str(
+            r#"
+l7-log-sampling:
+  MySQL:
+    interval: 10
+  HTTP:
+    max-per-second: 100
+  Redis: {}
+  NoSuchProtocol:
+    interval: 2
+"#,
+        )
+        .unwrap();
+        let sampling = c.get_l7_log_sampling();
+        assert_eq!(sampling.len(), 2);
+        assert_eq!(
+            sampling.get(&L7Protocol::MySQL),
+            Some(&L7LogSampling {
+                interval: 10,
+                max_per_second: 0
+            })
+        );
+        assert_eq!(
+            sampling.get(&L7Protocol::Http1),
+            Some(&L7LogSampling {
+                interval: 0,
+                max_per_second: 100
+            })
+        );
+    }
 }
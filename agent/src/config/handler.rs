@@ -41,11 +41,14 @@ use super::{
     OS_PROC_REGEXP_MATCH_TYPE_PROC_NAME,
 };
 use super::{
-    config::{Config, PcapConfig, PortConfig, YamlConfig},
+    config::{Config, L7LogSampling, PcapConfig, PortConfig, YamlConfig},
     ConfigError, IngressFlavour, KubernetesPollerType, RuntimeConfig,
 };
 use crate::{
-    common::{decapsulate::TunnelTypeBitmap, enums::TapType, l7_protocol_log::L7ProtocolBitmap},
+    common::{
+        decapsulate::TunnelTypeBitmap, enums::TapType, flow::L7Protocol,
+        l7_protocol_log::L7ProtocolBitmap,
+    },
     dispatcher::recv_engine,
     exception::ExceptionHandler,
     flow_generator::{protocol_logs::SOFA_NEW_RPC_TRACE_CTX_KEY, FlowTimeout, TcpTimeout},
@@ -436,9 +439,61 @@ impl fmt::Debug for FlowConfig {
 pub struct LogParserConfig {
     pub l7_log_collect_nps_threshold: u64,
     pub l7_log_session_aggr_timeout: Duration,
+    pub l7_log_session_slot_width: Duration,
+    // DNS 响应中最多采集的回答记录数
+    // max number of answer records collected from dns response
+    pub l7_log_dns_max_answers: usize,
+    // 是否记录 SQL 在原始报文中的位置
+    // whether to record position of sql in raw packet
+    pub l7_log_sql_query_offset: bool,
+    // 是否把 SQL 中的字符串和数字字面量替换为 ?
+    // whether to replace string and numeric literals in sql with ?
+    pub l7_log_sql_mask_literals: bool,
+    // MySQL 客户端错误码, 其余错误码为服务端错误
+    // MySQL client error codes, other error codes are server errors
+    pub l7_log_mysql_client_error_codes: Arc<Bitmap>,
+    // 不采集日志的 MySQL 数据库
+    // MySQL databases whose logs are not collected
+    pub l7_log_mysql_ignored_schemas: Vec<String>,
+    // MySQL 错误信息的最大长度, 为 0 时不限制
+    // maximum length of MySQL error message, unlimited if 0
+    pub l7_log_mysql_error_message_max_length: usize,
+    // 解码 gzip 和 deflate 编码的 HTTP 报文体的最大解压长度, 为 0 时不解码
+    // maximum decompressed size when decoding gzip and deflate encoded HTTP bodies, not decoded if 0
+    pub l7_log_http_decompress_max_size: usize,
+    // 运行时关闭的协议，修改后无需重启 agent
+    // protocols disabled at runtime, no agent restart is required when changed
+    pub l7_protocol_disabled: Vec<L7Protocol>,
+    // 按协议的应用日志采样，错误日志总是保留
+    // per protocol l7 log sampling, error logs are always kept
+    pub l7_log_sampling: HashMap<L7Protocol, L7LogSampling>,
     pub l7_log_dynamic: L7LogDynamicConfig,
 }
 
+// 不开启可选的解析功能也不做限制的配置, MySQL 客户端错误码使用默认值
+// config with optional parsing features disabled and without limits, MySQL client error codes
+// are the default ones
+impl Default for LogParserConfig {
+    fn default() -> Self {
+        Self {
+            l7_log_collect_nps_threshold: 0,
+            l7_log_session_aggr_timeout: Duration::ZERO,
+            l7_log_session_slot_width: Duration::ZERO,
+            l7_log_dns_max_answers: 0,
+            l7_log_sql_query_offset: false,
+            l7_log_sql_mask_literals: false,
+            l7_log_mysql_client_error_codes: YamlConfig::default()
+                .get_l7_log_mysql_client_error_codes(),
+            l7_log_mysql_ignored_schemas: vec![],
+            l7_log_mysql_error_message_max_length: 0,
+            l7_log_http_decompress_max_size: 0,
+            l7_protocol_disabled: vec![],
+            l7_log_sampling: HashMap::new(),
+            l7_log_dynamic: L7LogDynamicConfig::default(),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct DebugConfig {
     pub vtap_id: u16,
@@ -957,6 +1012,20 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
             log_parser: LogParserConfig {
                 l7_log_collect_nps_threshold: conf.l7_log_collect_nps_threshold,
                 l7_log_session_aggr_timeout: conf.yaml_config.l7_log_session_aggr_timeout,
+                l7_log_session_slot_width: conf.yaml_config.l7_log_session_slot_width,
+                l7_log_dns_max_answers: conf.yaml_config.l7_log_dns_max_answers,
+                l7_log_sql_query_offset: conf.yaml_config.l7_log_sql_query_offset,
+                l7_log_sql_mask_literals: conf.yaml_config.l7_log_sql_mask_literals,
+                l7_log_mysql_client_error_codes: conf
+                    .yaml_config
+                    .get_l7_log_mysql_client_error_codes(),
+                l7_log_mysql_ignored_schemas: conf.yaml_config.l7_log_mysql_ignored_schemas.clone(),
+                l7_log_mysql_error_message_max_length: conf
+                    .yaml_config
+                    .l7_log_mysql_error_message_max_length,
+                l7_log_http_decompress_max_size: conf.yaml_config.l7_log_http_decompress_max_size,
+                l7_protocol_disabled: conf.yaml_config.get_l7_protocol_disabled(),
+                l7_log_sampling: conf.yaml_config.get_l7_log_sampling(),
                 l7_log_dynamic: L7LogDynamicConfig::new(
                     conf.http_log_proxy_client.to_string().to_ascii_lowercase(),
                     conf.http_log_x_request_id.to_string().to_ascii_lowercase(),
@@ -18,10 +18,10 @@ mod config;
 pub mod handler;
 
 pub use config::{
-    Config, ConfigError, FlowGeneratorConfig, IngressFlavour, KubernetesPollerType, OsProcRegexp,
-    PcapConfig, RuntimeConfig, TripleMapConfig, UprobeProcRegExp, XflowGeneratorConfig, YamlConfig,
-    OS_PROC_REGEXP_MATCH_ACTION_ACCEPT, OS_PROC_REGEXP_MATCH_ACTION_DROP,
-    OS_PROC_REGEXP_MATCH_TYPE_CMD, OS_PROC_REGEXP_MATCH_TYPE_PARENT_PROC_NAME,
-    OS_PROC_REGEXP_MATCH_TYPE_PROC_NAME,
+    Config, ConfigError, FlowGeneratorConfig, IngressFlavour, KubernetesPollerType, L7LogSampling,
+    OsProcRegexp, PcapConfig, RuntimeConfig, TripleMapConfig, UprobeProcRegExp,
+    XflowGeneratorConfig, YamlConfig, OS_PROC_REGEXP_MATCH_ACTION_ACCEPT,
+    OS_PROC_REGEXP_MATCH_ACTION_DROP, OS_PROC_REGEXP_MATCH_TYPE_CMD,
+    OS_PROC_REGEXP_MATCH_TYPE_PARENT_PROC_NAME, OS_PROC_REGEXP_MATCH_TYPE_PROC_NAME,
 };
 pub use handler::{DispatcherConfig, FlowAccess, FlowConfig, ModuleConfig, NpbConfig};
@@ -21,6 +21,7 @@ use thiserror::Error;
 use super::AppProtoLogsData;
 
 use crate::common::l7_protocol_info::L7ProtocolInfo;
+use public::l7_protocol::L7Protocol;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -37,6 +38,10 @@ pub enum Error {
     InvalidIpProtocol,
     #[error("dubbo header parse failed")]
     DubboHeaderParseFailed,
+    #[error("clickhouse log parse failed")]
+    ClickHouseLogParseFailed,
+    #[error("coap log parse failed")]
+    CoapLogParseFailed,
     #[error("http header parse failed")]
     HttpHeaderParseFailed,
     #[error("kafka log parse failed")]
@@ -47,12 +52,46 @@ pub enum Error {
     MqttLogParseFailed,
     #[error("mqtt perf parse failed")]
     MqttPerfParseFailed,
+    #[error("mssql log parse failed")]
+    MssqlLogParseFailed,
+    #[error("nats log parse failed")]
+    NatsLogParseFailed,
+    #[error("oracle log parse failed")]
+    OracleLogParseFailed,
+    #[error("rocketmq log parse failed")]
+    RocketMqLogParseFailed,
+    #[error("tls log parse failed")]
+    TlsLogParseFailed,
+    #[error("websocket log parse failed")]
+    WebsocketLogParseFailed,
+    #[error("thrift log parse failed")]
+    ThriftLogParseFailed,
+    #[error("smtp log parse failed")]
+    SmtpLogParseFailed,
+    #[error("zookeeper log parse failed")]
+    ZookeeperLogParseFailed,
+    #[error("ftp log parse failed")]
+    FtpLogParseFailed,
+    #[error("statsd log parse failed")]
+    StatsdLogParseFailed,
+    #[error("unknown log parse failed")]
+    UnknownLogParseFailed,
+    #[error("custom log parse failed")]
+    CustomLogParseFailed,
+    #[error("wasm plugin error: {0}")]
+    WasmPluginError(String),
     #[error("redis log parse failed")]
     RedisLogParseFailed,
     #[error("redis perf parse failed")]
     RedisPerfParseFailed,
-    #[error("mysql log parse failed")]
-    MysqlLogParseFailed,
+    #[error("mysql header invalid")]
+    MysqlHeaderInvalid,
+    #[error("mysql greeting invalid")]
+    MysqlGreetingInvalid,
+    #[error("mysql payload truncated")]
+    MysqlPayloadTruncated,
+    #[error("mysql unsupported command {0:#04x}")]
+    MysqlUnsupportedCommand(u8),
     #[error("mysql perf parse failed")]
     MysqlPerfParseFailed,
     #[error("{0}")]
@@ -65,6 +104,10 @@ pub enum Error {
     L7ProtocolCheckLimit,
     #[error("l7 protocol parse limit")]
     L7ProtocolParseLimit,
+    #[error("l7 protocol {0:?} conflict")]
+    L7ProtocolConflict(L7Protocol),
+    #[error("l7 protocol parser registry is already installed")]
+    L7ProtocolRegistryInstalled,
     #[error("l7 protocol can not merge")]
     L7ProtocolCanNotMerge(L7ProtocolInfo),
     #[error("l7 log can not merge")]
@@ -59,7 +59,9 @@ use crate::{
             SignalSource, TunnelField,
         },
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
-        l7_protocol_log::{L7PerfCache, L7ProtocolParser, L7ProtocolParserInterface},
+        l7_protocol_log::{
+            L7PerfCache, L7ProtocolParser, L7ProtocolParserInterface, ProtocolParserRegistry,
+        },
         lookup_key::LookupKey,
         meta_packet::{MetaPacket, MetaPacketTcpHeader},
         tagged_flow::TaggedFlow,
@@ -67,7 +69,7 @@ use crate::{
         Timestamp,
     },
     config::{
-        handler::{L7LogDynamicConfig, LogParserAccess, LogParserConfig},
+        handler::{LogParserAccess, LogParserConfig},
         FlowAccess, FlowConfig, ModuleConfig, RuntimeConfig,
     },
     policy::{Policy, PolicyGetter},
@@ -211,6 +213,7 @@ impl FlowMap {
                             .map(|p| (p.protocol(), bitmap.clone()))
                     })
                     .collect(),
+                ProtocolParserRegistry::global(),
             ),
             time_key_buffer: None,
         }
@@ -426,7 +429,8 @@ impl FlowMap {
                     let node = self.new_flow_node(&flow_config, &log_parser_config, meta_packet);
                     if node.is_some() {
                         let node = Box::new(node.unwrap());
-                        time_set[node.timestamp_key as usize & (self.time_window_size - 1)].insert(pkt_key);
+                        time_set[node.timestamp_key as usize & (self.time_window_size - 1)]
+                            .insert(pkt_key);
                         nodes.push(node);
                         max_depth += 1;
                     }
@@ -987,6 +991,7 @@ impl FlowMap {
                 from_app_tab,
                 self.flow_perf_counter.clone(),
                 port,
+                &self.l7_protocol_checker,
             )
             .map(|o| Box::new(o));
         }
@@ -1853,11 +1858,7 @@ pub fn _new_flow_map_and_receiver(
             flow_timeout: flow_timeout.unwrap_or(super::TcpTimeout::default().into()),
             ..(&RuntimeConfig::default()).into()
         },
-        log_parser: LogParserConfig {
-            l7_log_collect_nps_threshold: 0,
-            l7_log_session_aggr_timeout: Duration::new(0, 0),
-            l7_log_dynamic: L7LogDynamicConfig::default(),
-        },
+        log_parser: LogParserConfig::default(),
         ..Default::default()
     };
     // Any
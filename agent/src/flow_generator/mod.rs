@@ -53,5 +53,5 @@ const COUNTER_FLOW_ID_MASK: u64 = 0xFFFFFF;
 const SERVICE_TABLE_IPV4_CAPACITY: usize = 2048;
 // 暂定的Ipv6 ServiceTable LRU cache 容量
 const SERVICE_TABLE_IPV6_CAPACITY: usize = 256;
-const L7_RRT_CACHE_CAPACITY: usize = 8192;
+pub(crate) const L7_RRT_CACHE_CAPACITY: usize = 8192;
 const L7_PROTOCOL_UNKNOWN_LIMIT: Duration = Duration::from_secs(60);
@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+mod sampler;
 mod stats;
 pub mod tcp;
 pub(crate) mod udp;
@@ -37,10 +38,10 @@ use crate::common::l7_protocol_log::L7PerfCache;
 use crate::{
     common::{
         flow::{FlowPerfStats, L4Protocol, L7Protocol, PacketDirection, SignalSource},
-        l7_protocol_info::L7ProtocolInfo,
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
         l7_protocol_log::{
-            get_all_protocol, get_parser, L7ProtocolBitmap, L7ProtocolParser,
-            L7ProtocolParserInterface, ParseParam,
+            L7ProtocolBitmap, L7ProtocolParser, L7ProtocolParserInterface, ParseParam,
+            ProtocolParserRegistry, CHECK_SCORE_DEFAULT, CHECK_SCORE_NONE,
         },
         meta_packet::MetaPacket,
         Timestamp,
@@ -48,7 +49,7 @@ use crate::{
     config::{handler::LogParserConfig, FlowConfig},
 };
 
-use {tcp::TcpPerf, udp::UdpPerf};
+use {sampler::L7LogSampler, tcp::TcpPerf, udp::UdpPerf};
 
 pub use stats::FlowPerfCounter;
 pub use stats::PerfStats;
@@ -108,20 +109,37 @@ pub type L7ProtocolTuple = (L7Protocol, Option<Bitmap>);
 pub struct L7ProtocolChecker {
     tcp: Vec<L7ProtocolTuple>,
     udp: Vec<L7ProtocolTuple>,
+    // 默认端口对应的协议, 按协议顺序排列, 端口匹配时优先检查
+    // protocols of default port in protocol order, checked first when port matches
+    port_hints: HashMap<u16, Vec<L7Protocol>>,
+    registry: Arc<ProtocolParserRegistry>,
 }
 
 impl L7ProtocolChecker {
     pub fn new(
         protocol_bitmap: &L7ProtocolBitmap,
         port_bitmap: &HashMap<L7Protocol, Bitmap>,
+        registry: Arc<ProtocolParserRegistry>,
     ) -> Self {
         let mut tcp = vec![];
         let mut udp = vec![];
-        for parser in get_all_protocol() {
+        let mut port_hints = HashMap::new();
+        let protocol_bitmap = registry.enabled_bitmap(*protocol_bitmap);
+        // 注册的协议最先检查, 从而可以覆盖内置协议
+        // registered protocols are checked first, so that they are able to override inner protocols
+        let mut parsers = registry.get_all_protocol();
+        parsers.sort_by_key(|p| !registry.is_registered(p.protocol()));
+        for parser in parsers {
             let protocol = parser.protocol();
             if !protocol_bitmap.is_enabled(protocol) {
                 continue;
             }
+            for port in parser.default_ports() {
+                port_hints
+                    .entry(*port)
+                    .or_insert_with(Vec::new)
+                    .push(protocol);
+            }
             if parser.parsable_on_tcp() {
                 tcp.push((protocol, port_bitmap.get(&protocol).map(|m| m.clone())));
             }
@@ -130,39 +148,140 @@ impl L7ProtocolChecker {
             }
         }
 
-        L7ProtocolChecker { tcp, udp }
+        L7ProtocolChecker {
+            tcp,
+            udp,
+            port_hints,
+            registry,
+        }
     }
 
+    // 创建内置或注册协议的解析器
+    // create parser of inner or registered protocol
+    pub fn get_parser(&self, protocol: L7ProtocolEnum) -> Option<L7ProtocolParser> {
+        self.registry.get_parser(protocol)
+    }
+
+    pub fn is_enabled(&self, l4_protocol: L4Protocol, protocol: L7Protocol) -> bool {
+        let protocols = match l4_protocol {
+            L4Protocol::Tcp => self.tcp.as_slice(),
+            L4Protocol::Udp => self.udp.as_slice(),
+            L4Protocol::Unknown => &[],
+        };
+        protocols.iter().any(|(p, _)| *p == protocol)
+    }
+
+    // 默认端口与 port 相同的协议最先返回，其余协议保持原有顺序
+    // protocols whose default port equals to port are returned first, others keep the original order
     pub fn possible_protocols(
         &self,
         l4_protocol: L4Protocol,
         port: u16,
     ) -> L7ProtocolCheckerIterator {
+        let protocols = match l4_protocol {
+            L4Protocol::Tcp => self.tcp.as_slice(),
+            L4Protocol::Udp => self.udp.as_slice(),
+            L4Protocol::Unknown => &[],
+        };
         L7ProtocolCheckerIterator {
-            iter: match l4_protocol {
-                L4Protocol::Tcp => self.tcp.iter(),
-                L4Protocol::Udp => self.udp.iter(),
-                L4Protocol::Unknown => [].iter(),
-            },
+            hints: self
+                .port_hints
+                .get(&port)
+                .map(|h| h.as_slice())
+                .unwrap_or(&[]),
+            hint_index: 0,
+            protocols,
+            iter: protocols.iter(),
             port,
         }
     }
+
+    /*
+        按 possible_protocols 的顺序检查 payload, 任意协议以不低于 CHECK_SCORE_DEFAULT 的分数匹配时立即返回,
+        否则返回低分匹配中分数最高的解析器, 其他协议都不匹配时返回兜底解析器.
+
+        check payload in the order of possible_protocols, return as soon as any protocol matches with
+        a score no less than CHECK_SCORE_DEFAULT, otherwise return the parser with the highest score
+        among low-confidence matches, or the fallback parser if no other protocol matches.
+    */
+    pub fn check_payload(
+        &self,
+        payload: &[u8],
+        param: &ParseParam,
+        disabled: &[L7Protocol],
+    ) -> Option<L7ProtocolParser> {
+        let port = match param.direction {
+            PacketDirection::ClientToServer => param.port_dst,
+            PacketDirection::ServerToClient => param.port_src,
+        };
+        let mut fallback = None;
+        let mut best: Option<(u8, L7ProtocolParser)> = None;
+        for protocol in self.possible_protocols(param.l4_protocol.into(), port) {
+            if disabled.contains(protocol) {
+                continue;
+            }
+            let Some(mut parser) = self.get_parser(L7ProtocolEnum::L7Protocol(*protocol)) else {
+                continue;
+            };
+            // 兜底解析器在其他协议都检查失败后才使用
+            // fallback parser is used only after all other protocols fail to check
+            if *protocol == L7Protocol::Unrecognized {
+                fallback = Some(parser);
+                continue;
+            }
+            let score = parser.check_payload_score(payload, param);
+            if score >= CHECK_SCORE_DEFAULT {
+                return Some(parser);
+            }
+            if score > best.as_ref().map(|(s, _)| *s).unwrap_or(CHECK_SCORE_NONE) {
+                best = Some((score, parser));
+            }
+        }
+        match (best, fallback) {
+            (Some((_, parser)), _) => Some(parser),
+            (None, Some(mut parser)) if parser.check_payload(payload, param) => Some(parser),
+            _ => None,
+        }
+    }
 }
 
 pub struct L7ProtocolCheckerIterator<'a> {
+    hints: &'a [L7Protocol],
+    hint_index: usize,
+    protocols: &'a [L7ProtocolTuple],
     iter: slice::Iter<'a, L7ProtocolTuple>,
     port: u16,
 }
 
+impl<'a> L7ProtocolCheckerIterator<'a> {
+    fn port_enabled(&self, bitmap: &Option<Bitmap>) -> bool {
+        match bitmap {
+            // if bitmap is not None and does not has port in it, check next protocol
+            Some(b) => b.get(self.port as usize).unwrap_or_default(),
+            None => true,
+        }
+    }
+}
+
 impl<'a> Iterator for L7ProtocolCheckerIterator<'a> {
     type Item = &'a L7Protocol;
 
     fn next(&mut self) -> Option<Self::Item> {
+        while let Some(hint) = self.hints.get(self.hint_index) {
+            self.hint_index += 1;
+            let Some((proto, bitmap)) = self.protocols.iter().find(|(p, _)| p == hint) else {
+                continue;
+            };
+            if self.port_enabled(bitmap) {
+                return Some(proto);
+            }
+        }
         while let Some((proto, bitmap)) = self.iter.next() {
-            match bitmap {
-                // if bitmap is not None and does not has port in it, check next protocol
-                Some(b) if !b.get(self.port as usize).unwrap_or_default() => continue,
-                _ => return Some(proto),
+            if self.hints.contains(proto) {
+                continue;
+            }
+            if self.port_enabled(bitmap) {
+                return Some(proto);
             }
         }
         None
@@ -175,6 +294,7 @@ pub struct FlowLog {
     // use for cache previous log info, use for calculate rrt
     perf_cache: Rc<RefCell<L7PerfCache>>,
     l7_protocol_enum: L7ProtocolEnum,
+    l7_log_sampler: L7LogSampler,
 
     // Only for eBPF data, the server_port will be set in l7_check() method, it checks the first
     // request packet's payload, and then set self.server_port = packet.lookup_key.dst_port,
@@ -274,17 +394,11 @@ impl FlowLog {
                 !is_parse_log,
                 log_parser_config,
             ));
-            for protocol in checker.possible_protocols(
-                packet.lookup_key.proto.into(),
-                match packet.lookup_key.direction {
-                    PacketDirection::ClientToServer => packet.lookup_key.dst_port,
-                    PacketDirection::ServerToClient => packet.lookup_key.src_port,
-                },
-            ) {
-                let Some(mut parser) = get_parser(L7ProtocolEnum::L7Protocol(*protocol)) else {
-                    continue;
-                };
-                if parser.check_payload(payload, &param) {
+            let parser =
+                checker.check_payload(payload, &param, &log_parser_config.l7_protocol_disabled);
+            let fallback = match parser {
+                Some(parser) if parser.protocol() == L7Protocol::Unrecognized => Some(parser),
+                Some(parser) => {
                     self.l7_protocol_enum = parser.l7_protocl_enum();
 
                     // redis can not determine dirction by RESP protocol when pakcet is from ebpf, special treatment
@@ -309,7 +423,8 @@ impl FlowLog {
                     )?;
                     return Ok(ret);
                 }
-            }
+                None => None,
+            };
 
             self.is_skip = match packet.signal_source {
                 SignalSource::EBPF => app_table.set_protocol_from_ebpf(
@@ -320,6 +435,13 @@ impl FlowLog {
                 ),
                 _ => app_table.set_protocol(packet, L7ProtocolEnum::default()),
             };
+
+            // 兜底解析器不锁定流的协议，下一个包依然会检查其他协议
+            // fallback parser does not lock the protocol of flow, other protocols are still
+            // checked for the next packet
+            if let Some(mut parser) = fallback {
+                return parser.parse_payload(payload, &param);
+            }
         }
 
         return Err(Error::L7ProtocolUnknown);
@@ -348,7 +470,7 @@ impl FlowLog {
         }
 
         if self.l7_protocol_log_parser.is_some() {
-            return self.l7_parse_log(
+            let ret = self.l7_parse_log(
                 flow_config,
                 packet,
                 app_table,
@@ -361,6 +483,10 @@ impl FlowLog {
                 local_epc,
                 remote_epc,
             );
+            if let Ok(infos) = ret.as_ref() {
+                self.check_upgrade(infos, packet.lookup_key.proto.into(), checker);
+            }
+            return ret;
         }
 
         if self.is_from_app {
@@ -383,6 +509,28 @@ impl FlowLog {
         )
     }
 
+    // 协议升级后(如 HTTP 升级为 WebSocket)把流交给升级后协议的解析器,
+    // 升级前协议未上报的性能统计会丢弃
+    // hand off the flow to the parser of upgraded protocol (such as HTTP upgrades to WebSocket),
+    // perf stats of the previous protocol which are not reported yet are dropped
+    fn check_upgrade(
+        &mut self,
+        infos: &[L7ProtocolInfo],
+        l4_protocol: L4Protocol,
+        checker: &L7ProtocolChecker,
+    ) {
+        let Some(protocol) = infos.iter().find_map(|i| i.upgrade_protocol()) else {
+            return;
+        };
+        if !checker.is_enabled(l4_protocol, protocol) {
+            return;
+        }
+        if let Some(parser) = checker.get_parser(L7ProtocolEnum::L7Protocol(protocol)) {
+            self.l7_protocol_enum = parser.l7_protocl_enum();
+            self.l7_protocol_log_parser = Some(Box::new(parser));
+        }
+    }
+
     pub fn new(
         l4_enabled: bool,
         l7_enabled: bool,
@@ -392,13 +540,16 @@ impl FlowLog {
         is_from_app_tab: bool,
         counter: Arc<FlowPerfCounter>,
         server_port: u16,
+        checker: &L7ProtocolChecker,
     ) -> Option<Self> {
         if !l4_enabled && !l7_enabled {
             return None;
         }
         let l4 = if l4_enabled {
             match l4_proto {
-                L4Protocol::Tcp => Some(L4FlowPerfTable::Tcp(Box::new(TcpPerf::new(counter)))),
+                L4Protocol::Tcp => Some(L4FlowPerfTable::Tcp(Box::new(TcpPerf::new(
+                    counter.clone(),
+                )))),
                 L4Protocol::Udp => Some(L4FlowPerfTable::Udp(UdpPerf::new())),
                 _ => None,
             }
@@ -408,9 +559,10 @@ impl FlowLog {
 
         Some(Self {
             l4: l4.map(|o| Box::new(o)),
-            l7_protocol_log_parser: get_parser(l7_protocol_enum).map(|o| Box::new(o)),
+            l7_protocol_log_parser: checker.get_parser(l7_protocol_enum).map(|o| Box::new(o)),
             perf_cache,
             l7_protocol_enum,
+            l7_log_sampler: L7LogSampler::new(counter),
             is_from_app: is_from_app_tab,
             is_success: false,
             is_skip: false,
@@ -432,12 +584,24 @@ impl FlowLog {
         checker: &L7ProtocolChecker,
     ) -> Result<Vec<L7ProtocolInfo>> {
         if let Some(l4) = self.l4.as_mut() {
-            l4.parse(packet, is_first_packet_direction)?;
+            let ret = l4.parse(packet, is_first_packet_direction);
+            packet.is_retransmission = matches!(ret, Err(Error::RetransPacket));
+            // 重传包只交给需要它的解析器
+            // retransmissions are handed only to parsers requiring them
+            let parse_retransmission = (l7_performance_enabled || l7_log_parse_enabled)
+                && self
+                    .l7_protocol_log_parser
+                    .as_ref()
+                    .map(|p| p.parse_retransmission())
+                    .unwrap_or_default();
+            if !(packet.is_retransmission && parse_retransmission) {
+                ret?;
+            }
         }
 
         if l7_performance_enabled || l7_log_parse_enabled {
             // 抛出错误由flowMap.FlowPerfCounter处理
-            return self.l7_parse(
+            let mut infos = self.l7_parse(
                 flow_config,
                 log_parser_config,
                 packet,
@@ -446,7 +610,17 @@ impl FlowLog {
                 local_epc,
                 remote_epc,
                 checker,
+            )?;
+            // 采样在性能统计之后，只影响日志
+            // sampling is after perf stats, only logs are affected
+            self.l7_log_sampler.sample(
+                log_parser_config
+                    .l7_log_sampling
+                    .get(&self.l7_protocol_enum.get_l7_protocol()),
+                packet.lookup_key.timestamp.as_micros() as u64,
+                &mut infos,
             );
+            return Ok(infos);
         }
         Ok(vec![])
     }
@@ -503,3 +677,456 @@ impl FlowLog {
         stats
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::{
+        common::{
+            enums::IpProtocol,
+            l7_protocol_log::{get_all_protocol, get_parser, CHECK_SCORE_LOW, CHECK_SCORE_MAX},
+        },
+        config::handler::ModuleConfig,
+        flow_generator::{
+            protocol_logs::{CustomInfo, LogMessageType},
+            L7_RRT_CACHE_CAPACITY,
+        },
+        utils::test::Capture,
+    };
+
+    const FILE_DIR: &str = "resources/test/flow_generator";
+
+    #[test]
+    fn check_port_hint() {
+        let all_protocols = get_all_protocol()
+            .iter()
+            .map(|p| p.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let checker = L7ProtocolChecker::new(
+            &L7ProtocolBitmap::from(&all_protocols),
+            &HashMap::new(),
+            Default::default(),
+        );
+
+        let protocols = checker
+            .possible_protocols(L4Protocol::Tcp, 3306)
+            .collect::<Vec<_>>();
+        assert_eq!(protocols[0], &L7Protocol::MySQL);
+        // 端口提示不影响其他协议的检查
+        // port hint does not skip other protocols
+        assert_eq!(
+            protocols.len(),
+            checker.possible_protocols(L4Protocol::Tcp, 80).count()
+        );
+        assert_eq!(
+            protocols
+                .iter()
+                .filter(|p| ***p == L7Protocol::MySQL)
+                .count(),
+            1
+        );
+
+        let protocols = checker
+            .possible_protocols(L4Protocol::Tcp, 80)
+            .collect::<Vec<_>>();
+        assert_ne!(protocols[0], &L7Protocol::MySQL);
+
+        let cases = [
+            (L4Protocol::Tcp, 9092, L7Protocol::Kafka),
+            (L4Protocol::Tcp, 1883, L7Protocol::MQTT),
+            (L4Protocol::Tcp, 20880, L7Protocol::Dubbo),
+            (L4Protocol::Tcp, 10911, L7Protocol::RocketMq),
+            (L4Protocol::Udp, 53, L7Protocol::DNS),
+        ];
+        for (l4_protocol, port, expected) in cases {
+            assert_eq!(
+                checker.possible_protocols(l4_protocol, port).next(),
+                Some(&expected),
+                "port {}",
+                port
+            );
+        }
+    }
+
+    #[test]
+    fn check_payload_with_port_hint() {
+        let all_protocols = get_all_protocol()
+            .iter()
+            .map(|p| p.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let checker = L7ProtocolChecker::new(
+            &L7ProtocolBitmap::from(&all_protocols),
+            &HashMap::new(),
+            Default::default(),
+        );
+
+        let query = [
+            0x09, 0, 0, 0, 0x03, b'S', b'E', b'L', b'E', b'C', b'T', b' ', b'1',
+        ];
+        let http = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        // 默认端口的协议不匹配或者端口未知时检查其他协议
+        // other protocols are checked if protocol of default port does not match or port is unknown
+        let cases: [(u16, &[u8], Option<L7Protocol>); 5] = [
+            (3306, &query, Some(L7Protocol::MySQL)),
+            (8080, &query, Some(L7Protocol::MySQL)),
+            (3306, http, Some(L7Protocol::Http1)),
+            (8080, http, Some(L7Protocol::Http1)),
+            (
+                3306,
+                &[0xde, 0xad, 0xbe, 0xef, 0x00, 0x01, 0x02, 0x03],
+                Some(L7Protocol::Unrecognized),
+            ),
+        ];
+        for (port, payload, expected) in cases {
+            packet.lookup_key.dst_port = port;
+            let param =
+                ParseParam::from((&packet, Rc::new(RefCell::new(L7PerfCache::new(1))), false));
+            assert_eq!(
+                checker
+                    .check_payload(payload, &param, &[])
+                    .map(|p| p.protocol()),
+                expected,
+                "port {}",
+                port
+            );
+        }
+
+        packet.lookup_key.dst_port = 3306;
+        let param = ParseParam::from((&packet, Rc::new(RefCell::new(L7PerfCache::new(1))), false));
+        let parser = checker.check_payload(&query, &param, &[L7Protocol::MySQL]);
+        assert_ne!(parser.map(|p| p.protocol()), Some(L7Protocol::MySQL));
+    }
+
+    #[test]
+    fn check_fallback() {
+        // 默认不开启
+        // disabled by default
+        let default_protocols = get_all_protocol()
+            .iter()
+            .filter(|p| p.parse_default())
+            .map(|p| p.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let checker = L7ProtocolChecker::new(
+            &L7ProtocolBitmap::from(&default_protocols),
+            &HashMap::new(),
+            Default::default(),
+        );
+        assert!(!checker.is_enabled(L4Protocol::Tcp, L7Protocol::Unrecognized));
+        assert!(!checker.is_enabled(L4Protocol::Udp, L7Protocol::Unrecognized));
+
+        let all_protocols = get_all_protocol()
+            .iter()
+            .map(|p| p.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let checker = L7ProtocolChecker::new(
+            &L7ProtocolBitmap::from(&all_protocols),
+            &HashMap::new(),
+            Default::default(),
+        );
+        for (l4_protocol, port) in [(L4Protocol::Tcp, 3306), (L4Protocol::Udp, 53)] {
+            assert_eq!(
+                checker.possible_protocols(l4_protocol, port).last(),
+                Some(&L7Protocol::Unrecognized)
+            );
+        }
+
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.lookup_key.dst_port = 8080;
+        let param = ParseParam::from((&packet, Rc::new(RefCell::new(L7PerfCache::new(1))), false));
+        let first_checked = |payload: &[u8]| {
+            checker
+                .possible_protocols(L4Protocol::Tcp, 8080)
+                .find(|p| {
+                    get_parser(L7ProtocolEnum::L7Protocol(**p))
+                        .map(|mut parser| parser.check_payload(payload, &param))
+                        .unwrap_or_default()
+                })
+                .copied()
+        };
+        // 其他协议识别成功时不会使用兜底解析器
+        // fallback parser is not used when other protocol is recognized
+        assert_eq!(
+            first_checked(b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n"),
+            Some(L7Protocol::Http1)
+        );
+        assert_eq!(
+            first_checked(&[0xde, 0xad, 0xbe, 0xef, 0x00, 0x01, 0x02, 0x03]),
+            Some(L7Protocol::Unrecognized)
+        );
+    }
+
+    // httpv1.pcap 中的一个请求和一个响应
+    // one request and one response in httpv1.pcap
+    fn http_packets(capture: &Capture) -> Vec<MetaPacket<'_>> {
+        let mut packets = capture.as_meta_packets();
+        assert_eq!(packets.len(), 2);
+        let client_port = packets[0].lookup_key.src_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.src_port == client_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+        }
+        packets
+    }
+
+    #[test]
+    fn check_retransmission() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("http/httpv1.pcap"), None);
+        let mut packets = http_packets(&capture);
+
+        let config = ModuleConfig::default();
+        let all_protocols = get_all_protocol()
+            .iter()
+            .map(|p| p.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let checker = L7ProtocolChecker::new(
+            &L7ProtocolBitmap::from(&all_protocols),
+            &HashMap::new(),
+            Default::default(),
+        );
+        let mut app_table = AppTable::new(1, 10);
+        let mut flow_log = FlowLog::new(
+            true,
+            true,
+            Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY))),
+            L4Protocol::Tcp,
+            L7ProtocolEnum::default(),
+            false,
+            Arc::new(FlowPerfCounter::default()),
+            0,
+            &checker,
+        )
+        .unwrap();
+        let mut parse = |packet: &mut MetaPacket, fpd: bool| {
+            flow_log.parse(
+                &config.flow,
+                &config.log_parser,
+                packet,
+                fpd,
+                true,
+                true,
+                &mut app_table,
+                0,
+                0,
+                &checker,
+            )
+        };
+
+        let infos = parse(&mut packets[0], true).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(
+            infos[0].app_proto_head().map(|h| h.msg_type),
+            Some(LogMessageType::Request)
+        );
+        // 重传的请求不会进入应用层解析, 不会产生重复的日志
+        // retransmitted request does not reach l7 parsing, no duplicated log is generated
+        assert!(matches!(
+            parse(&mut packets[0], true),
+            Err(Error::RetransPacket)
+        ));
+        let infos = parse(&mut packets[1], false).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(
+            infos[0].app_proto_head().map(|h| h.msg_type),
+            Some(LogMessageType::Response)
+        );
+    }
+
+    // 注册的解析器, 以最高的分数识别 HTTP GET 请求, 从而覆盖内置的 HTTP 解析器
+    // registered parser, recognizes HTTP GET request with the highest score, which overrides the
+    // inner HTTP parser
+    struct RegisteredLog;
+
+    impl L7ProtocolParserInterface for RegisteredLog {
+        fn check_payload(&mut self, payload: &[u8], _: &ParseParam) -> bool {
+            payload.starts_with(b"GET ")
+        }
+
+        fn check_payload_score(&mut self, payload: &[u8], param: &ParseParam) -> u8 {
+            if self.check_payload(payload, param) {
+                CHECK_SCORE_MAX
+            } else {
+                CHECK_SCORE_NONE
+            }
+        }
+
+        fn parse_payload(&mut self, _: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+            let msg_type = match param.direction {
+                PacketDirection::ClientToServer => LogMessageType::Request,
+                PacketDirection::ServerToClient => LogMessageType::Response,
+            };
+            Ok(vec![L7ProtocolInfo::CustomInfo(CustomInfo {
+                msg_type,
+                ..Default::default()
+            })])
+        }
+
+        fn protocol(&self) -> L7Protocol {
+            L7Protocol::Custom
+        }
+
+        fn parsable_on_udp(&self) -> bool {
+            false
+        }
+
+        fn perf_stats(&mut self) -> Option<L7PerfStats> {
+            None
+        }
+    }
+
+    #[test]
+    fn check_registered_parser() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("http/httpv1.pcap"), None);
+        let mut packets = http_packets(&capture);
+
+        let config = ModuleConfig::default();
+        let mut registry = ProtocolParserRegistry::default();
+        registry
+            .register(Box::new(|| Box::new(RegisteredLog)))
+            .unwrap();
+        // 注册的协议不需要在 l7-protocol-enabled 中配置
+        // registered protocols need not be configured in l7-protocol-enabled
+        let checker = L7ProtocolChecker::new(
+            &config.flow.l7_protocol_enabled_bitmap,
+            &HashMap::new(),
+            Arc::new(registry),
+        );
+        assert!(checker.is_enabled(L4Protocol::Tcp, L7Protocol::Custom));
+        assert!(!checker.is_enabled(L4Protocol::Udp, L7Protocol::Custom));
+
+        let mut app_table = AppTable::new(1, 10);
+        let perf_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let counter = Arc::new(FlowPerfCounter::default());
+        let mut flow_log = FlowLog::new(
+            false,
+            true,
+            perf_cache.clone(),
+            L4Protocol::Tcp,
+            L7ProtocolEnum::default(),
+            false,
+            counter.clone(),
+            0,
+            &checker,
+        )
+        .unwrap();
+        for (i, packet) in packets.iter_mut().enumerate() {
+            let infos = flow_log
+                .parse(
+                    &config.flow,
+                    &config.log_parser,
+                    packet,
+                    i == 0,
+                    false,
+                    true,
+                    &mut app_table,
+                    0,
+                    0,
+                    &checker,
+                )
+                .unwrap();
+            assert!(
+                matches!(infos.as_slice(), [L7ProtocolInfo::CustomInfo(_)]),
+                "packet#{}",
+                i
+            );
+        }
+        assert_eq!(
+            flow_log.l7_protocol_enum,
+            L7ProtocolEnum::L7Protocol(L7Protocol::Custom)
+        );
+
+        // 协议由 app table 得知时同样创建注册的解析器
+        // registered parser is created as well when protocol is known from app table
+        let flow_log = FlowLog::new(
+            false,
+            true,
+            perf_cache,
+            L4Protocol::Tcp,
+            L7ProtocolEnum::L7Protocol(L7Protocol::Custom),
+            true,
+            counter,
+            0,
+            &checker,
+        )
+        .unwrap();
+        assert!(matches!(
+            flow_log.l7_protocol_log_parser.as_deref(),
+            Some(L7ProtocolParser::Registered(_))
+        ));
+    }
+
+    // 以固定分数识别 HTTP GET 请求的注册解析器
+    // registered parser recognizing HTTP GET request with a fixed score
+    struct ScoredLog(u8);
+
+    impl L7ProtocolParserInterface for ScoredLog {
+        fn check_payload(&mut self, payload: &[u8], _: &ParseParam) -> bool {
+            payload.starts_with(b"GET ")
+        }
+
+        fn check_payload_score(&mut self, payload: &[u8], param: &ParseParam) -> u8 {
+            if self.check_payload(payload, param) {
+                self.0
+            } else {
+                CHECK_SCORE_NONE
+            }
+        }
+
+        fn parse_payload(&mut self, _: &[u8], _: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+            Ok(vec![])
+        }
+
+        fn protocol(&self) -> L7Protocol {
+            L7Protocol::Custom
+        }
+
+        fn perf_stats(&mut self) -> Option<L7PerfStats> {
+            None
+        }
+    }
+
+    #[test]
+    fn check_payload_early_exit() {
+        let config = ModuleConfig::default();
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.lookup_key.dst_port = 8080;
+        let param = ParseParam::from((&packet, Rc::new(RefCell::new(L7PerfCache::new(1))), false));
+        let payload = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n";
+
+        // 低分匹配时继续检查其他协议, 第一个不低于 CHECK_SCORE_DEFAULT 的匹配立即返回
+        // other protocols are checked after a low-confidence match, the first match no less than
+        // CHECK_SCORE_DEFAULT returns immediately
+        for (score, expected) in [
+            (CHECK_SCORE_LOW, L7Protocol::Http1),
+            (CHECK_SCORE_DEFAULT, L7Protocol::Custom),
+            (CHECK_SCORE_MAX, L7Protocol::Custom),
+        ] {
+            let mut registry = ProtocolParserRegistry::default();
+            registry
+                .register(Box::new(move || Box::new(ScoredLog(score))))
+                .unwrap();
+            let checker = L7ProtocolChecker::new(
+                &config.flow.l7_protocol_enabled_bitmap,
+                &HashMap::new(),
+                Arc::new(registry),
+            );
+            assert_eq!(
+                checker
+                    .check_payload(payload, &param, &[])
+                    .map(|p| p.protocol()),
+                Some(expected),
+                "score {}",
+                score
+            );
+        }
+    }
+}
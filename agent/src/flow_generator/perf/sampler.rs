@@ -0,0 +1,234 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use super::stats::FlowPerfCounter;
+use crate::{
+    common::l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+    config::L7LogSampling,
+    flow_generator::protocol_logs::LogMessageType,
+};
+
+// 记录的被丢弃请求超过此数量时清空, 避免响应丢失时无限增长
+// dropped requests are cleared when exceeding this number, avoiding unbounded growth when responses are lost
+const DROPPED_SESSIONS_MAX: usize = 1024;
+
+// 单个流的应用日志采样, 按请求 1/N 采样并限制每秒日志数, 错误日志总是保留
+// 请求被丢弃时对应的正常响应也会被丢弃
+// l7 log sampling of a flow, keeps 1 in N requests and caps logs per second, error logs are always kept
+// normal responses of dropped requests are dropped as well
+pub struct L7LogSampler {
+    counter: Arc<FlowPerfCounter>,
+
+    request_count: u32,
+    second: u64,
+    second_count: u32,
+    dropped_sessions: HashSet<Option<u32>>,
+}
+
+impl L7LogSampler {
+    pub fn new(counter: Arc<FlowPerfCounter>) -> Self {
+        Self {
+            counter,
+            request_count: 0,
+            second: 0,
+            second_count: 0,
+            dropped_sessions: HashSet::new(),
+        }
+    }
+
+    // time 单位为微秒
+    // time is in microseconds
+    pub fn sample(
+        &mut self,
+        config: Option<&L7LogSampling>,
+        time: u64,
+        infos: &mut Vec<L7ProtocolInfo>,
+    ) {
+        let Some(config) = config.filter(|c| c.is_enabled()) else {
+            return;
+        };
+        let count = infos.len();
+        infos.retain(|info| self.keep(config, time, info));
+        let dropped = count - infos.len();
+        if dropped > 0 {
+            self.counter
+                .l7_log_sampled_drop
+                .fetch_add(dropped as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn keep(&mut self, config: &L7LogSampling, time: u64, info: &L7ProtocolInfo) -> bool {
+        // 需要多次合并的日志不采样
+        // logs that need to be merged more than once are not sampled
+        if info.need_merge() {
+            return true;
+        }
+        let msg_type = info
+            .app_proto_head()
+            .map(|h| h.msg_type)
+            .unwrap_or_default();
+        if msg_type == LogMessageType::Response {
+            return !self.dropped_sessions.remove(&info.session_id()) || info.is_error();
+        }
+        if self.sample_one(config, time) {
+            return true;
+        }
+        if msg_type == LogMessageType::Request {
+            if self.dropped_sessions.len() >= DROPPED_SESSIONS_MAX {
+                self.dropped_sessions.clear();
+            }
+            self.dropped_sessions.insert(info.session_id());
+            return false;
+        }
+        info.is_error()
+    }
+
+    fn sample_one(&mut self, config: &L7LogSampling, time: u64) -> bool {
+        let second = time / 1_000_000;
+        if second != self.second {
+            self.second = second;
+            self.second_count = 0;
+        }
+
+        let index = self.request_count;
+        self.request_count = self.request_count.wrapping_add(1);
+        if config.interval > 1 && index % config.interval != 0 {
+            return false;
+        }
+        if config.max_per_second > 0 && self.second_count >= config.max_per_second {
+            return false;
+        }
+        self.second_count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::flow_generator::protocol_logs::{CustomInfo, L7ResponseStatus};
+
+    fn custom_info(msg_type: LogMessageType, status: L7ResponseStatus) -> L7ProtocolInfo {
+        L7ProtocolInfo::CustomInfo(CustomInfo {
+            msg_type,
+            status,
+            ..Default::default()
+        })
+    }
+
+    fn sample_sessions(
+        sampler: &mut L7LogSampler,
+        config: &L7LogSampling,
+        statuses: &[L7ResponseStatus],
+        time: u64,
+    ) -> Vec<(LogMessageType, L7ResponseStatus)> {
+        let mut kept = vec![];
+        for status in statuses {
+            for msg_type in [LogMessageType::Request, LogMessageType::Response] {
+                let resp_status = if msg_type == LogMessageType::Request {
+                    L7ResponseStatus::Ok
+                } else {
+                    *status
+                };
+                let mut infos = vec![custom_info(msg_type, resp_status)];
+                sampler.sample(Some(config), time, &mut infos);
+                kept.extend(infos.into_iter().map(|i| match i {
+                    L7ProtocolInfo::CustomInfo(i) => (i.msg_type, i.status),
+                    _ => unreachable!(),
+                }));
+            }
+        }
+        kept
+    }
+
+    #[test]
+    fn check_sample_interval() {
+        let counter = Arc::new(FlowPerfCounter::default());
+        let mut sampler = L7LogSampler::new(counter.clone());
+        let config = L7LogSampling {
+            interval: 3,
+            max_per_second: 0,
+        };
+        let statuses = [
+            L7ResponseStatus::Ok,
+            L7ResponseStatus::Ok,
+            L7ResponseStatus::ServerError,
+            L7ResponseStatus::Ok,
+            L7ResponseStatus::Ok,
+        ];
+        let kept = sample_sessions(&mut sampler, &config, &statuses, 0);
+        assert_eq!(
+            kept,
+            vec![
+                (LogMessageType::Request, L7ResponseStatus::Ok),
+                (LogMessageType::Response, L7ResponseStatus::Ok),
+                // 请求被丢弃, 错误响应依然保留
+                // error response is kept although its request is dropped
+                (LogMessageType::Response, L7ResponseStatus::ServerError),
+                (LogMessageType::Request, L7ResponseStatus::Ok),
+                (LogMessageType::Response, L7ResponseStatus::Ok),
+            ]
+        );
+        assert_eq!(counter.l7_log_sampled_drop.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn check_sample_max_per_second() {
+        let mut sampler = L7LogSampler::new(Arc::new(FlowPerfCounter::default()));
+        let config = L7LogSampling {
+            interval: 0,
+            max_per_second: 2,
+        };
+        let statuses = [L7ResponseStatus::Ok; 4];
+        assert_eq!(
+            sample_sessions(&mut sampler, &config, &statuses, 1_000_000).len(),
+            4
+        );
+        assert_eq!(
+            sample_sessions(&mut sampler, &config, &statuses, 1_500_000).len(),
+            0
+        );
+        assert_eq!(
+            sample_sessions(&mut sampler, &config, &statuses, 2_000_000).len(),
+            4
+        );
+
+        let mut infos = vec![custom_info(
+            LogMessageType::Session,
+            L7ResponseStatus::ClientError,
+        )];
+        sampler.sample(Some(&config), 2_000_000, &mut infos);
+        assert_eq!(infos.len(), 1);
+    }
+
+    #[test]
+    fn check_sample_disabled() {
+        let mut sampler = L7LogSampler::new(Arc::new(FlowPerfCounter::default()));
+        let statuses = [L7ResponseStatus::Ok; 4];
+        assert_eq!(
+            sample_sessions(&mut sampler, &L7LogSampling::default(), &statuses, 0).len(),
+            8
+        );
+        let mut infos = vec![custom_info(LogMessageType::Request, L7ResponseStatus::Ok)];
+        sampler.sample(None, 0, &mut infos);
+        assert_eq!(infos.len(), 1);
+    }
+}
@@ -45,6 +45,7 @@ pub struct FlowPerfCounter {
     // L7 stats
     pub mismatched_response: AtomicU64,
     pub unknown_l7_protocol: AtomicU64,
+    pub l7_log_sampled_drop: AtomicU64,
 }
 
 impl RefCountable for FlowPerfCounter {
@@ -53,6 +54,7 @@ impl RefCountable for FlowPerfCounter {
         let invalid = self.invalid_packet_count.swap(0, Ordering::Relaxed);
         let mismatched = self.mismatched_response.swap(0, Ordering::Relaxed);
         let unknown_l7_protocol = self.unknown_l7_protocol.swap(0, Ordering::Relaxed);
+        let sampled_drop = self.l7_log_sampled_drop.swap(0, Ordering::Relaxed);
 
         vec![
             (
@@ -75,6 +77,11 @@ impl RefCountable for FlowPerfCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(unknown_l7_protocol),
             ),
+            (
+                "l7_log_sampled_drop",
+                CounterType::Counted,
+                CounterValue::Unsigned(sampled_drop),
+            ),
         ]
     }
 }
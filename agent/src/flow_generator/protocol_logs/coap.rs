@@ -0,0 +1,377 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response};
+use super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::error::{Error, Result},
+    utils::bytes::read_u16_be,
+};
+
+// 参考：https://www.rfc-editor.org/rfc/rfc7252#section-3
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |Ver| T |  TKL  |      Code     |          Message ID           |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |   Token (if any, TKL bytes) ...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |   Options (if any) ...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |1 1 1 1 1 1 1 1|    Payload (if any) ...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+const HEADER_SIZE: usize = 4;
+const VERSION: u8 = 1;
+const MAX_TOKEN_LEN: usize = 8;
+const PAYLOAD_MARKER: u8 = 0xff;
+
+// option delta/length 的扩展标记
+// extended marker of option delta/length
+const OPTION_EXT_8BIT: u8 = 13;
+const OPTION_EXT_16BIT: u8 = 14;
+const OPTION_EXT_RESERVED: u8 = 15;
+
+const OPTION_URI_PATH: u16 = 11;
+
+// code class
+const CODE_CLASS_REQUEST: u8 = 0;
+const CODE_CLASS_SUCCESS: u8 = 2;
+const CODE_CLASS_CLIENT_ERROR: u8 = 4;
+const CODE_CLASS_SERVER_ERROR: u8 = 5;
+
+// request method
+const METHOD_GET: u8 = 1;
+const METHOD_POST: u8 = 2;
+const METHOD_PUT: u8 = 3;
+const METHOD_DELETE: u8 = 4;
+const METHOD_FETCH: u8 = 5;
+const METHOD_PATCH: u8 = 6;
+const METHOD_IPATCH: u8 = 7;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct CoapInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
+    pub message_id: u16,
+    #[serde(skip)]
+    pub token: Vec<u8>,
+
+    // request
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub method: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub path: String,
+
+    // response
+    // code 按 class * 100 + detail 表示, 如 2.05 表示为 205
+    // code is represented as class * 100 + detail, for example 2.05 is 205
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub response_code: Option<i32>,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for CoapInfo {
+    // 使用 message id 和 token 作为会话标识
+    // use message id and token as session key
+    fn session_id(&self) -> Option<u32> {
+        let mut id = self.message_id as u32;
+        for (i, b) in self.token.iter().enumerate() {
+            id ^= (*b as u32) << (16 + (i % 2) * 8);
+        }
+        Some(id)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::CoapInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.response_code = other.response_code;
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Coap,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<CoapInfo> for L7ProtocolSendLog {
+    fn from(f: CoapInfo) -> Self {
+        L7ProtocolSendLog {
+            req: L7Request {
+                req_type: f.method,
+                resource: f.path.clone(),
+                endpoint: f.path,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.response_code,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: Some(f.message_id as u32),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+struct Header<'a> {
+    code_class: u8,
+    code_detail: u8,
+    message_id: u16,
+    token: &'a [u8],
+    options: &'a [u8],
+}
+
+impl<'a> Header<'a> {
+    fn decode(payload: &'a [u8]) -> Option<Self> {
+        if payload.len() < HEADER_SIZE || payload[0] >> 6 != VERSION {
+            return None;
+        }
+        let token_len = (payload[0] & 0xf) as usize;
+        if token_len > MAX_TOKEN_LEN || payload.len() < HEADER_SIZE + token_len {
+            return None;
+        }
+        let code_class = payload[1] >> 5;
+        let code_detail = payload[1] & 0x1f;
+        match (code_class, code_detail) {
+            (CODE_CLASS_REQUEST, METHOD_GET..=METHOD_IPATCH)
+            | (CODE_CLASS_SUCCESS, _)
+            | (CODE_CLASS_CLIENT_ERROR, _)
+            | (CODE_CLASS_SERVER_ERROR, _) => {}
+            // 空消息和保留的 code 不解析
+            // empty message and reserved code are not parsed
+            _ => return None,
+        }
+        Some(Header {
+            code_class,
+            code_detail,
+            message_id: read_u16_be(&payload[2..]),
+            token: &payload[HEADER_SIZE..HEADER_SIZE + token_len],
+            options: &payload[HEADER_SIZE + token_len..],
+        })
+    }
+
+    fn is_request(&self) -> bool {
+        self.code_class == CODE_CLASS_REQUEST
+    }
+
+    fn method(&self) -> &'static str {
+        match self.code_detail {
+            METHOD_GET => "GET",
+            METHOD_POST => "POST",
+            METHOD_PUT => "PUT",
+            METHOD_DELETE => "DELETE",
+            METHOD_FETCH => "FETCH",
+            METHOD_PATCH => "PATCH",
+            METHOD_IPATCH => "iPATCH",
+            _ => "",
+        }
+    }
+
+    // 遍历 options 并拼接 Uri-Path, options 格式错误时返回 None
+    // iterate options and join Uri-Path, return None if options are malformed
+    fn uri_path(&self) -> Option<String> {
+        let mut path = String::new();
+        let mut number = 0u16;
+        let mut data = self.options;
+        while !data.is_empty() && data[0] != PAYLOAD_MARKER {
+            let (delta, length) = (data[0] >> 4, data[0] & 0xf);
+            data = &data[1..];
+            let delta = read_option_ext(&mut data, delta)?;
+            let length = read_option_ext(&mut data, length)? as usize;
+            if data.len() < length {
+                return None;
+            }
+            number = number.checked_add(delta)?;
+            if number == OPTION_URI_PATH {
+                path.push('/');
+                path.push_str(&String::from_utf8_lossy(&data[..length]));
+            }
+            data = &data[length..];
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        Some(path)
+    }
+}
+
+fn read_option_ext(data: &mut &[u8], v: u8) -> Option<u16> {
+    match v {
+        OPTION_EXT_8BIT => {
+            let ext = *data.first()?;
+            *data = &data[1..];
+            Some(ext as u16 + 13)
+        }
+        OPTION_EXT_16BIT => {
+            if data.len() < 2 {
+                return None;
+            }
+            let ext = read_u16_be(data);
+            *data = &data[2..];
+            ext.checked_add(269)
+        }
+        OPTION_EXT_RESERVED => None,
+        _ => Some(v as u16),
+    }
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct CoapLog {
+    info: CoapInfo,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for CoapLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Udp {
+            return false;
+        }
+        match Header::decode(payload) {
+            Some(h) => h.is_request() && h.uri_path().is_some(),
+            None => false,
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Udp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.info.is_tls = param.is_tls();
+        self.parse(payload)?;
+        self.info.cal_rrt(param).map(|rrt| {
+            self.info.rrt = rrt;
+            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+        });
+        Ok(vec![L7ProtocolInfo::CoapInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Coap
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[5683]
+    }
+
+    fn parsable_on_tcp(&self) -> bool {
+        false
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) {
+        self.info = CoapInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl CoapLog {
+    fn parse(&mut self, payload: &[u8]) -> Result<()> {
+        let header = Header::decode(payload).ok_or(Error::CoapLogParseFailed)?;
+        self.info.message_id = header.message_id;
+        self.info.token = header.token.to_vec();
+        let perf_stats = self.perf_stats.as_mut().unwrap();
+        if header.is_request() {
+            self.info.msg_type = LogMessageType::Request;
+            self.info.method = header.method().to_owned();
+            self.info.path = header.uri_path().ok_or(Error::CoapLogParseFailed)?;
+            perf_stats.inc_req();
+        } else {
+            self.info.msg_type = LogMessageType::Response;
+            self.info.response_code =
+                Some(header.code_class as i32 * 100 + header.code_detail as i32);
+            self.info.status = match header.code_class {
+                CODE_CLASS_SUCCESS => L7ResponseStatus::Ok,
+                CODE_CLASS_CLIENT_ERROR => {
+                    perf_stats.inc_req_err();
+                    L7ResponseStatus::ClientError
+                }
+                _ => {
+                    perf_stats.inc_resp_err();
+                    L7ResponseStatus::ServerError
+                }
+            };
+            perf_stats.inc_resp();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::ProtocolTester;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/coap";
+
+    fn tester() -> ProtocolTester<CoapLog> {
+        ProtocolTester::new(CoapLog::default()).step(|_, payload, param| {
+            // 每个包使用新的解析器
+            // a new parser for each packet
+            let mut coap = CoapLog::default();
+            let is_coap = coap.check_payload(payload, param);
+            let _ = coap.parse_payload(payload, param);
+            coap.info.rrt = 0;
+            format!("{:?} is_coap: {}\r\n", coap.info, is_coap)
+        })
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(dir.join("coap.pcap"), dir.join("coap.result"));
+    }
+}
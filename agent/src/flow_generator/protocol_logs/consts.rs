@@ -19,6 +19,8 @@ pub const HTTP_RESP_MAX_LINE: usize = 40;
 pub const H2C_HEADER_SIZE: usize = 9;
 
 pub const FRAME_HEADERS: u8 = 0x1;
+pub const FLAG_HEADERS_END_STREAM: u8 = 0x1;
+pub const FLAG_HEADERS_END_HEADERS: u8 = 0x4;
 pub const FLAG_HEADERS_PADDED: u8 = 0x8;
 pub const FLAG_HEADERS_PRIORITY: u8 = 0x20;
 
@@ -27,6 +29,7 @@ pub const HTTP_V1_0_VERSION: &str = "HTTP/1.0";
 pub const HTTP_V1_1_VERSION: &str = "HTTP/1.1";
 pub const HTTP_V1_VERSION_LEN: usize = 8;
 pub const HTTP_STATUS_CODE_MIN: u16 = 100;
+pub const HTTP_STATUS_SWITCHING_PROTOCOLS: u16 = 101;
 pub const HTTP_STATUS_CODE_MAX: u16 = 600;
 pub const HTTP_STATUS_CLIENT_ERROR_MIN: u16 = 400;
 pub const HTTP_STATUS_CLIENT_ERROR_MAX: u16 = 499;
@@ -46,10 +49,28 @@ pub const HTTPV2_MAGIC_PREFIX: [u8; 10] =
 
 pub const HTTPV2_FRAME_DATA_TYPE: u8 = 0x00;
 pub const HTTPV2_FRAME_HEADERS_TYPE: u8 = 0x01;
+pub const HTTPV2_FRAME_PUSH_PROMISE_TYPE: u8 = 0x05;
+pub const HTTPV2_FRAME_CONTINUATION_TYPE: u8 = 0x09;
 
 pub const HTTPV2_FRAME_TYPE_MIN: u8 = 0x00;
 pub const HTTPV2_FRAME_TYPE_MAX: u8 = 0x09;
 
+// grpc status code
+// 参考：https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+pub const GRPC_STATUS_OK: i32 = 0;
+pub const GRPC_STATUS_CANCELLED: i32 = 1;
+pub const GRPC_STATUS_INVALID_ARGUMENT: i32 = 3;
+pub const GRPC_STATUS_NOT_FOUND: i32 = 5;
+pub const GRPC_STATUS_ALREADY_EXISTS: i32 = 6;
+pub const GRPC_STATUS_PERMISSION_DENIED: i32 = 7;
+pub const GRPC_STATUS_FAILED_PRECONDITION: i32 = 9;
+pub const GRPC_STATUS_OUT_OF_RANGE: i32 = 11;
+pub const GRPC_STATUS_UNAUTHENTICATED: i32 = 16;
+
+// etcd v3 gRPC service
+pub const ETCD_KV_SERVICE: &str = "etcdserverpb.KV";
+pub const ETCD_WATCH_SERVICE: &str = "etcdserverpb.Watch";
+
 pub const TRACE_ID_TYPE: usize = 0;
 pub const SPAN_ID_TYPE: usize = 1;
 
@@ -115,6 +136,18 @@ pub const PROTOCOL_VERSION_OFFSET: usize = 0;
 pub const SERVER_VERSION_OFFSET: usize = PROTOCOL_VERSION_OFFSET + PROTOCOL_VERSION_LEN;
 pub const THREAD_ID_OFFSET_B: usize = SERVER_VERSION_OFFSET;
 
+// Handshake Response: capability_flags(4) max_packet_size(4) character_set(1) filler(23) ...
+pub const CAPABILITY_FLAGS_OFFSET: usize = 0;
+pub const HANDSHAKE_RESPONSE_FILLER_OFFSET: usize = 9;
+pub const HANDSHAKE_RESPONSE_FILLER_LEN: usize = 23;
+pub const HANDSHAKE_RESPONSE_NUMBER: u8 = 1;
+
+pub const CLIENT_CONNECT_WITH_DB: u32 = 0x8;
+pub const CLIENT_PROTOCOL_41: u32 = 0x200;
+pub const CLIENT_SECURE_CONNECTION: u32 = 0x8000;
+pub const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 0x200000;
+pub const CLIENT_QUERY_ATTRIBUTES: u32 = 1 << 27;
+
 // Request
 pub const COMMAND_OFFSET: usize = 0;
 pub const COMMAND_LEN: usize = 1;
@@ -138,6 +171,50 @@ pub const INT_FLAGS_8: u8 = 0xfe;
 
 pub const INT_BASE_LEN: usize = 1;
 
+// 二进制协议中的参数类型
+// parameter types in binary protocol
+pub const MYSQL_TYPE_TINY: u8 = 0x01;
+pub const MYSQL_TYPE_SHORT: u8 = 0x02;
+pub const MYSQL_TYPE_LONG: u8 = 0x03;
+pub const MYSQL_TYPE_FLOAT: u8 = 0x04;
+pub const MYSQL_TYPE_DOUBLE: u8 = 0x05;
+pub const MYSQL_TYPE_NULL: u8 = 0x06;
+pub const MYSQL_TYPE_TIMESTAMP: u8 = 0x07;
+pub const MYSQL_TYPE_LONGLONG: u8 = 0x08;
+pub const MYSQL_TYPE_INT24: u8 = 0x09;
+pub const MYSQL_TYPE_DATE: u8 = 0x0a;
+pub const MYSQL_TYPE_TIME: u8 = 0x0b;
+pub const MYSQL_TYPE_DATETIME: u8 = 0x0c;
+pub const MYSQL_TYPE_YEAR: u8 = 0x0d;
+
+pub const EOF_PACKET_MAX_LEN: usize = 9;
+// EOF 包: header(1) warnings(2) status_flags(2)
+pub const EOF_PACKET_LEN: usize = 5;
+pub const EOF_STATUS_FLAGS_OFFSET: usize = 3;
+
+// 服务端状态: 事务进行中
+// server status: a transaction is active
+pub const SERVER_STATUS_IN_TRANS: u16 = 0x1;
+pub const SERVER_MORE_RESULTS_EXISTS: u16 = 0x8;
+
+// TCP 分段重组: 声明长度超过该值的包不重组, 按截断的包解析
+// tcp reassembly: packets declared longer than this are not reassembled and parsed as truncated
+pub const MYSQL_REASSEMBLY_MAX_LEN: usize = 65536;
+// 等待缺失的分段时最多缓存的乱序分段数
+// max out-of-order segments buffered while waiting for the missing one
+pub const MYSQL_REASSEMBLY_MAX_SEGMENTS: usize = 8;
+// 每条流最多记录的预处理的 CALL 语句数
+// max prepared CALL statements recorded per flow
+pub const MYSQL_PREPARED_PROCEDURES_MAX: usize = 64;
+
+// COM_STMT_PREPARE_OK: status(1) statement_id(4) num_columns(2) num_params(2) ...
+pub const STMT_NUM_COLUMNS_OFFSET: usize = 5;
+pub const STMT_NUM_PARAMS_OFFSET: usize = 7;
+
+// COM_STMT_SEND_LONG_DATA: command(1) statement_id(4) param_id(2) data(EOF)
+pub const STMT_ID_OFFSET: usize = 1;
+pub const LONG_DATA_PARAM_ID_OFFSET: usize = 5;
+
 pub const MYSQL_RESPONSE_CODE_OK: u8 = 0;
 pub const MYSQL_RESPONSE_CODE_ERR: u8 = 0xff;
 pub const MYSQL_RESPONSE_CODE_EOF: u8 = 0xfe;
@@ -146,9 +223,12 @@ pub const COM_QUIT: u8 = 1;
 pub const COM_INIT_DB: u8 = 2;
 pub const COM_QUERY: u8 = 3;
 pub const COM_FIELD_LIST: u8 = 4;
+pub const COM_STATISTICS: u8 = 9;
+pub const COM_DEBUG: u8 = 13;
 pub const COM_PING: u8 = 14;
 pub const COM_STMT_PREPARE: u8 = 22;
 pub const COM_STMT_EXECUTE: u8 = 23;
+pub const COM_STMT_SEND_LONG_DATA: u8 = 24;
 pub const COM_STMT_CLOSE: u8 = 25;
 pub const COM_STMT_FETCH: u8 = 28;
 pub const COM_MAX: u8 = 26;
@@ -187,18 +267,31 @@ pub const DNS_NAME_MAX_SIZE: usize = 255;
 pub const DNS_HEADER_QDCOUNT_OFFSET: usize = 4;
 pub const DNS_HEADER_ANCOUNT_OFFSET: usize = 6;
 pub const DNS_HEADER_NSCOUNT_OFFSET: usize = 8;
+pub const DNS_HEADER_ARCOUNT_OFFSET: usize = 10;
 pub const QUESTION_CLASS_OFFSET: usize = 2;
 pub const QUESTION_CLASS_TYPE_SIZE: usize = 4;
 pub const RR_CLASS_OFFSET: usize = 2;
 pub const RR_DATALENGTH_OFFSET: usize = 8;
 pub const RR_RDATA_OFFSET: usize = 10;
+// OPT 记录 TTL 的第一个字节为扩展响应码的高 8 位 (RFC 6891 6.1.3)
+// the first byte of OPT record TTL is the upper 8 bits of extended rcode (RFC 6891 6.1.3)
+pub const RR_OPT_EXTENDED_RCODE_OFFSET: usize = 4;
 pub const DNS_TYPE_A: u16 = 1;
 pub const DNS_TYPE_NS: u16 = 2;
+pub const DNS_TYPE_CNAME: u16 = 5;
 pub const DNS_TYPE_SOA: u16 = 6;
 pub const DNS_TYPE_WKS: u16 = 11;
 pub const DNS_TYPE_PTR: u16 = 12;
+pub const DNS_TYPE_MX: u16 = 15;
+pub const DNS_TYPE_TXT: u16 = 16;
 pub const DNS_TYPE_AAAA: u16 = 28;
+pub const DNS_TYPE_SRV: u16 = 33;
 pub const DNS_TYPE_DNAME: u16 = 39;
+pub const DNS_TYPE_OPT: u16 = 41;
 pub const DNS_TYPE_WKS_LENGTH: usize = 5;
 pub const DNS_TYPE_PTR_LENGTH: usize = 2;
+// MX 记录的 preference 和 SRV 记录的 priority, weight, port 的长度
+// length of preference of MX record and priority, weight, port of SRV record
+pub const DNS_TYPE_MX_PREFERENCE_LENGTH: usize = 2;
+pub const DNS_TYPE_SRV_HEADER_LENGTH: usize = 6;
 pub const DOMAIN_NAME_SPLIT: char = ';';
@@ -13,29 +13,46 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use std::net::IpAddr;
+
 use serde::Serialize;
 
-use super::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response};
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
 use super::{consts::*, value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
 use crate::common::flow::L7PerfStats;
 use crate::{
     common::{
+        ebpf::EbpfType,
         enums::IpProtocol,
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
         l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
-        IPV4_ADDR_LEN, IPV6_ADDR_LEN,
+        IPV4_ADDR_LEN, IPV6_ADDR_LEN, L7_LOG_DNS_MAX_ANSWERS,
     },
     flow_generator::error::{Error, Result},
     utils::bytes::read_u16_be,
 };
 use public::{l7_protocol::L7Protocol, utils::net::parse_ip_slice};
 
+// 回答部分的记录
+// record of answer section
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum DnsAnswer {
+    // A/AAAA 记录的地址
+    // address of A/AAAA record
+    Addr(IpAddr),
+    // CNAME 记录指向的域名
+    // target name of CNAME record
+    Cname(String),
+}
+
 #[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
 pub struct DnsInfo {
     #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
     pub trans_id: u16,
     #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
     pub query_type: u8,
+    // 问题部分的查询类型
+    // query type of question section
     #[serde(skip)]
     pub domain_type: u16,
 
@@ -47,6 +64,14 @@ pub struct DnsInfo {
     // SOA: primary name server
     #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
     pub answers: String,
+    // 只包含回答部分的 A/AAAA 和 CNAME 记录, 数量受 l7_log_dns_max_answers 限制
+    // only A/AAAA and CNAME records in answer section, limited by l7_log_dns_max_answers
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub answer_records: Vec<DnsAnswer>,
+    // 响应中回答部分的记录数, 请求中为 None
+    // number of records in answer section of response, None for request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_count: Option<u16>,
 
     #[serde(rename = "response_status")]
     pub status: L7ResponseStatus,
@@ -87,6 +112,10 @@ impl L7ProtocolInfoInterface for DnsInfo {
 impl DnsInfo {
     pub fn merge(&mut self, other: Self) {
         self.answers = other.answers;
+        self.answer_records = other.answer_records;
+        if other.answer_count.is_some() {
+            self.answer_count = other.answer_count;
+        }
         if other.status != L7ResponseStatus::default() {
             self.status = other.status;
         }
@@ -107,6 +136,7 @@ impl DnsInfo {
         match self.domain_type {
             1..=16 => typ[self.domain_type as usize],
             28 => "AAAA",
+            33 => "SRV",
             252 => "AXFR",
             253 => "MAILB",
             254 => "MAILA",
@@ -119,6 +149,33 @@ impl DnsInfo {
 impl From<DnsInfo> for L7ProtocolSendLog {
     fn from(f: DnsInfo) -> Self {
         let req_type = String::from(f.get_domain_str());
+        let mut addrs = vec![];
+        let mut cnames = vec![];
+        for record in f.answer_records {
+            match record {
+                DnsAnswer::Addr(addr) => addrs.push(addr.to_string()),
+                DnsAnswer::Cname(name) => cnames.push(name),
+            }
+        }
+        let mut attributes = vec![];
+        if !addrs.is_empty() {
+            attributes.push(KeyVal {
+                key: "answer_addrs".to_string(),
+                val: addrs.join(","),
+            });
+        }
+        if !cnames.is_empty() {
+            attributes.push(KeyVal {
+                key: "answer_cnames".to_string(),
+                val: cnames.join(","),
+            });
+        }
+        if let Some(count) = f.answer_count {
+            attributes.push(KeyVal {
+                key: "answer_count".to_string(),
+                val: count.to_string(),
+            });
+        }
         let log = L7ProtocolSendLog {
             req: L7Request {
                 req_type,
@@ -133,6 +190,11 @@ impl From<DnsInfo> for L7ProtocolSendLog {
             },
             ext_info: Some(ExtendedInfo {
                 request_id: Some(f.trans_id as u32),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -145,9 +207,14 @@ impl From<DnsInfo> for L7ProtocolSendLog {
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct DnsLog {
     info: DnsInfo,
+    // tcp 的单个报文中可能包含多个长度前缀的 dns 消息
+    // a single tcp segment may contain multiple length-prefixed dns messages
+    infos: Vec<DnsInfo>,
     // 是否已经解析过,避免check后重复解析
     parsed: bool,
     perf_stats: Option<L7PerfStats>,
+    #[serde(skip)]
+    max_answers: usize,
 }
 
 //解析器接口实现
@@ -156,32 +223,60 @@ impl L7ProtocolParserInterface for DnsLog {
         if !param.ebpf_type.is_raw_protocol() {
             return false;
         }
-        let ret = self.parse(payload, param.l4_protocol);
-        self.parsed = ret.is_ok() && self.info.msg_type == LogMessageType::Request;
+        self.set_max_answers(param);
+        // 旁路报文中 tcp 的 DNS 消息总是带 2 字节长度前缀 (RFC 1035 4.2.2),
+        // 无前缀的回退解析只用于 eBPF, 否则会误识别 Kafka, MySQL 等协议
+        // raw tcp DNS messages always carry a 2-byte length prefix (RFC 1035 4.2.2),
+        // the fallback without prefix is only for eBPF, otherwise Kafka, MySQL etc. are misidentified
+        let ret = self.parse(
+            payload,
+            param.l4_protocol,
+            param.ebpf_type == EbpfType::None,
+        );
+        self.parsed = ret.is_ok()
+            && self
+                .infos
+                .iter()
+                .all(|i| i.msg_type == LogMessageType::Request);
         self.parsed
     }
 
     fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
-        if self.parsed {
-            return Ok(vec![L7ProtocolInfo::DnsInfo(self.info.clone())]);
+        if !self.parsed {
+            self.set_max_answers(param);
+            self.parse(payload, param.l4_protocol, false)?;
+            for info in self.infos.iter_mut() {
+                info.cal_rrt(param).map(|rrt| {
+                    info.rrt = rrt;
+                    self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+                });
+            }
+            if let Some(last) = self.infos.last() {
+                self.info.rrt = last.rrt;
+            }
         }
-        self.parse(payload, param.l4_protocol)?;
-        self.info.cal_rrt(param).map(|rrt| {
-            self.info.rrt = rrt;
-            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
-        });
-        Ok(vec![L7ProtocolInfo::DnsInfo(self.info.clone())])
+        Ok(self
+            .infos
+            .iter()
+            .map(|i| L7ProtocolInfo::DnsInfo(i.clone()))
+            .collect())
     }
 
     fn protocol(&self) -> L7Protocol {
         L7Protocol::DNS
     }
 
+    fn default_ports(&self) -> &[u16] {
+        &[53]
+    }
+
     fn reset(&mut self) {
         *self = Self {
             info: DnsInfo::default(),
+            infos: vec![],
             parsed: false,
             perf_stats: self.perf_stats.take(),
+            max_answers: self.max_answers,
         };
     }
 
@@ -191,6 +286,19 @@ impl L7ProtocolParserInterface for DnsLog {
 }
 
 impl DnsLog {
+    fn set_max_answers(&mut self, param: &ParseParam) {
+        self.max_answers = param
+            .parse_config
+            .map(|c| c.l7_log_dns_max_answers)
+            .unwrap_or(L7_LOG_DNS_MAX_ANSWERS);
+    }
+
+    fn push_answer_record(&mut self, record: DnsAnswer) {
+        if self.info.answer_records.len() < self.max_answers {
+            self.info.answer_records.push(record);
+        }
+    }
+
     fn decode_name(&self, payload: &[u8], g_offset: usize) -> Result<(String, usize)> {
         let mut l_offset = g_offset;
         let mut index = g_offset;
@@ -279,15 +387,20 @@ impl DnsLog {
             self.info.query_name.push(DOMAIN_NAME_SPLIT);
         }
         self.info.query_name.push_str(&name);
+        self.info.domain_type = read_u16_be(&payload[offset..]);
         if self.info.query_type == DNS_REQUEST {
-            self.info.domain_type = read_u16_be(&payload[offset..]);
             self.info.msg_type = LogMessageType::Request;
         }
 
         Ok(offset + QUESTION_CLASS_TYPE_SIZE)
     }
 
-    fn decode_resource_record(&mut self, payload: &[u8], g_offset: usize) -> Result<usize> {
+    fn decode_resource_record(
+        &mut self,
+        payload: &[u8],
+        g_offset: usize,
+        is_answer: bool,
+    ) -> Result<usize> {
         let (_, offset) = self.decode_name(payload, g_offset)?;
 
         if payload.len() <= offset {
@@ -301,16 +414,52 @@ impl DnsLog {
             return Err(Error::DNSLogParseFailed(err_msg));
         }
 
-        self.info.domain_type = read_u16_be(&payload[offset..]);
+        let rr_type = read_u16_be(&payload[offset..]);
         let data_length = read_u16_be(&payload[offset + RR_DATALENGTH_OFFSET..]) as usize;
         if data_length != 0 {
-            self.decode_rdata(payload, offset + RR_RDATA_OFFSET, data_length)?;
+            self.decode_rdata(
+                payload,
+                offset + RR_RDATA_OFFSET,
+                rr_type,
+                data_length,
+                is_answer,
+            )?;
         }
 
         Ok(offset + RR_RDATA_OFFSET + data_length)
     }
 
-    fn decode_rdata(&mut self, payload: &[u8], g_offset: usize, data_length: usize) -> Result<()> {
+    // 返回下一条记录的位置, 是 OPT 记录时同时返回扩展响应码的高 8 位
+    // return position of the next record, and upper 8 bits of extended rcode if it is OPT record
+    fn decode_additional_record(
+        &self,
+        payload: &[u8],
+        g_offset: usize,
+    ) -> Result<(usize, Option<u16>)> {
+        let (_, offset) = self.decode_name(payload, g_offset)?;
+        if payload.len() < offset + RR_RDATA_OFFSET {
+            let err_msg = format!("resource record length error: {}", payload.len());
+            return Err(Error::DNSLogParseFailed(err_msg));
+        }
+        let data_length = read_u16_be(&payload[offset + RR_DATALENGTH_OFFSET..]) as usize;
+        let next = offset + RR_RDATA_OFFSET + data_length;
+        if payload.len() < next {
+            let err_msg = format!("resource record length error: {}", payload.len());
+            return Err(Error::DNSLogParseFailed(err_msg));
+        }
+        let extended_rcode = (read_u16_be(&payload[offset..]) == DNS_TYPE_OPT)
+            .then(|| payload[offset + RR_OPT_EXTENDED_RCODE_OFFSET] as u16);
+        Ok((next, extended_rcode))
+    }
+
+    fn decode_rdata(
+        &mut self,
+        payload: &[u8],
+        g_offset: usize,
+        rr_type: u16,
+        data_length: usize,
+        is_answer: bool,
+    ) -> Result<()> {
         if payload.len() < g_offset + data_length {
             return Err(Error::DNSLogParseFailed(
                 "invalid data: payload.len() < g_offset + data_length".to_string(),
@@ -324,18 +473,21 @@ impl DnsLog {
             self.info.answers.push(DOMAIN_NAME_SPLIT);
         }
 
-        match self.info.domain_type {
+        match rr_type {
             DNS_TYPE_A | DNS_TYPE_AAAA => match data_length {
                 IPV4_ADDR_LEN | IPV6_ADDR_LEN => {
                     if let Some(ipaddr) = parse_ip_slice(&payload[g_offset..g_offset + data_length])
                     {
                         self.info.answers.push_str(&ipaddr.to_string());
+                        if is_answer {
+                            self.push_answer_record(DnsAnswer::Addr(ipaddr));
+                        }
                     }
                 }
                 _ => {
                     let err_msg = format!(
                         "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
+                        rr_type, data_length
                     );
                     return Err(Error::DNSLogParseFailed(err_msg));
                 }
@@ -344,7 +496,7 @@ impl DnsLog {
                 if data_length > DNS_NAME_MAX_SIZE {
                     let err_msg = format!(
                         "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
+                        rr_type, data_length
                     );
                     return Err(Error::DNSLogParseFailed(err_msg));
                 }
@@ -352,11 +504,26 @@ impl DnsLog {
                 let (name, _) = self.decode_name(payload, g_offset)?;
                 self.info.answers.push_str(&name);
             }
+            DNS_TYPE_CNAME => {
+                if data_length > DNS_NAME_MAX_SIZE {
+                    let err_msg = format!(
+                        "domain type {} data length {} invalid",
+                        rr_type, data_length
+                    );
+                    return Err(Error::DNSLogParseFailed(err_msg));
+                }
+
+                let (name, _) = self.decode_name(payload, g_offset)?;
+                self.info.answers.push_str(&name);
+                if is_answer {
+                    self.push_answer_record(DnsAnswer::Cname(name));
+                }
+            }
             DNS_TYPE_WKS => {
                 if data_length < DNS_TYPE_WKS_LENGTH {
                     let err_msg = format!(
                         "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
+                        rr_type, data_length
                     );
                     return Err(Error::DNSLogParseFailed(err_msg));
                 }
@@ -364,11 +531,31 @@ impl DnsLog {
                     self.info.answers.push_str(&ipaddr.to_string());
                 }
             }
+            DNS_TYPE_MX | DNS_TYPE_SRV => {
+                let name_offset = if rr_type == DNS_TYPE_MX {
+                    DNS_TYPE_MX_PREFERENCE_LENGTH
+                } else {
+                    DNS_TYPE_SRV_HEADER_LENGTH
+                };
+                if data_length <= name_offset || data_length > name_offset + DNS_NAME_MAX_SIZE {
+                    let err_msg = format!(
+                        "domain type {} data length {} invalid",
+                        rr_type, data_length
+                    );
+                    return Err(Error::DNSLogParseFailed(err_msg));
+                }
+
+                let (name, _) = self.decode_name(payload, g_offset + name_offset)?;
+                self.info.answers.push_str(&name);
+            }
+            // TXT 记录的内容不采集
+            // content of TXT record is not collected
+            DNS_TYPE_TXT => (),
             DNS_TYPE_PTR => {
                 if data_length != DNS_TYPE_PTR_LENGTH {
                     let err_msg = format!(
                         "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
+                        rr_type, data_length
                     );
                     return Err(Error::DNSLogParseFailed(err_msg));
                 }
@@ -376,7 +563,7 @@ impl DnsLog {
             _ => {
                 let err_msg = format!(
                     "other domain type {} data length {} invalid",
-                    self.info.domain_type, data_length
+                    rr_type, data_length
                 );
                 return Err(Error::DNSLogParseFailed(err_msg));
             }
@@ -384,7 +571,7 @@ impl DnsLog {
         Ok(())
     }
 
-    fn set_status(&mut self, status_code: u8) {
+    fn set_status(&mut self, status_code: u16) {
         if status_code == 0 {
             self.info.status = L7ResponseStatus::Ok;
         } else if status_code == 1 || status_code == 3 {
@@ -409,6 +596,7 @@ impl DnsLog {
         let qd_count = read_u16_be(&payload[DNS_HEADER_QDCOUNT_OFFSET..]);
         let an_count = read_u16_be(&payload[DNS_HEADER_ANCOUNT_OFFSET..]);
         let ns_count = read_u16_be(&payload[DNS_HEADER_NSCOUNT_OFFSET..]);
+        let ar_count = read_u16_be(&payload[DNS_HEADER_ARCOUNT_OFFSET..]);
 
         let mut g_offset = DNS_HEADER_SIZE;
         for _i in 0..qd_count {
@@ -419,13 +607,28 @@ impl DnsLog {
             self.info.query_type = 1;
 
             for _i in 0..an_count {
-                g_offset = self.decode_resource_record(payload, g_offset)?;
+                g_offset = self.decode_resource_record(payload, g_offset, true)?;
             }
 
             for _i in 0..ns_count {
-                g_offset = self.decode_resource_record(payload, g_offset)?;
+                g_offset = self.decode_resource_record(payload, g_offset, false)?;
             }
 
+            // 附加部分只用于查找 OPT 记录, 解析失败时忽略
+            // additional section is only used to find OPT record, ignored if decoding fails
+            let mut code = code as u16;
+            for _i in 0..ar_count {
+                match self.decode_additional_record(payload, g_offset) {
+                    Ok((offset, extended_rcode)) => {
+                        code |= extended_rcode.unwrap_or_default() << 4;
+                        g_offset = offset;
+                    }
+                    Err(_) => break,
+                }
+            }
+            self.info.status_code = Some(code as i32);
+            self.info.answer_count = Some(an_count);
+
             self.perf_stats.as_mut().unwrap().inc_resp();
             self.set_status(code);
             self.info.msg_type = LogMessageType::Response;
@@ -436,33 +639,69 @@ impl DnsLog {
         Ok(())
     }
 
-    fn parse(&mut self, payload: &[u8], proto: IpProtocol) -> Result<()> {
+    fn parse(&mut self, payload: &[u8], proto: IpProtocol, strict: bool) -> Result<()> {
         if self.perf_stats.is_none() {
             self.perf_stats = Some(L7PerfStats::default())
         };
+        self.infos.clear();
         match proto {
-            IpProtocol::Udp => self.decode_payload(payload),
+            IpProtocol::Udp => self.decode_payload(payload)?,
             IpProtocol::Tcp => {
                 if payload.len() <= DNS_TCP_PAYLOAD_OFFSET {
                     let err_msg = format!("dns payload length error:{}", payload.len());
                     return Err(Error::DNSLogParseFailed(err_msg));
                 }
 
-                let size = read_u16_be(payload) as usize;
-                if size != payload[DNS_TCP_PAYLOAD_OFFSET..].len() {
-                    self.decode_payload(payload)
-                } else {
-                    self.decode_payload(&payload[DNS_TCP_PAYLOAD_OFFSET..])
-                        .or_else(|_| {
-                            self.reset();
-                            self.decode_payload(payload)
-                        })
+                if self.decode_tcp_messages(payload) {
+                    return Ok(());
+                }
+                if strict {
+                    let err_msg = "dns tcp payload without length prefix".to_string();
+                    return Err(Error::DNSLogParseFailed(err_msg));
                 }
+                // 没有长度前缀时按单个消息解析
+                // parse as a single message when there is no length prefix
+                self.reset();
+                self.decode_payload(payload)?;
             }
             _ => {
                 let err_msg = format!("dns payload length error:{}", payload.len());
-                Err(Error::DNSLogParseFailed(err_msg))
+                return Err(Error::DNSLogParseFailed(err_msg));
+            }
+        }
+        self.infos.push(self.info.clone());
+        Ok(())
+    }
+
+    // 依次解析 tcp 报文中 2 字节长度前缀的消息, 末尾不完整的消息会被忽略
+    // 第一个消息解析成功时返回 true
+    // ===============================================================
+    // decode the 2-byte length-prefixed messages in tcp payload in order,
+    // incomplete message at the end is ignored.
+    // return true if the first message is decoded successfully
+    fn decode_tcp_messages(&mut self, payload: &[u8]) -> bool {
+        let mut data = payload;
+        while data.len() > DNS_TCP_PAYLOAD_OFFSET {
+            let size = read_u16_be(data) as usize;
+            if size > data.len() - DNS_TCP_PAYLOAD_OFFSET {
+                break;
             }
+            self.info = DnsInfo::default();
+            if self
+                .decode_payload(&data[DNS_TCP_PAYLOAD_OFFSET..DNS_TCP_PAYLOAD_OFFSET + size])
+                .is_err()
+            {
+                break;
+            }
+            self.infos.push(self.info.clone());
+            data = &data[DNS_TCP_PAYLOAD_OFFSET + size..];
+        }
+        match self.infos.last() {
+            Some(last) => {
+                self.info = last.clone();
+                true
+            }
+            None => false,
         }
     }
 }
@@ -539,6 +778,190 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_tcp_multi_message() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("dns-tcp-multi.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        assert_eq!(packets.len(), 2);
+
+        let mut output = vec![];
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let payload = packet.get_l4_payload().unwrap();
+            let param = &ParseParam::from((&*packet, log_cache.clone(), false));
+            let mut dns = DnsLog::default();
+            for info in dns.parse_payload(payload, param).unwrap() {
+                if let L7ProtocolInfo::DnsInfo(info) = info {
+                    output.push((info.msg_type, info.trans_id, info.query_name, info.answers));
+                }
+            }
+        }
+
+        // 两个请求在同一个报文中, 第二个响应不完整
+        // two requests in one segment, the second response is incomplete
+        assert_eq!(
+            output,
+            vec![
+                (
+                    LogMessageType::Request,
+                    0x1001,
+                    "deepflow.io".to_owned(),
+                    "".to_owned()
+                ),
+                (
+                    LogMessageType::Request,
+                    0x1002,
+                    "yunshan.net".to_owned(),
+                    "".to_owned()
+                ),
+                (
+                    LogMessageType::Response,
+                    0x1001,
+                    "deepflow.io".to_owned(),
+                    "1.2.3.4".to_owned()
+                ),
+            ]
+        );
+    }
+
+    fn parse_response(name: &str, max_answers: usize) -> DnsInfo {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let packets = capture.as_meta_packets();
+        let mut dns = DnsLog::default();
+        dns.max_answers = max_answers;
+        dns.parse(packets[1].get_l4_payload().unwrap(), IpProtocol::Udp, false)
+            .unwrap();
+        dns.info
+    }
+
+    #[test]
+    fn check_answer_records() {
+        let info = parse_response("dns-multi-a.pcap", L7_LOG_DNS_MAX_ANSWERS);
+        assert_eq!(info.msg_type, LogMessageType::Response);
+        assert_eq!(info.answers, "10.0.0.1;10.0.0.2;10.0.0.3");
+        assert_eq!(
+            info.answer_records,
+            vec![
+                DnsAnswer::Addr("10.0.0.1".parse().unwrap()),
+                DnsAnswer::Addr("10.0.0.2".parse().unwrap()),
+                DnsAnswer::Addr("10.0.0.3".parse().unwrap()),
+            ]
+        );
+
+        // 超出上限的记录不采集
+        // records beyond the limit are not collected
+        let info = parse_response("dns-multi-a.pcap", 2);
+        assert_eq!(info.answers, "10.0.0.1;10.0.0.2;10.0.0.3");
+        assert_eq!(info.answer_records.len(), 2);
+
+        // CNAME 链, 记录名和 CNAME 目标都使用了名称压缩
+        // CNAME chain, both record names and CNAME targets use name compression
+        let info = parse_response("dns-cname.pcap", L7_LOG_DNS_MAX_ANSWERS);
+        assert_eq!(info.query_name, "www.deepflow.io");
+        assert_eq!(info.answers, "web.deepflow.io;edge.cdn.net;1.1.1.1");
+        assert_eq!(
+            info.answer_records,
+            vec![
+                DnsAnswer::Cname("web.deepflow.io".to_owned()),
+                DnsAnswer::Cname("edge.cdn.net".to_owned()),
+                DnsAnswer::Addr("1.1.1.1".parse().unwrap()),
+            ]
+        );
+    }
+
+    // 构造包含一个问题的响应, answer 为回答的类型和数据, opt 为 OPT 记录中扩展响应码的高 8 位
+    // build a response with one question, answer is type and data of the answer,
+    // opt is upper 8 bits of extended rcode in OPT record
+    fn build_response(
+        qtype: u16,
+        rcode: u8,
+        answer: Option<(u16, &[u8])>,
+        opt: Option<u8>,
+    ) -> Vec<u8> {
+        let mut payload = vec![0x12, 0x34, 0x81, 0x80 | rcode, 0, 1];
+        payload.extend_from_slice(&(answer.is_some() as u16).to_be_bytes());
+        payload.extend_from_slice(&[0, 0]);
+        payload.extend_from_slice(&(opt.is_some() as u16).to_be_bytes());
+        payload.extend_from_slice(b"\x08deepflow\x02io\x00");
+        payload.extend_from_slice(&qtype.to_be_bytes());
+        payload.extend_from_slice(&[0, 1]);
+        if let Some((rr_type, rdata)) = answer {
+            payload.extend_from_slice(&[0xc0, 0x0c]);
+            payload.extend_from_slice(&rr_type.to_be_bytes());
+            payload.extend_from_slice(&[0, 1, 0, 0, 0x0e, 0x10]);
+            payload.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            payload.extend_from_slice(rdata);
+        }
+        if let Some(extended_rcode) = opt {
+            payload.extend_from_slice(&[0, 0, 41, 0x10, 0, extended_rcode, 0, 0, 0, 0, 0]);
+        }
+        payload
+    }
+
+    #[test]
+    fn check_rcode_and_query_type() {
+        let cases = [
+            (
+                build_response(
+                    DNS_TYPE_MX,
+                    0,
+                    Some((DNS_TYPE_MX, b"\x00\x0a\x04mail\xc0\x0c")),
+                    None,
+                ),
+                ("MX", "mail.deepflow.io", Some(1), 0, L7ResponseStatus::Ok),
+            ),
+            (
+                build_response(
+                    DNS_TYPE_SRV,
+                    0,
+                    Some((DNS_TYPE_SRV, b"\x00\x01\x00\x02\x1f\x90\x03srv\xc0\x0c")),
+                    Some(0),
+                ),
+                ("SRV", "srv.deepflow.io", Some(1), 0, L7ResponseStatus::Ok),
+            ),
+            (
+                build_response(DNS_TYPE_TXT, 0, Some((DNS_TYPE_TXT, b"\x05hello")), None),
+                ("TXT", "", Some(1), 0, L7ResponseStatus::Ok),
+            ),
+            (
+                build_response(DNS_TYPE_PTR, 0, Some((DNS_TYPE_PTR, b"\xc0\x0c")), None),
+                ("PTR", "", Some(1), 0, L7ResponseStatus::Ok),
+            ),
+            // NXDOMAIN, SERVFAIL
+            (
+                build_response(DNS_TYPE_A, 3, None, Some(0)),
+                ("A", "", Some(0), 3, L7ResponseStatus::ClientError),
+            ),
+            (
+                build_response(DNS_TYPE_AAAA, 2, None, None),
+                ("AAAA", "", Some(0), 2, L7ResponseStatus::ServerError),
+            ),
+            // OPT 记录中的扩展响应码, 16 为 BADVERS
+            // extended rcode in OPT record, 16 is BADVERS
+            (
+                build_response(DNS_TYPE_A, 0, None, Some(1)),
+                ("A", "", Some(0), 16, L7ResponseStatus::ServerError),
+            ),
+        ];
+        for (payload, (domain_type, answers, answer_count, code, status)) in cases {
+            let mut dns = DnsLog::default();
+            dns.parse(&payload, IpProtocol::Udp, false).unwrap();
+            let info = dns.info;
+            assert_eq!(info.msg_type, LogMessageType::Response);
+            assert_eq!(info.get_domain_str(), domain_type);
+            assert_eq!(info.answers, answers);
+            assert_eq!(info.answer_count, answer_count);
+            assert_eq!(info.status_code, Some(code));
+            assert_eq!(info.status, status);
+        }
+    }
+
     #[test]
     fn check_perf() {
         let expected = vec![(
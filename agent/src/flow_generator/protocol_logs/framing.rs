@@ -0,0 +1,203 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+    长度前缀的分帧: 帧头固定位置有固定宽度的长度字段, 例如 MySQL (3 字节小端, 不包含 4 字节的包头),
+    MongoDB (4 字节小端, 包含整个帧) 和 DNS over TCP (2 字节大端, 不包含长度字段本身).
+    所有的边界检查都在这里完成, 解析器只需要处理完整的帧, 跨分段的缓存由解析器实现 (例如 MySQL 按 TCP 序列号重组).
+
+    length-prefixed framing: a fixed-width length field at a fixed offset of frame header, such as
+    MySQL (3 bytes little endian, 4 bytes header excluded), MongoDB (4 bytes little endian, whole
+    frame included) and DNS over TCP (2 bytes big endian, the field itself excluded).
+    all bounds checks are done here, parsers only need to handle complete frames, buffering across
+    segments is implemented by parsers (MySQL reassembles by tcp sequence number for example).
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LengthPrefixed {
+    offset: usize,
+    width: usize,
+    endian: Endian,
+    // 长度字段的值不包含的头部长度, 默认为长度字段的结束位置
+    // header length not counted in value of length field, end of length field by default
+    header_len: usize,
+    max_len: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame<'a> {
+    Complete(&'a [u8]),
+    // 不完整的帧及其长度, 长度字段也不完整时为 None
+    // incomplete frame and its length, None if length field is incomplete too
+    Partial(Option<usize>),
+    // 帧长度小于长度字段的结束位置或者超过 max_len
+    // frame length is less than end of length field or exceeds max_len
+    Invalid,
+}
+
+impl LengthPrefixed {
+    pub const fn new(offset: usize, width: usize, endian: Endian) -> Self {
+        assert!(width > 0 && width <= 8);
+        Self {
+            offset,
+            width,
+            endian,
+            header_len: offset + width,
+            max_len: usize::MAX,
+        }
+    }
+
+    // 长度字段的值为整个帧的长度时为 0
+    // 0 if value of length field is length of the whole frame
+    pub const fn header_len(mut self, header_len: usize) -> Self {
+        self.header_len = header_len;
+        self
+    }
+
+    pub const fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    // 长度字段的值, 数据不足时为 None
+    // value of length field, None if data is insufficient
+    pub fn length(&self, data: &[u8]) -> Option<u64> {
+        let field = data.get(self.offset..self.offset + self.width)?;
+        let value = match self.endian {
+            Endian::Big => field.iter().fold(0, |v, &b| v << 8 | b as u64),
+            Endian::Little => field.iter().rev().fold(0, |v, &b| v << 8 | b as u64),
+        };
+        Some(value)
+    }
+
+    // data 开头的帧
+    // frame at the beginning of data
+    pub fn frame<'a>(&self, data: &'a [u8]) -> Frame<'a> {
+        let Some(length) = self.length(data) else {
+            return Frame::Partial(None);
+        };
+        let frame_len = usize::try_from(length)
+            .ok()
+            .and_then(|length| length.checked_add(self.header_len))
+            .filter(|&len| len >= self.offset + self.width && len <= self.max_len);
+        match frame_len {
+            Some(len) => match data.get(..len) {
+                Some(frame) => Frame::Complete(frame),
+                None => Frame::Partial(Some(len)),
+            },
+            None => Frame::Invalid,
+        }
+    }
+
+    // data 中连续的完整帧, 遇到不完整或非法的帧时结束
+    // consecutive complete frames in data, ends at an incomplete or invalid frame
+    pub fn frames<'a>(&self, data: &'a [u8]) -> Frames<'a> {
+        Frames {
+            framing: *self,
+            data,
+            offset: 0,
+        }
+    }
+}
+
+pub struct Frames<'a> {
+    framing: LengthPrefixed,
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Frames<'a> {
+    // 完整帧之后剩余的数据
+    // data remaining after complete frames
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.offset..]
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.framing.frame(self.remaining()) {
+            Frame::Complete(frame) => {
+                self.offset += frame.len();
+                Some(frame)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_prefixed_frame() {
+        // MySQL: 3 字节小端, 不包含 4 字节的包头
+        // MySQL: 3 bytes little endian, 4 bytes header excluded
+        let mysql = LengthPrefixed::new(0, 3, Endian::Little).header_len(4);
+        let packet = [0x02, 0, 0, 1, 0xaa, 0xbb, 0xcc];
+        assert_eq!(mysql.length(&packet), Some(2));
+        assert_eq!(mysql.frame(&packet), Frame::Complete(&packet[..6]));
+        assert_eq!(mysql.frame(&packet[..5]), Frame::Partial(Some(6)));
+        assert_eq!(mysql.frame(&packet[..2]), Frame::Partial(None));
+        assert_eq!(mysql.max_len(5).frame(&packet), Frame::Invalid);
+
+        // DNS over TCP: 2 字节大端, 不包含长度字段本身
+        // DNS over TCP: 2 bytes big endian, the field itself excluded
+        let dns = LengthPrefixed::new(0, 2, Endian::Big);
+        assert_eq!(
+            dns.frame(&[0, 1, 0xaa, 0xbb]),
+            Frame::Complete(&[0, 1, 0xaa])
+        );
+
+        // MongoDB: 4 字节小端, 包含整个帧, 长度小于长度字段本身是非法的
+        // MongoDB: 4 bytes little endian, whole frame included, length less than the field itself
+        // is invalid
+        let mongo = LengthPrefixed::new(0, 4, Endian::Little).header_len(0);
+        assert_eq!(
+            mongo.frame(&[5, 0, 0, 0, 0xaa]),
+            Frame::Complete(&[5, 0, 0, 0, 0xaa])
+        );
+        assert_eq!(mongo.frame(&[0, 0, 0, 0, 0xaa]), Frame::Invalid);
+
+        // 长度字段不在开头, 长度超过 usize
+        // length field not at the beginning, length exceeds usize
+        let wide = LengthPrefixed::new(2, 8, Endian::Big);
+        assert_eq!(
+            wide.frame(&[0xaa, 0xbb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Frame::Invalid
+        );
+    }
+
+    #[test]
+    fn length_prefixed_frames() {
+        let framing = LengthPrefixed::new(0, 1, Endian::Big);
+        let data = [1, 0xaa, 0, 2, 0xbb];
+        let mut frames = framing.frames(&data);
+        assert_eq!(frames.next(), Some(&[1, 0xaa][..]));
+        assert_eq!(frames.next(), Some(&[0][..]));
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.remaining(), &[2, 0xbb]);
+    }
+}
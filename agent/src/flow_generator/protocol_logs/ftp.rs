@@ -0,0 +1,422 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
+use super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::error::{Error, Result},
+};
+
+// 参考：https://www.rfc-editor.org/rfc/rfc959#section-4.1
+// 以及 RFC 2228, 2389, 2428, 3659 扩展的命令
+// and commands extended by RFC 2228, 2389, 2428, 3659
+const COMMANDS: [&str; 42] = [
+    "USER", "PASS", "ACCT", "CWD", "CDUP", "SMNT", "QUIT", "REIN", "PORT", "PASV", "TYPE", "STRU",
+    "MODE", "RETR", "STOR", "STOU", "APPE", "ALLO", "REST", "RNFR", "RNTO", "ABOR", "DELE", "RMD",
+    "MKD", "PWD", "LIST", "NLST", "SITE", "SYST", "STAT", "HELP", "NOOP", "AUTH", "PBSZ", "PROT",
+    "FEAT", "OPTS", "EPRT", "EPSV", "SIZE", "MDTM",
+];
+// 用于协议识别的命令, 不包含 SMTP 等协议同样使用的命令
+// commands used for protocol detection, commands also used by protocols such as SMTP are excluded
+const CHECK_COMMANDS: [&str; 7] = ["USER", "PASS", "RETR", "STOR", "LIST", "PASV", "PORT"];
+const COMMAND_USER: &str = "USER";
+// 参数为凭据的命令, 不记录参数
+// arguments of these commands are credentials and never recorded
+const SENSITIVE_COMMANDS: [&str; 2] = ["PASS", "ACCT"];
+
+const REPLY_CODE_SIZE: usize = 3;
+const REPLY_CODE_MIN: u16 = 100;
+const REPLY_CODE_MAX: u16 = 599;
+const REPLY_PRELIMINARY_MAX: u16 = 199;
+const REPLY_SERVICE_READY: u16 = 220;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct FtpInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub command: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub argument: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub user: String,
+
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub reply_code: Option<u16>,
+    #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
+    pub reply_text: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for FtpInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::FtpInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.resp_len = other.resp_len;
+                self.reply_code = other.reply_code;
+                self.reply_text = other.reply_text;
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Ftp,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<FtpInfo> for L7ProtocolSendLog {
+    fn from(f: FtpInfo) -> Self {
+        let (exception, result) = match f.status {
+            L7ResponseStatus::ClientError | L7ResponseStatus::ServerError => {
+                (f.reply_text, String::new())
+            }
+            _ => (String::new(), f.reply_text),
+        };
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.command,
+                resource: f.argument,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.reply_code.map(|c| c as i32),
+                exception,
+                result,
+            },
+            ext_info: if f.user.is_empty() {
+                None
+            } else {
+                Some(ExtendedInfo {
+                    attributes: Some(vec![KeyVal {
+                        key: "user".to_string(),
+                        val: f.user,
+                    }]),
+                    ..Default::default()
+                })
+            },
+            ..Default::default()
+        }
+    }
+}
+
+// 按行切分, 去掉行尾的 CRLF 和空行
+// split into lines, trailing CRLF and empty lines are removed
+fn lines(payload: &[u8]) -> impl Iterator<Item = &[u8]> {
+    payload
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty())
+}
+
+// 回复行: 3 位回复码, 之后为 ' ' (最后一行) 或 '-' (多行回复首行), 以及文本
+// reply line: 3-digit reply code, followed by ' ' (last line) or '-' (first line
+// of multiline reply), and text
+fn parse_reply_line(line: &[u8]) -> Option<(u16, &[u8])> {
+    let code = line.get(..REPLY_CODE_SIZE)?;
+    if !code.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let code = code
+        .iter()
+        .fold(0u16, |acc, b| acc * 10 + (b - b'0') as u16);
+    if code < REPLY_CODE_MIN || code > REPLY_CODE_MAX {
+        return None;
+    }
+    match line.get(REPLY_CODE_SIZE) {
+        None => Some((code, &[])),
+        Some(b' ' | b'-') => Some((code, &line[REPLY_CODE_SIZE + 1..])),
+        _ => None,
+    }
+}
+
+// 返回最后一个回复的回复码和第一行的文本, 多行回复的中间行可以是任意文本
+// return reply code of the last reply and text of the first line,
+// intermediate lines of multiline reply can be any text
+fn parse_reply(payload: &[u8]) -> Option<(u16, String)> {
+    let mut lines = lines(payload);
+    let (mut code, text) = parse_reply_line(lines.next()?)?;
+    for line in lines {
+        if let Some((c, _)) = parse_reply_line(line) {
+            code = c;
+        }
+    }
+    Some((code, String::from_utf8_lossy(text).into_owned()))
+}
+
+// 返回大写的命令和参数
+// return command in uppercase and its argument
+fn parse_command(line: &[u8]) -> Option<(&'static str, &[u8])> {
+    let (verb, arg) = match line.iter().position(|b| *b == b' ') {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None => (line, &line[line.len()..]),
+    };
+    COMMANDS
+        .iter()
+        .find(|c| c.as_bytes().eq_ignore_ascii_case(verb))
+        .map(|c| (*c, arg))
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct FtpLog {
+    info: FtpInfo,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+
+    #[serde(skip)]
+    has_command: bool,
+}
+
+impl L7ProtocolParserInterface for FtpLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        if !payload.ends_with(b"\r\n") {
+            return false;
+        }
+        let Some(line) = lines(payload).next() else {
+            return false;
+        };
+        match param.direction {
+            PacketDirection::ClientToServer => parse_command(line)
+                .map(|(command, _)| CHECK_COMMANDS.contains(&command))
+                .unwrap_or_default(),
+            // 服务端问候语, SMTP 也使用 220, 排除包含 SMTP 的问候语
+            // server greeting, 220 is also used by SMTP, so greeting containing SMTP is excluded
+            PacketDirection::ServerToClient => match parse_reply_line(line) {
+                Some((REPLY_SERVICE_READY, text)) => {
+                    line.get(REPLY_CODE_SIZE) == Some(&b' ')
+                        && !text.windows(4).any(|w| w.eq_ignore_ascii_case(b"SMTP"))
+                }
+                _ => false,
+            },
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        if !self.parse(payload, param.direction)? {
+            return Ok(vec![]);
+        }
+        self.info.is_tls = param.is_tls();
+        if self.info.msg_type != LogMessageType::Session {
+            self.info.cal_rrt(param).map(|rrt| {
+                self.info.rrt = rrt;
+                self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+            });
+        }
+        Ok(vec![L7ProtocolInfo::FtpInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Ftp
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[21]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = FtpInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl FtpLog {
+    fn set_status(&mut self, code: u16) {
+        let perf_stats = self.perf_stats.as_mut().unwrap();
+        // 4xx 为暂时性错误, 多由服务端状态导致; 5xx 为永久性错误, 多由请求导致
+        // 4xx is transient failure mostly caused by server state,
+        // 5xx is permanent failure mostly caused by the request
+        self.info.status = match code {
+            400..=499 => {
+                perf_stats.inc_resp_err();
+                L7ResponseStatus::ServerError
+            }
+            500..=599 => {
+                perf_stats.inc_req_err();
+                L7ResponseStatus::ClientError
+            }
+            _ => L7ResponseStatus::Ok,
+        };
+    }
+
+    // 返回是否需要上报, 1xx 预备回复不上报
+    // return whether to report, 1xx preliminary replies are not reported
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<bool> {
+        match direction {
+            PacketDirection::ClientToServer => self.parse_request(payload).map(|_| true),
+            PacketDirection::ServerToClient => self.parse_response(payload),
+        }
+    }
+
+    fn parse_request(&mut self, payload: &[u8]) -> Result<()> {
+        let line = lines(payload).next().ok_or(Error::FtpLogParseFailed)?;
+        let (command, arg) = parse_command(line).ok_or(Error::FtpLogParseFailed)?;
+        let arg = String::from_utf8_lossy(arg).trim().to_owned();
+        match command {
+            COMMAND_USER => self.info.user = arg,
+            _ if SENSITIVE_COMMANDS.contains(&command) => (),
+            _ => self.info.argument = arg,
+        }
+        self.info.command = command.to_owned();
+        self.info.req_len = Some(payload.len() as u32);
+        self.has_command = true;
+        self.info.msg_type = LogMessageType::Request;
+        self.perf_stats.as_mut().unwrap().inc_req();
+        Ok(())
+    }
+
+    fn parse_response(&mut self, payload: &[u8]) -> Result<bool> {
+        let (code, text) = parse_reply(payload).ok_or(Error::FtpLogParseFailed)?;
+        // 1xx 之后还会有最终回复, 例如 RETR 的 150 和 226
+        // 1xx is followed by a final reply, such as 150 and 226 for RETR
+        if code <= REPLY_PRELIMINARY_MAX {
+            return Ok(false);
+        }
+        // 客户端发送命令之前的回复为服务端问候语
+        // reply before any command sent by client is the server greeting
+        if self.has_command {
+            self.info.msg_type = LogMessageType::Response;
+            self.perf_stats.as_mut().unwrap().inc_resp();
+        } else {
+            self.info.msg_type = LogMessageType::Session;
+        }
+        self.info.reply_code = Some(code);
+        self.info.reply_text = text;
+        self.info.resp_len = Some(payload.len() as u32);
+        self.set_status(code);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use super::*;
+
+    use crate::common::l7_protocol_log::L7PerfCache;
+    use crate::flow_generator::L7_RRT_CACHE_CAPACITY;
+    use crate::utils::test::{Capture, ProtocolTester};
+
+    const FILE_DIR: &str = "resources/test/flow_generator/ftp";
+
+    fn tester() -> ProtocolTester<FtpLog> {
+        ProtocolTester::new(FtpLog::default())
+            // 第一个报文为服务端问候语
+            // the first packet is server greeting
+            .server_first()
+            .step(|ftp, payload, param| {
+                let is_ftp = match ftp.parse_payload(payload, param) {
+                    Ok(infos) => !infos.is_empty(),
+                    Err(_) => false,
+                };
+                ftp.info.rrt = 0;
+                format!("{:?} is_ftp: {}\r\n", ftp.info, is_ftp)
+            })
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(dir.join("ftp.pcap"), dir.join("ftp.result"));
+    }
+
+    #[test]
+    fn check_payload() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("ftp.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        let mut ftp = FtpLog::default();
+
+        packets[0].lookup_key.direction = PacketDirection::ServerToClient;
+        let param = &ParseParam::from((&packets[0], log_cache.clone(), false));
+        assert!(ftp.check_payload(packets[0].get_l4_payload().unwrap(), param));
+        // SMTP 问候语
+        // SMTP greeting
+        assert!(!ftp.check_payload(b"220 mail.example.com ESMTP Postfix\r\n", param));
+
+        packets[1].lookup_key.direction = PacketDirection::ClientToServer;
+        let param = &ParseParam::from((&packets[1], log_cache.clone(), false));
+        assert!(ftp.check_payload(packets[1].get_l4_payload().unwrap(), param));
+        assert!(!ftp.check_payload(b"EHLO example.com\r\n", param));
+        assert!(!ftp.check_payload(b"NOOP\r\n", param));
+    }
+
+    #[test]
+    fn check_reply() {
+        assert_eq!(
+            parse_reply(b"211-Features:\r\n MDTM\r\n SIZE\r\n211 End\r\n"),
+            Some((211, "Features:".to_owned()))
+        );
+        assert_eq!(
+            parse_reply(b"150 Opening BINARY mode data connection\r\n226 Transfer complete\r\n"),
+            Some((226, "Opening BINARY mode data connection".to_owned()))
+        );
+        assert_eq!(parse_reply(b"USER anonymous\r\n"), None);
+        assert_eq!(parse_reply(b"999 unknown\r\n"), None);
+    }
+}
@@ -14,12 +14,16 @@
  * limitations under the License.
  */
 
-use std::str;
+use std::{borrow::Cow, collections::HashMap, fmt, io::Read, mem, str};
+
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
 
 use nom::AsBytes;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use super::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo};
+use super::pb_adapter::{
+    ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+};
 use super::value_is_default;
 use super::{consts::*, AppProtoHead, L7ResponseStatus};
 use super::{decode_new_rpc_trace_context_with_type, LogMessageType};
@@ -88,11 +92,40 @@ pub struct HttpInfo {
     pub req_content_length: Option<u32>,
     #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
     pub resp_content_length: Option<u32>,
+    // HTTP/1 请求的 Content-Type (不包含参数) 和是否使用 chunked 传输编码
+    // Content-Type (parameters excluded) of HTTP/1 request and whether chunked transfer encoding is used
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub req_content_type: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub req_chunked: bool,
 
     #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
     pub status_code: Option<i32>,
     #[serde(rename = "response_status")]
     status: L7ResponseStatus,
+
+    // grpc 信息: trailers 中的 grpc-status 和 grpc-message, 以及 :path 中的 service 和 method
+    // grpc info: grpc-status and grpc-message from trailers, service and method from :path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc_status: Option<i32>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub grpc_message: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub grpc_service: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub grpc_method: String,
+
+    // GraphQL 请求的操作类型(query/mutation/subscription)和操作名
+    // operation type (query/mutation/subscription) and operation name of GraphQL request
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub graphql_operation_type: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub graphql_operation_name: String,
+
+    // 101 响应中 Upgrade 头部的值, 用于把流交给升级后的协议解析
+    // value of Upgrade header in 101 response, used to hand off the flow to the upgraded protocol
+    #[serde(skip)]
+    pub upgrade: String,
 }
 
 impl L7ProtocolInfoInterface for HttpInfo {
@@ -134,6 +167,19 @@ impl L7ProtocolInfoInterface for HttpInfo {
     fn is_req_resp_end(&self) -> (bool, bool) {
         (self.is_req_end, self.is_resp_end)
     }
+
+    fn upgrade_protocol(&self) -> Option<L7Protocol> {
+        if self.msg_type != LogMessageType::Response
+            || self.status_code != Some(HTTP_STATUS_SWITCHING_PROTOCOLS as i32)
+        {
+            return None;
+        }
+        if self.upgrade.eq_ignore_ascii_case("websocket") {
+            Some(L7Protocol::Websocket)
+        } else {
+            None
+        }
+    }
 }
 
 impl HttpInfo {
@@ -167,6 +213,18 @@ impl HttpInfo {
                 if self.req_content_length.is_none() {
                     self.req_content_length = other.req_content_length;
                 }
+                if self.req_content_type.is_empty() {
+                    self.req_content_type = other.req_content_type;
+                }
+                self.req_chunked |= other.req_chunked;
+                if self.grpc_service.is_empty() {
+                    self.grpc_service = other.grpc_service;
+                    self.grpc_method = other.grpc_method;
+                }
+                if self.graphql_operation_type.is_empty() {
+                    self.graphql_operation_type = other.graphql_operation_type;
+                    self.graphql_operation_name = other.graphql_operation_name;
+                }
             }
             // merge with response
             LogMessageType::Response => {
@@ -180,6 +238,12 @@ impl HttpInfo {
                 if self.resp_content_length.is_none() {
                     self.resp_content_length = other.resp_content_length;
                 }
+                if self.grpc_status.is_none() {
+                    self.grpc_status = other.grpc_status;
+                }
+                if self.grpc_message.is_empty() {
+                    self.grpc_message = other.grpc_message;
+                }
 
                 if other.is_resp_end {
                     self.is_resp_end = true;
@@ -207,7 +271,8 @@ impl HttpInfo {
         return self.host.is_empty()
             && self.method.is_empty()
             && self.path.is_empty()
-            && self.status_code == None;
+            && self.status_code == None
+            && self.grpc_status == None;
     }
 
     pub fn is_req_resp_end(&self) -> (bool, bool) {
@@ -261,6 +326,37 @@ impl HttpInfo {
         }
         None
     }
+
+    // grpc path: /packageName.Servicename/rcpMethodName
+    // service: packageName.Servicename, method: rcpMethodName
+    fn set_grpc_service_method(&mut self) {
+        if !self.is_grpc() || !self.grpc_service.is_empty() {
+            return;
+        }
+        let Some((service, method)) = self.path.strip_prefix('/').and_then(|p| p.split_once('/'))
+        else {
+            return;
+        };
+        if service.is_empty() || method.is_empty() || method.contains('/') {
+            return;
+        }
+        self.grpc_service = service.to_owned();
+        self.grpc_method = method.to_owned();
+    }
+
+    // etcd v3 基于 gRPC, 通过 :path 中的 service 和 method 识别 KV 操作类型
+    // etcd v3 is based on gRPC, KV operation type is recognized by service and method in :path
+    pub fn etcd_operation(&self) -> Option<&'static str> {
+        match (self.grpc_service.as_str(), self.grpc_method.as_str()) {
+            (ETCD_KV_SERVICE, "Range") => Some("Range"),
+            (ETCD_KV_SERVICE, "Put") => Some("Put"),
+            (ETCD_KV_SERVICE, "DeleteRange") => Some("DeleteRange"),
+            (ETCD_KV_SERVICE, "Txn") => Some("Txn"),
+            (ETCD_KV_SERVICE, "Compact") => Some("Compact"),
+            (ETCD_WATCH_SERVICE, "Watch") => Some("Watch"),
+            _ => None,
+        }
+    }
 }
 
 impl From<HttpInfo> for L7ProtocolSendLog {
@@ -273,19 +369,62 @@ impl From<HttpInfo> for L7ProtocolSendLog {
             None
         };
 
+        // grpc 优先使用 grpc-status 作为响应码
+        // grpc use grpc-status as response code first
+        let code = if is_grpc && f.grpc_status.is_some() {
+            f.grpc_status
+        } else {
+            f.status_code
+        };
+
+        let etcd_operation = f.etcd_operation();
+
         // grpc protocol special treatment
         let (req_type, resource, domain, endpoint) = if is_grpc {
             // server endpoint = req_type
             (
-                String::from("POST"), // grpc method always post, reference https://chromium.googlesource.com/external/github.com/grpc/grpc/+/HEAD/doc/PROTOCOL-HTTP2.md
+                // grpc method always post, reference https://chromium.googlesource.com/external/github.com/grpc/grpc/+/HEAD/doc/PROTOCOL-HTTP2.md
+                // etcd 使用操作类型区分
+                // etcd uses operation type instead
+                String::from(etcd_operation.unwrap_or("POST")),
                 service_name.clone().unwrap_or_default(),
                 f.host,
                 f.path,
             )
         } else {
-            (f.method, f.path, f.host, String::new())
+            (f.method, f.path, f.host, f.graphql_operation_name.clone())
         };
 
+        let mut attributes = vec![];
+        if !f.graphql_operation_type.is_empty() {
+            attributes.push(KeyVal {
+                key: "graphql_operation_type".to_string(),
+                val: f.graphql_operation_type,
+            });
+            attributes.push(KeyVal {
+                key: "graphql_operation_name".to_string(),
+                val: f.graphql_operation_name,
+            });
+        }
+        if !f.req_content_type.is_empty() {
+            attributes.push(KeyVal {
+                key: "request_content_type".to_string(),
+                val: f.req_content_type,
+            });
+        }
+        if f.req_chunked {
+            attributes.push(KeyVal {
+                key: "request_chunked".to_string(),
+                val: "true".to_string(),
+            });
+        }
+        if let Some(operation) = etcd_operation {
+            attributes.push(KeyVal {
+                key: "etcd_operation".to_string(),
+                val: operation.to_string(),
+            });
+        }
+
         L7ProtocolSendLog {
             req_len: f.req_content_length,
             resp_len: f.resp_content_length,
@@ -298,7 +437,8 @@ impl From<HttpInfo> for L7ProtocolSendLog {
             },
             resp: L7Response {
                 status: f.status,
-                code: f.status_code,
+                code,
+                exception: f.grpc_message,
                 ..Default::default()
             },
             trace_info: Some(TraceInfo {
@@ -313,6 +453,11 @@ impl From<HttpInfo> for L7ProtocolSendLog {
                 user_agent: f.user_agent,
                 referer: f.referer,
                 rpc_service: service_name,
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -328,9 +473,167 @@ pub struct HttpLog {
     parsed: bool,
     proto: L7Protocol,
 
+    // 按 flow_id 和方向维护的 HPACK 动态表, reset 时保留
+    // HPACK dynamic tables maintained by flow_id and direction, kept across reset
+    #[serde(skip)]
+    hpack: HpackState,
+
     perf_stats: Option<L7PerfStats>,
 }
 
+// 一条流两个方向的 HPACK 动态表, 下标为 PacketDirection
+// HPACK dynamic tables of both directions in flow, indexed by PacketDirection
+#[derive(Default)]
+struct HpackState {
+    flow_id: u64,
+    tables: [HpackTable; 2],
+}
+
+// 只有从连接的第一个头部块开始按序解码了该方向所有的头部块, 动态表才是准确的,
+// 否则 (中途加入, 丢包, 头部块被截断等) 和之前一样跳过动态表的索引
+// dynamic table is accurate only if all header blocks of the direction are decoded in order
+// since the first one of connection, otherwise (joining midway, packet loss, truncated header
+// block, etc.) indices of dynamic table are skipped as before
+#[derive(Default)]
+enum HpackTable {
+    // 还没有见到该方向的头部块
+    // no header block of the direction seen yet
+    #[default]
+    Empty,
+    Synced {
+        parser: h2pack::parser::Parser<'static>,
+        // 下一个帧头的 TCP 序列号, 用于跳过跨包的帧的剩余部分
+        // tcp sequence number of the next frame header, used to skip the rest of frame spanning packets
+        next_frame_seq: u32,
+    },
+    // 有头部块没有被解码, 动态表不再准确
+    // some header block is not decoded, dynamic table is no longer accurate
+    Lost,
+}
+
+// 解码状态无法复制, 复制的解析器从空的动态表开始
+// decoder state can not be cloned, cloned parser starts with empty dynamic tables
+impl Clone for HpackState {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for HpackState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let synced = self
+            .tables
+            .iter()
+            .map(|t| matches!(t, HpackTable::Synced { .. }))
+            .collect::<Vec<_>>();
+        f.debug_struct("HpackState")
+            .field("flow_id", &self.flow_id)
+            .field("synced", &synced)
+            .finish()
+    }
+}
+
+impl HpackState {
+    // 按序解码 payload 中所有的头部块以维护动态表, 返回 Headers 帧解码后的头部, 键为帧在 payload 中的偏移
+    // decode all header blocks in payload in order to maintain dynamic table,
+    // returns decoded headers of Headers frames, keyed by offset of frame in payload
+    fn decode(
+        &mut self,
+        payload: &[u8],
+        param: &ParseParam,
+    ) -> HashMap<usize, Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut blocks = HashMap::new();
+        // eBPF 的数据没有 TCP 序列号
+        // no tcp sequence number for ebpf payload
+        if param.ebpf_type != EbpfType::None {
+            return blocks;
+        }
+        if self.flow_id != param.flow_id {
+            *self = Self {
+                flow_id: param.flow_id,
+                ..Default::default()
+            };
+        }
+
+        let table = &mut self.tables[param.direction as usize];
+        let mut offset = match table {
+            HpackTable::Lost => return blocks,
+            HpackTable::Empty => 0,
+            HpackTable::Synced { next_frame_seq, .. } => {
+                let skip = next_frame_seq.wrapping_sub(param.tcp_seq);
+                // 中间有数据丢失
+                // data is missing in between
+                if (skip as i32) < 0 {
+                    *table = HpackTable::Lost;
+                    return blocks;
+                }
+                skip as usize
+            }
+        };
+        while offset + HTTPV2_FRAME_HEADER_LENGTH <= payload.len() {
+            if HttpLog::has_magic(&payload[offset..]) {
+                offset += HTTPV2_MAGIC_LENGTH;
+                continue;
+            }
+            let mut header = Httpv2Headers::default();
+            if header.parse_headers_frame(&payload[offset..]).is_err() {
+                if !matches!(table, HpackTable::Empty) {
+                    *table = HpackTable::Lost;
+                }
+                return blocks;
+            }
+            let frame_offset = offset;
+            offset += HTTPV2_FRAME_HEADER_LENGTH + header.frame_length as usize;
+            if header.frame_type != HTTPV2_FRAME_HEADERS_TYPE
+                && header.frame_type != HTTPV2_FRAME_PUSH_PROMISE_TYPE
+                && header.frame_type != HTTPV2_FRAME_CONTINUATION_TYPE
+            {
+                continue;
+            }
+
+            // 连接的第一个头部块在 stream 1 上
+            // the first header block of connection is on stream 1
+            if matches!(table, HpackTable::Empty) {
+                if header.frame_type != HTTPV2_FRAME_HEADERS_TYPE || header.stream_id != 1 {
+                    *table = HpackTable::Lost;
+                    return blocks;
+                }
+                *table = HpackTable::Synced {
+                    parser: h2pack::parser::Parser::with_dynamic_table(),
+                    next_frame_seq: 0,
+                };
+            }
+            let HpackTable::Synced { parser, .. } = table else {
+                unreachable!()
+            };
+            // 不支持 PUSH_PROMISE 和 CONTINUATION 帧, 头部块需要在一个包内
+            // PUSH_PROMISE and CONTINUATION frames are not supported, header block should be in one packet
+            let header_list = if header.frame_type == HTTPV2_FRAME_HEADERS_TYPE
+                && header.flags & FLAG_HEADERS_END_HEADERS != 0
+            {
+                header
+                    .header_block(&payload[frame_offset + HTTPV2_FRAME_HEADER_LENGTH..])
+                    .and_then(|block| parser.parse(block).ok())
+            } else {
+                None
+            };
+            match header_list {
+                Some(header_list) => {
+                    blocks.insert(frame_offset, header_list);
+                }
+                None => {
+                    *table = HpackTable::Lost;
+                    return blocks;
+                }
+            }
+        }
+        if let HpackTable::Synced { next_frame_seq, .. } = table {
+            *next_frame_seq = param.tcp_seq.wrapping_add(offset as u32);
+        }
+        blocks
+    }
+}
+
 impl L7ProtocolParserInterface for HttpLog {
     fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
         self.info.is_tls = param.is_tls();
@@ -452,6 +755,7 @@ impl L7ProtocolParserInterface for HttpLog {
             _ => unreachable!(),
         };
         new_log.perf_stats = self.perf_stats.take();
+        new_log.hpack = mem::take(&mut self.hpack);
         *self = new_log
     }
 
@@ -463,6 +767,10 @@ impl L7ProtocolParserInterface for HttpLog {
 impl HttpLog {
     pub const TRACE_ID: u8 = 0;
     pub const SPAN_ID: u8 = 1;
+    // trace id, span id 和 x-request-id 的最大长度, 超过时截断
+    // max length of trace id, span id and x-request-id, truncated if exceeded
+    const TRACE_ID_MAX_LEN: usize = 256;
+    const CONTENT_TYPE_MAX_LEN: usize = 128;
 
     pub fn new_v1() -> Self {
         Self {
@@ -531,6 +839,28 @@ impl HttpLog {
         }
     }
 
+    // 参考：https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+    fn set_grpc_status(&mut self, grpc_status: i32) {
+        match grpc_status {
+            GRPC_STATUS_OK => {}
+            GRPC_STATUS_CANCELLED
+            | GRPC_STATUS_INVALID_ARGUMENT
+            | GRPC_STATUS_NOT_FOUND
+            | GRPC_STATUS_ALREADY_EXISTS
+            | GRPC_STATUS_PERMISSION_DENIED
+            | GRPC_STATUS_FAILED_PRECONDITION
+            | GRPC_STATUS_OUT_OF_RANGE
+            | GRPC_STATUS_UNAUTHENTICATED => {
+                self.perf_stats.as_mut().unwrap().inc_req_err();
+                self.info.status = L7ResponseStatus::ClientError;
+            }
+            _ => {
+                self.perf_stats.as_mut().unwrap().inc_resp_err();
+                self.info.status = L7ResponseStatus::ServerError;
+            }
+        }
+    }
+
     // 解析由 ebpf probe 上报的自定义数据类型,小端编码,一次只带一个头.
     // +---------------------------------------------------------------+
     // |                          fd (32)                              |
@@ -599,10 +929,8 @@ impl HttpLog {
     }
 
     pub fn parse_http_v1(&mut self, payload: &[u8], param: &ParseParam) -> Result<()> {
-        let (direction, config) = (
-            param.direction,
-            &param.parse_config.as_ref().unwrap().l7_log_dynamic,
-        );
+        let parse_config = param.parse_config.as_ref().unwrap();
+        let (direction, config) = (param.direction, &parse_config.l7_log_dynamic);
         if !is_http_v1_payload(payload) {
             return Err(Error::HttpHeaderParseFailed);
         }
@@ -636,10 +964,16 @@ impl HttpLog {
             self.info.msg_type = LogMessageType::Request;
             self.perf_stats.as_mut().unwrap().inc_req();
         }
-        if param.perf_only {
+        // 101 响应需要解析 Upgrade 头部, 以便交给升级后的协议
+        // Upgrade header of 101 response is required to hand off to the upgraded protocol
+        if param.perf_only && self.info.status_code != Some(HTTP_STATUS_SWITCHING_PROTOCOLS as i32)
+        {
             return Ok(());
         }
         let mut content_length: Option<u32> = None;
+        let mut content_type = None;
+        let mut content_encoding = None;
+        let mut chunked = false;
         for body_line in headers {
             let col_index = body_line.find(':');
             if col_index.is_none() {
@@ -662,6 +996,29 @@ impl HttpLog {
             );
             if &lower_key == "content-length" {
                 content_length = Some(value.trim_start().parse::<u32>().unwrap_or_default());
+            } else if &lower_key == "content-type" {
+                content_type = Some(value.trim());
+            } else if &lower_key == "content-encoding" {
+                content_encoding = Some(value.trim());
+            } else if &lower_key == "transfer-encoding" {
+                // chunked 必须是最后一个编码
+                // chunked must be the last encoding
+                chunked = matches!(
+                    value.rsplit(',').next(),
+                    Some(v) if v.trim().eq_ignore_ascii_case("chunked")
+                );
+            }
+        }
+
+        if direction == PacketDirection::ClientToServer && self.info.method == "POST" {
+            if let (Some(content_type), Some(content_length)) = (content_type, content_length) {
+                self.parse_graphql(
+                    payload,
+                    content_type,
+                    content_length,
+                    content_encoding,
+                    parse_config.l7_log_http_decompress_max_size,
+                );
             }
         }
 
@@ -670,10 +1027,72 @@ impl HttpLog {
             self.info.resp_content_length = content_length;
         } else {
             self.info.req_content_length = content_length;
+            if let Some(content_type) = content_type {
+                let media_type = content_type.split(';').next().unwrap_or_default();
+                self.info.req_content_type = Self::truncate(
+                    media_type.trim().to_ascii_lowercase(),
+                    Self::CONTENT_TYPE_MAX_LEN,
+                );
+            }
+            self.info.req_chunked = chunked;
         }
         Ok(())
     }
 
+    // 只解析完整的请求体, 请求体被截断(超过 l7_log_packet_size)时不解析
+    // only complete body is parsed, body truncated by l7_log_packet_size is skipped
+    fn parse_graphql(
+        &mut self,
+        payload: &[u8],
+        content_type: &str,
+        content_length: u32,
+        content_encoding: Option<&str>,
+        decompress_max_size: usize,
+    ) {
+        let Some(offset) = payload.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return;
+        };
+        let body = &payload[offset + 4..];
+        if body.len() < content_length as usize {
+            return;
+        }
+        let body = &body[..content_length as usize];
+        // 压缩的请求体解码失败或解压后超过限制时不解析
+        // compressed body is skipped if decoding fails or the decompressed size exceeds the limit
+        let Some(body) = decode_body(body, content_encoding, decompress_max_size) else {
+            return;
+        };
+        let body = body.as_ref();
+
+        let content_type = content_type.to_ascii_lowercase();
+        let request = if content_type.starts_with("application/json") {
+            match serde_json::from_slice::<GraphqlRequest>(body) {
+                Ok(r) => r,
+                Err(_) => return,
+            }
+        } else if content_type.starts_with("application/graphql") {
+            GraphqlRequest {
+                query: str::from_utf8(body).ok().map(|q| q.to_owned()),
+                operation_name: None,
+            }
+        } else {
+            return;
+        };
+
+        let Some(query) = request.query.as_ref() else {
+            return;
+        };
+        if let Some((op_type, op_name)) =
+            parse_graphql_operation(query, request.operation_name.as_deref())
+        {
+            self.info.graphql_operation_type = op_type.to_owned();
+            self.info.graphql_operation_name = request
+                .operation_name
+                .clone()
+                .unwrap_or_else(|| op_name.to_owned());
+        }
+    }
+
     fn has_magic(payload: &[u8]) -> bool {
         if payload.len() < HTTPV2_MAGIC_LENGTH {
             return false;
@@ -691,12 +1110,14 @@ impl HttpLog {
         let mut is_httpv2 = false;
         let mut frame_payload = payload;
         let mut httpv2_header = Httpv2Headers::default();
+        let mut header_blocks = self.hpack.decode(payload, param);
 
         while frame_payload.len() > HTTPV2_FRAME_HEADER_LENGTH {
             if Self::has_magic(frame_payload) {
                 frame_payload = &frame_payload[HTTPV2_MAGIC_LENGTH..];
                 continue;
             }
+            let frame_offset = payload.len() - frame_payload.len();
             if httpv2_header.parse_headers_frame(frame_payload).is_err() {
                 // 当已经解析了Headers帧(该Headers帧未携带“Content-Length”)且发现该报文被截断时，无法进行后续解析，ContentLength为None
                 if header_frame_parsed {
@@ -735,13 +1156,20 @@ impl HttpLog {
                 let header_frame_payload =
                     &frame_payload[l_offset as usize..httpv2_header.frame_length as usize];
 
-                let mut parser = h2pack::parser::Parser::new();
-                let parse_rst = parser.parse(header_frame_payload);
+                // 动态表不可用时跳过动态表的索引
+                // indices of dynamic table are skipped when it is not available
+                let header_list = match header_blocks.remove(&frame_offset) {
+                    Some(header_list) => header_list,
+                    None => {
+                        let mut parser = h2pack::parser::Parser::new();
+                        let parse_rst = parser.parse(header_frame_payload);
 
-                if let Err(_) = parse_rst {
-                    return Err(Error::HttpHeaderParseFailed);
-                }
-                let header_list = parse_rst.unwrap();
+                        if let Err(_) = parse_rst {
+                            return Err(Error::HttpHeaderParseFailed);
+                        }
+                        parse_rst.unwrap()
+                    }
+                };
 
                 for (key, val) in header_list.iter() {
                     self.on_header(config, key, val, direction);
@@ -769,11 +1197,40 @@ impl HttpLog {
                 // 若未在Headers帧中携带，则去解析Headers帧后的Data帧的数据长度以进行“Content-Length”解析
                 // 如grpc-go源码中，在封装FrameHeader头时，不封装“Content-Length”，需要解析其关联的Data帧进行“Content-Length”解析
                 // 参考：https://github.com/grpc/grpc-go/blob/master/internal/transport/handler_server.go#L246
-                content_length = Some(httpv2_header.frame_length);
-                if httpv2_header.flags & FLAG_HEADERS_PADDED != 0 {
-                    if content_length.unwrap_or_default() > frame_payload[0] as u32 {
-                        content_length =
-                            Some(content_length.unwrap_or_default() - frame_payload[0] as u32);
+                if content_length.is_none() {
+                    content_length = Some(httpv2_header.frame_length);
+                    if httpv2_header.flags & FLAG_HEADERS_PADDED != 0 {
+                        if content_length.unwrap_or_default() > frame_payload[0] as u32 {
+                            content_length =
+                                Some(content_length.unwrap_or_default() - frame_payload[0] as u32);
+                        }
+                    }
+                }
+                // grpc 的 grpc-status 在 Data 帧之后的 Headers 帧(trailers)中, 需要继续解析
+                // grpc-status is carried by the Headers frame (trailers) after Data frames
+                if !self.info.is_grpc() {
+                    break;
+                }
+            } else if header_frame_parsed
+                && self.info.is_grpc()
+                && httpv2_header.frame_type == HTTPV2_FRAME_HEADERS_TYPE
+                && httpv2_header.flags & FLAG_HEADERS_END_STREAM != 0
+            {
+                let header_list = header_blocks.remove(&frame_offset).or_else(|| {
+                    if httpv2_header.flags & FLAG_HEADERS_PADDED == 0
+                        && httpv2_header.frame_length as usize <= frame_payload.len()
+                    {
+                        let mut parser = h2pack::parser::Parser::new();
+                        parser
+                            .parse(&frame_payload[..httpv2_header.frame_length as usize])
+                            .ok()
+                    } else {
+                        None
+                    }
+                });
+                if let Some(header_list) = header_list {
+                    for (key, val) in header_list.iter() {
+                        self.on_header(config, key, val, direction);
                     }
                 }
                 break;
@@ -808,10 +1265,12 @@ impl HttpLog {
                     if code < HTTP_STATUS_CODE_MIN || code > HTTP_STATUS_CODE_MAX {
                         return Err(Error::HttpHeaderParseFailed);
                     }
-                } else {
+                    self.perf_stats.as_mut().unwrap().inc_resp();
+                } else if self.info.grpc_status.is_none() {
+                    // 只有 grpc trailers 的 Headers 帧没有 :status
+                    // Headers frame with only grpc trailers has no :status
                     return Err(Error::HttpHeaderParseFailed);
                 }
-                self.perf_stats.as_mut().unwrap().inc_resp();
                 self.info.resp_content_length = content_length;
             }
             self.info.version = String::from("2");
@@ -850,16 +1309,29 @@ impl HttpLog {
                 self.set_status(code);
             }
             "host" | ":authority" => self.info.host = String::from_utf8_lossy(val).into_owned(),
-            ":path" => self.info.path = String::from_utf8_lossy(val).into_owned(),
+            ":path" => {
+                self.info.path = String::from_utf8_lossy(val).into_owned();
+                self.info.set_grpc_service_method();
+            }
             "content-type" => {
                 // change to grpc protocol
                 if val.starts_with(b"application/grpc") {
                     self.proto = L7Protocol::Grpc;
                     self.info.proto = L7Protocol::Grpc;
+                    self.info.set_grpc_service_method();
                 }
             }
+            "grpc-status" => {
+                self.info.msg_type = LogMessageType::Response;
+                if let Ok(code) = str::from_utf8(val).unwrap_or_default().parse::<i32>() {
+                    self.info.grpc_status = Some(code);
+                    self.set_grpc_status(code);
+                }
+            }
+            "grpc-message" => self.info.grpc_message = String::from_utf8_lossy(val).into_owned(),
             "user-agent" => self.info.user_agent = Some(String::from_utf8_lossy(val).into_owned()),
             "referer" => self.info.referer = Some(String::from_utf8_lossy(val).into_owned()),
+            "upgrade" => self.info.upgrade = String::from_utf8_lossy(val).into_owned(),
             _ => {}
         }
 
@@ -872,24 +1344,37 @@ impl HttpLog {
             return;
         };
 
-        if config.is_trace_id(key) {
+        // 多个头部匹配时使用第一个
+        // the first header wins if multiple headers match
+        if self.info.trace_id.is_empty() && config.is_trace_id(key) {
             if let Some(id) = Self::decode_id(val, key, Self::TRACE_ID) {
-                self.info.trace_id = id;
+                self.info.trace_id = Self::truncate(id, Self::TRACE_ID_MAX_LEN);
             }
         }
-        if config.is_span_id(key) {
+        if self.info.span_id.is_empty() && config.is_span_id(key) {
             if let Some(id) = Self::decode_id(val, key, Self::SPAN_ID) {
-                self.info.span_id = id;
+                self.info.span_id = Self::truncate(id, Self::TRACE_ID_MAX_LEN);
             }
         }
-        if key == &config.x_request_id {
-            self.info.x_request_id = val.to_owned();
+        if self.info.x_request_id.is_empty() && key == &config.x_request_id {
+            self.info.x_request_id = Self::truncate(val.to_owned(), Self::TRACE_ID_MAX_LEN);
         }
         if direction == PacketDirection::ClientToServer && key == &config.proxy_client {
             self.info.client_ip = val.to_owned();
         }
     }
 
+    fn truncate(mut value: String, max_len: usize) -> String {
+        if value.len() > max_len {
+            let mut end = max_len;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            value.truncate(end);
+        }
+        value
+    }
+
     // uber-trace-id: TRACEID:SPANID:PARENTSPANID:FLAGS
     // 使用':'分隔，第一个字段为TRACEID，第三个字段为SPANID
     fn decode_uber_id(value: &str, id_type: u8) -> Option<String> {
@@ -1000,6 +1485,28 @@ impl Httpv2Headers {
 
         Ok(())
     }
+
+    // Headers 帧去掉填充和优先级后的头部块, frame_payload 从帧头之后开始
+    // header block of Headers frame without padding and priority, frame_payload starts after frame header
+    fn header_block<'a>(&self, frame_payload: &'a [u8]) -> Option<&'a [u8]> {
+        let mut end = self.frame_length as usize;
+        if end > frame_payload.len() {
+            return None;
+        }
+        let mut start = 0;
+        if self.flags & FLAG_HEADERS_PADDED != 0 {
+            let padding = frame_payload[0] as usize;
+            if end <= padding {
+                return None;
+            }
+            end -= padding;
+            start += 1;
+        }
+        if self.flags & FLAG_HEADERS_PRIORITY != 0 {
+            start += 5;
+        }
+        frame_payload.get(start..end)
+    }
 }
 
 const HTTP_METHODS: [&'static str; 15] = [
@@ -1171,6 +1678,173 @@ pub fn parse_v1_headers(payload: &[u8]) -> V1HeaderIterator<'_> {
     V1HeaderIterator(payload)
 }
 
+/*
+    按 Content-Encoding 解码报文体, 支持 gzip 和 deflate (zlib 格式或原始 deflate 数据).
+    max_size 为 0 (不解码), 编码不支持, 数据损坏或解压后超过 max_size 时返回 None,
+    最多解压 max_size + 1 字节, 避免解压炸弹占用过多内存.
+
+    decode body according to Content-Encoding, gzip and deflate (zlib format or raw deflate data)
+    are supported. None is returned if max_size is 0 (decoding disabled), the encoding is not
+    supported, data is corrupted or the decompressed size exceeds max_size. at most max_size + 1
+    bytes are decompressed so that decompression bombs do not take up too much memory.
+*/
+pub fn decode_body<'a>(
+    body: &'a [u8],
+    content_encoding: Option<&str>,
+    max_size: usize,
+) -> Option<Cow<'a, [u8]>> {
+    let encoding = match content_encoding {
+        Some(e) if !e.is_empty() && !e.eq_ignore_ascii_case("identity") => e,
+        _ => return Some(Cow::Borrowed(body)),
+    };
+    if max_size == 0 {
+        return None;
+    }
+    let limit = max_size as u64 + 1;
+    let mut decoded = vec![];
+    let result = if encoding.eq_ignore_ascii_case("gzip") || encoding.eq_ignore_ascii_case("x-gzip")
+    {
+        GzDecoder::new(body).take(limit).read_to_end(&mut decoded)
+    } else if encoding.eq_ignore_ascii_case("deflate") {
+        // 部分服务端发送不带 zlib 头部的原始 deflate 数据
+        // some servers send raw deflate data without zlib header
+        ZlibDecoder::new(body)
+            .take(limit)
+            .read_to_end(&mut decoded)
+            .or_else(|_| {
+                decoded.clear();
+                DeflateDecoder::new(body)
+                    .take(limit)
+                    .read_to_end(&mut decoded)
+            })
+    } else {
+        return None;
+    };
+    match result {
+        Ok(size) if size <= max_size => Some(Cow::Owned(decoded)),
+        _ => None,
+    }
+}
+
+// GraphQL over HTTP 的 JSON 请求体, 其余字段(如 variables)忽略
+// JSON body of GraphQL over HTTP, other fields (such as variables) are ignored
+#[derive(Deserialize)]
+struct GraphqlRequest {
+    query: Option<String>,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+}
+
+/*
+    从 GraphQL 文档中找出要执行的操作, 返回操作类型和操作名(匿名操作为空).
+    文档中有多个操作时返回与 operation_name 同名的操作, 否则返回第一个操作.
+    片段定义会被跳过, 简写形式 `{ ... }` 视为 query.
+
+    find the operation to execute in GraphQL document, return operation type and name (empty if anonymous).
+    if there are multiple operations, the one named operation_name is returned, otherwise the first one.
+    fragment definitions are skipped, shorthand form `{ ... }` is treated as query.
+*/
+pub fn parse_graphql_operation<'a>(
+    document: &'a str,
+    operation_name: Option<&str>,
+) -> Option<(&'static str, &'a str)> {
+    let doc = document.as_bytes();
+    let (mut depth, mut paren_depth) = (0usize, 0usize);
+    // 当前定义的关键字是否已出现, 以及关键字对应的操作 (片段定义为 None)
+    // whether keyword of current definition is seen, and the operation of keyword (None for fragment)
+    let mut in_definition = false;
+    let mut pending: Option<(&'static str, &'a str)> = None;
+    let mut expect_name = false;
+    let mut first = None;
+
+    let mut i = 0;
+    while i < doc.len() {
+        match doc[i] {
+            b'#' => {
+                while i < doc.len() && doc[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'"' => {
+                expect_name = false;
+                if doc[i..].starts_with(b"\"\"\"") {
+                    i += 3;
+                    while i < doc.len() && !doc[i..].starts_with(b"\"\"\"") {
+                        i += if doc[i..].starts_with(b"\\\"\"\"") {
+                            4
+                        } else {
+                            1
+                        };
+                    }
+                    i += 3;
+                } else {
+                    i += 1;
+                    while i < doc.len() && doc[i] != b'"' && doc[i] != b'\n' {
+                        i += if doc[i] == b'\\' { 2 } else { 1 };
+                    }
+                    i += 1;
+                }
+                continue;
+            }
+            b'{' => {
+                if depth == 0 && paren_depth == 0 {
+                    let op = if in_definition {
+                        pending.take()
+                    } else {
+                        Some(("query", ""))
+                    };
+                    in_definition = false;
+                    if let Some(op) = op {
+                        if operation_name.is_some() && operation_name == Some(op.1) {
+                            return Some(op);
+                        }
+                        if first.is_none() {
+                            first = Some(op);
+                        }
+                    }
+                }
+                depth += 1;
+            }
+            b'}' => depth = depth.saturating_sub(1),
+            b'(' => paren_depth += 1,
+            b')' => paren_depth = paren_depth.saturating_sub(1),
+            c if c == b'_' || c.is_ascii_alphabetic() => {
+                let start = i;
+                while i < doc.len() && (doc[i] == b'_' || doc[i].is_ascii_alphanumeric()) {
+                    i += 1;
+                }
+                if depth > 0 || paren_depth > 0 {
+                    continue;
+                }
+                // 前面已经确认是字母/下划线, 一定是合法的 utf8
+                // all bytes are checked to be alphanumeric or underscore, so it is valid utf8
+                let name = &document[start..i];
+                if !in_definition {
+                    in_definition = true;
+                    pending = match name {
+                        "query" => Some(("query", "")),
+                        "mutation" => Some(("mutation", "")),
+                        "subscription" => Some(("subscription", "")),
+                        _ => None,
+                    };
+                    expect_name = pending.is_some();
+                } else if expect_name {
+                    if let Some(op) = pending.as_mut() {
+                        op.1 = name;
+                    }
+                    expect_name = false;
+                }
+                continue;
+            }
+            c if c.is_ascii_whitespace() || c == b',' => (),
+            _ => expect_name = false,
+        }
+        i += 1;
+    }
+    first
+}
+
 #[cfg(test)]
 mod tests {
     use crate::common::l7_protocol_log::L7PerfCache;
@@ -1211,7 +1885,9 @@ mod tests {
         let parse_config = &LogParserConfig {
             l7_log_collect_nps_threshold: 10,
             l7_log_session_aggr_timeout: Duration::from_secs(10),
+            l7_log_session_slot_width: Duration::from_secs(10),
             l7_log_dynamic: config,
+            ..Default::default()
         };
         for packet in packets.iter_mut() {
             packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
@@ -1257,6 +1933,7 @@ mod tests {
             ("h2c_ascii.pcap", "h2c_ascii.result"),
             ("httpv2-stream-id.pcap", "httpv2-stream-id.result"),
             ("istio-tcp-frag.pcap", "istio-tcp-frag.result"),
+            ("graphql.pcap", "graphql.result"),
         ];
         for item in files.iter() {
             let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
@@ -1275,6 +1952,446 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_websocket_upgrade() {
+        let capture = Capture::load_pcap(
+            Path::new("resources/test/flow_generator/websocket/websocket.pcap"),
+            None,
+        );
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        packets[0].lookup_key.direction = PacketDirection::ClientToServer;
+        packets[1].lookup_key.direction = PacketDirection::ServerToClient;
+        let parse_config = &LogParserConfig::default();
+
+        let mut http = HttpLog::new_v1();
+        let param = &ParseParam::from((&packets[0], log_cache.clone(), false, parse_config));
+        let req = http
+            .parse_payload(packets[0].get_l4_payload().unwrap(), param)
+            .unwrap()
+            .swap_remove(0);
+        assert_eq!(req.upgrade_protocol(), None);
+        http.reset();
+
+        // perf_only 时也需要识别协议升级
+        // upgrade is also recognized when perf_only
+        let param = &ParseParam::from((&packets[1], log_cache.clone(), true, parse_config));
+        let resp = http
+            .parse_payload(packets[1].get_l4_payload().unwrap(), param)
+            .unwrap()
+            .swap_remove(0);
+        assert_eq!(http.info.status_code, Some(101));
+        assert_eq!(resp.upgrade_protocol(), Some(L7Protocol::Websocket));
+    }
+
+    #[test]
+    fn check_trace_headers() {
+        let config = L7LogDynamicConfig::new(
+            "".to_owned(),
+            "X-Request-ID".to_owned(),
+            vec![TraceType::TraceParent, TraceType::XB3, TraceType::Uber],
+            vec![TraceType::TraceParent, TraceType::XB3Span, TraceType::Uber],
+        );
+        let long_id = "a".repeat(300);
+        let headers = [
+            ("x-b3-traceid", "80f198ee56343ba864fe8b2a57d3eff7"),
+            ("x-b3-spanid", "e457b5a2e4d86bd1"),
+            (
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            ),
+            ("uber-trace-id", "5a2e4d86bd1:e457b5a2:0:1"),
+            ("x-request-id", &long_id),
+            ("x-request-id", "req-2"),
+        ];
+        let mut http = HttpLog::new_v1();
+        for (key, val) in headers {
+            http.on_header(
+                &config,
+                key.as_bytes(),
+                val.as_bytes(),
+                PacketDirection::ClientToServer,
+            );
+        }
+        // 多个头部匹配时使用第一个, 值超过最大长度时截断
+        // the first matching header wins, values exceeding max length are truncated
+        assert_eq!(http.info.trace_id, "80f198ee56343ba864fe8b2a57d3eff7");
+        assert_eq!(http.info.span_id, "e457b5a2e4d86bd1");
+        assert_eq!(http.info.x_request_id, long_id[..HttpLog::TRACE_ID_MAX_LEN]);
+
+        let mut http = HttpLog::new_v1();
+        let traceparent = format!("00-x{}-b7ad6b7169203331-01", "é".repeat(200));
+        http.on_header(
+            &config,
+            b"traceparent",
+            traceparent.as_bytes(),
+            PacketDirection::ClientToServer,
+        );
+        assert_eq!(http.info.trace_id, format!("x{}", "é".repeat(127)));
+        assert_eq!(http.info.span_id, "b7ad6b7169203331");
+    }
+
+    #[test]
+    fn check_request_content_type() {
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let parse_config = &LogParserConfig::default();
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.direction = PacketDirection::ClientToServer;
+        let long_type = format!("application/{}", "x".repeat(200));
+        let cases = [
+            (
+                "POST /upload HTTP/1.1\r\nContent-Type: Multipart/Form-Data; boundary=abc\r\nTransfer-Encoding: gzip, chunked\r\n\r\n".to_owned(),
+                ("multipart/form-data", true, None),
+            ),
+            (
+                "POST /api HTTP/1.1\r\nContent-Length: 2\r\nContent-Type: application/json\r\n\r\n{}".to_owned(),
+                ("application/json", false, Some(2)),
+            ),
+            // chunked 不是最后一个编码时不是 chunked 请求
+            // not a chunked request if chunked is not the last encoding
+            (
+                "POST /api HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n".to_owned(),
+                ("", false, None),
+            ),
+            (
+                format!("PUT /file HTTP/1.1\r\nContent-Type: {}\r\n\r\n", long_type),
+                (&long_type[..HttpLog::CONTENT_TYPE_MAX_LEN], false, None),
+            ),
+        ];
+        for (payload, (content_type, chunked, content_length)) in cases {
+            let param = &ParseParam::from((&packet, log_cache.clone(), false, parse_config));
+            let mut http = HttpLog::new_v1();
+            http.perf_stats = Some(L7PerfStats::default());
+            http.parse_http_v1(payload.as_bytes(), param).unwrap();
+            assert_eq!(http.info.req_content_type, content_type, "{}", payload);
+            assert_eq!(http.info.req_chunked, chunked, "{}", payload);
+            assert_eq!(http.info.req_content_length, content_length, "{}", payload);
+        }
+    }
+
+    #[test]
+    fn check_decode_body() {
+        use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+        use std::io::Write;
+
+        let data = br#"{"query":"mutation AddUser { add }"}"#;
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(data).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+        zlib.write_all(data).unwrap();
+        let zlib = zlib.finish().unwrap();
+        let mut raw = DeflateEncoder::new(vec![], Compression::default());
+        raw.write_all(data).unwrap();
+        let raw = raw.finish().unwrap();
+
+        let max_size = data.len();
+        for (body, encoding) in [
+            (&data[..], None),
+            (&data[..], Some("Identity")),
+            (&gzip[..], Some("GZIP")),
+            (&gzip[..], Some("x-gzip")),
+            (&zlib[..], Some("deflate")),
+            (&raw[..], Some("deflate")),
+        ] {
+            assert_eq!(
+                decode_body(body, encoding, max_size).as_deref(),
+                Some(&data[..]),
+                "{:?}",
+                encoding
+            );
+        }
+        // 解压后超过限制, 未开启解码, 不支持的编码和损坏的数据
+        // decompressed size exceeds the limit, decoding disabled, unsupported encoding and corrupted data
+        assert_eq!(decode_body(&gzip, Some("gzip"), max_size - 1), None);
+        assert_eq!(decode_body(&gzip, Some("gzip"), 0), None);
+        assert_eq!(decode_body(&gzip, Some("br"), max_size), None);
+        assert_eq!(
+            decode_body(&gzip[..gzip.len() / 2], Some("gzip"), max_size),
+            None
+        );
+        assert_eq!(decode_body(&[0xff; 4], Some("deflate"), max_size), None);
+        // 未压缩的报文体不受限制
+        // uncompressed body is not limited
+        assert_eq!(decode_body(data, None, 0).as_deref(), Some(&data[..]));
+    }
+
+    #[test]
+    fn check_graphql_compressed_body() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(br#"{"query":"mutation AddUser { add }"}"#)
+            .unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut payload = format!(
+            "POST /graphql HTTP/1.1\r\nContent-Type: application/json\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gzip.len()
+        )
+        .into_bytes();
+        payload.extend_from_slice(&gzip);
+
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.direction = PacketDirection::ClientToServer;
+        // 默认不解码, 解码后的请求体超过 16 字节时不解析
+        // not decoded by default, decoded body larger than 16 bytes is skipped
+        for (max_size, op_type) in [(0, ""), (16, ""), (1024, "mutation")] {
+            let parse_config = &LogParserConfig {
+                l7_log_http_decompress_max_size: max_size,
+                ..Default::default()
+            };
+            let param = &ParseParam::from((&packet, log_cache.clone(), false, parse_config));
+            let mut http = HttpLog::new_v1();
+            http.perf_stats = Some(L7PerfStats::default());
+            http.parse_http_v1(&payload, param).unwrap();
+            assert_eq!(http.info.graphql_operation_type, op_type, "{}", max_size);
+            if !op_type.is_empty() {
+                assert_eq!(http.info.graphql_operation_name, "AddUser");
+            }
+        }
+    }
+
+    #[test]
+    fn check_graphql_operation() {
+        let cases = [
+            ("{ me { id } }", None, Some(("query", ""))),
+            ("query { me }", None, Some(("query", ""))),
+            (
+                "subscription OnEvent @live { event }",
+                None,
+                Some(("subscription", "OnEvent")),
+            ),
+            (
+                "query A { a } mutation B { b }",
+                Some("B"),
+                Some(("mutation", "B")),
+            ),
+            (
+                "query A { a } mutation B { b }",
+                Some("C"),
+                Some(("query", "A")),
+            ),
+            (
+                "fragment F on User { id } query Q($s: String = \"{\") { ...F }",
+                None,
+                Some(("query", "Q")),
+            ),
+            (
+                "# mutation X { x }\nquery Q(\"\"\" mutation \\\"\"\" {\"\"\" $a: Int) { q }",
+                None,
+                Some(("query", "Q")),
+            ),
+            ("fragment F on User { id }", None, None),
+            ("", None, None),
+        ];
+        for (document, operation_name, expected) in cases {
+            assert_eq!(
+                parse_graphql_operation(document, operation_name),
+                expected,
+                "{}",
+                document
+            );
+        }
+    }
+
+    #[test]
+    fn check_grpc() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("grpc-status.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        packets[0].lookup_key.direction = PacketDirection::ClientToServer;
+        packets[1].lookup_key.direction = PacketDirection::ServerToClient;
+        let parse_config = &LogParserConfig::default();
+
+        let mut http = HttpLog::new_v2(false);
+        let param = &ParseParam::from((&packets[0], log_cache.clone(), false, parse_config));
+        let payload = packets[0].get_l4_payload().unwrap();
+        assert!(http.check_payload(payload, param));
+        let mut req = http.parse_payload(payload, param).unwrap().swap_remove(0);
+        assert_eq!(http.protocol(), L7Protocol::Grpc);
+        assert_eq!(http.info.grpc_service, "helloworld.Greeter");
+        assert_eq!(http.info.grpc_method, "SayHello");
+        assert_eq!(http.info.grpc_status, None);
+        http.reset();
+
+        let param = &ParseParam::from((&packets[1], log_cache.clone(), false, parse_config));
+        let payload = packets[1].get_l4_payload().unwrap();
+        assert!(http.check_payload(payload, param));
+        let resp = http.parse_payload(payload, param).unwrap().swap_remove(0);
+        assert_eq!(http.info.status_code, Some(200));
+        assert_eq!(http.info.grpc_status, Some(5));
+        assert_eq!(http.info.grpc_message, "user not found");
+        assert_eq!(http.info.status, L7ResponseStatus::ClientError);
+
+        req.merge_log(resp).unwrap();
+        let L7ProtocolInfo::HttpInfo(info) = req else {
+            unreachable!()
+        };
+        assert_eq!(info.grpc_service, "helloworld.Greeter");
+        assert_eq!(info.grpc_status, Some(5));
+        let log = L7ProtocolSendLog::from(info);
+        assert_eq!(log.resp.code, Some(5));
+        assert_eq!(log.resp.status, L7ResponseStatus::ClientError);
+        assert_eq!(log.resp.exception, "user not found");
+        assert_eq!(log.req.resource, "helloworld.Greeter");
+    }
+
+    #[test]
+    fn check_hpack_dynamic_table() {
+        let capture =
+            Capture::load_pcap(Path::new(FILE_DIR).join("httpv2-dynamic-table.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        let parse_config = &LogParserConfig::default();
+
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut http = HttpLog::new_v2(false);
+        let mut output = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let param = &ParseParam::from((&*packet, log_cache.clone(), false, parse_config));
+            let payload = packet.get_l4_payload().unwrap();
+            http.parse_payload(payload, param).unwrap();
+            output.push((
+                http.info.stream_id,
+                http.info.method.clone(),
+                http.info.host.clone(),
+                http.info.path.clone(),
+                http.info.status_code,
+            ));
+            http.reset();
+        }
+        // 后续请求引用第一个请求写入动态表的 :authority 和 :path, 大小更新淘汰了旧的表项
+        // following requests refer to :authority and :path indexed by the first request,
+        // and size update evicts old entries
+        let expected = [
+            (1, "GET", "example.com", "/api/users"),
+            (3, "GET", "example.com", "/api/users"),
+            (5, "POST", "example.com", "/api/orders"),
+            (7, "GET", "example.com", "/api/orders"),
+        ];
+        for (i, (stream_id, method, host, path)) in expected.into_iter().enumerate() {
+            assert_eq!(
+                output[i * 2],
+                (
+                    Some(stream_id),
+                    method.to_owned(),
+                    host.to_owned(),
+                    path.to_owned(),
+                    None
+                )
+            );
+            assert_eq!(
+                output[i * 2 + 1],
+                (
+                    Some(stream_id),
+                    "".to_owned(),
+                    "".to_owned(),
+                    "".to_owned(),
+                    Some(200)
+                )
+            );
+        }
+
+        // 没有见到连接的第一个头部块时跳过动态表的索引
+        // indices of dynamic table are skipped without the first header block of connection
+        let mut http = HttpLog::new_v2(false);
+        let param = &ParseParam::from((&packets[2], log_cache.clone(), false, parse_config));
+        http.parse_payload(packets[2].get_l4_payload().unwrap(), param)
+            .unwrap();
+        assert_eq!(http.info.method, "GET");
+        assert_eq!(http.info.host, "");
+        assert_eq!(http.info.path, "");
+    }
+
+    #[test]
+    fn check_etcd_operation() {
+        let mut info = HttpInfo {
+            proto: L7Protocol::Grpc,
+            path: "/etcdserverpb.KV/Range".to_owned(),
+            ..Default::default()
+        };
+        info.set_grpc_service_method();
+        assert_eq!(info.etcd_operation(), Some("Range"));
+        let log = L7ProtocolSendLog::from(info);
+        assert_eq!(log.req.req_type, "Range");
+        assert_eq!(log.req.resource, "etcdserverpb.KV");
+        let attributes = log.ext_info.unwrap().attributes.unwrap();
+        assert_eq!(attributes[0].key, "etcd_operation");
+        assert_eq!(attributes[0].val, "Range");
+
+        let mut info = HttpInfo {
+            proto: L7Protocol::Grpc,
+            path: "/etcdserverpb.Watch/Watch".to_owned(),
+            ..Default::default()
+        };
+        info.set_grpc_service_method();
+        assert_eq!(info.etcd_operation(), Some("Watch"));
+
+        // 非 etcd 的 gRPC 请求
+        // gRPC request other than etcd
+        let mut info = HttpInfo {
+            proto: L7Protocol::Grpc,
+            path: "/helloworld.Greeter/SayHello".to_owned(),
+            ..Default::default()
+        };
+        info.set_grpc_service_method();
+        assert_eq!(info.etcd_operation(), None);
+        let log = L7ProtocolSendLog::from(info);
+        assert_eq!(log.req.req_type, "POST");
+    }
+
+    #[test]
+    fn check_etcd_pcap() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("etcd.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        assert_eq!(packets.len(), 4);
+        let parse_config = &LogParserConfig::default();
+
+        // Put 和 Range 的请求和响应依次出现在同一条流中
+        // requests and responses of Put and Range appear in turn in the same flow
+        let mut http = HttpLog::new_v2(false);
+        let mut infos = vec![];
+        for (i, packet) in packets.iter_mut().enumerate() {
+            packet.lookup_key.direction = if i % 2 == 0 {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let param = &ParseParam::from((&*packet, log_cache.clone(), false, parse_config));
+            let payload = packet.get_l4_payload().unwrap();
+            assert!(http.check_payload(payload, param));
+            infos.push(http.parse_payload(payload, param).unwrap().swap_remove(0));
+            http.reset();
+        }
+
+        let mut infos = infos.into_iter();
+        for operation in ["Put", "Range"] {
+            let (mut req, resp) = (infos.next().unwrap(), infos.next().unwrap());
+            req.merge_log(resp).unwrap();
+            let L7ProtocolInfo::HttpInfo(info) = req else {
+                unreachable!()
+            };
+            assert_eq!(info.proto, L7Protocol::Grpc);
+            assert_eq!(info.etcd_operation(), Some(operation));
+            assert_eq!(info.grpc_status, Some(0));
+            let log = L7ProtocolSendLog::from(info);
+            assert_eq!(log.req.req_type, operation);
+            assert_eq!(log.req.resource, "etcdserverpb.KV");
+            assert_eq!(log.resp.code, Some(0));
+            assert_eq!(log.resp.status, L7ResponseStatus::Ok);
+        }
+    }
+
     #[test]
     fn test_go_uprobe() {
         #[derive(Debug)]
@@ -1466,11 +2583,7 @@ mod tests {
 
         let first_dst_port = packets[0].lookup_key.dst_port;
 
-        let config = LogParserConfig {
-            l7_log_collect_nps_threshold: 0,
-            l7_log_session_aggr_timeout: Duration::ZERO,
-            l7_log_dynamic: L7LogDynamicConfig::default(),
-        };
+        let config = LogParserConfig::default();
 
         for packet in packets.iter_mut() {
             if packet.lookup_key.dst_port == first_dst_port {
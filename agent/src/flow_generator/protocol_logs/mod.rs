@@ -14,30 +14,55 @@
  * limitations under the License.
  */
 
+pub(crate) mod coap;
 pub mod consts;
 pub(crate) mod dns;
+pub(crate) mod framing;
+pub(crate) mod ftp;
 pub(crate) mod http;
 pub(crate) mod mq;
 mod parser;
 pub mod pb_adapter;
+pub(crate) mod plugin;
 pub(crate) mod rpc;
+pub(crate) mod smtp;
 pub(crate) mod sql;
+pub(crate) mod statsd;
+pub(crate) mod tls;
+pub(crate) mod unknown;
+pub(crate) mod websocket;
+pub(crate) mod zookeeper;
 pub use self::http::{
     check_http_method, get_http_request_info, get_http_request_version, get_http_resp_info,
     is_http_v1_payload, parse_v1_headers, HttpInfo, HttpLog, Httpv2Headers,
 };
 use self::pb_adapter::L7ProtocolSendLog;
+pub use coap::{CoapInfo, CoapLog};
 pub use dns::{DnsInfo, DnsLog};
-pub use mq::{mqtt, KafkaInfo, KafkaLog, MqttInfo, MqttLog};
-pub use parser::{MetaAppProto, SessionAggregator};
+pub use ftp::{FtpInfo, FtpLog};
+pub use mq::{
+    mqtt, KafkaInfo, KafkaLog, MqttInfo, MqttLog, NatsInfo, NatsLog, RocketMqInfo, RocketMqLog,
+};
+pub use parser::{
+    decode_session_key, session_key, MetaAppProto, SessionAggrCounter, SessionAggrStats,
+    SessionAggregator,
+};
+pub use plugin::CustomInfo;
 pub use rpc::{
     decode_new_rpc_trace_context, decode_new_rpc_trace_context_with_type, get_protobuf_rpc_parser,
     DubboHeader, DubboInfo, DubboLog, ProtobufRpcInfo, ProtobufRpcWrapLog, SofaRpcInfo, SofaRpcLog,
-    SOFA_NEW_RPC_TRACE_CTX_KEY,
+    ThriftInfo, ThriftLog, SOFA_NEW_RPC_TRACE_CTX_KEY,
 };
+pub use smtp::{SmtpInfo, SmtpLog};
 pub use sql::{
-    decode, MysqlHeader, MysqlInfo, MysqlLog, PostgreInfo, PostgresqlLog, RedisInfo, RedisLog,
+    _fuzz_mysql_parse, decode, ClickHouseInfo, ClickHouseLog, MssqlInfo, MssqlLog, MysqlHeader,
+    MysqlInfo, MysqlLog, OracleInfo, OracleLog, PostgreInfo, PostgresqlLog, RedisInfo, RedisLog,
 };
+pub use statsd::{StatsdInfo, StatsdLog};
+pub use tls::{TlsInfo, TlsLog};
+pub use unknown::{UnknownInfo, UnknownLog};
+pub use websocket::{WebsocketInfo, WebsocketLog};
+pub use zookeeper::{ZookeeperInfo, ZookeeperLog};
 
 use std::{
     fmt,
@@ -47,7 +72,7 @@ use std::{
 };
 
 use prost::Message;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
 
 use crate::common::l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface};
 use crate::{
@@ -68,7 +93,7 @@ use public::utils::net::MacAddr;
 const NANOS_PER_MICRO: u64 = 1000;
 const FLOW_LOG_VERSION: u32 = 20220128;
 
-#[derive(Serialize, Debug, PartialEq, Copy, Clone, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Copy, Clone, Eq)]
 #[repr(u8)]
 pub enum L7ResponseStatus {
     Ok,
@@ -84,7 +109,7 @@ impl Default for L7ResponseStatus {
     }
 }
 
-#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 #[repr(u8)]
 pub enum LogMessageType {
     Request,
@@ -236,6 +261,18 @@ where
     serializer.serialize_str(&d.to_string())
 }
 
+// 合法的 UTF-8 原样输出, 否则按 base64 编码, 保证输出总是合法的 JSON
+// valid UTF-8 is output as is, otherwise base64 encoded, so that the output is always valid JSON
+pub fn vec_u8_to_string<S>(v: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match str::from_utf8(v) {
+        Ok(s) => serializer.serialize_str(s),
+        Err(_) => serializer.serialize_str(&base64::encode(v)),
+    }
+}
+
 pub fn value_is_default<T>(t: &T) -> bool
 where
     T: Default + std::cmp::PartialEq,
@@ -349,7 +386,6 @@ pub struct AppProtoLogsData {
     pub base_info: AppProtoLogsBaseInfo,
     #[serde(flatten)]
     pub special_info: L7ProtocolInfo,
-    #[serde(flatten)]
     pub direction_score: u8,
 }
 
@@ -377,6 +413,13 @@ impl AppProtoLogsData {
         }
     }
 
+    // 字段名与 to_kv_string 输出一致, 二进制字段按 UTF-8 输出, 否则按 base64 编码
+    // field names are the same as to_kv_string output, binary fields are output as UTF-8,
+    // or base64 encoded if not valid UTF-8
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
     pub fn is_request(&self) -> bool {
         self.base_info.head.msg_type == LogMessageType::Request
     }
@@ -477,8 +520,7 @@ impl Sendable for BoxAppProtoLogsData {
     }
 
     fn to_kv_string(&self, kv_string: &mut String) {
-        let json = serde_json::to_string(&(*self.0)).unwrap();
-        kv_string.push_str(&json);
+        kv_string.push_str(&self.0.to_json());
         kv_string.push('\n');
     }
 
@@ -13,6 +13,8 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use std::ops::RangeInclusive;
+
 use serde::Serialize;
 
 use crate::{
@@ -25,15 +27,125 @@ use crate::{
     flow_generator::{
         error::{Error, Result},
         protocol_logs::{
-            consts::KAFKA_REQ_HEADER_LEN,
-            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+            consts::{KAFKA_REQ_HEADER_LEN, KAFKA_RESP_HEADER_LEN},
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
             value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
         },
     },
-    utils::bytes::{read_i16_be, read_u16_be, read_u32_be},
+    utils::bytes::{read_i16_be, read_u16_be, read_u32_be, read_varint},
 };
 
+const KAFKA_PRODUCE: u16 = 0;
 const KAFKA_FETCH: u16 = 1;
+const KAFKA_METADATA: u16 = 3;
+const KAFKA_OFFSET_COMMIT: u16 = 8;
+const KAFKA_OFFSET_FETCH: u16 = 9;
+const KAFKA_JOIN_GROUP: u16 = 11;
+const KAFKA_HEARTBEAT: u16 = 12;
+const KAFKA_LEAVE_GROUP: u16 = 13;
+const KAFKA_SYNC_GROUP: u16 = 14;
+const KAFKA_INIT_PRODUCER_ID: u16 = 22;
+const KAFKA_ADD_PARTITIONS_TO_TXN: u16 = 24;
+// Produce v9, Fetch v12 和 Metadata v9 开始使用 flexible version, 即 compact 编码和 tagged fields
+// Produce v9, Fetch v12 and Metadata v9 start to use flexible version, i.e. compact encoding and
+// tagged fields
+const KAFKA_PRODUCE_FLEXIBLE_VERSION: u16 = 9;
+const KAFKA_FETCH_FLEXIBLE_VERSION: u16 = 12;
+const KAFKA_METADATA_FLEXIBLE_VERSION: u16 = 9;
+// 需要解析消息体的 api_key 及已知的版本范围, 超出范围时只记录 api_key, api_version 和 correlation_id
+// api_keys whose body is parsed and their known version ranges, out of range only api_key, api_version and
+// correlation_id are recorded
+const KAFKA_API_VERSIONS: [(u16, RangeInclusive<u16>); 11] = [
+    (KAFKA_PRODUCE, 0..=10),
+    (KAFKA_FETCH, 0..=16),
+    (KAFKA_METADATA, 0..=12),
+    (KAFKA_OFFSET_COMMIT, 0..=9),
+    (KAFKA_OFFSET_FETCH, 0..=9),
+    (KAFKA_JOIN_GROUP, 0..=9),
+    (KAFKA_HEARTBEAT, 0..=4),
+    (KAFKA_LEAVE_GROUP, 0..=5),
+    (KAFKA_SYNC_GROUP, 0..=5),
+    (KAFKA_INIT_PRODUCER_ID, 0..=5),
+    (KAFKA_ADD_PARTITIONS_TO_TXN, 0..=5),
+];
+
+// 由 broker 状态导致的错误码, 其余非 0 错误码视为客户端错误
+// error codes caused by broker state, other non-zero error codes are regarded as client errors
+// reference: https://kafka.apache.org/protocol.html#protocol_error_codes
+const KAFKA_BROKER_ERROR_CODES: [i32; 15] = [
+    -1, // UNKNOWN_SERVER_ERROR
+    5,  // LEADER_NOT_AVAILABLE
+    6,  // NOT_LEADER_OR_FOLLOWER
+    7,  // REQUEST_TIMED_OUT
+    8,  // BROKER_NOT_AVAILABLE
+    9,  // REPLICA_NOT_AVAILABLE
+    13, // NETWORK_EXCEPTION
+    14, // COORDINATOR_LOAD_IN_PROGRESS
+    15, // COORDINATOR_NOT_AVAILABLE
+    16, // NOT_COORDINATOR
+    19, // NOT_ENOUGH_REPLICAS
+    20, // NOT_ENOUGH_REPLICAS_AFTER_APPEND
+    41, // NOT_CONTROLLER
+    56, // KAFKA_STORAGE_ERROR
+    57, // LOG_DIR_NOT_FOUND
+];
+
+fn error_code_status(code: i32) -> L7ResponseStatus {
+    match code {
+        0 => L7ResponseStatus::Ok,
+        _ if KAFKA_BROKER_ERROR_CODES.contains(&code) => L7ResponseStatus::ServerError,
+        _ => L7ResponseStatus::ClientError,
+    }
+}
+
+fn status_severity(status: L7ResponseStatus) -> u8 {
+    match status {
+        L7ResponseStatus::ServerError => 2,
+        L7ResponseStatus::ClientError => 1,
+        _ => 0,
+    }
+}
+
+fn is_known_version(api_key: u16, api_version: u16) -> bool {
+    KAFKA_API_VERSIONS
+        .iter()
+        .any(|(key, versions)| *key == api_key && versions.contains(&api_version))
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KafkaCompression {
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl KafkaCompression {
+    // record batch 和 message 的 attributes 低 3 位为压缩算法
+    // the lowest 3 bits of record batch and message attributes is the compression codec
+    fn from_attributes(attributes: u16) -> Option<Self> {
+        match attributes & 0x07 {
+            0 => Some(Self::None),
+            1 => Some(Self::Gzip),
+            2 => Some(Self::Snappy),
+            3 => Some(Self::Lz4),
+            4 => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Snappy => "snappy",
+            Self::Lz4 => "lz4",
+            Self::Zstd => "zstd",
+        }
+    }
+}
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct KafkaInfo {
@@ -47,12 +159,33 @@ pub struct KafkaInfo {
     // request
     #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
     pub req_msg_size: Option<u32>,
-    #[serde(skip)]
     pub api_version: u16,
     #[serde(rename = "request_type")]
     pub api_key: u16,
+    // 响应中的 api_name 来自对应的请求, 没有请求时为空
+    // api_name of response is taken from the corresponding request, empty if there is none
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub api_name: &'static str,
     #[serde(skip)]
     pub client_id: String,
+    // Produce 和 Fetch 请求中第一个 topic 的第一个 partition
+    // the first partition of the first topic in Produce and Fetch request
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub topic_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<KafkaCompression>,
+    // 消费组相关请求中的 group_id, 批量请求中为第一个
+    // group_id in consumer group requests, the first one in batched requests
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub group_id: String,
+    // 事务相关请求中的 transactional_id, 批量请求中为第一个
+    // transactional_id in transaction requests, the first one in batched requests
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub transactional_id: String,
 
     // reponse
     #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
@@ -66,6 +199,10 @@ pub struct KafkaInfo {
     // only fetch and api version > 7 can get the correct err code
     #[serde(skip)]
     pub resp_data: Option<[u8; 14]>,
+    // Fetch 响应中所有 partition 的 records 字节数
+    // bytes of records of all partitions in Fetch response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch_bytes: Option<u32>,
 
     rrt: u64,
 }
@@ -102,23 +239,51 @@ impl KafkaInfo {
         if self.resp_msg_size.is_none() {
             self.resp_msg_size = other.resp_msg_size;
         }
+        if self.fetch_bytes.is_none() {
+            self.fetch_bytes = other.fetch_bytes;
+        }
+        if self.topic_name.is_empty() {
+            self.topic_name = other.topic_name;
+            self.partition = other.partition;
+            self.record_count = other.record_count;
+            self.compression = other.compression;
+        }
+        if self.group_id.is_empty() {
+            self.group_id = other.group_id;
+        }
+        if self.transactional_id.is_empty() {
+            self.transactional_id = other.transactional_id;
+        }
+        // 响应中已根据请求的版本解析出错误码
+        // error code is already parsed in response according to the version of request
+        if self.status_code.is_none() && other.status_code.is_some() {
+            self.status = other.status;
+            self.status_code = other.status_code;
+            return;
+        }
         /*
             reference:  https://kafka.apache.org/protocol.html#protocol_messages
 
-            only fetch api and api version > 7 parse the error code
+            only fetch api and api version > 7 parse the error code,
+            flexible version (v12+) has tagged fields in response header, so the offset is not fixed
 
             Fetch Response (Version: 7) => throttle_time_ms error_code session_id [responses]
                 throttle_time_ms => INT32
                 error_code => INT16
                 ...
         */
+        let with_error_code = 7..KAFKA_FETCH_FLEXIBLE_VERSION;
         match other.msg_type {
-            LogMessageType::Response if self.api_key == KAFKA_FETCH && self.api_version >= 7 => {
+            LogMessageType::Response
+                if self.api_key == KAFKA_FETCH && with_error_code.contains(&self.api_version) =>
+            {
                 if let Some(d) = other.resp_data {
                     self.set_status_code(read_i16_be(&d[12..]) as i32)
                 }
             }
-            LogMessageType::Request if other.api_key == KAFKA_FETCH && other.api_version >= 7 => {
+            LogMessageType::Request
+                if other.api_key == KAFKA_FETCH && with_error_code.contains(&other.api_version) =>
+            {
                 if let Some(d) = self.resp_data {
                     self.set_status_code(read_i16_be(&d[12..]) as i32)
                 }
@@ -127,12 +292,13 @@ impl KafkaInfo {
         }
     }
 
+    // 响应中有多个错误码时保留最严重的一个
+    // keep the most severe one when there are multiple error codes in response
     pub fn set_status_code(&mut self, code: i32) {
-        self.status_code = Some(code);
-        if code == 0 {
-            self.status = L7ResponseStatus::Ok;
-        } else {
-            self.status = L7ResponseStatus::ServerError;
+        let status = error_code_status(code);
+        if self.status_code.is_none() || status_severity(status) > status_severity(self.status) {
+            self.status_code = Some(code);
+            self.status = status;
         }
     }
 
@@ -202,29 +368,84 @@ impl KafkaInfo {
             //50
             "DescribeUserScramCredentials",
             "AlterUserScramCredentials",
-            "AlterIsr",
+            "Vote",
+            "BeginQuorumEpoch",
+            "EndQuorumEpoch",
+            "DescribeQuorum",
+            "AlterPartition",
             "UpdateFeatures",
+            "Envelope",
+            "FetchSnapshot",
+            // 60
             "DescribeCluster",
             "DescribeProducers",
+            "BrokerRegistration",
+            "BrokerHeartbeat",
+            "UnregisterBroker",
             "DescribeTransactions",
             "ListTransactions",
             "AllocateProducerIds",
         ];
-        match self.api_key {
-            0..=58 => command_str[self.api_key as usize],
-            _ => "",
-        }
+        command_str
+            .get(self.api_key as usize)
+            .copied()
+            .unwrap_or_default()
     }
 }
 
 impl From<KafkaInfo> for L7ProtocolSendLog {
     fn from(f: KafkaInfo) -> Self {
-        let command_str = f.get_command();
+        let mut attributes = vec![];
+        if let Some(partition) = f.partition {
+            attributes.push(KeyVal {
+                key: "partition".to_string(),
+                val: partition.to_string(),
+            });
+        }
+        if let Some(record_count) = f.record_count {
+            attributes.push(KeyVal {
+                key: "record_count".to_string(),
+                val: record_count.to_string(),
+            });
+        }
+        if let Some(compression) = f.compression {
+            attributes.push(KeyVal {
+                key: "compression".to_string(),
+                val: compression.as_str().to_string(),
+            });
+        }
+        if let Some(fetch_bytes) = f.fetch_bytes {
+            attributes.push(KeyVal {
+                key: "fetch_bytes".to_string(),
+                val: fetch_bytes.to_string(),
+            });
+        }
+        if !f.group_id.is_empty() {
+            attributes.push(KeyVal {
+                key: "group_id".to_string(),
+                val: f.group_id,
+            });
+        }
+        if !f.transactional_id.is_empty() {
+            attributes.push(KeyVal {
+                key: "transactional_id".to_string(),
+                val: f.transactional_id,
+            });
+        }
+        // 不同版本的消息体格式不同, 版本作为协议版本上报
+        // body layout differs across versions, so api_version is reported as protocol version
+        let version = if f.api_name.is_empty() {
+            None
+        } else {
+            Some(f.api_version.to_string())
+        };
         let log = L7ProtocolSendLog {
             req_len: f.req_msg_size,
             resp_len: f.resp_msg_size,
+            version,
             req: L7Request {
-                req_type: String::from(command_str),
+                req_type: String::from(f.api_name),
+                resource: f.topic_name,
                 ..Default::default()
             },
             resp: L7Response {
@@ -234,6 +455,11 @@ impl From<KafkaInfo> for L7ProtocolSendLog {
             },
             ext_info: Some(ExtendedInfo {
                 request_id: Some(f.correlation_id),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -245,6 +471,11 @@ impl From<KafkaInfo> for L7ProtocolSendLog {
 #[derive(Clone, Serialize, Default)]
 pub struct KafkaLog {
     info: KafkaInfo,
+    // 最近请求的 (correlation_id, api_key, api_version), 响应中没有 api_key 和 api_version, 需要用请求的解析
+    // (correlation_id, api_key, api_version) of recent requests, the response does not contain
+    // api_key and api_version, so they are taken from the request
+    #[serde(skip)]
+    requests: Vec<(u32, u16, u16)>,
     #[serde(skip)]
     perf_stats: Option<L7PerfStats>,
 }
@@ -278,6 +509,10 @@ impl L7ProtocolParserInterface for KafkaLog {
         L7Protocol::Kafka
     }
 
+    fn default_ports(&self) -> &[u16] {
+        &[9092]
+    }
+
     fn parsable_on_udp(&self) -> bool {
         false
     }
@@ -293,6 +528,7 @@ impl L7ProtocolParserInterface for KafkaLog {
 
 impl KafkaLog {
     const MSG_LEN_SIZE: usize = 4;
+    const MAX_CACHED_REQUESTS: usize = 16;
 
     // 协议识别的时候严格检查避免误识别，日志解析的时候不用严格检查因为可能有长度截断
     // ================================================================================
@@ -314,6 +550,7 @@ impl KafkaLog {
         self.info.api_key = read_u16_be(&payload[4..]);
         self.info.api_version = read_u16_be(&payload[6..]);
         self.info.correlation_id = read_u32_be(&payload[8..]);
+        self.info.api_name = self.info.get_command();
         self.info.client_id =
             String::from_utf8_lossy(&payload[14..14 + client_id_len]).into_owned();
 
@@ -333,6 +570,304 @@ impl KafkaLog {
         Ok(())
     }
 
+    fn save_request(&mut self) {
+        if self.requests.len() >= Self::MAX_CACHED_REQUESTS {
+            self.requests.remove(0);
+        }
+        self.requests.push((
+            self.info.correlation_id,
+            self.info.api_key,
+            self.info.api_version,
+        ));
+    }
+
+    fn take_request(&mut self, correlation_id: u32) -> Option<(u16, u16)> {
+        let index = self
+            .requests
+            .iter()
+            .position(|(id, _, _)| *id == correlation_id)?;
+        let (_, api_key, api_version) = self.requests.remove(index);
+        Some((api_key, api_version))
+    }
+
+    /*
+        Produce Request => transactional_id(v3+) acks timeout_ms [topic_data]
+            topic_data => name [partition_data]
+                partition_data => index records
+    */
+    fn parse_produce(&mut self, payload: &[u8]) -> Option<()> {
+        let version = self.info.api_version;
+        let flexible = version >= KAFKA_PRODUCE_FLEXIBLE_VERSION;
+        let mut reader = Reader::new(payload, flexible);
+        if flexible {
+            // request header v2 在 client_id 之后有 tagged fields
+            // request header v2 has tagged fields after client_id
+            reader.tagged_fields()?;
+        }
+        if version >= 3 {
+            reader.string()?;
+        }
+        reader.skip(2 + 4)?;
+        if reader.array_len()? == 0 {
+            return Some(());
+        }
+        self.info.topic_name =
+            String::from_utf8_lossy(reader.string()?.unwrap_or_default()).into_owned();
+        if reader.array_len()? == 0 {
+            return Some(());
+        }
+        self.info.partition = Some(reader.i32()?);
+        let records = reader.records()?;
+        let (record_count, compression) = parse_record_set(records)?;
+        self.info.record_count = Some(record_count);
+        self.info.compression = compression;
+        Some(())
+    }
+
+    /*
+        Fetch Request => replica_id(v0-14) max_wait_ms min_bytes max_bytes(v3+) isolation_level(v4+)
+            session_id(v7+) session_epoch(v7+) [topics] ...
+            topics => topic(v0-12)/topic_id(v13+) [partitions]
+                partitions => partition current_leader_epoch(v9+) fetch_offset ...
+    */
+    fn parse_fetch_request(&mut self, payload: &[u8]) -> Option<()> {
+        let version = self.info.api_version;
+        let flexible = version >= KAFKA_FETCH_FLEXIBLE_VERSION;
+        let mut reader = Reader::new(payload, flexible);
+        if flexible {
+            reader.tagged_fields()?;
+        }
+        // v15 开始 replica_id 移到 tagged fields 中
+        // replica_id is moved into tagged fields since v15
+        if version < 15 {
+            reader.skip(4)?;
+        }
+        reader.skip(4 + 4)?;
+        if version >= 3 {
+            reader.skip(4)?;
+        }
+        if version >= 4 {
+            reader.skip(1)?;
+        }
+        if version >= 7 {
+            reader.skip(4 + 4)?;
+        }
+        if reader.array_len()? == 0 {
+            return Some(());
+        }
+        // v13 开始使用 topic_id, 无法得到 topic 名称
+        // topic_id is used since v13, topic name is not available
+        if version >= 13 {
+            reader.skip(16)?;
+        } else {
+            self.info.topic_name =
+                String::from_utf8_lossy(reader.string()?.unwrap_or_default()).into_owned();
+        }
+        if reader.array_len()? == 0 {
+            return Some(());
+        }
+        self.info.partition = Some(reader.i32()?);
+        Some(())
+    }
+
+    /*
+        消费组和事务相关请求的第一个字段为 group_id 或 transactional_id, 批量请求中为数组中第一个元素的第一个字段
+        the first field of consumer group and transaction requests is group_id or transactional_id, in batched
+        requests it is the first field of the first element in the array
+
+        OffsetCommit/JoinGroup/Heartbeat/LeaveGroup/SyncGroup Request => group_id ...
+        OffsetFetch Request => group_id(v0-7) ... [groups](v8+)
+            groups => group_id ...
+        InitProducerId Request => transactional_id ...
+        AddPartitionsToTxn Request => transactional_id(v0-3) ... [transactions](v4+)
+            transactions => transactional_id ...
+    */
+    fn parse_group_or_txn_request(&mut self, payload: &[u8]) -> Option<()> {
+        // (flexible version, 批量请求的起始版本)
+        // (flexible version, first version of batched request)
+        let (flexible_version, batched_version) = match self.info.api_key {
+            KAFKA_OFFSET_COMMIT => (8, None),
+            KAFKA_OFFSET_FETCH => (6, Some(8)),
+            KAFKA_JOIN_GROUP => (6, None),
+            KAFKA_HEARTBEAT | KAFKA_LEAVE_GROUP | KAFKA_SYNC_GROUP => (4, None),
+            KAFKA_INIT_PRODUCER_ID => (2, None),
+            KAFKA_ADD_PARTITIONS_TO_TXN => (3, Some(4)),
+            _ => return None,
+        };
+        let version = self.info.api_version;
+        let mut reader = Reader::new(payload, version >= flexible_version);
+        reader.tagged_fields()?;
+        if batched_version.map_or(false, |v| version >= v) && reader.array_len()? == 0 {
+            return Some(());
+        }
+        let id = String::from_utf8_lossy(reader.string()?.unwrap_or_default()).into_owned();
+        match self.info.api_key {
+            KAFKA_INIT_PRODUCER_ID | KAFKA_ADD_PARTITIONS_TO_TXN => self.info.transactional_id = id,
+            _ => self.info.group_id = id,
+        }
+        Some(())
+    }
+
+    /*
+        Produce Response => [responses] throttle_time_ms(v1+)
+            responses => name [partition_responses]
+                partition_responses => index error_code base_offset log_append_time_ms(v2+)
+                    log_start_offset(v5+) [record_errors](v8+) error_message(v8+)
+    */
+    fn parse_produce_response(&mut self, payload: &[u8], version: u16) -> Option<()> {
+        let flexible = version >= KAFKA_PRODUCE_FLEXIBLE_VERSION;
+        let mut reader = Reader::new(payload, flexible);
+        if flexible {
+            reader.tagged_fields()?;
+        }
+        for _ in 0..reader.array_len()? {
+            reader.string()?;
+            for _ in 0..reader.array_len()? {
+                reader.skip(4)?;
+                self.info.set_status_code(reader.i16()? as i32);
+                reader.skip(8)?;
+                if version >= 2 {
+                    reader.skip(8)?;
+                }
+                if version >= 5 {
+                    reader.skip(8)?;
+                }
+                if version >= 8 {
+                    for _ in 0..reader.array_len()? {
+                        reader.skip(4)?;
+                        reader.string()?;
+                        reader.tagged_fields()?;
+                    }
+                    reader.string()?;
+                }
+                reader.tagged_fields()?;
+            }
+            reader.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        Metadata Response => throttle_time_ms(v3+) [brokers] cluster_id(v2+) controller_id(v1+) [topics]
+            brokers => node_id host port rack(v1+)
+            topics => error_code name topic_id(v10+) is_internal(v1+) [partitions]
+                topic_authorized_operations(v8+)
+                partitions => error_code partition_index leader_id leader_epoch(v7+) [replica_nodes]
+                    [isr_nodes] [offline_replicas](v5+)
+    */
+    fn parse_metadata_response(&mut self, payload: &[u8], version: u16) -> Option<()> {
+        let flexible = version >= KAFKA_METADATA_FLEXIBLE_VERSION;
+        let mut reader = Reader::new(payload, flexible);
+        if flexible {
+            reader.tagged_fields()?;
+        }
+        if version >= 3 {
+            reader.skip(4)?;
+        }
+        for _ in 0..reader.array_len()? {
+            reader.skip(4)?;
+            reader.string()?;
+            reader.skip(4)?;
+            if version >= 1 {
+                reader.string()?;
+            }
+            reader.tagged_fields()?;
+        }
+        if version >= 2 {
+            reader.string()?;
+        }
+        if version >= 1 {
+            reader.skip(4)?;
+        }
+        for _ in 0..reader.array_len()? {
+            self.info.set_status_code(reader.i16()? as i32);
+            reader.string()?;
+            if version >= 10 {
+                reader.skip(16)?;
+            }
+            if version >= 1 {
+                reader.skip(1)?;
+            }
+            for _ in 0..reader.array_len()? {
+                self.info.set_status_code(reader.i16()? as i32);
+                reader.skip(4 + 4)?;
+                if version >= 7 {
+                    reader.skip(4)?;
+                }
+                let node_arrays = if version >= 5 { 3 } else { 2 };
+                for _ in 0..node_arrays {
+                    let len = reader.array_len()?;
+                    reader.skip(len.checked_mul(4)?)?;
+                }
+                reader.tagged_fields()?;
+            }
+            if version >= 8 {
+                reader.skip(4)?;
+            }
+            reader.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        Fetch Response => throttle_time_ms(v1+) error_code(v7+) session_id(v7+) [responses]
+            responses => topic(v0-12)/topic_id(v13+) [partitions]
+                partitions => partition_index error_code high_watermark last_stable_offset(v4+)
+                    log_start_offset(v5+) [aborted_transactions](v4+) preferred_read_replica(v11+) records
+    */
+    fn parse_fetch_response(&mut self, payload: &[u8], version: u16) -> Option<()> {
+        let flexible = version >= KAFKA_FETCH_FLEXIBLE_VERSION;
+        let mut reader = Reader::new(payload, flexible);
+        if flexible {
+            reader.tagged_fields()?;
+        }
+        if version >= 1 {
+            reader.skip(4)?;
+        }
+        if version >= 7 {
+            self.info.set_status_code(reader.i16()? as i32);
+            reader.skip(4)?;
+        }
+        let mut fetch_bytes = 0u32;
+        for _ in 0..reader.array_len()? {
+            if version >= 13 {
+                reader.skip(16)?;
+            } else {
+                reader.string()?;
+            }
+            for _ in 0..reader.array_len()? {
+                reader.skip(4)?;
+                self.info.set_status_code(reader.i16()? as i32);
+                reader.skip(8)?;
+                if version >= 4 {
+                    reader.skip(8)?;
+                }
+                if version >= 5 {
+                    reader.skip(8)?;
+                }
+                if version >= 4 {
+                    for _ in 0..reader.array_len()? {
+                        reader.skip(8 + 8)?;
+                        reader.tagged_fields()?;
+                    }
+                }
+                if version >= 11 {
+                    reader.skip(4)?;
+                }
+                // 报文可能被截断, 先累加长度再跳过 records
+                // payload may be truncated, add the size before skipping records
+                let size = reader.records_size()?;
+                fetch_bytes = fetch_bytes.saturating_add(size as u32);
+                self.info.fetch_bytes = Some(fetch_bytes);
+                reader.skip(size)?;
+                reader.tagged_fields()?;
+            }
+            reader.tagged_fields()?;
+        }
+        self.info.fetch_bytes = Some(fetch_bytes);
+        Some(())
+    }
+
     fn parse(
         &mut self,
         payload: &[u8],
@@ -349,16 +884,198 @@ impl KafkaLog {
             PacketDirection::ClientToServer => {
                 self.request(payload, false)?;
                 self.perf_stats.as_mut().unwrap().inc_req();
+                self.save_request();
+                // 未知的版本不解析消息体, 避免错误的字段
+                // body of unknown version is not parsed to avoid wrong fields
+                if is_known_version(self.info.api_key, self.info.api_version) {
+                    let client_id_len = read_u16_be(&payload[12..]) as usize;
+                    let body = &payload[KAFKA_REQ_HEADER_LEN + client_id_len..];
+                    let _ = match self.info.api_key {
+                        KAFKA_PRODUCE => self.parse_produce(body),
+                        KAFKA_FETCH => self.parse_fetch_request(body),
+                        _ => self.parse_group_or_txn_request(body),
+                    };
+                }
             }
             PacketDirection::ServerToClient => {
                 self.response(payload)?;
                 self.perf_stats.as_mut().unwrap().inc_resp();
+                if let Some((api_key, api_version)) = self.take_request(self.info.correlation_id) {
+                    self.info.api_key = api_key;
+                    self.info.api_version = api_version;
+                    self.info.api_name = self.info.get_command();
+                    if is_known_version(api_key, api_version) {
+                        let body = &payload[KAFKA_RESP_HEADER_LEN..];
+                        let _ = match api_key {
+                            KAFKA_PRODUCE => self.parse_produce_response(body, api_version),
+                            KAFKA_FETCH => self.parse_fetch_response(body, api_version),
+                            KAFKA_METADATA => self.parse_metadata_response(body, api_version),
+                            _ => None,
+                        };
+                        let perf_stats = self.perf_stats.as_mut().unwrap();
+                        match self.info.status {
+                            L7ResponseStatus::ClientError => perf_stats.inc_req_err(),
+                            L7ResponseStatus::ServerError => perf_stats.inc_resp_err(),
+                            _ => (),
+                        }
+                    }
+                }
             }
         }
         Ok(())
     }
 }
 
+/*
+    records 为 message set (magic 0/1) 或 record batch (magic 2), 返回 record 数量和压缩算法
+
+    message set (v0/v1) => [offset message_size crc magic attributes ...]
+        offset => INT64, message_size => INT32, crc => INT32, magic => INT8, attributes => INT8
+
+    record batch (v2) => base_offset batch_length partition_leader_epoch magic crc attributes
+        last_offset_delta first_timestamp max_timestamp producer_id producer_epoch base_sequence records_count ...
+*/
+fn parse_record_set(records: &[u8]) -> Option<(u32, Option<KafkaCompression>)> {
+    const MAGIC_OFFSET: usize = 16;
+    const MESSAGE_HEADER_LEN: usize = 12;
+    const BATCH_ATTRIBUTES_OFFSET: usize = 21;
+    const BATCH_RECORDS_COUNT_OFFSET: usize = 57;
+
+    match *records.get(MAGIC_OFFSET)? {
+        0 | 1 => {
+            // 压缩的消息被包装为一条 message, 只能计为一条
+            // compressed messages are wrapped in one message, which can only be counted as one
+            let attributes = *records.get(MAGIC_OFFSET + 1)?;
+            let mut count = 0;
+            let mut offset = 0;
+            while offset + MESSAGE_HEADER_LEN <= records.len() {
+                let message_size = read_u32_be(&records[offset + 8..]) as usize;
+                count += 1;
+                offset += MESSAGE_HEADER_LEN + message_size;
+            }
+            Some((count, KafkaCompression::from_attributes(attributes as u16)))
+        }
+        2 => {
+            let attributes =
+                read_u16_be(records.get(BATCH_ATTRIBUTES_OFFSET..BATCH_ATTRIBUTES_OFFSET + 2)?);
+            let count = read_u32_be(
+                records.get(BATCH_RECORDS_COUNT_OFFSET..BATCH_RECORDS_COUNT_OFFSET + 4)?,
+            );
+            Some((count, KafkaCompression::from_attributes(attributes)))
+        }
+        _ => None,
+    }
+}
+
+// flexible version 使用 compact 编码, 长度为 unsigned varint 且加 1, 0 表示 null
+// flexible version uses compact encoding, the length is unsigned varint plus 1, 0 means null
+struct Reader<'a> {
+    buf: &'a [u8],
+    flexible: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8], flexible: bool) -> Self {
+        Self { buf, flexible }
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        if self.buf.len() < n {
+            return None;
+        }
+        self.buf = &self.buf[n..];
+        Some(())
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        if self.buf.len() < 2 {
+            return None;
+        }
+        let v = read_i16_be(self.buf);
+        self.buf = &self.buf[2..];
+        Some(v)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        if self.buf.len() < 4 {
+            return None;
+        }
+        let v = read_u32_be(self.buf) as i32;
+        self.buf = &self.buf[4..];
+        Some(v)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let (v, n) = read_varint(self.buf)?;
+        self.buf = &self.buf[n..];
+        Some(v)
+    }
+
+    // 返回长度, None 表示 null
+    // return the length, None means null
+    fn length(&mut self, size_of_len: usize) -> Option<Option<usize>> {
+        if self.flexible {
+            return Some(match self.varint()? {
+                0 => None,
+                n => Some(n as usize - 1),
+            });
+        }
+        if self.buf.len() < size_of_len {
+            return None;
+        }
+        let len = match size_of_len {
+            2 => read_i16_be(self.buf) as i32,
+            _ => read_u32_be(self.buf) as i32,
+        };
+        self.buf = &self.buf[size_of_len..];
+        Some(if len < 0 { None } else { Some(len as usize) })
+    }
+
+    // nullable string
+    fn string(&mut self) -> Option<Option<&'a [u8]>> {
+        let Some(len) = self.length(2)? else {
+            return Some(None);
+        };
+        if self.buf.len() < len {
+            return None;
+        }
+        let (s, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Some(Some(s))
+    }
+
+    // nullable array 作为空数组处理
+    // nullable array is treated as empty array
+    fn array_len(&mut self) -> Option<usize> {
+        Some(self.length(4)?.unwrap_or_default())
+    }
+
+    fn records_size(&mut self) -> Option<usize> {
+        Some(self.length(4)?.unwrap_or_default())
+    }
+
+    // records 可能被截断, 返回剩余的部分
+    // records may be truncated, return the remaining part
+    fn records(&mut self) -> Option<&'a [u8]> {
+        let size = self.records_size()?;
+        let (records, rest) = self.buf.split_at(size.min(self.buf.len()));
+        self.buf = rest;
+        Some(records)
+    }
+
+    fn tagged_fields(&mut self) -> Option<()> {
+        if !self.flexible {
+            return Some(());
+        }
+        for _ in 0..self.varint()? {
+            self.varint()?;
+            let size = self.varint()? as usize;
+            self.skip(size)?;
+        }
+        Some(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
@@ -428,6 +1145,188 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_records() {
+        let files = vec![
+            ("kafka-records.pcap", "kafka-records.result"),
+            // Produce v3 和 Fetch v11
+            // Produce v3 and Fetch v11
+            ("kafka-versions.pcap", "kafka-versions.result"),
+            // 多个 partition 错误码及 Metadata 错误码
+            // error codes of multiple partitions and Metadata error code
+            ("kafka-errors.pcap", "kafka-errors.result"),
+        ];
+        for item in files.iter() {
+            let capture = Capture::load_pcap(Path::new(FILE_DIR).join(item.0), None);
+            let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+            let mut packets = capture.as_meta_packets();
+
+            // 响应需要请求的 api_version, 所有报文使用同一个 KafkaLog
+            // response requires api_version of request, all packets use the same KafkaLog
+            let mut kafka = KafkaLog::default();
+            let mut output = String::new();
+            let first_dst_port = packets[0].lookup_key.dst_port;
+            for packet in packets.iter_mut() {
+                packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                    PacketDirection::ClientToServer
+                } else {
+                    PacketDirection::ServerToClient
+                };
+                let Some(payload) = packet.get_l4_payload() else {
+                    continue;
+                };
+                let param = &ParseParam::from((packet as &MetaPacket, log_cache.clone(), false));
+                let _ = kafka.parse_payload(payload, param);
+                output.push_str(&format!("{:?}\r\n", kafka.info));
+                kafka.reset();
+            }
+
+            let expected = fs::read_to_string(Path::new(FILE_DIR).join(item.1)).unwrap();
+            assert_eq!(output, expected, "parse pcap {} unexpected", item.0);
+        }
+    }
+
+    #[test]
+    fn check_api_name() {
+        let mut info = KafkaInfo::default();
+        assert_eq!(info.get_command(), "Produce");
+        info.api_key = 3;
+        assert_eq!(info.get_command(), "Metadata");
+        info.api_key = 60;
+        assert_eq!(info.get_command(), "DescribeCluster");
+        info.api_key = KafkaInfo::API_KEY_MAX;
+        assert_eq!(info.get_command(), "AllocateProducerIds");
+        info.api_key = KafkaInfo::API_KEY_MAX + 1;
+        assert_eq!(info.get_command(), "");
+    }
+
+    #[test]
+    fn check_unknown_version() {
+        assert!(is_known_version(KAFKA_PRODUCE, 10));
+        assert!(!is_known_version(KAFKA_PRODUCE, 11));
+        assert!(is_known_version(KAFKA_FETCH, 16));
+        assert!(!is_known_version(18, 0));
+
+        let produce = |api_version: u16| {
+            let mut payload = vec![];
+            payload.extend_from_slice(&KAFKA_PRODUCE.to_be_bytes());
+            payload.extend_from_slice(&api_version.to_be_bytes());
+            payload.extend_from_slice(&7u32.to_be_bytes());
+            payload.extend_from_slice(b"\x00\x02c1");
+            // transactional_id acks timeout_ms
+            payload.extend_from_slice(b"\xff\xff\x00\x01\x00\x00\x75\x30");
+            // [topic_data] name [partition_data] index records
+            payload.extend_from_slice(b"\x00\x00\x00\x01\x00\x01t");
+            payload.extend_from_slice(b"\x00\x00\x00\x01\x00\x00\x00\x02\x00\x00\x00\x00");
+            let mut request = (payload.len() as u32).to_be_bytes().to_vec();
+            request.extend(payload);
+            request
+        };
+
+        let mut kafka = KafkaLog::default();
+        kafka.perf_stats = Some(L7PerfStats::default());
+        kafka
+            .parse(
+                &produce(3),
+                IpProtocol::Tcp,
+                PacketDirection::ClientToServer,
+            )
+            .unwrap();
+        assert_eq!(kafka.info.topic_name, "t");
+        assert_eq!(kafka.info.partition, Some(2));
+
+        // 未知版本只记录请求头
+        // only request header is recorded for unknown version
+        let mut kafka = KafkaLog::default();
+        kafka.perf_stats = Some(L7PerfStats::default());
+        kafka
+            .parse(
+                &produce(99),
+                IpProtocol::Tcp,
+                PacketDirection::ClientToServer,
+            )
+            .unwrap();
+        assert_eq!(kafka.info.api_key, KAFKA_PRODUCE);
+        assert_eq!(kafka.info.api_version, 99);
+        assert_eq!(kafka.info.correlation_id, 7);
+        assert!(kafka.info.topic_name.is_empty());
+        assert_eq!(kafka.info.partition, None);
+    }
+
+    #[test]
+    fn check_group_and_txn() {
+        let parse = |api_key: u16, api_version: u16, body: &[u8]| {
+            let mut payload = vec![];
+            payload.extend_from_slice(&api_key.to_be_bytes());
+            payload.extend_from_slice(&api_version.to_be_bytes());
+            payload.extend_from_slice(&7u32.to_be_bytes());
+            payload.extend_from_slice(b"\x00\x02c1");
+            payload.extend_from_slice(body);
+            let mut request = (payload.len() as u32).to_be_bytes().to_vec();
+            request.extend(payload);
+
+            let mut kafka = KafkaLog::default();
+            kafka.perf_stats = Some(L7PerfStats::default());
+            kafka
+                .parse(&request, IpProtocol::Tcp, PacketDirection::ClientToServer)
+                .unwrap();
+            kafka.info
+        };
+
+        // JoinGroup v5: group_id session_timeout_ms ...
+        let info = parse(KAFKA_JOIN_GROUP, 5, b"\x00\x02g1\x00\x00\x27\x10");
+        assert_eq!(info.api_name, "JoinGroup");
+        assert_eq!(info.group_id, "g1");
+        assert!(info.transactional_id.is_empty());
+        // flexible 版本的请求头有 tagged fields, 字符串为 compact 编码
+        // request header of flexible version has tagged fields, strings are compact encoded
+        let info = parse(KAFKA_HEARTBEAT, 4, b"\x00\x03g2\x00\x00\x00\x01");
+        assert_eq!(info.api_name, "Heartbeat");
+        assert_eq!(info.group_id, "g2");
+        // OffsetFetch v8: [groups] => group_id ...
+        let info = parse(KAFKA_OFFSET_FETCH, 8, b"\x00\x02\x03g3\x01");
+        assert_eq!(info.group_id, "g3");
+        let info = parse(KAFKA_OFFSET_FETCH, 8, b"\x00\x01");
+        assert!(info.group_id.is_empty());
+        // InitProducerId v2: transactional_id 为 null
+        // InitProducerId v2: transactional_id is null
+        let info = parse(KAFKA_INIT_PRODUCER_ID, 2, b"\x00\x00\x00\x00\x00\x01");
+        assert_eq!(info.api_name, "InitProducerId");
+        assert!(info.transactional_id.is_empty());
+        let info = parse(KAFKA_ADD_PARTITIONS_TO_TXN, 3, b"\x00\x03t1");
+        assert_eq!(info.transactional_id, "t1");
+        assert!(info.group_id.is_empty());
+        // 未知版本不解析
+        // unknown version is not parsed
+        let info = parse(KAFKA_SYNC_GROUP, 99, b"\x00\x02g4");
+        assert!(info.group_id.is_empty());
+
+        let log = L7ProtocolSendLog::from(parse(KAFKA_SYNC_GROUP, 3, b"\x00\x02g4"));
+        assert_eq!(log.req.req_type, "SyncGroup");
+        let attributes = log.ext_info.unwrap().attributes.unwrap();
+        assert_eq!(attributes[0].key, "group_id");
+        assert_eq!(attributes[0].val, "g4");
+    }
+
+    #[test]
+    fn check_record_set() {
+        // record batch 过短
+        // record batch too short
+        assert_eq!(parse_record_set(&[0; 16]), None);
+        let mut batch = [0u8; 61];
+        batch[16] = 2;
+        batch[22] = 3;
+        batch[60] = 7;
+        assert_eq!(
+            parse_record_set(&batch),
+            Some((7, Some(KafkaCompression::Lz4)))
+        );
+        // 未知的 magic
+        // unknown magic
+        batch[16] = 3;
+        assert_eq!(parse_record_set(&batch), None);
+    }
+
     #[test]
     fn check_perf() {
         let expected = vec![
@@ -16,6 +16,10 @@
 
 mod kafka;
 pub mod mqtt;
+mod nats;
+mod rocketmq;
 
-pub use kafka::{KafkaInfo, KafkaLog};
+pub use kafka::{KafkaCompression, KafkaInfo, KafkaLog};
 pub use mqtt::{MqttInfo, MqttLog};
+pub use nats::{NatsInfo, NatsLog};
+pub use rocketmq::{RocketMqInfo, RocketMqLog};
@@ -36,7 +36,7 @@ use crate::{
     flow_generator::{
         error::{Error, Result},
         protocol_logs::{
-            pb_adapter::{L7ProtocolSendLog, L7Request, L7Response},
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
             value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
         },
     },
@@ -65,6 +65,12 @@ pub struct MqttInfo {
     pub subscribe_topics: Option<Vec<MqttTopic>>,
     #[serde(skip)]
     pub publish_topic: Option<String>,
+    // MQTT v5 PUBLISH 属性
+    // MQTT v5 PUBLISH properties
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_topic: Option<String>,
     #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
     pub code: Option<i32>, // connect_ack packet return code
     pub status: L7ResponseStatus,
@@ -116,6 +122,8 @@ impl Default for MqttInfo {
             res_msg_size: None,
             subscribe_topics: None,
             publish_topic: None,
+            content_type: None,
+            response_topic: None,
             code: None,
             status: L7ResponseStatus::Ok,
             msg_type: LogMessageType::Other,
@@ -138,6 +146,8 @@ impl MqttInfo {
         match other.pkt_type {
             PacketKind::Publish { .. } => {
                 self.publish_topic = other.publish_topic;
+                self.content_type = other.content_type;
+                self.response_topic = other.response_topic;
             }
             PacketKind::Unsubscribe | PacketKind::Subscribe => {
                 self.subscribe_topics = other.subscribe_topics;
@@ -178,6 +188,19 @@ impl From<MqttInfo> for L7ProtocolSendLog {
             }
             _ => {}
         };
+        let mut attributes = vec![];
+        if let Some(content_type) = f.content_type {
+            attributes.push(KeyVal {
+                key: "content_type".to_string(),
+                val: content_type,
+            });
+        }
+        if let Some(response_topic) = f.response_topic {
+            attributes.push(KeyVal {
+                key: "response_topic".to_string(),
+                val: response_topic,
+            });
+        }
         L7ProtocolSendLog {
             version: version,
             req_len: f.req_msg_size,
@@ -193,6 +216,14 @@ impl From<MqttInfo> for L7ProtocolSendLog {
                 code: f.code,
                 ..Default::default()
             },
+            ext_info: if attributes.is_empty() {
+                None
+            } else {
+                Some(ExtendedInfo {
+                    attributes: Some(attributes),
+                    ..Default::default()
+                })
+            },
             ..Default::default()
         }
     }
@@ -248,6 +279,10 @@ impl L7ProtocolParserInterface for MqttLog {
         L7Protocol::MQTT
     }
 
+    fn default_ports(&self) -> &[u16] {
+        &[1883]
+    }
+
     fn parsable_on_udp(&self) -> bool {
         false
     }
@@ -266,10 +301,12 @@ impl L7ProtocolParserInterface for MqttLog {
 
 impl MqttLog {
     fn parse_mqtt_info(&mut self, mut payload: &[u8]) -> Result<()> {
-        // 现在只支持MQTT 3.1.1解析，不支持v5.0
-        // Now only supports MQTT 3.1.1 parsing, not support v5.0
-        if self.version != 0 && self.version != 4 {
-            warn!("cannot parse packet, log parser only support to parse MQTT V3.1.1 packet");
+        // 现在只支持MQTT 3.1.1和v5.0解析
+        // Now only supports MQTT 3.1.1 and v5.0 parsing
+        if self.version != 0 && self.version != 4 && self.version != MQTT_V5 {
+            warn!(
+                "cannot parse packet, log parser only support to parse MQTT V3.1.1 and V5.0 packet"
+            );
             return Err(Error::MqttLogParseFailed);
         }
 
@@ -300,8 +337,8 @@ impl MqttLog {
                     self.version = version;
                 }
                 PacketKind::Connack => {
-                    let (_, return_code) =
-                        parse_connack_packet(input).map_err(|_| Error::MqttLogParseFailed)?;
+                    let (_, return_code) = parse_connack_packet(input, self.version)
+                        .map_err(|_| Error::MqttLogParseFailed)?;
                     info.code = Some(return_code as i32);
                     info.version = self.version;
                     self.msg_type = LogMessageType::Response;
@@ -310,12 +347,27 @@ impl MqttLog {
                     self.status = self.parse_status_code(return_code);
                 }
                 PacketKind::Publish { dup, qos, .. } => {
-                    let (_, topic_name) =
+                    let (input, topic_name) =
                         mqtt_string(input).map_err(|_| Error::MqttLogParseFailed)?;
                     if dup && qos == QualityOfService::AtMostOnce {
                         debug!("mqtt publish packet has invalid dup flags={}", dup);
                         return Err(Error::MqttLogParseFailed);
                     }
+                    if self.version == MQTT_V5 {
+                        // v5的属性位于报文标识符之后
+                        // v5 properties follow the packet identifier
+                        let input = if qos == QualityOfService::AtMostOnce {
+                            input
+                        } else {
+                            let (input, _) = mqtt_packet_identifier(input)
+                                .map_err(|_| Error::MqttLogParseFailed)?;
+                            input
+                        };
+                        let (_, (content_type, response_topic)) = mqtt_publish_properties(input)
+                            .map_err(|_| Error::MqttLogParseFailed)?;
+                        info.content_type = content_type.map(str::to_string);
+                        info.response_topic = response_topic.map(str::to_string);
+                    }
                     // QOS=1,2会有报文标识符
                     // QOS=1,2 there will be a message identifier
                     if qos == QualityOfService::AtLeastOnce || qos == QualityOfService::ExactlyOnce
@@ -333,9 +385,11 @@ impl MqttLog {
                 PacketKind::Subscribe => {
                     // 跳过解析报文标识符
                     // skip parsing packet identifier
-                    let (_, (_, result)) = mqtt_packet_identifier
-                        .and(mqtt_subscription_requests)
-                        .parse(input)
+                    let (input, _) =
+                        mqtt_packet_identifier(input).map_err(|_| Error::MqttLogParseFailed)?;
+                    let (input, _) = mqtt_skip_properties(input, self.version)
+                        .map_err(|_| Error::MqttLogParseFailed)?;
+                    let (_, result) = mqtt_subscription_requests(input, self.version)
                         .map_err(|_| Error::MqttLogParseFailed)?;
                     self.msg_type = LogMessageType::Request;
                     info.req_msg_size = Some(header.remaining_length as u32);
@@ -352,9 +406,11 @@ impl MqttLog {
                     );
                 }
                 PacketKind::Unsubscribe => {
-                    let (_, (_, reqs)) = mqtt_packet_identifier
-                        .and(mqtt_unsubscription_requests)
-                        .parse(input)
+                    let (input, _) =
+                        mqtt_packet_identifier(input).map_err(|_| Error::MqttLogParseFailed)?;
+                    let (input, _) = mqtt_skip_properties(input, self.version)
+                        .map_err(|_| Error::MqttLogParseFailed)?;
+                    let (_, reqs) = mqtt_unsubscription_requests(input)
                         .map_err(|_| Error::MqttLogParseFailed)?;
                     self.msg_type = LogMessageType::Request;
                     info.req_msg_size = Some(header.remaining_length as u32);
@@ -471,11 +527,33 @@ impl MqttLog {
                 self.perf_stats.as_mut().unwrap().inc_req_err();
                 L7ResponseStatus::ServerError
             }
+            /*
+            v5 reason code:
+            UnspecifiedError = 0x80,
+            ImplementationSpecificError = 0x83,
+            ServerUnavailable = 0x88,
+            ServerBusy = 0x89,
+            */
+            0x80 | 0x83 | 0x88 | 0x89 => {
+                self.perf_stats.as_mut().unwrap().inc_req_err();
+                L7ResponseStatus::ServerError
+            }
+            0x81..=0xFF => {
+                self.perf_stats.as_mut().unwrap().inc_resp_err();
+                L7ResponseStatus::ClientError
+            }
             _ => L7ResponseStatus::NotExist,
         }
     }
 }
 
+const MQTT_V5: u8 = 5;
+
+// MQTT v5 属性标识符
+// MQTT v5 property identifiers
+const PROPERTY_CONTENT_TYPE: u8 = 0x03;
+const PROPERTY_RESPONSE_TOPIC: u8 = 0x08;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PacketHeader {
     pub kind: PacketKind,
@@ -694,13 +772,17 @@ pub fn parse_connect_packet(input: &[u8]) -> IResult<&[u8], (u8, &str)> {
     }
 
     let (input, protocol_level) = number::complete::u8(input)?;
-    let (input, _) = number::complete::be_u16(&input[1..])?;
+    // 跳过connect flags和keep alive
+    // skip connect flags and keep alive
+    let (input, _) = number::complete::u8(input)?;
+    let (input, _) = number::complete::be_u16(input)?;
+    let (input, _) = mqtt_skip_properties(input, protocol_level)?;
     // Payload
     let (input, client_id) = mqtt_string(input)?;
     Ok((input, (protocol_level, client_id)))
 }
 
-pub fn parse_connack_packet(input: &[u8]) -> IResult<&[u8], u8> {
+pub fn parse_connack_packet(input: &[u8], version: u8) -> IResult<&[u8], u8> {
     let (input, (reserved, _)): (_, (u8, u8)) =
         bits::bits::<_, _, error::Error<(&[u8], usize)>, _, _>(sequence::tuple((
             bits::complete::take(7usize),
@@ -715,18 +797,32 @@ pub fn parse_connack_packet(input: &[u8]) -> IResult<&[u8], u8> {
     }
 
     let (input, connect_return_code) = number::complete::u8(input)?;
+    let (input, _) = mqtt_skip_properties(input, version)?;
 
     Ok((input, connect_return_code))
 }
 
-fn mqtt_subscription_requests(input: &[u8]) -> IResult<&[u8], Vec<(&str, QualityOfService)>> {
-    fn subscription_request(input: &[u8]) -> IResult<&[u8], (&str, QualityOfService)> {
+fn mqtt_subscription_requests(
+    input: &[u8],
+    version: u8,
+) -> IResult<&[u8], Vec<(&str, QualityOfService)>> {
+    fn subscription_request(input: &[u8], version: u8) -> IResult<&[u8], (&str, QualityOfService)> {
         let (input, topic) = mqtt_string(input)?;
-        let (input, qos) = map_res(number::complete::u8, mqtt_quality_of_service).parse(input)?;
+        let (input, options) = number::complete::u8(input)?;
+        // v5的subscription options高位为no local、retain等标志, 低2位为QoS
+        // in v5 the upper bits of subscription options are no local, retain, etc. flags,
+        // the lowest 2 bits are QoS
+        let options = if version == MQTT_V5 {
+            options & 0x03
+        } else {
+            options
+        };
+        let qos = mqtt_quality_of_service(options)
+            .map_err(|_| nom::Err::Error(error::Error::new(input, error::ErrorKind::MapRes)))?;
         Ok((input, (topic, qos)))
     }
 
-    let (input, count) = many1(subscription_request)(input)?;
+    let (input, count) = many1(|input| subscription_request(input, version))(input)?;
     Ok((input, count))
 }
 
@@ -789,6 +885,74 @@ fn mqtt_unsubscription_requests(input: &[u8]) -> IResult<&[u8], Vec<&str>> {
     Ok((input, reqs))
 }
 
+// v5的属性由变长整数编码的长度和属性列表组成, 低版本没有属性
+// v5 properties consist of a variable byte integer length and the property list,
+// earlier versions have no properties
+fn mqtt_properties(input: &[u8], version: u8) -> IResult<&[u8], &[u8]> {
+    if version != MQTT_V5 {
+        return Ok((input, &[]));
+    }
+    let (input, len) = decode_variable_length(input)?;
+    bytes::complete::take(len)(input)
+}
+
+fn mqtt_skip_properties(input: &[u8], version: u8) -> IResult<&[u8], ()> {
+    let (input, _) = mqtt_properties(input, version)?;
+    Ok((input, ()))
+}
+
+// 解析单个属性, 仅返回字符串类型属性的值
+// parse a single property, only the value of string typed property is returned
+fn mqtt_property(input: &[u8]) -> IResult<&[u8], (u8, Option<&str>)> {
+    let (input, id) = number::complete::u8(input)?;
+    let (input, value) = match id {
+        // Byte
+        0x01 | 0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2A => {
+            (number::complete::u8(input)?.0, None)
+        }
+        // Two Byte Integer
+        0x13 | 0x21 | 0x22 | 0x23 => (number::complete::be_u16(input)?.0, None),
+        // Four Byte Integer
+        0x02 | 0x11 | 0x18 | 0x27 => (number::complete::be_u32(input)?.0, None),
+        // Variable Byte Integer
+        0x0B => (decode_variable_length(input)?.0, None),
+        // UTF-8 Encoded String
+        0x03 | 0x08 | 0x12 | 0x15 | 0x1A | 0x1C | 0x1F => {
+            let (input, s) = mqtt_string(input)?;
+            (input, Some(s))
+        }
+        // Binary Data
+        0x09 | 0x16 => {
+            let (input, len) = number::complete::be_u16(input)?;
+            (bytes::complete::take(len)(input)?.0, None)
+        }
+        // UTF-8 String Pair
+        0x26 => (mqtt_string.and(mqtt_string).parse(input)?.0, None),
+        _ => {
+            return Err(nom::Err::Error(error::Error::new(
+                input,
+                error::ErrorKind::Switch,
+            )))
+        }
+    };
+    Ok((input, (id, value)))
+}
+
+fn mqtt_publish_properties(input: &[u8]) -> IResult<&[u8], (Option<&str>, Option<&str>)> {
+    let (input, mut properties) = mqtt_properties(input, MQTT_V5)?;
+    let (mut content_type, mut response_topic) = (None, None);
+    while !properties.is_empty() {
+        let (rest, (id, value)) = mqtt_property(properties)?;
+        match id {
+            PROPERTY_CONTENT_TYPE => content_type = value,
+            PROPERTY_RESPONSE_TOPIC => response_topic = value,
+            _ => (),
+        }
+        properties = rest;
+    }
+    Ok((input, (content_type, response_topic)))
+}
+
 #[cfg(test)]
 mod tests {
     use std::cell::RefCell;
@@ -850,6 +1014,7 @@ mod tests {
                 "mqtt_one_packet_multi_publish.result",
             ),
             ("mqtt_pub.pcap", "mqtt_pub.result"),
+            ("mqtt_v5.pcap", "mqtt_v5.result"),
         ];
 
         for item in files.iter() {
@@ -923,7 +1088,7 @@ mod tests {
             2,    // QoS 2
         ];
 
-        let (rest, subs) = mqtt_subscription_requests(input).unwrap();
+        let (rest, subs) = mqtt_subscription_requests(input, 4).unwrap();
         assert_eq!(rest.len(), 0);
         assert_eq!(
             subs,
@@ -988,6 +1153,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_v5_properties() {
+        let input = &[
+            0x1C, // Properties length
+            0x01, 0x01, // Payload format indicator
+            0x03, 0x00, 0x0A, b't', b'e', b'x', b't', b'/', b'p', b'l', b'a', b'i',
+            b'n', // Content type
+            0x08, 0x00, 0x05, b'r', b'e', b'p', b'l', b'y', // Response topic
+            0x09, 0x00, 0x02, 0xAB, 0xCD, // Correlation data
+            b'o', b'k', // Payload
+        ];
+
+        let (rest, properties) = mqtt_publish_properties(input).unwrap();
+        assert_eq!(rest, b"ok");
+        assert_eq!(properties, (Some("text/plain"), Some("reply")));
+
+        let (rest, _) = mqtt_skip_properties(input, 4).unwrap();
+        assert_eq!(rest.len(), input.len());
+        let (rest, _) = mqtt_skip_properties(input, MQTT_V5).unwrap();
+        assert_eq!(rest, b"ok");
+    }
+
     #[test]
     fn check_simple_string() {
         let input = [0x00, 0x05, 0x41, 0xF0, 0xAA, 0x9B, 0x94];
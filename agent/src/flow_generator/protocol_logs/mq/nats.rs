@@ -0,0 +1,531 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::{
+        error::{Error, Result},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+};
+
+const CRLF: &[u8] = b"\r\n";
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct NatsInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub command: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub subject: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub reply_to: String,
+
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error: String,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for NatsInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::NatsInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.status = other.status;
+                self.error = other.error;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Nats,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<NatsInfo> for L7ProtocolSendLog {
+    fn from(f: NatsInfo) -> Self {
+        let attributes = if f.reply_to.is_empty() {
+            None
+        } else {
+            Some(vec![KeyVal {
+                key: String::from("reply_to"),
+                val: f.reply_to,
+            }])
+        };
+        L7ProtocolSendLog {
+            req: L7Request {
+                req_type: f.command,
+                resource: f.subject,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                exception: f.error,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NatsLog {
+    info: Vec<NatsInfo>,
+    // CONNECT 中的 verbose, 开启时服务端用 +OK 响应每个客户端命令, 默认关闭
+    // verbose in CONNECT, the server responds every client command with +OK when enabled,
+    // disabled by default
+    #[serde(skip)]
+    verbose: bool,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for NatsLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() {
+            return false;
+        }
+        self.nats_check_protocol(payload, param)
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+
+        self.parse(payload, param.l4_protocol, param.direction)?;
+        let mut v = vec![];
+        for mut info in self.info.drain(..) {
+            info.is_tls = param.is_tls();
+            match info.msg_type {
+                LogMessageType::Request => self.perf_stats.as_mut().unwrap().inc_req(),
+                LogMessageType::Response => self.perf_stats.as_mut().unwrap().inc_resp(),
+                _ => {}
+            }
+            if info.msg_type != LogMessageType::Session {
+                info.cal_rrt(param).map(|rrt| {
+                    info.rrt = rrt;
+                    self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+                });
+            }
+            v.push(L7ProtocolInfo::NatsInfo(info));
+        }
+        Ok(v)
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Nats
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[4222]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info.clear();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl NatsLog {
+    // 连接建立时服务端发送 INFO {...}, 或者报文以带参数的命令开头且能完整解析
+    // PING/PONG/+OK 过于简单容易误识别, 不用于协议识别
+    // ===============================================================================
+    // on connect the server sends `INFO {...}`, otherwise the payload must start with a
+    // verb carrying arguments and the whole payload must be parsable.
+    // PING/PONG/+OK are too short to identify the protocol.
+    fn nats_check_protocol(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        if payload.starts_with(b"INFO {") {
+            return param.direction == PacketDirection::ServerToClient;
+        }
+
+        let Some(verb) = payload.split(|c| *c == b' ').next() else {
+            return false;
+        };
+        let ok = match (verb, param.direction) {
+            (b"CONNECT" | b"PUB" | b"HPUB" | b"SUB", PacketDirection::ClientToServer) => true,
+            (b"MSG" | b"HMSG", PacketDirection::ServerToClient) => true,
+            _ => false,
+        };
+        let ok = ok
+            && self
+                .parse(payload, param.l4_protocol, param.direction)
+                .is_ok();
+        self.reset();
+        ok
+    }
+
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        direction: PacketDirection,
+    ) -> Result<()> {
+        if proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.info.clear();
+
+        // 协议以 \r\n 分隔, 一个报文中可能有多个命令
+        // the protocol is delimited by \r\n, one payload may contain many commands
+        let mut rest = payload;
+        while !rest.is_empty() {
+            let Some(end) = find_crlf(rest) else {
+                break;
+            };
+            let line = &rest[..end];
+            rest = &rest[end + CRLF.len()..];
+
+            let Some((info, payload_size)) = self.parse_line(line, direction) else {
+                return Err(Error::NatsLogParseFailed);
+            };
+            self.info.push(info);
+
+            // 跳过 PUB/MSG 携带的消息体
+            // skip message body of PUB/MSG
+            if let Some(size) = payload_size {
+                let Some(size) = size.checked_add(CRLF.len()) else {
+                    break;
+                };
+                if rest.len() < size {
+                    break;
+                }
+                rest = &rest[size..];
+            }
+        }
+
+        if self.info.is_empty() {
+            return Err(Error::NatsLogParseFailed);
+        }
+        Ok(())
+    }
+
+    // 返回解析出的 info 和命令携带的消息体长度
+    // 客户端命令只有在 verbose 模式下才有 +OK 响应, 否则记为 Session, 避免请求一直等不到响应
+    // 客户端发送的 PING 和服务端的 PONG 是请求和响应, 服务端发送的 PING 和客户端的 PONG 记为 Session
+    // ===============================================================================
+    // return the info and the message body size carried by the command.
+    // client commands are responded with +OK only in verbose mode, otherwise they are logged as
+    // Session, so that requests do not wait for responses that never come.
+    // PING from client and PONG from server are request and response, PING from server and PONG
+    // from client are logged as Session.
+    fn parse_line(
+        &mut self,
+        line: &[u8],
+        direction: PacketDirection,
+    ) -> Option<(NatsInfo, Option<usize>)> {
+        let line = std::str::from_utf8(line).ok()?;
+        let (verb, args) = match line.split_once(|c| c == ' ' || c == '\t') {
+            Some((verb, args)) => (verb, args.trim()),
+            None => (line, ""),
+        };
+        let verb = verb.to_ascii_uppercase();
+        if verb == "CONNECT" {
+            self.verbose = serde_json::from_str::<serde_json::Value>(args)
+                .ok()
+                .and_then(|options| options.get("verbose")?.as_bool())
+                .unwrap_or_default();
+        }
+        let command_type = if self.verbose {
+            LogMessageType::Request
+        } else {
+            LogMessageType::Session
+        };
+        let mut info = NatsInfo::default();
+        let mut payload_size = None;
+        match verb.as_str() {
+            // PUB <subject> [reply-to] <#bytes>
+            "PUB" => {
+                let args = args.split_whitespace().collect::<Vec<_>>();
+                let (subject, reply_to, size) = match args.as_slice() {
+                    [subject, size] => (*subject, "", *size),
+                    [subject, reply_to, size] => (*subject, *reply_to, *size),
+                    _ => return None,
+                };
+                info.msg_type = command_type;
+                info.subject = subject.to_owned();
+                info.reply_to = reply_to.to_owned();
+                payload_size = Some(size.parse::<usize>().ok()?);
+            }
+            // HPUB <subject> [reply-to] <#header bytes> <#total bytes>
+            "HPUB" => {
+                let args = args.split_whitespace().collect::<Vec<_>>();
+                let (subject, reply_to, size) = match args.as_slice() {
+                    [subject, _, size] => (*subject, "", *size),
+                    [subject, reply_to, _, size] => (*subject, *reply_to, *size),
+                    _ => return None,
+                };
+                info.msg_type = command_type;
+                info.subject = subject.to_owned();
+                info.reply_to = reply_to.to_owned();
+                payload_size = Some(size.parse::<usize>().ok()?);
+            }
+            // MSG <subject> <sid> [reply-to] <#bytes>
+            "MSG" => {
+                let args = args.split_whitespace().collect::<Vec<_>>();
+                let (subject, reply_to, size) = match args.as_slice() {
+                    [subject, _, size] => (*subject, "", *size),
+                    [subject, _, reply_to, size] => (*subject, *reply_to, *size),
+                    _ => return None,
+                };
+                info.msg_type = LogMessageType::Session;
+                info.subject = subject.to_owned();
+                info.reply_to = reply_to.to_owned();
+                payload_size = Some(size.parse::<usize>().ok()?);
+            }
+            // HMSG <subject> <sid> [reply-to] <#header bytes> <#total bytes>
+            "HMSG" => {
+                let args = args.split_whitespace().collect::<Vec<_>>();
+                let (subject, reply_to, size) = match args.as_slice() {
+                    [subject, _, _, size] => (*subject, "", *size),
+                    [subject, _, reply_to, _, size] => (*subject, *reply_to, *size),
+                    _ => return None,
+                };
+                info.msg_type = LogMessageType::Session;
+                info.subject = subject.to_owned();
+                info.reply_to = reply_to.to_owned();
+                payload_size = Some(size.parse::<usize>().ok()?);
+            }
+            // SUB <subject> [queue group] <sid>
+            "SUB" => {
+                let subject = args.split_whitespace().next()?;
+                info.msg_type = command_type;
+                info.subject = subject.to_owned();
+            }
+            "UNSUB" | "CONNECT" => info.msg_type = command_type,
+            "PING" if direction == PacketDirection::ClientToServer => {
+                info.msg_type = LogMessageType::Request
+            }
+            "PONG" if direction == PacketDirection::ServerToClient => {
+                info.msg_type = LogMessageType::Response
+            }
+            "PING" | "PONG" => info.msg_type = LogMessageType::Session,
+            "+OK" => info.msg_type = LogMessageType::Response,
+            "-ERR" => {
+                info.msg_type = LogMessageType::Response;
+                info.error = args.trim_matches('\'').to_owned();
+                info.status = self.parse_error(&info.error);
+            }
+            "INFO" => info.msg_type = LogMessageType::Session,
+            _ => return None,
+        }
+        info.command = verb;
+        Some((info, payload_size))
+    }
+
+    // 参考：https://docs.nats.io/reference/reference-protocols/nats-protocol#-err
+    fn parse_error(&mut self, error: &str) -> L7ResponseStatus {
+        let error = error.to_ascii_lowercase();
+        if error.starts_with("maximum connections exceeded")
+            || error.starts_with("stale connection")
+            || error.starts_with("slow consumer")
+        {
+            if let Some(perf_stats) = self.perf_stats.as_mut() {
+                perf_stats.inc_resp_err();
+            }
+            L7ResponseStatus::ServerError
+        } else {
+            if let Some(perf_stats) = self.perf_stats.as_mut() {
+                perf_stats.inc_req_err();
+            }
+            L7ResponseStatus::ClientError
+        }
+    }
+}
+
+fn find_crlf(payload: &[u8]) -> Option<usize> {
+    payload.windows(CRLF.len()).position(|w| w == CRLF)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::ProtocolTester;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/nats";
+    const NATS_PORT: u16 = 4222;
+
+    fn tester() -> ProtocolTester<NatsLog> {
+        ProtocolTester::new(NatsLog::default())
+            .server_port(NATS_PORT)
+            .step(|nats, payload, param| {
+                let is_nats = nats.nats_check_protocol(payload, param);
+                let mut lines = String::new();
+                for i in nats.parse_payload(payload, param).unwrap_or_default() {
+                    let L7ProtocolInfo::NatsInfo(mut i) = i else {
+                        unreachable!()
+                    };
+                    i.rrt = 0;
+                    lines.push_str(&format!("{:?} is_nats: {}\r\n", i, is_nats));
+                }
+                lines
+            })
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(
+            dir.join("nats_pub_msg.pcap"),
+            dir.join("nats_pub_msg.result"),
+        );
+    }
+
+    #[test]
+    fn parse_multi_commands() {
+        let mut nats = NatsLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let payload = b"SUB foo.* 1\r\nPUB foo.bar _INBOX.1 5\r\nhello\r\nPING\r\n";
+        nats.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(nats.info.len(), 3);
+        assert_eq!(nats.info[0].command, "SUB");
+        assert_eq!(nats.info[0].subject, "foo.*");
+        assert_eq!(nats.info[1].command, "PUB");
+        assert_eq!(nats.info[1].subject, "foo.bar");
+        assert_eq!(nats.info[1].reply_to, "_INBOX.1");
+        assert_eq!(nats.info[2].command, "PING");
+
+        let payload = b"MSG foo.bar 1 _INBOX.1 5\r\nhello\r\n-ERR 'Permissions Violation for Publish to foo'\r\n";
+        nats.parse(payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(nats.info.len(), 2);
+        assert_eq!(nats.info[0].command, "MSG");
+        assert_eq!(nats.info[0].subject, "foo.bar");
+        assert_eq!(nats.info[0].reply_to, "_INBOX.1");
+        assert_eq!(nats.info[0].msg_type, LogMessageType::Session);
+        assert_eq!(nats.info[1].command, "-ERR");
+        assert_eq!(
+            nats.info[1].error,
+            "Permissions Violation for Publish to foo"
+        );
+        assert_eq!(nats.info[1].status, L7ResponseStatus::ClientError);
+
+        assert!(nats
+            .parse(
+                b"FOO bar\r\n",
+                IpProtocol::Tcp,
+                PacketDirection::ClientToServer
+            )
+            .is_err());
+
+        // 消息体长度溢出时停止解析
+        // parsing stops when the message body size overflows
+        let payload = b"PUB foo 18446744073709551615\r\nPING\r\n";
+        nats.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(nats.info.len(), 1);
+        assert_eq!(nats.info[0].command, "PUB");
+        assert_eq!(nats.info[0].subject, "foo");
+    }
+
+    #[test]
+    fn parse_msg_type() {
+        let mut nats = NatsLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let client_types = |nats: &mut NatsLog, payload: &[u8]| {
+            nats.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+                .unwrap();
+            nats.info.iter().map(|i| i.msg_type).collect::<Vec<_>>()
+        };
+
+        // 默认非 verbose 模式下服务端不会响应 +OK
+        // the server does not respond +OK in the default non-verbose mode
+        let payload = b"CONNECT {\"verbose\":false}\r\nSUB foo 1\r\nPUB foo 5\r\nhello\r\nPING\r\n";
+        assert_eq!(
+            client_types(&mut nats, payload),
+            vec![
+                LogMessageType::Session,
+                LogMessageType::Session,
+                LogMessageType::Session,
+                LogMessageType::Request,
+            ]
+        );
+
+        let payload = b"CONNECT {\"verbose\":true}\r\nUNSUB 1\r\nPONG\r\n";
+        assert_eq!(
+            client_types(&mut nats, payload),
+            vec![
+                LogMessageType::Request,
+                LogMessageType::Request,
+                LogMessageType::Session,
+            ]
+        );
+
+        // 服务端发送的 PING 不是请求
+        // PING sent by the server is not a request
+        nats.parse(
+            b"PING\r\nPONG\r\n",
+            IpProtocol::Tcp,
+            PacketDirection::ServerToClient,
+        )
+        .unwrap();
+        assert_eq!(nats.info[0].msg_type, LogMessageType::Session);
+        assert_eq!(nats.info[1].msg_type, LogMessageType::Response);
+    }
+}
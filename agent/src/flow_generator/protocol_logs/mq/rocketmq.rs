@@ -0,0 +1,473 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::{
+        error::{Error, Result},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    utils::bytes::{read_u16_be, read_u32_be},
+};
+
+// 参考：https://github.com/apache/rocketmq/blob/develop/remoting/src/main/java/org/apache/rocketmq/remoting/protocol/RemotingCommand.java
+// frame: total length(4) | serialize type(1) + header length(3) | header | body
+const FRAME_LEN_SIZE: usize = 4;
+const HEADER_LEN_SIZE: usize = 4;
+const HEADER_LEN_MASK: u32 = 0xffffff;
+
+const SERIALIZE_TYPE_JSON: u8 = 0;
+const SERIALIZE_TYPE_ROCKETMQ: u8 = 1;
+
+// flag
+const FLAG_RPC_TYPE_RESPONSE: i32 = 1;
+const FLAG_RPC_ONEWAY: i32 = 2;
+
+// ROCKETMQ header: code(2) | language(1) | version(2) | opaque(4) | flag(4) | remark | ext fields
+const ROCKETMQ_HEADER_FIXED_LEN: usize = 13;
+
+const TOPIC_KEY: &str = "topic";
+// SEND_MESSAGE_V2 等请求使用缩写的字段名, b 为 topic
+// requests such as SEND_MESSAGE_V2 use abbreviated field names, b is topic
+const TOPIC_KEY_V2: &str = "b";
+
+// request code
+const SEND_MESSAGE: i32 = 10;
+const PULL_MESSAGE: i32 = 11;
+const QUERY_MESSAGE: i32 = 12;
+const QUERY_CONSUMER_OFFSET: i32 = 14;
+const UPDATE_CONSUMER_OFFSET: i32 = 15;
+const UPDATE_AND_CREATE_TOPIC: i32 = 17;
+const HEART_BEAT: i32 = 34;
+const UNREGISTER_CLIENT: i32 = 35;
+const CONSUMER_SEND_MSG_BACK: i32 = 36;
+const END_TRANSACTION: i32 = 37;
+const GET_CONSUMER_LIST_BY_GROUP: i32 = 38;
+const LOCK_BATCH_MQ: i32 = 41;
+const UNLOCK_BATCH_MQ: i32 = 42;
+const GET_ROUTEINFO_BY_TOPIC: i32 = 105;
+const SEND_MESSAGE_V2: i32 = 310;
+const SEND_BATCH_MESSAGE: i32 = 320;
+
+// response code
+// 参考：https://github.com/apache/rocketmq/blob/develop/remoting/src/main/java/org/apache/rocketmq/remoting/protocol/ResponseCode.java
+const SUCCESS: i32 = 0;
+const REQUEST_CODE_NOT_SUPPORTED: i32 = 3;
+const MESSAGE_ILLEGAL: i32 = 13;
+const VERSION_NOT_SUPPORTED: i32 = 15;
+const NO_PERMISSION: i32 = 16;
+const TOPIC_NOT_EXIST: i32 = 17;
+const TOPIC_EXIST_ALREADY: i32 = 18;
+const PULL_NOT_FOUND: i32 = 19;
+const PULL_RETRY_IMMEDIATELY: i32 = 20;
+const PULL_OFFSET_MOVED: i32 = 21;
+const QUERY_NOT_FOUND: i32 = 22;
+const SUBSCRIPTION_PARSE_FAILED: i32 = 23;
+const SUBSCRIPTION_NOT_EXIST: i32 = 24;
+const SUBSCRIPTION_NOT_LATEST: i32 = 25;
+const SUBSCRIPTION_GROUP_NOT_EXIST: i32 = 26;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct RocketMqInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
+    pub opaque: u32,
+
+    // request
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_msg_size: Option<u32>,
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub request_code: i32,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub topic: String,
+
+    // response
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_msg_size: Option<u32>,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub response_code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub remark: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for RocketMqInfo {
+    fn session_id(&self) -> Option<u32> {
+        Some(self.opaque)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::RocketMqInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.resp_msg_size = other.resp_msg_size;
+                self.response_code = other.response_code;
+                self.remark = other.remark;
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::RocketMq,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl RocketMqInfo {
+    pub fn get_command(&self) -> &'static str {
+        match self.request_code {
+            SEND_MESSAGE => "SendMessage",
+            PULL_MESSAGE => "PullMessage",
+            QUERY_MESSAGE => "QueryMessage",
+            QUERY_CONSUMER_OFFSET => "QueryConsumerOffset",
+            UPDATE_CONSUMER_OFFSET => "UpdateConsumerOffset",
+            UPDATE_AND_CREATE_TOPIC => "UpdateAndCreateTopic",
+            HEART_BEAT => "HeartBeat",
+            UNREGISTER_CLIENT => "UnregisterClient",
+            CONSUMER_SEND_MSG_BACK => "ConsumerSendMsgBack",
+            END_TRANSACTION => "EndTransaction",
+            GET_CONSUMER_LIST_BY_GROUP => "GetConsumerListByGroup",
+            LOCK_BATCH_MQ => "LockBatchMQ",
+            UNLOCK_BATCH_MQ => "UnlockBatchMQ",
+            GET_ROUTEINFO_BY_TOPIC => "GetRouteinfoByTopic",
+            SEND_MESSAGE_V2 => "SendMessageV2",
+            SEND_BATCH_MESSAGE => "SendBatchMessage",
+            _ => "",
+        }
+    }
+
+    fn set_status(&mut self, code: i32) {
+        self.response_code = Some(code);
+        self.status = match code {
+            SUCCESS
+            | PULL_NOT_FOUND
+            | PULL_RETRY_IMMEDIATELY
+            | PULL_OFFSET_MOVED
+            | QUERY_NOT_FOUND => L7ResponseStatus::Ok,
+            REQUEST_CODE_NOT_SUPPORTED
+            | MESSAGE_ILLEGAL
+            | VERSION_NOT_SUPPORTED
+            | NO_PERMISSION
+            | TOPIC_NOT_EXIST
+            | TOPIC_EXIST_ALREADY
+            | SUBSCRIPTION_PARSE_FAILED
+            | SUBSCRIPTION_NOT_EXIST
+            | SUBSCRIPTION_NOT_LATEST
+            | SUBSCRIPTION_GROUP_NOT_EXIST => L7ResponseStatus::ClientError,
+            _ => L7ResponseStatus::ServerError,
+        };
+    }
+}
+
+impl From<RocketMqInfo> for L7ProtocolSendLog {
+    fn from(f: RocketMqInfo) -> Self {
+        let req_type = match f.get_command() {
+            "" if f.msg_type != LogMessageType::Response => f.request_code.to_string(),
+            command => String::from(command),
+        };
+        L7ProtocolSendLog {
+            req_len: f.req_msg_size,
+            resp_len: f.resp_msg_size,
+            req: L7Request {
+                req_type,
+                resource: f.topic,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.response_code,
+                exception: f.remark,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: Some(f.opaque),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+// 从 JSON 或 ROCKETMQ 格式的头部中解析出的字段
+// fields decoded from header in JSON or ROCKETMQ format
+#[derive(Default)]
+struct Header {
+    code: i32,
+    opaque: u32,
+    flag: i32,
+    remark: String,
+    topic: String,
+}
+
+impl Header {
+    fn decode(serialize_type: u8, header: &[u8]) -> Option<Self> {
+        match serialize_type {
+            SERIALIZE_TYPE_JSON => Self::decode_json(header),
+            SERIALIZE_TYPE_ROCKETMQ => Self::decode_rocketmq(header),
+            _ => None,
+        }
+    }
+
+    // {"code":310,"extFields":{"b":"TopicTest",...},"flag":0,"language":"JAVA","opaque":1,...}
+    fn decode_json(header: &[u8]) -> Option<Self> {
+        let value: Value = serde_json::from_slice(header).ok()?;
+        let value = value.as_object()?;
+        // language 为必有字段, 用于避免误识别
+        // language is a required field, used to avoid misidentification
+        value.get("language")?.as_str()?;
+        let mut h = Header {
+            code: value.get("code")?.as_i64()? as i32,
+            opaque: value.get("opaque")?.as_i64()? as u32,
+            flag: value.get("flag")?.as_i64()? as i32,
+            remark: value
+                .get("remark")
+                .and_then(|r| r.as_str())
+                .unwrap_or_default()
+                .to_owned(),
+            ..Default::default()
+        };
+        if let Some(ext) = value.get("extFields").and_then(|e| e.as_object()) {
+            let topic = match h.code {
+                SEND_MESSAGE_V2 | SEND_BATCH_MESSAGE => ext.get(TOPIC_KEY_V2),
+                _ => ext.get(TOPIC_KEY),
+            };
+            if let Some(topic) = topic.and_then(|t| t.as_str()) {
+                h.topic = topic.to_owned();
+            }
+        }
+        Some(h)
+    }
+
+    fn decode_rocketmq(header: &[u8]) -> Option<Self> {
+        if header.len() < ROCKETMQ_HEADER_FIXED_LEN {
+            return None;
+        }
+        let mut h = Header {
+            code: read_u16_be(header) as i16 as i32,
+            opaque: read_u32_be(&header[5..]),
+            flag: read_u32_be(&header[9..]) as i32,
+            ..Default::default()
+        };
+        let mut data = &header[ROCKETMQ_HEADER_FIXED_LEN..];
+        let remark = read_bytes(&mut data, 4)?;
+        h.remark = String::from_utf8_lossy(remark).into_owned();
+
+        if data.len() < 4 {
+            return Some(h);
+        }
+        let ext_len = read_u32_be(data) as usize;
+        data = &data[4..];
+        let mut ext = &data[..ext_len.min(data.len())];
+        let topic_key = match h.code {
+            SEND_MESSAGE_V2 | SEND_BATCH_MESSAGE => TOPIC_KEY_V2,
+            _ => TOPIC_KEY,
+        };
+        while !ext.is_empty() {
+            let key = read_bytes(&mut ext, 2)?;
+            let value = read_bytes(&mut ext, 4)?;
+            if key == topic_key.as_bytes() {
+                h.topic = String::from_utf8_lossy(value).into_owned();
+                break;
+            }
+        }
+        Some(h)
+    }
+}
+
+// 读取长度前缀的字节, len_size 为长度字段的字节数
+// read length-prefixed bytes, len_size is the size of length field
+fn read_bytes<'a>(data: &mut &'a [u8], len_size: usize) -> Option<&'a [u8]> {
+    if data.len() < len_size {
+        return None;
+    }
+    let len = match len_size {
+        2 => read_u16_be(data) as usize,
+        _ => read_u32_be(data) as usize,
+    };
+    if data.len() < len_size + len {
+        return None;
+    }
+    let bytes = &data[len_size..len_size + len];
+    *data = &data[len_size + len..];
+    Some(bytes)
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct RocketMqLog {
+    info: RocketMqInfo,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for RocketMqLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol()
+            || param.l4_protocol != IpProtocol::Tcp
+            || param.direction != PacketDirection::ClientToServer
+            || payload.len() < FRAME_LEN_SIZE + HEADER_LEN_SIZE
+        {
+            return false;
+        }
+        if read_u32_be(payload) as usize != payload.len() - FRAME_LEN_SIZE {
+            return false;
+        }
+        match Self::decode(payload) {
+            Some(h) => h.flag & FLAG_RPC_TYPE_RESPONSE == 0,
+            None => false,
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.info.is_tls = param.is_tls();
+        self.parse(payload)?;
+        self.info.cal_rrt(param).map(|rrt| {
+            self.info.rrt = rrt;
+            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+        });
+        Ok(vec![L7ProtocolInfo::RocketMqInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::RocketMq
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[9876, 10911]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = RocketMqInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl RocketMqLog {
+    fn decode(payload: &[u8]) -> Option<Header> {
+        if payload.len() < FRAME_LEN_SIZE + HEADER_LEN_SIZE {
+            return None;
+        }
+        let v = read_u32_be(&payload[FRAME_LEN_SIZE..]);
+        let serialize_type = (v >> 24) as u8;
+        let header_len = (v & HEADER_LEN_MASK) as usize;
+        let header = &payload[FRAME_LEN_SIZE + HEADER_LEN_SIZE..];
+        if header.len() < header_len {
+            return None;
+        }
+        Header::decode(serialize_type, &header[..header_len])
+    }
+
+    fn parse(&mut self, payload: &[u8]) -> Result<()> {
+        let header = Self::decode(payload).ok_or(Error::RocketMqLogParseFailed)?;
+        let frame_len = Some(read_u32_be(payload));
+        self.info.opaque = header.opaque;
+        if header.flag & FLAG_RPC_TYPE_RESPONSE != 0 {
+            self.info.msg_type = LogMessageType::Response;
+            self.info.resp_msg_size = frame_len;
+            self.info.remark = header.remark;
+            self.info.set_status(header.code);
+            let perf_stats = self.perf_stats.as_mut().unwrap();
+            perf_stats.inc_resp();
+            match self.info.status {
+                L7ResponseStatus::ClientError => perf_stats.inc_req_err(),
+                L7ResponseStatus::ServerError => perf_stats.inc_resp_err(),
+                _ => {}
+            }
+        } else {
+            // oneway 请求没有响应, 直接作为会话发送
+            // oneway request has no response, send as session directly
+            self.info.msg_type = if header.flag & FLAG_RPC_ONEWAY != 0 {
+                LogMessageType::Session
+            } else {
+                LogMessageType::Request
+            };
+            self.info.req_msg_size = frame_len;
+            self.info.request_code = header.code;
+            self.info.topic = header.topic;
+            self.perf_stats.as_mut().unwrap().inc_req();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::ProtocolTester;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/rocketmq";
+
+    fn tester() -> ProtocolTester<RocketMqLog> {
+        ProtocolTester::new(RocketMqLog::default()).step(|_, payload, param| {
+            // 每个包使用新的解析器
+            // a new parser for each packet
+            let mut rocketmq = RocketMqLog::default();
+            let is_rocketmq = rocketmq.check_payload(payload, param);
+            let _ = rocketmq.parse_payload(payload, param);
+            rocketmq.info.rrt = 0;
+            format!("{:?} is_rocketmq: {}\r\n", rocketmq.info, is_rocketmq)
+        })
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(dir.join("rocketmq.pcap"), dir.join("rocketmq.result"));
+    }
+}
@@ -57,13 +57,38 @@ use public::{
 
 const QUEUE_BATCH_SIZE: usize = 1024;
 const RCV_TIMEOUT: Duration = Duration::from_secs(1);
-// 尽力而为的聚合默认120秒(AppProtoLogs.aggr*SLOT_WIDTH)内的请求和响应
-const SLOT_WIDTH: u64 = 10; // 每个slot存10秒
+// 尽力而为的聚合默认120秒(l7_log_session_aggr_timeout)内的请求和响应,
+// 每个slot默认存10秒(l7_log_session_slot_width), slot数量至少为2
+const MIN_SLOT_COUNT: usize = 2;
 const SLOT_CACHED_COUNT: u64 = 100000; // 每个slot平均缓存的FLOW数
 
 const THROTTLE_BUCKET_BITS: u8 = 2;
 const THROTTLE_BUCKET: usize = 1 << THROTTLE_BUCKET_BITS; // 2^N。由于发送方是有突发的，需要累积一定时间做采样
 
+const SESSION_KEY_SESSION_MASK: u32 = 0xFFFFFF;
+
+// 会话聚合的 key, 需保证流日志 1 分钟内唯一, 布局为:
+// | flow_id 高 8 位 | flow_id 低 24 位 | 协议 8 位 | session id 低 24 位 |
+// flow_id 高 8 位是时间的低 8 位, 低 24 位是流计数器
+// key of session aggregation, unique in one minute, the layout is:
+// | flow_id high 8 | flow_id low 24 | protocol 8 | session id low 24 |
+// high 8 bits of flow_id are the low 8 bits of time, low 24 bits are the flow counter
+pub fn session_key(flow_id: u64, proto: u8, session: u32) -> u64 {
+    get_uniq_flow_id_in_one_minute(flow_id) << 32
+        | (proto as u64) << 24
+        | (session & SESSION_KEY_SESSION_MASK) as u64
+}
+
+// 解析 session_key 生成的 key, 返回 (flow_id 高 8 位和低 24 位, 协议, session id 低 24 位)
+// decode key built by session_key, returns (flow_id high 8 and low 24 bits, protocol, session id low 24 bits)
+pub fn decode_session_key(key: u64) -> (u32, u8, u32) {
+    (
+        (key >> 32) as u32,
+        (key >> 24) as u8,
+        key as u32 & SESSION_KEY_SESSION_MASK,
+    )
+}
+
 #[derive(Debug)]
 pub struct MetaAppProto {
     base_info: AppProtoLogsBaseInfo,
@@ -186,6 +211,30 @@ pub struct SessionAggrCounter {
     merge: AtomicU64,
     cached: AtomicU64,
     throttle_drop: AtomicU64,
+    // 超出时间窗口仍未匹配而被移出的请求或响应
+    // requests or responses evicted from time window without match
+    evict_unmatched: AtomicU64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SessionAggrStats {
+    pub receive: u64,
+    pub merge: u64,
+    pub cached: u64,
+    pub evict_unmatched: u64,
+}
+
+impl SessionAggrCounter {
+    // 读取但不清零计数, 用于调整时间窗口
+    // read counters without reset, used to tune the time window
+    pub fn stats(&self) -> SessionAggrStats {
+        SessionAggrStats {
+            receive: self.receive.load(Ordering::Relaxed),
+            merge: self.merge.load(Ordering::Relaxed),
+            cached: self.cached.load(Ordering::Relaxed),
+            evict_unmatched: self.evict_unmatched.load(Ordering::Relaxed),
+        }
+    }
 }
 
 // FIXME: counter not registered
@@ -217,6 +266,11 @@ impl RefCountable for SessionAggrCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.throttle_drop.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "evict-unmatched",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.evict_unmatched.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
@@ -226,6 +280,7 @@ struct SessionQueue {
     last_flush_time: Duration,
 
     window_size: usize,
+    slot_width: u64,
     time_window: Option<Vec<HashMap<u64, AppProtoLogsData>>>,
 
     log_rate: Arc<LeakyBucket>,
@@ -243,8 +298,10 @@ impl SessionQueue {
         log_rate: Arc<LeakyBucket>,
     ) -> Self {
         //l7_log_session_timeout 20s-300s ，window_size = 2-30，所以 SessionQueue.time_window 预分配内存
-        let window_size =
-            (config.load().l7_log_session_aggr_timeout.as_secs() / SLOT_WIDTH) as usize;
+        let slot_width = config.load().l7_log_session_slot_width.as_secs().max(1);
+        let window_size = ((config.load().l7_log_session_aggr_timeout.as_secs() / slot_width)
+            as usize)
+            .max(MIN_SLOT_COUNT);
         let time_window = vec![HashMap::new(); window_size];
 
         Self {
@@ -253,6 +310,7 @@ impl SessionQueue {
             time_window: Some(time_window),
             config,
             window_size,
+            slot_width,
 
             log_rate,
 
@@ -273,7 +331,7 @@ impl SessionQueue {
             warn!("SystemTime::now call error check host associated time syscall");
             return;
         }
-        if interval.as_secs() < 2 * SLOT_WIDTH {
+        if interval.as_secs() < 2 * self.slot_width {
             return;
         }
         let mut time_window = match self.time_window.take() {
@@ -324,7 +382,8 @@ impl SessionQueue {
             return;
         }
 
-        let mut slot = ((slot_time - self.aggregate_start_time.as_secs()) / SLOT_WIDTH) as usize;
+        let mut slot =
+            ((slot_time - self.aggregate_start_time.as_secs()) / self.slot_width) as usize;
         let mut time_window = match self.time_window.take() {
             Some(t) => t,
             None => return,
@@ -505,8 +564,11 @@ impl SessionQueue {
         } else {
             0
         };
-        // key需保证流日志1分钟内唯一，由1分钟内唯一的flow_id和request_id组成
-        get_uniq_flow_id_in_one_minute(item.base_info.flow_id) << 32 | (request_id as u64)
+        session_key(
+            item.base_info.flow_id,
+            item.base_info.head.proto as u8,
+            request_id,
+        )
     }
 
     fn flush_window(&mut self, n: usize, time_window: &mut Vec<HashMap<u64, AppProtoLogsData>>) {
@@ -516,6 +578,13 @@ impl SessionQueue {
             self.counter
                 .cached
                 .fetch_sub(map.len() as u64, Ordering::Relaxed);
+            let unmatched = map
+                .values()
+                .filter(|item| item.base_info.head.msg_type != LogMessageType::Session)
+                .count();
+            self.counter
+                .evict_unmatched
+                .fetch_add(unmatched as u64, Ordering::Relaxed);
             self.send_all(map.drain().map(|(_, item)| item).collect());
         }
         let mut maps = time_window.drain(0..delete_num).collect();
@@ -523,7 +592,7 @@ impl SessionQueue {
 
         // update timestamp
         self.aggregate_start_time =
-            Duration::from_secs(self.aggregate_start_time.as_secs() + n as u64 * SLOT_WIDTH);
+            Duration::from_secs(self.aggregate_start_time.as_secs() + n as u64 * self.slot_width);
     }
 
     fn send(&mut self, item: AppProtoLogsData) {
@@ -649,3 +718,191 @@ impl SessionAggregator {
         info!("app protocol logs parser (id={}) stopped", self.id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arc_swap::{access::Map, ArcSwap};
+
+    use crate::{
+        common::flow::L7Protocol,
+        config::handler::{LogParserConfig, ModuleConfig},
+        flow_generator::protocol_logs::DnsInfo,
+    };
+    use public::{debug::QueueDebugger, queue};
+
+    fn new_queue(
+        aggr_timeout: u64,
+        slot_width: u64,
+    ) -> (SessionQueue, Receiver<BoxAppProtoLogsData>) {
+        let config = ModuleConfig {
+            log_parser: LogParserConfig {
+                l7_log_session_aggr_timeout: Duration::from_secs(aggr_timeout),
+                l7_log_session_slot_width: Duration::from_secs(slot_width),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let current_config = Arc::new(ArcSwap::from_pointee(config));
+        let (output_queue, receiver, _) = queue::bounded_with_debug(16, "", &QueueDebugger::new());
+        let session_queue = SessionQueue::new(
+            Default::default(),
+            output_queue,
+            Map::new(current_config, |config| -> &LogParserConfig {
+                &config.log_parser
+            }),
+            Arc::new(LeakyBucket::new(None)),
+        );
+        (session_queue, receiver)
+    }
+
+    fn new_log(msg_type: LogMessageType, time: u64, rrt: u64, trans_id: u16) -> AppProtoLogsData {
+        let info = DnsInfo {
+            trans_id,
+            ..Default::default()
+        };
+        let head = AppProtoHead {
+            proto: L7Protocol::DNS,
+            msg_type,
+            rrt: Duration::from_secs(rrt).as_micros() as u64,
+        };
+        let meta = MetaAppProto::new(
+            &TaggedFlow::default(),
+            &MetaPacket::default(),
+            L7ProtocolInfo::DnsInfo(info),
+            head,
+        )
+        .unwrap();
+        let mut log = AppProtoLogsData::new(meta.base_info, meta.l7_info, meta.direction_score);
+        log.base_info.start_time = Duration::from_secs(time);
+        log.base_info.end_time = Duration::from_secs(time);
+        log
+    }
+
+    fn aggregate(
+        session_queue: &mut SessionQueue,
+        receiver: &Receiver<BoxAppProtoLogsData>,
+    ) -> Vec<LogMessageType> {
+        // 请求在 slot 0, 后续请求推动窗口前进, 响应在几个 slot 之后到达
+        // request in slot 0, later request moves the window forward, response arrives several slots later
+        session_queue.aggregate_session_and_send(new_log(LogMessageType::Request, 1000, 0, 1));
+        session_queue.aggregate_session_and_send(new_log(LogMessageType::Request, 1200, 0, 2));
+        session_queue.aggregate_session_and_send(new_log(LogMessageType::Response, 1250, 250, 1));
+
+        let mut output = Vec::with_capacity(16);
+        let _ = receiver.recv_all(&mut output, Some(Duration::from_millis(10)));
+        output
+            .into_iter()
+            .map(|log| log.0.base_info.head.msg_type)
+            .collect()
+    }
+
+    #[test]
+    fn check_slot_config() {
+        // 5 个 60 秒的 slot, 请求和响应在窗口内, 合并为会话
+        // 5 slots of 60s, request and response are in window, merged as session
+        let (mut session_queue, receiver) = new_queue(300, 60);
+        assert_eq!(session_queue.window_size, 5);
+        assert_eq!(
+            aggregate(&mut session_queue, &receiver),
+            vec![LogMessageType::Session]
+        );
+
+        // 2 个 10 秒的 slot, 请求被移出窗口, 请求和响应分别发送
+        // 2 slots of 10s, request is flushed out of window, request and response are sent separately
+        let (mut session_queue, receiver) = new_queue(20, 10);
+        assert_eq!(session_queue.window_size, 2);
+        assert_eq!(
+            aggregate(&mut session_queue, &receiver),
+            vec![LogMessageType::Request, LogMessageType::Response]
+        );
+
+        // slot 数量至少为 2
+        // at least 2 slots
+        let (session_queue, _) = new_queue(10, 60);
+        assert_eq!(session_queue.window_size, MIN_SLOT_COUNT);
+    }
+
+    #[test]
+    fn check_evict_counter() {
+        let (mut session_queue, receiver) = new_queue(20, 10);
+        let _ = aggregate(&mut session_queue, &receiver);
+        // 请求 1 超出窗口被移出, 请求 2 仍在缓存中, 响应在窗口之前直接发送
+        // request 1 is evicted out of window, request 2 is still cached, response is sent before window
+        assert_eq!(
+            session_queue.counter.stats(),
+            SessionAggrStats {
+                receive: 3,
+                merge: 0,
+                cached: 1,
+                evict_unmatched: 1,
+            }
+        );
+
+        let (mut session_queue, receiver) = new_queue(300, 60);
+        let _ = aggregate(&mut session_queue, &receiver);
+        assert_eq!(
+            session_queue.counter.stats(),
+            SessionAggrStats {
+                receive: 3,
+                merge: 1,
+                cached: 1,
+                evict_unmatched: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn check_session_rrt() {
+        // 响应没有携带 RRT 时, 合并后的会话用请求和响应的时间计算 RRT
+        // when response carries no rrt, merged session calculates rrt from request and response time
+        let (mut session_queue, receiver) = new_queue(300, 60);
+        session_queue.aggregate_session_and_send(new_log(LogMessageType::Request, 1000, 0, 1));
+        session_queue.aggregate_session_and_send(new_log(LogMessageType::Response, 1010, 0, 1));
+
+        let mut output = Vec::with_capacity(16);
+        let _ = receiver.recv_all(&mut output, Some(Duration::from_millis(10)));
+        assert_eq!(output.len(), 1);
+        let head = &output[0].0.base_info.head;
+        assert_eq!(head.msg_type, LogMessageType::Session);
+        assert_eq!(head.rrt, Duration::from_secs(10).as_micros() as u64);
+    }
+
+    #[test]
+    fn check_session_key() {
+        let flow_id = 0xAB12_3456_78CD_EF01;
+        let key = session_key(flow_id, L7Protocol::DNS as u8, 0x1234);
+        assert_eq!(key, 0xAB_CDEF01_00_001234 | (L7Protocol::DNS as u64) << 24);
+        assert_eq!(
+            decode_session_key(key),
+            (0xABCD_EF01, L7Protocol::DNS as u8, 0x1234)
+        );
+
+        // session id 的高 8 位被截断
+        // high 8 bits of session id are truncated
+        let key = session_key(flow_id, 0xFF, 0xFFAB_CDEF);
+        assert_eq!(key, 0xABCD_EF01_FFAB_CDEF);
+        assert_eq!(decode_session_key(key), (0xABCD_EF01, 0xFF, 0xAB_CDEF));
+        assert_eq!(key, session_key(flow_id, 0xFF, 0x00AB_CDEF));
+
+        // flow_id 只保留高 8 位和低 24 位
+        // only high 8 and low 24 bits of flow_id are kept
+        assert_eq!(
+            session_key(0xAB00_0000_FFCD_EF01, 0, 0),
+            session_key(flow_id, 0, 0)
+        );
+        assert_eq!(session_key(0, 0, 0), 0);
+    }
+
+    #[test]
+    fn check_log_to_json() {
+        let log = new_log(LogMessageType::Request, 1000, 0, 1);
+        let json: serde_json::Value = serde_json::from_str(&log.to_json()).unwrap();
+        assert_eq!(json["l7_protocol"], "DNS");
+        assert_eq!(json["msg_type"], "Request");
+        assert_eq!(json["request_id"], 1);
+        assert_eq!(json["start_time"], 1_000_000_000u64);
+        assert_eq!(json["direction_score"], 0);
+    }
+}
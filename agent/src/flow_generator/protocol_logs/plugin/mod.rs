@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "wasm")]
+pub use wasm::{WasmPlugin, WasmPluginParser};
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
+use super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::{
+    common::{
+        flow::L7Protocol,
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+    },
+    flow_generator::error::Result,
+};
+
+// 插件解析出的协议信息, 插件按此结构的 json 序列化格式返回
+// info of protocols parsed by plugins, plugins return it serialized as json in this layout
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct CustomInfo {
+    pub msg_type: LogMessageType,
+    #[serde(skip)]
+    pub is_tls: bool,
+    #[serde(skip)]
+    pub rrt: u64,
+    // 插件名称, 由 agent 填写
+    // name of plugin, filled by agent
+    #[serde(skip_deserializing)]
+    pub plugin: String,
+
+    #[serde(skip)]
+    pub req_len: Option<u32>,
+    #[serde(skip)]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub req_type: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub domain: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub resource: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub endpoint: String,
+
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub exception: String,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl L7ProtocolInfoInterface for CustomInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::CustomInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.resp_len = other.resp_len;
+                self.status = other.status;
+                self.code = other.code;
+                self.exception = other.exception;
+                self.attributes.extend(other.attributes);
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Custom,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<CustomInfo> for L7ProtocolSendLog {
+    fn from(f: CustomInfo) -> Self {
+        let mut attributes = vec![KeyVal {
+            key: "plugin".to_string(),
+            val: f.plugin,
+        }];
+        attributes.extend(
+            f.attributes
+                .into_iter()
+                .map(|(key, val)| KeyVal { key, val }),
+        );
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.req_type,
+                domain: f.domain,
+                resource: f.resource,
+                endpoint: f.endpoint,
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.code,
+                exception: f.exception,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes: Some(attributes),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,438 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+    WASM 插件 ABI, 插件需要导出:
+        memory: 线性内存
+        alloc(len: i32) -> i32: 分配 len 字节用于写入载荷, 返回地址, 地址在下一次调用前有效
+        check_payload(ptr, len, l4_protocol, direction, port_src, port_dst: i32) -> i32: 非 0 表示是该协议
+        parse_payload(ptr, len, l4_protocol, direction, port_src, port_dst: i32) -> i64:
+            返回 (地址 << 32 | 长度), 指向 json 序列化的 CustomInfo, 长度为 0 表示解析失败
+    l4_protocol 为 IP 协议号, direction 为 0 (客户端到服务端) 或 1 (服务端到客户端)
+    每个载荷的处理最多消耗 PLUGIN_CALL_FUEL 燃料, 耗尽时调用失败
+
+    WASM plugin ABI, plugins should export:
+        memory: the linear memory
+        alloc(len: i32) -> i32: allocate len bytes to write payload in, returns the address which is
+            valid until next call
+        check_payload(ptr, len, l4_protocol, direction, port_src, port_dst: i32) -> i32: non-zero
+            means the payload is of this protocol
+        parse_payload(ptr, len, l4_protocol, direction, port_src, port_dst: i32) -> i64:
+            returns (address << 32 | length) of CustomInfo serialized in json, length 0 means failure
+    l4_protocol is the IP protocol number, direction is 0 (client to server) or 1 (server to client)
+    handling of each payload consumes at most PLUGIN_CALL_FUEL fuel, the call fails when exhausted
+*/
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use log::debug;
+use parking_lot::Mutex;
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, TypedFunc};
+
+use super::super::L7ResponseStatus;
+use super::CustomInfo;
+use crate::{
+    common::{
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{
+            L7ProtocolParserBuilder, L7ProtocolParserInterface, ParseParam, ProtocolParserRegistry,
+        },
+    },
+    flow_generator::{
+        error::{Error, Result},
+        LogMessageType,
+    },
+};
+
+const EXPORT_MEMORY: &str = "memory";
+const EXPORT_ALLOC: &str = "alloc";
+const EXPORT_CHECK_PAYLOAD: &str = "check_payload";
+const EXPORT_PARSE_PAYLOAD: &str = "parse_payload";
+
+// 实例化和每个载荷的处理可消耗的燃料, 大致等于执行的 wasm 指令数, 避免插件死循环阻塞流水线
+// fuel consumed by instantiation and handling of each payload, roughly the number of wasm
+// instructions executed, to avoid blocking the pipeline with infinite loops in plugins
+const PLUGIN_CALL_FUEL: u64 = 10_000_000;
+// 缓存的空闲实例数上限
+// max number of idle instances cached
+const MAX_IDLE_INSTANCES: usize = 16;
+
+type PluginArgs = (i32, i32, i32, i32, i32, i32);
+
+// 编译后的插件, 所有解析器共享
+// compiled plugin, shared by all parsers
+pub struct WasmPlugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    // 空闲的实例, 每次调用时取出, 调用成功后放回, 避免在每次检查时实例化
+    // idle instances, taken out on every call and put back after success,
+    // to avoid instantiation on every check
+    instances: Mutex<Vec<PluginInstance>>,
+}
+
+impl WasmPlugin {
+    // bytes 可以是 wasm 二进制或 wat 文本
+    // bytes can be either wasm binary or wat text
+    pub fn new(name: &str, bytes: &[u8]) -> Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| Error::WasmPluginError(e.to_string()))?;
+        let module =
+            Module::new(&engine, bytes).map_err(|e| Error::WasmPluginError(e.to_string()))?;
+        for export in [
+            EXPORT_MEMORY,
+            EXPORT_ALLOC,
+            EXPORT_CHECK_PAYLOAD,
+            EXPORT_PARSE_PAYLOAD,
+        ] {
+            if module.get_export(export).is_none() {
+                return Err(Error::WasmPluginError(format!(
+                    "{} does not export {}",
+                    name, export
+                )));
+            }
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            engine,
+            module,
+            instances: Mutex::new(vec![]),
+        })
+    }
+
+    // 从文件加载插件, 插件名为文件名去掉扩展名
+    // load plugin from file, name of plugin is file name without extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| {
+            Error::WasmPluginError(format!("read {} failed: {}", path.display(), e))
+        })?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        Self::new(&name, &bytes)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn builder(self: Arc<Self>) -> L7ProtocolParserBuilder {
+        Box::new(move || Box::new(WasmPluginParser::new(self.clone())))
+    }
+
+    // 所有插件都使用 L7Protocol::Custom 协议号, 所以只能注册一个插件
+    // all plugins use L7Protocol::Custom as protocol number, so only one plugin can be registered
+    pub fn register(self: Arc<Self>, registry: &mut ProtocolParserRegistry) -> Result<()> {
+        if registry.is_registered(L7Protocol::Custom) {
+            return Err(Error::WasmPluginError(format!(
+                "{} not registered, only one wasm plugin is supported",
+                self.name
+            )));
+        }
+        registry.register(self.builder())
+    }
+
+    // 使用空闲实例调用插件, 调用失败 (例如燃料耗尽) 后实例的状态未知, 不再复用
+    // call plugin with an idle instance, instance is not reused after a failed call
+    // (fuel exhausted for example) as its state is unknown
+    fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut PluginInstance) -> anyhow::Result<T>,
+    {
+        let instance = self.instances.lock().pop();
+        let mut instance = match instance {
+            Some(instance) => instance,
+            None => PluginInstance::new(self).map_err(|e| Error::WasmPluginError(e.to_string()))?,
+        };
+        let ret = f(&mut instance).map_err(|e| Error::WasmPluginError(e.to_string()))?;
+        let mut instances = self.instances.lock();
+        if instances.len() < MAX_IDLE_INSTANCES {
+            instances.push(instance);
+        }
+        Ok(ret)
+    }
+}
+
+struct PluginInstance {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    check_payload: TypedFunc<PluginArgs, i32>,
+    parse_payload: TypedFunc<PluginArgs, i64>,
+}
+
+impl PluginInstance {
+    fn new(plugin: &WasmPlugin) -> anyhow::Result<Self> {
+        let mut store = Store::new(&plugin.engine, ());
+        store.add_fuel(PLUGIN_CALL_FUEL)?;
+        let instance = Instance::new(&mut store, &plugin.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, EXPORT_MEMORY)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a memory", EXPORT_MEMORY))?;
+        Ok(Self {
+            alloc: instance.get_typed_func(&mut store, EXPORT_ALLOC)?,
+            check_payload: instance.get_typed_func(&mut store, EXPORT_CHECK_PAYLOAD)?,
+            parse_payload: instance.get_typed_func(&mut store, EXPORT_PARSE_PAYLOAD)?,
+            store,
+            memory,
+        })
+    }
+
+    // 补充燃料到 PLUGIN_CALL_FUEL
+    // refill fuel up to PLUGIN_CALL_FUEL
+    fn refuel(&mut self) -> anyhow::Result<()> {
+        let remaining = self.store.consume_fuel(0)?;
+        self.store
+            .add_fuel(PLUGIN_CALL_FUEL.saturating_sub(remaining))?;
+        Ok(())
+    }
+
+    // 把载荷写入插件内存, 返回调用参数
+    // write payload into plugin memory and return the call arguments
+    fn write_args(&mut self, payload: &[u8], param: &ParseParam) -> anyhow::Result<PluginArgs> {
+        self.refuel()?;
+        let len = payload.len() as i32;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory.write(&mut self.store, ptr as usize, payload)?;
+        let direction = match param.direction {
+            PacketDirection::ClientToServer => 0,
+            PacketDirection::ServerToClient => 1,
+        };
+        Ok((
+            ptr,
+            len,
+            u8::from(param.l4_protocol) as i32,
+            direction,
+            param.port_src as i32,
+            param.port_dst as i32,
+        ))
+    }
+
+    fn check(&mut self, payload: &[u8], param: &ParseParam) -> anyhow::Result<bool> {
+        let args = self.write_args(payload, param)?;
+        Ok(self.check_payload.call(&mut self.store, args)? != 0)
+    }
+
+    fn parse(&mut self, payload: &[u8], param: &ParseParam) -> anyhow::Result<Option<CustomInfo>> {
+        let args = self.write_args(payload, param)?;
+        let ret = self.parse_payload.call(&mut self.store, args)? as u64;
+        let (ptr, len) = ((ret >> 32) as usize, (ret & 0xffffffff) as usize);
+        if len == 0 {
+            return Ok(None);
+        }
+        let data = self
+            .memory
+            .data(&self.store)
+            .get(ptr..ptr + len)
+            .ok_or_else(|| anyhow::anyhow!("result out of memory bounds"))?;
+        Ok(Some(serde_json::from_slice(data)?))
+    }
+}
+
+// 通过 WASM 插件解析的协议, 统一使用 L7Protocol::Custom 协议号
+// protocols parsed by WASM plugins, all of which use L7Protocol::Custom as protocol number
+pub struct WasmPluginParser {
+    plugin: Arc<WasmPlugin>,
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl WasmPluginParser {
+    pub fn new(plugin: Arc<WasmPlugin>) -> Self {
+        Self {
+            plugin,
+            perf_stats: None,
+        }
+    }
+}
+
+impl L7ProtocolParserInterface for WasmPluginParser {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        match self.plugin.call(|i| i.check(payload, param)) {
+            Ok(ok) => ok,
+            Err(e) => {
+                debug!("plugin {} check payload failed: {}", self.plugin.name, e);
+                false
+            }
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        let mut info = self
+            .plugin
+            .call(|i| i.parse(payload, param))?
+            .ok_or(Error::CustomLogParseFailed)?;
+        info.is_tls = param.is_tls();
+        info.plugin = self.plugin.name.clone();
+
+        let perf_stats = self.perf_stats.as_mut().unwrap();
+        match info.msg_type {
+            LogMessageType::Request => {
+                info.req_len = Some(payload.len() as u32);
+                perf_stats.inc_req();
+            }
+            LogMessageType::Response => {
+                info.resp_len = Some(payload.len() as u32);
+                perf_stats.inc_resp();
+                match info.status {
+                    L7ResponseStatus::ClientError => perf_stats.inc_req_err(),
+                    L7ResponseStatus::ServerError => perf_stats.inc_resp_err(),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+        if info.msg_type != LogMessageType::Session {
+            info.cal_rrt(param).map(|rrt| {
+                info.rrt = rrt;
+                perf_stats.update_rrt(rrt);
+            });
+        }
+        Ok(vec![L7ProtocolInfo::CustomInfo(info)])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Custom
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        true
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::*;
+
+    use crate::common::{enums::IpProtocol, l7_protocol_log::L7PerfCache, meta_packet::MetaPacket};
+    use crate::flow_generator::L7_RRT_CACHE_CAPACITY;
+    use public::l7_protocol::L7ProtocolEnum;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/wasm";
+
+    #[test]
+    fn check() {
+        let path = Path::new(FILE_DIR).join("ping.wat");
+        let plugin = Arc::new(WasmPlugin::load(&path).unwrap());
+        let mut registry = ProtocolParserRegistry::default();
+        assert!(plugin.clone().register(&mut registry).is_ok());
+        // 所有插件使用同一个协议号, 不能注册第二个插件
+        // all plugins share one protocol number, the second plugin can not be registered
+        assert!(matches!(
+            Arc::new(WasmPlugin::load(&path).unwrap()).register(&mut registry),
+            Err(Error::WasmPluginError(_))
+        ));
+        let mut parser = registry
+            .get_parser(L7ProtocolEnum::L7Protocol(L7Protocol::Custom))
+            .unwrap();
+
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.lookup_key.timestamp = Duration::from_micros(100);
+        let cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let param = ParseParam::from((&packet, cache.clone(), false));
+
+        assert!(!parser.check_payload(b"HELLO", &param));
+        assert!(parser.check_payload(b"PING 1", &param));
+        let infos = parser.parse_payload(b"PING 1", &param).unwrap();
+        let L7ProtocolInfo::CustomInfo(req) = &infos[0] else {
+            unreachable!();
+        };
+        assert_eq!(req.plugin, "ping");
+        assert_eq!(req.msg_type, LogMessageType::Request);
+        assert_eq!(req.req_type, "PING");
+        assert_eq!(req.req_len, Some(6));
+
+        packet.lookup_key.direction = PacketDirection::ServerToClient;
+        packet.lookup_key.timestamp = Duration::from_micros(300);
+        let param = ParseParam::from((&packet, cache, false));
+        let infos = parser.parse_payload(b"PONG 1", &param).unwrap();
+        let L7ProtocolInfo::CustomInfo(resp) = &infos[0] else {
+            unreachable!();
+        };
+        assert_eq!(resp.msg_type, LogMessageType::Response);
+        assert_eq!(resp.status, L7ResponseStatus::Ok);
+        assert_eq!(resp.code, Some(0));
+        assert_eq!(resp.rrt, 200);
+
+        let perf_stats = parser.perf_stats().unwrap();
+        assert_eq!(perf_stats.request_count, 1);
+        assert_eq!(perf_stats.response_count, 1);
+
+        assert!(matches!(
+            parser.parse_payload(b"HELLO", &param),
+            Err(Error::CustomLogParseFailed)
+        ));
+        // 所有调用复用同一个实例
+        // all calls reuse the same instance
+        assert_eq!(plugin.instances.lock().len(), 1);
+    }
+
+    #[test]
+    fn check_fuel_exhausted() {
+        // 死循环的插件
+        // plugin with infinite loops
+        let bytes = br#"(module
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 0)
+            (func (export "check_payload") (param i32 i32 i32 i32 i32 i32) (result i32)
+                (loop $l (br $l))
+                i32.const 1)
+            (func (export "parse_payload") (param i32 i32 i32 i32 i32 i32) (result i64)
+                (loop $l (br $l))
+                i64.const 0))"#;
+        let plugin = Arc::new(WasmPlugin::new("loop", bytes).unwrap());
+        let mut parser = WasmPluginParser::new(plugin.clone());
+
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        let cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let param = ParseParam::from((&packet, cache, false));
+
+        assert!(!parser.check_payload(b"PING 1", &param));
+        assert!(plugin.instances.lock().is_empty());
+        assert!(matches!(
+            parser.parse_payload(b"PING 1", &param),
+            Err(Error::WasmPluginError(_))
+        ));
+        assert!(plugin.instances.lock().is_empty());
+    }
+
+    #[test]
+    fn check_missing_export() {
+        assert!(matches!(
+            WasmPlugin::new("empty", b"(module (memory (export \"memory\") 1))"),
+            Err(Error::WasmPluginError(_))
+        ));
+    }
+}
@@ -31,7 +31,9 @@ use crate::{
         protocol_logs::{
             consts::*,
             decode_base64_to_string,
-            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo},
+            pb_adapter::{
+                ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+            },
             value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
         },
     },
@@ -39,6 +41,13 @@ use crate::{
 };
 
 const TRACE_ID_MAX_LEN: usize = 1024;
+const ATTACHMENT_VALUE_MAX_LEN: usize = 1024;
+
+const ATTACHMENT_PATH: &str = "path";
+const ATTACHMENT_VERSION: &str = "version";
+const ATTACHMENT_GROUP: &str = "group";
+const ATTACHMENT_TRACE_ID: &str = "traceId";
+const ATTACHMENT_SPAN_ID: &str = "spanId";
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct DubboInfo {
@@ -64,6 +73,10 @@ pub struct DubboInfo {
     pub service_name: String,
     #[serde(skip)]
     pub service_version: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub service_group: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub service_path: String,
     #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
     pub method_name: String,
     #[serde(skip_serializing_if = "value_is_default")]
@@ -124,6 +137,19 @@ impl L7ProtocolInfoInterface for DubboInfo {
 impl From<DubboInfo> for L7ProtocolSendLog {
     fn from(f: DubboInfo) -> Self {
         let endpoint = format!("{}/{}", f.service_name, f.method_name);
+        let mut attributes = vec![];
+        for (key, val) in [
+            (ATTACHMENT_VERSION, f.service_version),
+            (ATTACHMENT_GROUP, f.service_group),
+            (ATTACHMENT_PATH, f.service_path),
+        ] {
+            if !val.is_empty() {
+                attributes.push(KeyVal {
+                    key: key.to_string(),
+                    val,
+                });
+            }
+        }
         L7ProtocolSendLog {
             req_len: f.req_msg_size,
             resp_len: f.resp_msg_size,
@@ -147,6 +173,11 @@ impl From<DubboInfo> for L7ProtocolSendLog {
             ext_info: Some(ExtendedInfo {
                 rpc_service: Some(f.service_name),
                 request_id: Some(f.request_id as u32),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -203,6 +234,10 @@ impl L7ProtocolParserInterface for DubboLog {
         L7Protocol::Dubbo
     }
 
+    fn default_ports(&self) -> &[u16] {
+        &[20880]
+    }
+
     fn parsable_on_udp(&self) -> bool {
         false
     }
@@ -246,14 +281,14 @@ impl DubboLog {
                     (((bytes[start] - BC_STRING_SHORT) as usize) << 8) + bytes[start + 1] as usize;
                 start += 2;
                 if start + field_len < end {
-                    return Some(payload[start..start + field_len].to_string());
+                    return payload.get(start..start + field_len).map(str::to_string);
                 }
             }
             0..=STRING_DIRECT_MAX => {
                 let field_len = bytes[start] as usize;
                 start += 1;
                 if start + field_len < end {
-                    return Some(payload[start..start + field_len].to_string());
+                    return payload.get(start..start + field_len).map(str::to_string);
                 }
             }
             b'S' => {
@@ -263,7 +298,7 @@ impl DubboLog {
                 let field_len = ((bytes[start + 1] as usize) << 8) + bytes[start + 2] as usize;
                 start += 3;
                 if start + field_len < end {
-                    return Some(payload[start..start + field_len].to_string());
+                    return payload.get(start..start + field_len).map(str::to_string);
                 }
             }
             _ => {}
@@ -383,6 +418,61 @@ impl DubboLog {
         };
     }
 
+    // attachments 是 body 末尾的 Hessian map, key 为短字符串, 取最后一次出现的值
+    // attachments is the Hessian map at the end of body, keys are short strings,
+    // the value of the last occurrence is used
+    fn decode_attachment(payload: &Cow<'_, str>, key: &str) -> Option<String> {
+        let mut value = None;
+        let mut start = 0;
+        while start < payload.len() {
+            if !payload.is_char_boundary(start) {
+                break;
+            }
+            let Some(index) = payload[start..].find(key) else {
+                break;
+            };
+            let key_start = start + index;
+            start = key_start + key.len();
+            if key_start == 0 || key.len() != payload.as_bytes()[key_start - 1] as usize {
+                continue;
+            }
+            let last_index = payload.len().min(ATTACHMENT_VALUE_MAX_LEN + start);
+            // 值为 null 等非字符串时忽略
+            // value such as null which is not string is ignored
+            if let Some(v) = Self::decode_field(payload, start, last_index) {
+                value = Some(v);
+            }
+        }
+        value
+    }
+
+    fn get_req_attachments(&mut self, payload: &[u8]) {
+        let payload_str = String::from_utf8_lossy(payload);
+        if let Some(path) = Self::decode_attachment(&payload_str, ATTACHMENT_PATH) {
+            self.info.service_path = path;
+        }
+        if let Some(group) = Self::decode_attachment(&payload_str, ATTACHMENT_GROUP) {
+            self.info.service_group = group;
+        }
+        if self.info.service_version.is_empty() {
+            if let Some(version) = Self::decode_attachment(&payload_str, ATTACHMENT_VERSION) {
+                self.info.service_version = version;
+            }
+        }
+        // 没有找到配置的 trace 类型时, 使用 attachments 中的 traceId 和 spanId
+        // traceId and spanId in attachments are used when no configured trace type is found
+        if self.info.trace_id.is_empty() {
+            if let Some(trace_id) = Self::decode_attachment(&payload_str, ATTACHMENT_TRACE_ID) {
+                self.info.trace_id = trace_id;
+            }
+        }
+        if self.info.span_id.is_empty() {
+            if let Some(span_id) = Self::decode_attachment(&payload_str, ATTACHMENT_SPAN_ID) {
+                self.info.span_id = span_id;
+            }
+        }
+    }
+
     // 尽力而为的去解析Dubbo请求中Body各参数
     fn get_req_body_info(&mut self, config: &L7LogDynamicConfig, payload: &[u8]) {
         let mut n = BODY_PARAM_MIN;
@@ -463,6 +553,7 @@ impl DubboLog {
         self.info.request_id = dubbo_header.request_id;
 
         self.get_req_body_info(config, &payload[DUBBO_HEADER_LEN..]);
+        self.get_req_attachments(&payload[DUBBO_HEADER_LEN..]);
     }
 
     fn set_status(&mut self, status_code: u8) {
@@ -588,7 +679,6 @@ pub fn get_req_param_len(payload: &[u8]) -> (usize, usize) {
 mod tests {
     use std::cell::RefCell;
     use std::path::Path;
-    use std::time::Duration;
     use std::{fs, rc::Rc};
 
     use super::*;
@@ -625,8 +715,6 @@ mod tests {
             };
 
             let config = LogParserConfig {
-                l7_log_collect_nps_threshold: 0,
-                l7_log_session_aggr_timeout: Duration::ZERO,
                 l7_log_dynamic: L7LogDynamicConfig::new(
                     "".to_owned(),
                     "".to_owned(),
@@ -639,6 +727,7 @@ mod tests {
                         TraceType::Sw8,
                     ],
                 ),
+                ..Default::default()
             };
             let mut dubbo = DubboLog::default();
             let param =
@@ -657,6 +746,7 @@ mod tests {
             ("dubbo_hessian2.pcap", "dubbo_hessian.result"),
             ("dubbo-eys.pcap", "dubbo-eys.result"),
             ("dubbo-sw8.pcap", "dubbo-sw8.result"),
+            ("dubbo-attachments.pcap", "dubbo-attachments.result"),
         ];
 
         for item in files.iter() {
@@ -706,8 +796,6 @@ mod tests {
         let mut packets = capture.as_meta_packets();
 
         let config = LogParserConfig {
-            l7_log_collect_nps_threshold: 0,
-            l7_log_session_aggr_timeout: Duration::ZERO,
             l7_log_dynamic: L7LogDynamicConfig::new(
                 "".to_owned(),
                 "".to_owned(),
@@ -720,6 +808,7 @@ mod tests {
                     TraceType::Sw8,
                 ],
             ),
+            ..Default::default()
         };
 
         let first_dst_port = packets[0].lookup_key.dst_port;
@@ -738,4 +827,22 @@ mod tests {
         }
         dubbo.perf_stats.unwrap()
     }
+
+    #[test]
+    fn check_trace_attachments() {
+        let attachments = b"H\x04path\x0bcom.foo.Bar\x07traceId\x03abc\x06spanId\x011Z";
+        let mut dubbo = DubboLog::default();
+        dubbo.get_req_attachments(attachments);
+        assert_eq!(dubbo.info.service_path, "com.foo.Bar");
+        assert_eq!(dubbo.info.trace_id, "abc");
+        assert_eq!(dubbo.info.span_id, "1");
+
+        // 配置的 trace 类型优先
+        // configured trace types take precedence
+        let mut dubbo = DubboLog::default();
+        dubbo.info.trace_id = "t1".to_string();
+        dubbo.get_req_attachments(attachments);
+        assert_eq!(dubbo.info.trace_id, "t1");
+        assert_eq!(dubbo.info.span_id, "1");
+    }
 }
@@ -14,6 +14,8 @@
  * limitations under the License.
  */
 
+use std::borrow::Cow;
+
 use nom::InputTakeAtPosition;
 use public::{
     bytes::{read_u16_be, read_u32_be},
@@ -29,7 +31,9 @@ use crate::{
     },
     flow_generator::{
         protocol_logs::{
-            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo},
+            pb_adapter::{
+                ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+            },
             L7ResponseStatus,
         },
         AppProtoHead, Error, HttpLog, LogMessageType, Result,
@@ -40,6 +44,7 @@ const REQ_HDR_LEN: usize = 22;
 const RESP_HDR_LEN: usize = 20;
 
 const PROTO_BOLT_V1: u8 = 1;
+const PROTO_BOLT_V2: u8 = 2;
 
 const TYPE_REQ: u8 = 1;
 const TYPE_RESP: u8 = 0;
@@ -53,11 +58,22 @@ const METHOD_KEY: &'static str = "sofa_head_method_name";
 const TRACE_ID_KEY: &'static str = "rpc_trace_context.sofaTraceId";
 pub const SOFA_NEW_RPC_TRACE_CTX_KEY: &'static str = "new_rpc_trace_context";
 
+// 参考 https://github.com/sofastack/sofa-rpc/blob/master/core/common/src/main/java/com/alipay/sofa/rpc/common/RpcConstants.java
+const CODEC_HESSIAN2: u8 = 1;
+const CODEC_JAVA: u8 = 2;
+const CODEC_PROTOBUF: u8 = 11;
+const CODEC_JSON: u8 = 12;
+
 struct Hdr {
+    // 定长头部的长度
+    // length of the fixed header
+    len: usize,
     proto: u8,
     typ: u8,
     cmd_code: u16,
     req_id: u32,
+    codec: u8,
+    timeout: u32,
     class_len: u16,
     resp_code: u16,
     hdr_len: u16,
@@ -91,60 +107,94 @@ impl TryFrom<&[u8]> for Hdr {
         * +                                                                                               +
         * |                               ... ...                                                         |
         * +-----------------------------------------------------------------------------------------------+
+
+        * v2 在 proto 之后多了 1 字节的 ver1, 在 codec 之后多了 1 字节的 switch, 其余字段与 v1 相同
+        * v2 has an extra 1-byte ver1 after proto and an extra 1-byte switch after codec,
+        * other fields are the same as v1
     */
     fn try_from(payload: &[u8]) -> Result<Self, Self::Error> {
-        if payload.len() < 2 {
+        if payload.is_empty() {
             return Err(Error::L7ProtocolUnknown);
         }
         let proto = payload[0];
-        let typ = payload[1];
-        match typ {
+        let (ver1_len, switch_len) = match proto {
+            PROTO_BOLT_V1 => (0, 0),
+            PROTO_BOLT_V2 => (1, 1),
+            _ => return Err(Error::L7ProtocolUnknown),
+        };
+        if payload.len() < 2 + ver1_len {
+            return Err(Error::L7ProtocolUnknown);
+        }
+        let typ = payload[1 + ver1_len];
+        // ver1 之后的偏移
+        // offset after ver1
+        let off = ver1_len;
+        let hdr = match typ {
             TYPE_REQ => {
-                if payload.len() < REQ_HDR_LEN {
+                let len = REQ_HDR_LEN + ver1_len + switch_len;
+                if payload.len() < len {
                     return Err(Error::L7ProtocolUnknown);
                 }
-                let cmd_code = read_u16_be(&payload[2..4]);
-                let req_id = read_u32_be(&payload[5..9]);
+                let cmd_code = read_u16_be(&payload[2 + off..4 + off]);
+                let req_id = read_u32_be(&payload[5 + off..9 + off]);
+                let codec = payload[9 + off];
+                // switch 之后的偏移
+                // offset after switch
+                let off = off + switch_len;
+                let timeout = read_u32_be(&payload[10 + off..14 + off]);
                 let (class_len, hdr_len, content_len) = (
-                    read_u16_be(&payload[14..16]),
-                    read_u16_be(&payload[16..18]),
-                    read_u32_be(&payload[18..22]),
+                    read_u16_be(&payload[14 + off..16 + off]),
+                    read_u16_be(&payload[16 + off..18 + off]),
+                    read_u32_be(&payload[18 + off..22 + off]),
                 );
-                Ok(Self {
+                Self {
+                    len,
                     proto,
                     typ,
                     cmd_code,
                     req_id,
+                    codec,
+                    timeout,
                     resp_code: 0,
                     class_len,
                     hdr_len,
                     content_len,
-                })
+                }
             }
             TYPE_RESP => {
-                if payload.len() < RESP_HDR_LEN {
+                let len = RESP_HDR_LEN + ver1_len + switch_len;
+                if payload.len() < len {
                     return Err(Error::L7ProtocolUnknown);
                 }
-                let cmd_code = read_u16_be(&payload[2..4]);
-                let req_id = read_u32_be(&payload[5..9]);
-                let resp_code = read_u16_be(&payload[10..12]);
+                let cmd_code = read_u16_be(&payload[2 + off..4 + off]);
+                let req_id = read_u32_be(&payload[5 + off..9 + off]);
+                let codec = payload[9 + off];
+                let off = off + switch_len;
+                let resp_code = read_u16_be(&payload[10 + off..12 + off]);
                 let (class_len, hdr_len, content_len) = (
-                    read_u16_be(&payload[12..14]),
-                    read_u16_be(&payload[14..16]),
-                    read_u32_be(&payload[16..20]),
+                    read_u16_be(&payload[12 + off..14 + off]),
+                    read_u16_be(&payload[14 + off..16 + off]),
+                    read_u32_be(&payload[16 + off..20 + off]),
                 );
-                Ok(Self {
+                Self {
+                    len,
                     proto,
                     typ,
                     cmd_code,
                     req_id,
+                    codec,
+                    timeout: 0,
                     resp_code,
                     class_len,
                     hdr_len,
                     content_len,
-                })
+                }
             }
             _ => return Err(Error::L7ProtocolUnknown),
+        };
+        match hdr.cmd_code {
+            CMD_CODE_HEARTBEAT | CMD_CODE_REQ | CMD_CODE_RESP => Ok(hdr),
+            _ => Err(Error::L7ProtocolUnknown),
         }
     }
 }
@@ -159,6 +209,10 @@ pub struct SofaRpcInfo {
     target_serv: String,
     method: String,
     cmd_code: u16,
+    codec: u8,
+    // 请求超时时间, 单位为毫秒
+    // request timeout in milliseconds
+    timeout: u32,
     trace_id: String,
     span_id: String,
     parent_span_id: String,
@@ -197,9 +251,32 @@ impl L7ProtocolInfoInterface for SofaRpcInfo {
     }
 }
 
+impl SofaRpcInfo {
+    fn codec_str(&self) -> Cow<'static, str> {
+        match self.codec {
+            CODEC_HESSIAN2 => "hessian2".into(),
+            CODEC_JAVA => "java".into(),
+            CODEC_PROTOBUF => "protobuf".into(),
+            CODEC_JSON => "json".into(),
+            c => c.to_string().into(),
+        }
+    }
+}
+
 impl From<SofaRpcInfo> for L7ProtocolSendLog {
     fn from(s: SofaRpcInfo) -> Self {
+        let mut attributes = vec![KeyVal {
+            key: "codec".to_string(),
+            val: s.codec_str().into_owned(),
+        }];
+        if s.timeout != 0 {
+            attributes.push(KeyVal {
+                key: "timeout".to_string(),
+                val: s.timeout.to_string(),
+            });
+        }
         Self {
+            version: Some(s.proto.to_string()),
             req_len: Some(s.req_len),
             resp_len: Some(s.resp_len),
             req: L7Request {
@@ -222,6 +299,7 @@ impl From<SofaRpcInfo> for L7ProtocolSendLog {
             ext_info: Some(ExtendedInfo {
                 rpc_service: Some(s.target_serv),
                 request_id: Some(s.req_id),
+                attributes: Some(attributes),
                 ..Default::default()
             }),
             ..Default::default()
@@ -263,6 +341,10 @@ impl L7ProtocolParserInterface for SofaRpcLog {
         L7Protocol::SofaRPC
     }
 
+    fn default_ports(&self) -> &[u16] {
+        &[12200]
+    }
+
     fn reset(&mut self) {
         self.parsed = false;
         self.info = SofaRpcInfo::default();
@@ -293,11 +375,7 @@ impl SofaRpcLog {
 
         let hdr = Hdr::try_from(payload)?;
         self.info.proto = hdr.proto;
-        // now only support bolt v1
-        if self.info.proto != PROTO_BOLT_V1 {
-            return Err(Error::L7ProtocolUnknown);
-        }
-
+        self.info.codec = hdr.codec;
         self.info.cmd_code = hdr.cmd_code;
         if self.info.cmd_code == CMD_CODE_HEARTBEAT {
             // skip heartbeat
@@ -310,12 +388,13 @@ impl SofaRpcLog {
         self.info.req_id = hdr.req_id;
         self.info.msg_type = match hdr.typ {
             TYPE_REQ => {
-                payload = &payload[REQ_HDR_LEN..];
+                payload = &payload[hdr.len..];
+                self.info.timeout = hdr.timeout;
                 self.info.req_len = hdr.content_len + (hdr.hdr_len as u32) + (hdr.class_len as u32);
                 LogMessageType::Request
             }
             TYPE_RESP => {
-                payload = &payload[RESP_HDR_LEN..];
+                payload = &payload[hdr.len..];
                 self.info.resp_code = hdr.resp_code;
                 self.info.resp_len =
                     hdr.content_len + (hdr.hdr_len as u32) + (hdr.class_len as u32);
@@ -526,13 +605,16 @@ mod test {
             l7_protocol_log::{L7PerfCache, L7ProtocolParserInterface, ParseParam},
         },
         flow_generator::{
-            protocol_logs::rpc::sofa_rpc::{CMD_CODE_REQ, CMD_CODE_RESP, PROTO_BOLT_V1},
+            protocol_logs::{
+                rpc::sofa_rpc::{CMD_CODE_REQ, CMD_CODE_RESP, PROTO_BOLT_V1, PROTO_BOLT_V2},
+                L7ResponseStatus,
+            },
             LogMessageType, L7_RRT_CACHE_CAPACITY,
         },
         utils::test::Capture,
     };
 
-    use super::{decode_new_rpc_trace_context, SofaRpcLog};
+    use super::{decode_new_rpc_trace_context, Hdr, SofaRpcLog, CODEC_HESSIAN2};
 
     #[test]
     fn test_decode_new_rpc_trace_context() {
@@ -698,4 +780,98 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn test_sofarpc_bolt_v2() {
+        let pcap_file = Path::new("resources/test/flow_generator/sofarpc/sofa-bolt-v2.pcap");
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let capture = Capture::load_pcap(pcap_file, None);
+        let mut p = capture.as_meta_packets();
+        p[0].lookup_key.direction = PacketDirection::ClientToServer;
+        p[1].lookup_key.direction = PacketDirection::ServerToClient;
+        let mut parser = SofaRpcLog::default();
+
+        let req_param = &mut ParseParam::from((&p[0], log_cache.clone(), false));
+        let req_payload = p[0].get_l4_payload().unwrap();
+        assert_eq!(parser.check_payload(req_payload, req_param), true);
+        let req_info = parser
+            .parse_payload(req_payload, req_param)
+            .unwrap()
+            .remove(0);
+
+        if let L7ProtocolInfo::SofaRpcInfo(k) = &req_info {
+            assert_eq!(k.msg_type, LogMessageType::Request);
+            assert_eq!(k.cmd_code, CMD_CODE_REQ);
+            assert_eq!(k.method, "sayHello");
+            assert_eq!(k.req_id, 7);
+            assert_eq!(k.trace_id, "0a0b0c0d1700000000001100012345");
+            assert_eq!(k.span_id, "0.1");
+            assert_eq!(k.parent_span_id, "0");
+            assert_eq!(k.proto, PROTO_BOLT_V2);
+            assert_eq!(k.codec, CODEC_HESSIAN2);
+            assert_eq!(k.timeout, 3000);
+            assert_eq!(k.req_len, 255);
+            assert_eq!(k.target_serv, "com.example.HelloService:1.0");
+        } else {
+            unreachable!()
+        }
+
+        parser.reset();
+
+        let resp_param = &mut ParseParam::from((&p[1], log_cache.clone(), false));
+        let resp_payload = p[1].get_l4_payload().unwrap();
+
+        let resp_info = parser
+            .parse_payload(resp_payload, resp_param)
+            .unwrap()
+            .remove(0);
+
+        if let L7ProtocolInfo::SofaRpcInfo(k) = &resp_info {
+            assert_eq!(k.msg_type, LogMessageType::Response);
+            assert_eq!(k.cmd_code, CMD_CODE_RESP);
+            assert_eq!(k.req_id, 7);
+            assert_eq!(k.proto, PROTO_BOLT_V2);
+            assert_eq!(k.resp_code, 0x10);
+            assert_eq!(k.resp_len, 59);
+            assert_eq!(k.status, L7ResponseStatus::ServerError);
+        } else {
+            unreachable!()
+        }
+
+        assert_eq!(
+            parser.perf_stats.unwrap(),
+            L7PerfStats {
+                request_count: 1,
+                response_count: 1,
+                err_client_count: 0,
+                err_server_count: 1,
+                err_timeout: 0,
+                rrt_count: 1,
+                rrt_sum: 1000,
+                rrt_max: 1000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_hdr() {
+        let capture = Capture::load_pcap(
+            Path::new("resources/test/flow_generator/sofarpc/sofa-bolt-v2.pcap"),
+            None,
+        );
+        let p = capture.as_meta_packets();
+        let mut payload = p[0].get_l4_payload().unwrap().to_vec();
+        assert!(Hdr::try_from(payload.as_slice()).is_ok());
+
+        // 未知的 cmdcode
+        // unknown cmdcode
+        payload[3..5].copy_from_slice(&[0x00, 0x03]);
+        assert!(Hdr::try_from(payload.as_slice()).is_err());
+
+        // 未知的 proto
+        // unknown proto
+        payload[0] = 0x03;
+        assert!(Hdr::try_from(payload.as_slice()).is_err());
+        assert!(Hdr::try_from(&[PROTO_BOLT_V1][..]).is_err());
+    }
 }
@@ -0,0 +1,547 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use public::{
+    bytes::{read_varint, try_read_u16_be, try_read_u32_be},
+    l7_protocol::L7Protocol,
+};
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::L7PerfStats,
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::{
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, L7ResponseStatus,
+        },
+        AppProtoHead, Error, LogMessageType, Result,
+    },
+};
+
+/*
+    referer https://github.com/apache/thrift/blob/master/doc/specs/thrift-binary-protocol.md
+    binary protocol (strict):
+    +--------+--------+--------+--------+--------+...+--------+--------+...+--------+
+    |1vvvvvvv|vvvvvvvv|unused  |00000mmm| name length       | name      | seq id    |
+    +--------+--------+--------+--------+--------+...+--------+--------+...+--------+
+
+    referer https://github.com/apache/thrift/blob/master/doc/specs/thrift-compact-protocol.md
+    compact protocol:
+    +--------+--------+...+--------+...+--------+...+--------+
+    |pppppppp|mmmvvvvv| seq id     | name length | name      |
+    +--------+--------+...+--------+...+--------+...+--------+
+
+    framed transport has a 4-byte frame size before the message
+*/
+const BINARY_VERSION_MASK: u32 = 0xffff0000;
+const BINARY_VERSION_1: u32 = 0x80010000;
+const BINARY_HEADER_MIN_LEN: usize = 12;
+
+const COMPACT_PROTOCOL_ID: u8 = 0x82;
+const COMPACT_VERSION: u8 = 1;
+const COMPACT_VERSION_MASK: u8 = 0x1f;
+const COMPACT_TYPE_SHIFT: u8 = 5;
+
+const FRAME_SIZE_LEN: usize = 4;
+// 方法名的最大长度, 用于协议识别时排除异常数据
+// max length of method name, used to exclude invalid data in protocol inference
+const METHOD_NAME_MAX_LEN: usize = 256;
+
+pub const MESSAGE_TYPE_CALL: u8 = 1;
+pub const MESSAGE_TYPE_REPLY: u8 = 2;
+pub const MESSAGE_TYPE_EXCEPTION: u8 = 3;
+pub const MESSAGE_TYPE_ONEWAY: u8 = 4;
+
+// TApplicationException 的字段
+// fields of TApplicationException
+const EXCEPTION_FIELD_MESSAGE: i16 = 1;
+
+const BINARY_TYPE_STOP: u8 = 0;
+const BINARY_TYPE_STRING: u8 = 11;
+const COMPACT_TYPE_BINARY: u8 = 8;
+
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum ThriftProtocol {
+    #[default]
+    Binary,
+    Compact,
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct ThriftInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+    #[serde(skip)]
+    rrt: u64,
+
+    pub protocol: ThriftProtocol,
+    pub framed: bool,
+    #[serde(rename = "request_resource")]
+    pub method: String,
+    pub message_type: u8,
+    #[serde(rename = "request_id")]
+    pub seq_id: i32,
+
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub exception: String,
+}
+
+impl ThriftInfo {
+    pub fn message_type_str(&self) -> &'static str {
+        match self.message_type {
+            MESSAGE_TYPE_CALL => "CALL",
+            MESSAGE_TYPE_REPLY => "REPLY",
+            MESSAGE_TYPE_EXCEPTION => "EXCEPTION",
+            MESSAGE_TYPE_ONEWAY => "ONEWAY",
+            _ => "",
+        }
+    }
+}
+
+impl L7ProtocolInfoInterface for ThriftInfo {
+    fn session_id(&self) -> Option<u32> {
+        Some(self.seq_id as u32)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::ThriftInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.resp_len = other.resp_len;
+                self.status = other.status;
+                self.exception = other.exception;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Thrift,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<ThriftInfo> for L7ProtocolSendLog {
+    fn from(f: ThriftInfo) -> Self {
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.message_type_str().to_owned(),
+                resource: f.method.clone(),
+                endpoint: f.method,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                exception: f.exception,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: Some(f.seq_id as u32),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+struct Header<'a> {
+    protocol: ThriftProtocol,
+    framed: bool,
+    message_type: u8,
+    name: &'a [u8],
+    seq_id: i32,
+    // 消息长度, 有 frame 时为 frame size
+    // message length, frame size if framed
+    length: u32,
+    // 消息头之后的结构体
+    // the struct after message header
+    body: &'a [u8],
+}
+
+impl<'a> Header<'a> {
+    fn decode(payload: &'a [u8]) -> Option<Self> {
+        match payload.first()? {
+            0x80 => Self::decode_binary(payload, false),
+            &COMPACT_PROTOCOL_ID => Self::decode_compact(payload, false),
+            // framed transport, frame size 不会大到首字节为 0x80 或 0x82
+            // framed transport, frame size will not be so large that first byte is 0x80 or 0x82
+            _ => {
+                let frame_size = try_read_u32_be(payload)?;
+                let message = &payload[FRAME_SIZE_LEN..];
+                let mut header = match message.first()? {
+                    0x80 => Self::decode_binary(message, true)?,
+                    &COMPACT_PROTOCOL_ID => Self::decode_compact(message, true)?,
+                    _ => return None,
+                };
+                header.length = frame_size;
+                Some(header)
+            }
+        }
+    }
+
+    fn decode_binary(payload: &'a [u8], framed: bool) -> Option<Self> {
+        if payload.len() < BINARY_HEADER_MIN_LEN {
+            return None;
+        }
+        let version = try_read_u32_be(payload)?;
+        if version & BINARY_VERSION_MASK != BINARY_VERSION_1 || payload[2] != 0 {
+            return None;
+        }
+        let name_len = try_read_u32_be(&payload[4..])? as usize;
+        if name_len > METHOD_NAME_MAX_LEN {
+            return None;
+        }
+        let name = payload.get(8..8 + name_len)?;
+        let seq_id = try_read_u32_be(&payload[8 + name_len..])? as i32;
+        Some(Self {
+            protocol: ThriftProtocol::Binary,
+            framed,
+            message_type: payload[3],
+            name,
+            seq_id,
+            length: payload.len() as u32,
+            body: &payload[12 + name_len..],
+        })
+    }
+
+    fn decode_compact(payload: &'a [u8], framed: bool) -> Option<Self> {
+        let type_and_version = *payload.get(1)?;
+        if type_and_version & COMPACT_VERSION_MASK != COMPACT_VERSION {
+            return None;
+        }
+        let mut offset = 2;
+        let (seq_id, size) = read_varint(&payload[offset..])?;
+        offset += size;
+        let (name_len, size) = read_varint(&payload[offset..])?;
+        offset += size;
+        if name_len as usize > METHOD_NAME_MAX_LEN {
+            return None;
+        }
+        let name = payload.get(offset..offset + name_len as usize)?;
+        offset += name_len as usize;
+        Some(Self {
+            protocol: ThriftProtocol::Compact,
+            framed,
+            message_type: type_and_version >> COMPACT_TYPE_SHIFT,
+            name,
+            seq_id: seq_id as i32,
+            length: payload.len() as u32,
+            body: &payload[offset..],
+        })
+    }
+}
+
+// 读取 TApplicationException 的 message 字段, 它总是第一个字段
+// read message field of TApplicationException, which is always the first field
+fn read_exception_message(protocol: ThriftProtocol, body: &[u8]) -> Option<String> {
+    let message = match protocol {
+        ThriftProtocol::Binary => {
+            // | type 1B | field id 2B | length 4B | string |
+            if *body.first()? == BINARY_TYPE_STOP
+                || body[0] != BINARY_TYPE_STRING
+                || try_read_u16_be(&body[1..])? as i16 != EXCEPTION_FIELD_MESSAGE
+            {
+                return None;
+            }
+            let len = try_read_u32_be(&body[3..])? as usize;
+            body.get(7..7usize.checked_add(len)?)?
+        }
+        ThriftProtocol::Compact => {
+            // | field id delta 4b type 4b | length varint | string |
+            let field = *body.first()?;
+            if field & 0xf != COMPACT_TYPE_BINARY || (field >> 4) as i16 != EXCEPTION_FIELD_MESSAGE
+            {
+                return None;
+            }
+            // 长度来自报文, 需要检查溢出
+            // length comes from payload, overflow needs to be checked
+            let (len, size) = read_varint(&body[1..])?;
+            let len = usize::try_from(len).ok()?;
+            body.get(1 + size..(1 + size).checked_add(len)?)?
+        }
+    };
+    Some(String::from_utf8_lossy(message).into_owned())
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ThriftLog {
+    info: ThriftInfo,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for ThriftLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        let Some(header) = Header::decode(payload) else {
+            return false;
+        };
+        (header.message_type == MESSAGE_TYPE_CALL || header.message_type == MESSAGE_TYPE_ONEWAY)
+            && !header.name.is_empty()
+            && header.name.iter().all(|c| c.is_ascii_graphic())
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.info.is_tls = param.is_tls();
+        self.parse(payload)?;
+        if self.info.msg_type != LogMessageType::Session {
+            self.info.cal_rrt(param).map(|rrt| {
+                self.info.rrt = rrt;
+                self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+            });
+        }
+        Ok(vec![L7ProtocolInfo::ThriftInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Thrift
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = ThriftInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl ThriftLog {
+    fn parse(&mut self, payload: &[u8]) -> Result<()> {
+        let header = Header::decode(payload).ok_or(Error::ThriftLogParseFailed)?;
+        self.info.protocol = header.protocol;
+        self.info.framed = header.framed;
+        self.info.message_type = header.message_type;
+        self.info.method = String::from_utf8_lossy(header.name).into_owned();
+        self.info.seq_id = header.seq_id;
+
+        let perf_stats = self.perf_stats.as_mut().unwrap();
+        match header.message_type {
+            MESSAGE_TYPE_CALL => {
+                self.info.msg_type = LogMessageType::Request;
+                self.info.req_len = Some(header.length);
+                perf_stats.inc_req();
+            }
+            // oneway 调用没有回复, 作为单独的会话上报
+            // oneway call has no reply, reported as a separate session
+            MESSAGE_TYPE_ONEWAY => {
+                self.info.msg_type = LogMessageType::Session;
+                self.info.req_len = Some(header.length);
+                perf_stats.inc_req();
+            }
+            MESSAGE_TYPE_REPLY => {
+                self.info.msg_type = LogMessageType::Response;
+                self.info.resp_len = Some(header.length);
+                perf_stats.inc_resp();
+            }
+            MESSAGE_TYPE_EXCEPTION => {
+                self.info.msg_type = LogMessageType::Response;
+                self.info.resp_len = Some(header.length);
+                self.info.status = L7ResponseStatus::ServerError;
+                self.info.exception =
+                    read_exception_message(header.protocol, header.body).unwrap_or_default();
+                perf_stats.inc_resp();
+                perf_stats.inc_resp_err();
+            }
+            _ => return Err(Error::ThriftLogParseFailed),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, path::Path, rc::Rc};
+
+    use super::*;
+
+    use crate::{
+        common::{flow::PacketDirection, l7_protocol_log::L7PerfCache, MetaPacket},
+        flow_generator::L7_RRT_CACHE_CAPACITY,
+        utils::test::Capture,
+    };
+
+    const FILE_DIR: &str = "resources/test/flow_generator/thrift";
+
+    #[test]
+    fn test_thrift() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("thrift.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+
+        let mut parser = ThriftLog::default();
+        let mut infos = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let param = &ParseParam::from((packet as &MetaPacket, log_cache.clone(), false));
+            let payload = packet.get_l4_payload().unwrap();
+            if packet.lookup_key.direction == PacketDirection::ClientToServer {
+                assert!(parser.check_payload(payload, param));
+            }
+            for info in parser.parse_payload(payload, param).unwrap() {
+                if let L7ProtocolInfo::ThriftInfo(info) = info {
+                    infos.push(info);
+                }
+            }
+            parser.reset();
+        }
+        assert_eq!(infos.len(), 5);
+
+        // binary protocol with framed transport
+        assert_eq!(infos[0].protocol, ThriftProtocol::Binary);
+        assert!(infos[0].framed);
+        assert_eq!(infos[0].msg_type, LogMessageType::Request);
+        assert_eq!(infos[0].message_type_str(), "CALL");
+        assert_eq!(infos[0].method, "getUser");
+        assert_eq!(infos[0].seq_id, 1);
+        assert_eq!(infos[0].req_len, Some(27));
+        assert_eq!(infos[1].msg_type, LogMessageType::Response);
+        assert_eq!(infos[1].message_type_str(), "REPLY");
+        assert_eq!(infos[1].seq_id, 1);
+        assert_eq!(infos[1].status, L7ResponseStatus::Ok);
+
+        // compact protocol
+        assert_eq!(infos[2].protocol, ThriftProtocol::Compact);
+        assert!(!infos[2].framed);
+        assert_eq!(infos[2].method, "ping");
+        assert_eq!(infos[2].seq_id, 2);
+        assert_eq!(infos[3].message_type_str(), "EXCEPTION");
+        assert_eq!(infos[3].status, L7ResponseStatus::ServerError);
+        assert_eq!(infos[3].exception, "Internal error");
+
+        // oneway
+        assert_eq!(infos[4].msg_type, LogMessageType::Session);
+        assert_eq!(infos[4].message_type_str(), "ONEWAY");
+        assert_eq!(infos[4].method, "log");
+        assert_eq!(infos[4].seq_id, 3);
+
+        let perf_stats = parser.perf_stats.unwrap();
+        assert_eq!(perf_stats.request_count, 3);
+        assert_eq!(perf_stats.response_count, 2);
+        assert_eq!(perf_stats.err_server_count, 1);
+        assert_eq!(perf_stats.rrt_count, 2);
+        assert_eq!(perf_stats.rrt_max, 1000);
+    }
+
+    #[test]
+    fn test_binary_exception() {
+        let message = [
+            // version 1, EXCEPTION
+            &b"\x80\x01\x00\x03"[..],
+            &b"\x00\x00\x00\x07getUser"[..],
+            // seq id
+            &b"\x00\x00\x00\x05"[..],
+            // field 1 of TApplicationException, string
+            &b"\x0b\x00\x01\x00\x00\x00\x0cUnknown user"[..],
+            // field 2, i32 type
+            &b"\x08\x00\x02\x00\x00\x00\x06\x00"[..],
+        ]
+        .concat();
+        let payload = [&(message.len() as u32).to_be_bytes()[..], &message[..]].concat();
+
+        let mut parser = ThriftLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        parser.parse(&payload).unwrap();
+        assert_eq!(parser.info.protocol, ThriftProtocol::Binary);
+        assert!(parser.info.framed);
+        assert_eq!(parser.info.msg_type, LogMessageType::Response);
+        assert_eq!(parser.info.message_type_str(), "EXCEPTION");
+        assert_eq!(parser.info.method, "getUser");
+        assert_eq!(parser.info.seq_id, 5);
+        assert_eq!(parser.info.resp_len, Some(message.len() as u32));
+        assert_eq!(parser.info.status, L7ResponseStatus::ServerError);
+        assert_eq!(parser.info.exception, "Unknown user");
+
+        // 截断的异常信息不影响消息头的解析
+        // truncated exception message does not affect parsing of message header
+        parser.reset();
+        parser.parse(&payload[..payload.len() - 12]).unwrap();
+        assert_eq!(parser.info.status, L7ResponseStatus::ServerError);
+        assert!(parser.info.exception.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        // 版本错误
+        // wrong version
+        assert!(Header::decode(b"\x80\x02\x00\x01\x00\x00\x00\x01a\x00\x00\x00\x01").is_none());
+        // 方法名被截断
+        // method name truncated
+        assert!(Header::decode(b"\x80\x01\x00\x01\x00\x00\x00\x08getUs\x00\x00\x00").is_none());
+        // compact 版本错误
+        // wrong compact version
+        assert!(Header::decode(b"\x82\x22\x01\x04ping").is_none());
+        assert!(Header::decode(b"\x00\x00").is_none());
+    }
+
+    #[test]
+    fn test_exception_message_overflow() {
+        // field 1 binary, 长度为 10 字节 varint 编码的 u64::MAX
+        // field 1 binary, length is u64::MAX encoded as 10-byte varint
+        let body = b"\x18\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01abc";
+        assert_eq!(read_exception_message(ThriftProtocol::Compact, body), None);
+        let body = b"\x18\x03abc";
+        assert_eq!(
+            read_exception_message(ThriftProtocol::Compact, body),
+            Some("abc".to_string())
+        );
+        let body = b"\x0b\x00\x01\xff\xff\xff\xffabc";
+        assert_eq!(read_exception_message(ThriftProtocol::Binary, body), None);
+    }
+}
@@ -0,0 +1,490 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
+use super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::error::{Error, Result},
+};
+
+// 参考：https://www.rfc-editor.org/rfc/rfc5321#section-4.1
+const COMMANDS: [&str; 13] = [
+    "HELO", "EHLO", "MAIL", "RCPT", "DATA", "RSET", "VRFY", "EXPN", "HELP", "NOOP", "QUIT",
+    "STARTTLS", "AUTH",
+];
+const COMMAND_MAIL: &str = "MAIL";
+const COMMAND_RCPT: &str = "RCPT";
+const COMMAND_DATA: &str = "DATA";
+const COMMAND_AUTH: &str = "AUTH";
+
+const REPLY_CODE_SIZE: usize = 3;
+const REPLY_CODE_MIN: u16 = 200;
+const REPLY_CODE_MAX: u16 = 599;
+const REPLY_SERVICE_READY: u16 = 220;
+const REPLY_AUTH_CONTINUE: u16 = 334;
+const REPLY_START_MAIL_INPUT: u16 = 354;
+
+// 邮件内容以单独一行的 "." 结束
+// mail content ends with a line containing only "."
+const DATA_TERMINATOR: &[u8] = b"\r\n.\r\n";
+const DATA_TERMINATOR_LINE: &[u8] = b".\r\n";
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct SmtpInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub command: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub helo_domain: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub mail_from: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub rcpt_to: Vec<String>,
+
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub reply_code: Option<u16>,
+    #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
+    pub reply_text: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for SmtpInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::SmtpInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.resp_len = other.resp_len;
+                self.reply_code = other.reply_code;
+                self.reply_text = other.reply_text;
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Smtp,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<SmtpInfo> for L7ProtocolSendLog {
+    fn from(f: SmtpInfo) -> Self {
+        let resource = match f.command.as_str() {
+            COMMAND_MAIL => f.mail_from.clone(),
+            COMMAND_RCPT => f.rcpt_to.join(","),
+            _ => String::new(),
+        };
+        let mut attributes = vec![];
+        if !f.mail_from.is_empty() {
+            attributes.push(KeyVal {
+                key: "mail_from".to_string(),
+                val: f.mail_from,
+            });
+        }
+        if !f.rcpt_to.is_empty() {
+            attributes.push(KeyVal {
+                key: "rcpt_to".to_string(),
+                val: f.rcpt_to.join(","),
+            });
+        }
+        let (exception, result) = match f.status {
+            L7ResponseStatus::ClientError | L7ResponseStatus::ServerError => {
+                (f.reply_text, String::new())
+            }
+            _ => (String::new(), f.reply_text),
+        };
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.command,
+                domain: f.helo_domain,
+                resource,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.reply_code.map(|c| c as i32),
+                exception,
+                result,
+            },
+            ext_info: if attributes.is_empty() {
+                None
+            } else {
+                Some(ExtendedInfo {
+                    attributes: Some(attributes),
+                    ..Default::default()
+                })
+            },
+            ..Default::default()
+        }
+    }
+}
+
+// 按行切分, 去掉行尾的 CRLF 和空行
+// split into lines, trailing CRLF and empty lines are removed
+fn lines(payload: &[u8]) -> impl Iterator<Item = &[u8]> {
+    payload
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty())
+}
+
+// 回复行: 3 位回复码, 之后为 ' ' (最后一行) 或 '-' (多行回复中间行), 以及文本
+// reply line: 3-digit reply code, followed by ' ' (last line) or '-' (intermediate line
+// of multiline reply), and text
+fn parse_reply_line(line: &[u8]) -> Option<(u16, &[u8])> {
+    let code = line.get(..REPLY_CODE_SIZE)?;
+    if !code.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let code = code
+        .iter()
+        .fold(0u16, |acc, b| acc * 10 + (b - b'0') as u16);
+    if code < REPLY_CODE_MIN || code > REPLY_CODE_MAX {
+        return None;
+    }
+    match line.get(REPLY_CODE_SIZE) {
+        None => Some((code, &[])),
+        Some(b' ' | b'-') => Some((code, &line[REPLY_CODE_SIZE + 1..])),
+        _ => None,
+    }
+}
+
+// 返回最后一个回复的回复码和第一行的文本
+// return reply code of the last reply and text of the first line
+fn parse_reply(payload: &[u8]) -> Option<(u16, String)> {
+    let mut reply = None;
+    for line in lines(payload) {
+        let (code, text) = parse_reply_line(line)?;
+        match reply.as_mut() {
+            None => reply = Some((code, String::from_utf8_lossy(text).into_owned())),
+            Some((c, _)) => *c = code,
+        }
+    }
+    reply
+}
+
+// 返回大写的命令和参数
+// return command in uppercase and its argument
+fn parse_command(line: &[u8]) -> Option<(&'static str, &[u8])> {
+    let (verb, arg) = match line.iter().position(|b| *b == b' ') {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None => (line, &line[line.len()..]),
+    };
+    COMMANDS
+        .iter()
+        .find(|c| c.as_bytes().eq_ignore_ascii_case(verb))
+        .map(|c| (*c, arg))
+}
+
+// MAIL FROM:<reverse-path> [params] 或 RCPT TO:<forward-path> [params]
+// MAIL FROM:<reverse-path> [params] or RCPT TO:<forward-path> [params]
+fn parse_path(arg: &[u8]) -> String {
+    let Some(colon) = arg.iter().position(|b| *b == b':') else {
+        return String::new();
+    };
+    let path = &arg[colon + 1..];
+    let path = &path[path.iter().position(|b| *b != b' ').unwrap_or(path.len())..];
+    let path = match path.strip_prefix(b"<") {
+        Some(p) => &p[..p.iter().position(|b| *b == b'>').unwrap_or(p.len())],
+        None => &path[..path.iter().position(|b| *b == b' ').unwrap_or(path.len())],
+    };
+    String::from_utf8_lossy(path).into_owned()
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct SmtpLog {
+    info: SmtpInfo,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+
+    // 收到 354 回复后进入 DATA 阶段, 邮件内容不按命令解析
+    // DATA phase starts after 354 reply, mail content is not parsed as commands
+    #[serde(skip)]
+    in_data: bool,
+    #[serde(skip)]
+    data_len: usize,
+    // 收到 334 回复后客户端发送的是认证数据
+    // client sends authentication data after 334 reply
+    #[serde(skip)]
+    in_auth: bool,
+    #[serde(skip)]
+    has_command: bool,
+}
+
+impl L7ProtocolParserInterface for SmtpLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        let Some(line) = lines(payload).next() else {
+            return false;
+        };
+        match param.direction {
+            PacketDirection::ClientToServer => {
+                matches!(parse_command(line), Some(("HELO" | "EHLO", arg)) if !arg.is_empty())
+            }
+            // 服务端问候语, FTP 等协议也使用 220, 要求包含 SMTP
+            // server greeting, 220 is also used by protocols such as FTP, so SMTP is required
+            PacketDirection::ServerToClient => match parse_reply_line(line) {
+                Some((REPLY_SERVICE_READY, text)) => {
+                    text.windows(4).any(|w| w.eq_ignore_ascii_case(b"SMTP"))
+                }
+                _ => false,
+            },
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        if !self.parse(payload, param.direction)? {
+            return Ok(vec![]);
+        }
+        self.info.is_tls = param.is_tls();
+        if self.info.msg_type != LogMessageType::Session {
+            self.info.cal_rrt(param).map(|rrt| {
+                self.info.rrt = rrt;
+                self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+            });
+        }
+        Ok(vec![L7ProtocolInfo::SmtpInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Smtp
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[25, 587]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = SmtpInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl SmtpLog {
+    fn set_status(&mut self, code: u16) {
+        let perf_stats = self.perf_stats.as_mut().unwrap();
+        // 4xx 为暂时性错误, 多由服务端状态导致; 5xx 为永久性错误, 多由请求导致
+        // 4xx is transient failure mostly caused by server state,
+        // 5xx is permanent failure mostly caused by the request
+        self.info.status = match code {
+            400..=499 => {
+                perf_stats.inc_resp_err();
+                L7ResponseStatus::ServerError
+            }
+            500..=599 => {
+                perf_stats.inc_req_err();
+                L7ResponseStatus::ClientError
+            }
+            _ => L7ResponseStatus::Ok,
+        };
+    }
+
+    // 返回是否需要上报, DATA 阶段中间的报文不上报
+    // return whether to report, packets in the middle of DATA phase are not reported
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<bool> {
+        match direction {
+            PacketDirection::ClientToServer => self.parse_request(payload),
+            PacketDirection::ServerToClient => self.parse_response(payload).map(|_| true),
+        }
+    }
+
+    fn parse_request(&mut self, payload: &[u8]) -> Result<bool> {
+        if self.in_data {
+            self.data_len += payload.len();
+            if !payload.ends_with(DATA_TERMINATOR) && payload != DATA_TERMINATOR_LINE {
+                return Ok(false);
+            }
+            self.in_data = false;
+            self.info.command = COMMAND_DATA.to_owned();
+            self.info.req_len = Some(self.data_len.min(u32::MAX as usize) as u32);
+            self.data_len = 0;
+        } else if self.in_auth {
+            self.in_auth = false;
+            self.info.command = COMMAND_AUTH.to_owned();
+            self.info.req_len = Some(payload.len() as u32);
+        } else {
+            // PIPELINING 时一个报文中可能有多个命令, 以第一个命令作为请求类型
+            // there may be multiple commands in one packet with PIPELINING,
+            // the first command is used as request type
+            let mut commands = lines(payload).map_while(parse_command).peekable();
+            let Some((command, _)) = commands.peek() else {
+                return Err(Error::SmtpLogParseFailed);
+            };
+            self.info.command = command.to_string();
+            for (command, arg) in commands {
+                match command {
+                    "HELO" | "EHLO" => {
+                        self.info.helo_domain = String::from_utf8_lossy(arg).trim().to_owned()
+                    }
+                    COMMAND_MAIL => self.info.mail_from = parse_path(arg),
+                    COMMAND_RCPT => self.info.rcpt_to.push(parse_path(arg)),
+                    _ => (),
+                }
+            }
+            self.info.req_len = Some(payload.len() as u32);
+        }
+        self.has_command = true;
+        self.info.msg_type = LogMessageType::Request;
+        self.perf_stats.as_mut().unwrap().inc_req();
+        Ok(true)
+    }
+
+    fn parse_response(&mut self, payload: &[u8]) -> Result<()> {
+        let (code, text) = parse_reply(payload).ok_or(Error::SmtpLogParseFailed)?;
+        match code {
+            REPLY_START_MAIL_INPUT => self.in_data = true,
+            REPLY_AUTH_CONTINUE => self.in_auth = true,
+            _ => (),
+        }
+        // 客户端发送命令之前的回复为服务端问候语
+        // reply before any command sent by client is the server greeting
+        if self.has_command {
+            self.info.msg_type = LogMessageType::Response;
+            self.perf_stats.as_mut().unwrap().inc_resp();
+        } else {
+            self.info.msg_type = LogMessageType::Session;
+        }
+        self.info.reply_code = Some(code);
+        self.info.reply_text = text;
+        self.info.resp_len = Some(payload.len() as u32);
+        self.set_status(code);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use super::*;
+
+    use crate::common::l7_protocol_log::L7PerfCache;
+    use crate::flow_generator::L7_RRT_CACHE_CAPACITY;
+    use crate::utils::test::{Capture, ProtocolTester};
+
+    const FILE_DIR: &str = "resources/test/flow_generator/smtp";
+
+    fn tester() -> ProtocolTester<SmtpLog> {
+        ProtocolTester::new(SmtpLog::default())
+            // 第一个报文为服务端问候语
+            // the first packet is server greeting
+            .server_first()
+            .step(|smtp, payload, param| {
+                let is_smtp = match smtp.parse_payload(payload, param) {
+                    Ok(infos) => !infos.is_empty(),
+                    Err(_) => false,
+                };
+                smtp.info.rrt = 0;
+                format!("{:?} is_smtp: {}\r\n", smtp.info, is_smtp)
+            })
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(dir.join("smtp.pcap"), dir.join("smtp.result"));
+    }
+
+    #[test]
+    fn check_payload() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("smtp.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        let mut smtp = SmtpLog::default();
+
+        packets[0].lookup_key.direction = PacketDirection::ServerToClient;
+        let param = &ParseParam::from((&packets[0], log_cache.clone(), false));
+        assert!(smtp.check_payload(packets[0].get_l4_payload().unwrap(), param));
+
+        packets[1].lookup_key.direction = PacketDirection::ClientToServer;
+        let param = &ParseParam::from((&packets[1], log_cache.clone(), false));
+        assert!(smtp.check_payload(packets[1].get_l4_payload().unwrap(), param));
+
+        // FTP 问候语
+        // FTP greeting
+        let param = &ParseParam::from((&packets[0], log_cache.clone(), false));
+        assert!(!smtp.check_payload(b"220 ProFTPD Server ready.\r\n", param));
+    }
+
+    #[test]
+    fn check_reply() {
+        assert_eq!(
+            parse_reply(b"250-mail.example.com\r\n250-PIPELINING\r\n250 8BITMIME\r\n"),
+            Some((250, "mail.example.com".to_owned()))
+        );
+        assert_eq!(
+            parse_reply(b"250 2.1.0 Ok\r\n354 End data\r\n"),
+            Some((354, "2.1.0 Ok".to_owned()))
+        );
+        assert_eq!(parse_reply(b"EHLO example.com\r\n"), None);
+        assert_eq!(parse_reply(b"999 unknown\r\n"), None);
+        assert_eq!(
+            parse_path(b"FROM:<alice@example.org> SIZE=100"),
+            "alice@example.org"
+        );
+        assert_eq!(parse_path(b"TO: bob@example.com"), "bob@example.com");
+        assert_eq!(parse_path(b"FROM:<>"), "");
+    }
+}
@@ -0,0 +1,527 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::{
+        error::{Error, Result},
+        protocol_logs::{
+            pb_adapter::{L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    utils::bytes::{read_u32_le, read_varint},
+};
+
+// 参考：https://github.com/ClickHouse/ClickHouse/blob/master/src/Core/Protocol.h
+// client packet type
+const CLIENT_HELLO: u64 = 0;
+const CLIENT_QUERY: u64 = 1;
+const CLIENT_DATA: u64 = 2;
+const CLIENT_CANCEL: u64 = 3;
+const CLIENT_PING: u64 = 4;
+const CLIENT_PACKET_MAX: u64 = 11;
+
+// server packet type
+const SERVER_HELLO: u64 = 0;
+const SERVER_EXCEPTION: u64 = 2;
+const SERVER_PACKET_MAX: u64 = 14;
+
+const SERVER_PACKET_NAMES: [&str; SERVER_PACKET_MAX as usize + 1] = [
+    "Hello",
+    "Data",
+    "Exception",
+    "Progress",
+    "Pong",
+    "EndOfStream",
+    "ProfileInfo",
+    "Totals",
+    "Extremes",
+    "TablesStatusResponse",
+    "Log",
+    "TableColumns",
+    "PartUUIDs",
+    "ReadTaskRequest",
+    "ProfileEvents",
+];
+
+// 参考：https://github.com/ClickHouse/ClickHouse/blob/master/src/Core/ProtocolDefines.h
+const REVISION_WITH_CLIENT_INFO: u64 = 54032;
+const REVISION_WITH_QUOTA_KEY_IN_CLIENT_INFO: u64 = 54060;
+const REVISION_WITH_VERSION_PATCH: u64 = 54401;
+const REVISION_WITH_SETTINGS_SERIALIZED_AS_STRINGS: u64 = 54429;
+const REVISION_WITH_INTERSERVER_SECRET: u64 = 54441;
+const REVISION_WITH_OPENTELEMETRY: u64 = 54442;
+const REVISION_WITH_DISTRIBUTED_DEPTH: u64 = 54448;
+const REVISION_WITH_INITIAL_QUERY_START_TIME: u64 = 54449;
+const REVISION_WITH_PARALLEL_REPLICAS: u64 = 54453;
+// 没有抓到 Hello 时使用的协议版本
+// the revision used when Hello packets are not captured
+const DEFAULT_REVISION: u64 = 54449;
+
+const INTERFACE_TCP: u8 = 1;
+const CLIENT_NAME_MAX_LEN: usize = 64;
+
+// 客户端原因导致的异常码
+// exception codes caused by client
+const NUMBER_OF_ARGUMENTS_DOESNT_MATCH: i32 = 42;
+const ILLEGAL_TYPE_OF_ARGUMENT: i32 = 43;
+const UNKNOWN_FUNCTION: i32 = 46;
+const UNKNOWN_IDENTIFIER: i32 = 47;
+const TYPE_MISMATCH: i32 = 53;
+const TABLE_ALREADY_EXISTS: i32 = 57;
+const UNKNOWN_TABLE: i32 = 60;
+const SYNTAX_ERROR: i32 = 62;
+const UNKNOWN_DATABASE: i32 = 81;
+const UNKNOWN_SETTING: i32 = 115;
+const READONLY: i32 = 164;
+const ACCESS_DENIED: i32 = 497;
+const AUTHENTICATION_FAILED: i32 = 516;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct ClickHouseInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub packet_type: &'static str,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub database: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub query: String,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub exception_code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub exception_message: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for ClickHouseInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::ClickHouseInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.exception_code = other.exception_code;
+                self.exception_message = other.exception_message;
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::ClickHouse,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<ClickHouseInfo> for L7ProtocolSendLog {
+    fn from(f: ClickHouseInfo) -> Self {
+        L7ProtocolSendLog {
+            req: L7Request {
+                req_type: String::from(f.packet_type),
+                domain: f.database,
+                resource: f.query,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.exception_code,
+                exception: f.exception_message,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ClickHouseLog {
+    info: ClickHouseInfo,
+    // Hello 中协商的协议版本, 解析 Query 时需要
+    // protocol revision negotiated by Hello packets, needed by parsing Query
+    client_revision: u64,
+    server_revision: u64,
+    // Hello 中的默认数据库
+    // default database in client Hello
+    database: String,
+    // 一个请求的响应由多个报文组成, 只用第一个响应报文生成日志
+    // the response of a request consists of many packets, only the first one is logged
+    wait_response: bool,
+
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for ClickHouseLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol()
+            || param.l4_protocol != IpProtocol::Tcp
+            || param.direction != PacketDirection::ClientToServer
+        {
+            return false;
+        }
+        Self::check_client_hello(payload)
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.info.is_tls = param.is_tls();
+
+        match param.direction {
+            PacketDirection::ClientToServer => self.parse_client(payload)?,
+            PacketDirection::ServerToClient => self.parse_server(payload)?,
+        }
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_stats.as_mut().unwrap().inc_req(),
+            LogMessageType::Response => self.perf_stats.as_mut().unwrap().inc_resp(),
+            // 报文不需要生成日志
+            // the packet is not logged
+            _ => return Ok(vec![]),
+        }
+        self.info.cal_rrt(param).map(|rrt| {
+            self.info.rrt = rrt;
+            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+        });
+        Ok(vec![L7ProtocolInfo::ClickHouseInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::ClickHouse
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[9000]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = ClickHouseInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl ClickHouseLog {
+    // client Hello: type | client name | major | minor | revision | database | user | password
+    fn check_client_hello(payload: &[u8]) -> bool {
+        let mut r = Reader::new(payload);
+        if r.varint() != Some(CLIENT_HELLO) {
+            return false;
+        }
+        let Some(name) = r.string() else {
+            return false;
+        };
+        if name.is_empty()
+            || name.len() > CLIENT_NAME_MAX_LEN
+            || !name.iter().all(|c| c.is_ascii_graphic() || *c == b' ')
+        {
+            return false;
+        }
+        let (Some(_), Some(_), Some(revision)) = (r.varint(), r.varint(), r.varint()) else {
+            return false;
+        };
+        revision >= REVISION_WITH_CLIENT_INFO && r.string().is_some() && r.string().is_some()
+    }
+
+    fn revision(&self) -> u64 {
+        match (self.client_revision, self.server_revision) {
+            (0, 0) => DEFAULT_REVISION,
+            (0, r) | (r, 0) => r,
+            (c, s) => c.min(s),
+        }
+    }
+
+    fn parse_client(&mut self, payload: &[u8]) -> Result<()> {
+        let mut r = Reader::new(payload);
+        let packet_type = r.varint().ok_or(Error::ClickHouseLogParseFailed)?;
+        match packet_type {
+            CLIENT_HELLO => {
+                let _ = r.string();
+                let (_, _, revision) = (r.varint(), r.varint(), r.varint());
+                self.client_revision = revision.ok_or(Error::ClickHouseLogParseFailed)?;
+                self.database =
+                    String::from_utf8_lossy(r.string().ok_or(Error::ClickHouseLogParseFailed)?)
+                        .into_owned();
+                self.info.packet_type = "Hello";
+            }
+            CLIENT_QUERY => {
+                let query = self
+                    .read_query(&mut r)
+                    .ok_or(Error::ClickHouseLogParseFailed)?;
+                self.info.query = String::from_utf8_lossy(query).into_owned();
+                self.info.packet_type = "Query";
+            }
+            CLIENT_PING => self.info.packet_type = "Ping",
+            // Data 和 Cancel 等跟随在 Query 之后, 不生成日志
+            // Data, Cancel and others follow the Query, not logged
+            CLIENT_DATA | CLIENT_CANCEL..=CLIENT_PACKET_MAX => return Ok(()),
+            _ => return Err(Error::ClickHouseLogParseFailed),
+        }
+        self.info.msg_type = LogMessageType::Request;
+        self.info.database = self.database.clone();
+        self.wait_response = true;
+        Ok(())
+    }
+
+    // Query: query id | client info | settings | interserver secret | stage | compression | query
+    // 参考：https://github.com/ClickHouse/ClickHouse/blob/master/src/Interpreters/ClientInfo.cpp
+    fn read_query<'a>(&self, r: &mut Reader<'a>) -> Option<&'a [u8]> {
+        let revision = self.revision();
+        // query id
+        r.string()?;
+        if revision >= REVISION_WITH_CLIENT_INFO {
+            let query_kind = r.u8()?;
+            if query_kind != 0 {
+                // initial user, initial query id, initial address
+                for _ in 0..3 {
+                    r.string()?;
+                }
+                if revision >= REVISION_WITH_INITIAL_QUERY_START_TIME {
+                    r.skip(8)?;
+                }
+                let interface = r.u8()?;
+                if interface != INTERFACE_TCP {
+                    return None;
+                }
+                // os user, client hostname, client name
+                for _ in 0..3 {
+                    r.string()?;
+                }
+                // major, minor, tcp protocol version
+                for _ in 0..3 {
+                    r.varint()?;
+                }
+                if revision >= REVISION_WITH_QUOTA_KEY_IN_CLIENT_INFO {
+                    r.string()?;
+                }
+                if revision >= REVISION_WITH_DISTRIBUTED_DEPTH {
+                    r.varint()?;
+                }
+                if revision >= REVISION_WITH_VERSION_PATCH {
+                    r.varint()?;
+                }
+                if revision >= REVISION_WITH_OPENTELEMETRY && r.u8()? != 0 {
+                    // trace id, span id, tracestate, trace flags
+                    r.skip(24)?;
+                    r.string()?;
+                    r.skip(1)?;
+                }
+                if revision >= REVISION_WITH_PARALLEL_REPLICAS {
+                    for _ in 0..3 {
+                        r.varint()?;
+                    }
+                }
+            }
+        }
+        // settings 以空字符串结尾, 旧版本的二进制格式无法解析
+        // settings end with an empty name, binary format of old revision is not supported
+        if revision < REVISION_WITH_SETTINGS_SERIALIZED_AS_STRINGS {
+            return None;
+        }
+        while !r.string()?.is_empty() {
+            // flags, value
+            r.varint()?;
+            r.string()?;
+        }
+        if revision >= REVISION_WITH_INTERSERVER_SECRET {
+            r.string()?;
+        }
+        // stage, compression
+        r.varint()?;
+        r.varint()?;
+        r.string()
+    }
+
+    fn parse_server(&mut self, payload: &[u8]) -> Result<()> {
+        let mut r = Reader::new(payload);
+        let packet_type = r.varint().ok_or(Error::ClickHouseLogParseFailed)?;
+        if packet_type > SERVER_PACKET_MAX {
+            return Err(Error::ClickHouseLogParseFailed);
+        }
+        match packet_type {
+            SERVER_HELLO => {
+                let _ = r.string();
+                let (_, _, revision) = (r.varint(), r.varint(), r.varint());
+                self.server_revision = revision.ok_or(Error::ClickHouseLogParseFailed)?;
+            }
+            // exception: code | name | message | stack trace | has nested
+            SERVER_EXCEPTION => {
+                let code = r.i32_le().ok_or(Error::ClickHouseLogParseFailed)?;
+                let _ = r.string();
+                let message = r.string().ok_or(Error::ClickHouseLogParseFailed)?;
+                self.info.exception_code = Some(code);
+                self.info.exception_message = String::from_utf8_lossy(message).into_owned();
+                self.set_status(code);
+            }
+            _ if !self.wait_response => return Ok(()),
+            _ => {}
+        }
+        self.info.packet_type = SERVER_PACKET_NAMES[packet_type as usize];
+        self.info.msg_type = LogMessageType::Response;
+        self.wait_response = false;
+        Ok(())
+    }
+
+    fn set_status(&mut self, code: i32) {
+        match code {
+            NUMBER_OF_ARGUMENTS_DOESNT_MATCH
+            | ILLEGAL_TYPE_OF_ARGUMENT
+            | UNKNOWN_FUNCTION
+            | UNKNOWN_IDENTIFIER
+            | TYPE_MISMATCH
+            | TABLE_ALREADY_EXISTS
+            | UNKNOWN_TABLE
+            | SYNTAX_ERROR
+            | UNKNOWN_DATABASE
+            | UNKNOWN_SETTING
+            | READONLY
+            | ACCESS_DENIED
+            | AUTHENTICATION_FAILED => {
+                self.info.status = L7ResponseStatus::ClientError;
+                self.perf_stats.as_mut().unwrap().inc_req_err();
+            }
+            _ => {
+                self.info.status = L7ResponseStatus::ServerError;
+                self.perf_stats.as_mut().unwrap().inc_resp_err();
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        if self.buf.len() < n {
+            return None;
+        }
+        self.buf = &self.buf[n..];
+        Some(())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let b = *self.buf.first()?;
+        self.buf = &self.buf[1..];
+        Some(b)
+    }
+
+    fn i32_le(&mut self) -> Option<i32> {
+        if self.buf.len() < 4 {
+            return None;
+        }
+        let v = read_u32_le(self.buf) as i32;
+        self.buf = &self.buf[4..];
+        Some(v)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let (v, n) = read_varint(self.buf)?;
+        self.buf = &self.buf[n..];
+        Some(v)
+    }
+
+    // string: varint length | bytes
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let len = self.varint()? as usize;
+        if self.buf.len() < len {
+            return None;
+        }
+        let (s, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::ProtocolTester;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/clickhouse";
+
+    fn tester() -> ProtocolTester<ClickHouseLog> {
+        ProtocolTester::new(ClickHouseLog::default()).step(|clickhouse, payload, param| {
+            let is_clickhouse = clickhouse.check_payload(payload, param);
+            let mut lines = String::new();
+            for i in clickhouse.parse_payload(payload, param).unwrap() {
+                let L7ProtocolInfo::ClickHouseInfo(mut i) = i else {
+                    unreachable!()
+                };
+                i.rrt = 0;
+                lines.push_str(&format!("{:?} is_clickhouse: {}\r\n", i, is_clickhouse));
+            }
+            lines
+        })
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(dir.join("clickhouse.pcap"), dir.join("clickhouse.result"));
+    }
+
+    #[test]
+    fn check_varint() {
+        assert_eq!(read_varint(&[0x01]), Some((1, 1)));
+        assert_eq!(read_varint(&[0xb1, 0xa9, 0x03]), Some((54449, 3)));
+        assert_eq!(read_varint(&[0x80, 0x80]), None);
+    }
+}
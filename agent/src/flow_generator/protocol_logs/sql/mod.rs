@@ -14,12 +14,18 @@
  * limitations under the License.
  */
 
+mod clickhouse;
+mod mssql;
 mod mysql;
+mod oracle;
 mod postgre_convert;
 mod postgresql;
 mod redis;
 mod sql_check;
 
-pub use mysql::{MysqlHeader, MysqlInfo, MysqlLog};
+pub use clickhouse::{ClickHouseInfo, ClickHouseLog};
+pub use mssql::{MssqlInfo, MssqlLog};
+pub use mysql::{_fuzz_mysql_parse, MysqlHeader, MysqlInfo, MysqlLog};
+pub use oracle::{OracleInfo, OracleLog};
 pub use postgresql::{PostgreInfo, PostgresqlLog};
 pub use redis::{decode, RedisInfo, RedisLog};
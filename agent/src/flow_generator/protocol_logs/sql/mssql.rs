@@ -0,0 +1,655 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::sql_check::is_mssql;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::{
+        error::{Error, Result},
+        protocol_logs::{
+            pb_adapter::{L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    utils::bytes::{read_u16_be, try_read_u16_le, try_read_u32_le, try_read_u64_le},
+};
+
+// 参考: https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-tds
+// TDS header: type(1) | status(1) | length(2, BE) | spid(2) | packet id(1) | window(1)
+// 一个消息可能由多个 TDS 报文组成, 最后一个报文的 status 带有 EOM
+// a message may consist of many TDS packets, the status of the last packet has EOM
+const TDS_HEADER_LEN: usize = 8;
+const TDS_STATUS_OFFSET: usize = 1;
+const TDS_LENGTH_OFFSET: usize = 2;
+const TDS_WINDOW_OFFSET: usize = 7;
+
+const TDS_TYPE_SQL_BATCH: u8 = 0x01;
+const TDS_TYPE_RPC: u8 = 0x03;
+const TDS_TYPE_TABULAR_RESULT: u8 = 0x04;
+const TDS_TYPE_ATTENTION: u8 = 0x06;
+const TDS_TYPE_BULK_LOAD: u8 = 0x07;
+const TDS_TYPE_TRANSACTION_MANAGER: u8 = 0x0e;
+const TDS_TYPE_LOGIN7: u8 = 0x10;
+const TDS_TYPE_SSPI: u8 = 0x11;
+const TDS_TYPE_PRELOGIN: u8 = 0x12;
+
+const TDS_STATUS_EOM: u8 = 0x01;
+// EOM | IGNORE | RESETCONNECTION | RESETCONNECTIONSKIPTRAN
+const TDS_STATUS_MASK: u8 = 0x01 | 0x02 | 0x08 | 0x10;
+
+// RPC 的 NameLenProcID 为 0xffff 时, 其后是内置存储过程的 id
+// when NameLenProcID of RPC is 0xffff, it is followed by id of built-in stored procedure
+const PROC_ID_SWITCH: u16 = 0xffff;
+const PROC_ID_SP_EXECUTESQL: u16 = 10;
+// 按 id 排列, 从 1 开始
+// ordered by id, starting from 1
+const PROC_NAMES: [&str; 15] = [
+    "sp_cursor",
+    "sp_cursoropen",
+    "sp_cursorprepare",
+    "sp_cursorexecute",
+    "sp_cursorprepexec",
+    "sp_cursorunprepare",
+    "sp_cursorfetch",
+    "sp_cursoroption",
+    "sp_cursorclose",
+    "sp_executesql",
+    "sp_prepare",
+    "sp_execute",
+    "sp_prepexec",
+    "sp_prepexecrpc",
+    "sp_unprepare",
+];
+// sysname 最长 128 个字符
+// sysname is at most 128 characters
+const PROC_NAME_MAX_LEN: usize = 128;
+const OPTION_FLAGS_LEN: usize = 2;
+
+const TYPE_NVARCHAR: u8 = 0xe7;
+const COLLATION_LEN: usize = 5;
+// 最大长度为 0xffff 时, 值为 PLP 格式: 总长度(8) 之后是若干个 长度(4) + 数据 的块
+// when max length is 0xffff, the value is in PLP format: total length(8) followed by chunks of length(4) + data
+const PLP_MAX_LEN: u16 = 0xffff;
+const PLP_NULL: u64 = u64::MAX;
+const NVARCHAR_NULL: u16 = 0xffff;
+
+const TOKEN_RETURN_STATUS: u8 = 0x79;
+const TOKEN_ORDER: u8 = 0xa9;
+const TOKEN_ERROR: u8 = 0xaa;
+const TOKEN_INFO: u8 = 0xab;
+const TOKEN_LOGIN_ACK: u8 = 0xad;
+const TOKEN_ENV_CHANGE: u8 = 0xe3;
+const TOKEN_DONE: u8 = 0xfd;
+const TOKEN_DONE_PROC: u8 = 0xfe;
+const TOKEN_DONE_IN_PROC: u8 = 0xff;
+const RETURN_STATUS_LEN: usize = 4;
+// status(2) | cur cmd(2) | row count(8)
+const DONE_LEN: usize = 12;
+const DONE_STATUS_ERROR: u16 = 0x02;
+
+// ERROR 中 class 为严重级别, 11 到 16 为用户可以修正的错误, 17 以上为服务端的错误
+// class in ERROR is the severity, 11 to 16 are errors that can be corrected by user,
+// and 17 or above are errors of server
+const SEVERITY_USER_ERROR_MAX: u8 = 16;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct MssqlInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub packet_type: &'static str,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub sql: String,
+    #[serde(rename = "endpoint", skip_serializing_if = "value_is_default")]
+    pub procedure: String,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub error_number: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error_message: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for MssqlInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::MssqlInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.error_number = other.error_number;
+                self.error_message = other.error_message;
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Mssql,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<MssqlInfo> for L7ProtocolSendLog {
+    fn from(f: MssqlInfo) -> Self {
+        // sp_executesql 以外的 RPC 没有 SQL, 用存储过程名作为资源
+        // RPC other than sp_executesql has no sql, name of stored procedure is used as resource
+        let resource = if f.sql.is_empty() {
+            f.procedure.clone()
+        } else {
+            f.sql
+        };
+        L7ProtocolSendLog {
+            req: L7Request {
+                req_type: String::from(f.packet_type),
+                resource,
+                endpoint: f.procedure,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.error_number,
+                exception: f.error_message,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MssqlLog {
+    info: MssqlInfo,
+    // 一个响应可能由多个 TDS 报文组成, 只用第一个报文生成日志
+    // a response may consist of many TDS packets, only the first one is logged
+    wait_response: bool,
+    // 请求的后续 TDS 报文, 不生成日志
+    // following TDS packets of request, which are not logged
+    request_continued: bool,
+
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for MssqlLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol()
+            || param.l4_protocol != IpProtocol::Tcp
+            || param.direction != PacketDirection::ClientToServer
+        {
+            return false;
+        }
+        Self::check(payload)
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.info.is_tls = param.is_tls();
+
+        self.parse(payload, param.direction)?;
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_stats.as_mut().unwrap().inc_req(),
+            LogMessageType::Response => self.perf_stats.as_mut().unwrap().inc_resp(),
+            // 报文不需要生成日志
+            // the packet is not logged
+            _ => return Ok(vec![]),
+        }
+        self.info.cal_rrt(param).map(|rrt| {
+            self.info.rrt = rrt;
+            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+        });
+        Ok(vec![L7ProtocolInfo::MssqlInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Mssql
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[1433]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = MssqlInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+struct Header {
+    packet_type: u8,
+    status: u8,
+    length: usize,
+}
+
+impl Header {
+    fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.len() < TDS_HEADER_LEN {
+            return None;
+        }
+        let status = payload[TDS_STATUS_OFFSET];
+        if status & !TDS_STATUS_MASK != 0 || payload[TDS_WINDOW_OFFSET] != 0 {
+            return None;
+        }
+        let length = read_u16_be(&payload[TDS_LENGTH_OFFSET..]) as usize;
+        if length < TDS_HEADER_LEN {
+            return None;
+        }
+        Some(Self {
+            packet_type: payload[0],
+            status,
+            length,
+        })
+    }
+}
+
+impl MssqlLog {
+    // 长度与报文长度一致, 且为 SQLBatch 或 RPC
+    // length equals to payload length, and type is SQLBatch or RPC
+    fn check(payload: &[u8]) -> bool {
+        let Some(header) = Header::decode(payload) else {
+            return false;
+        };
+        if header.length != payload.len() {
+            return false;
+        }
+        let body = &payload[TDS_HEADER_LEN..];
+        match header.packet_type {
+            TDS_TYPE_SQL_BATCH => is_mssql(&ucs2_string(skip_all_headers(body))),
+            TDS_TYPE_RPC => decode_rpc(body).is_some(),
+            _ => false,
+        }
+    }
+
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
+        if direction == PacketDirection::ClientToServer && self.request_continued {
+            return Ok(());
+        }
+        let header = Header::decode(payload).ok_or(Error::MssqlLogParseFailed)?;
+        let body = &payload[TDS_HEADER_LEN..header.length.min(payload.len()).max(TDS_HEADER_LEN)];
+        match (header.packet_type, direction) {
+            (TDS_TYPE_SQL_BATCH, PacketDirection::ClientToServer) => {
+                self.info.sql = ucs2_string(skip_all_headers(body)).trim().to_owned();
+                self.info.packet_type = "SQLBatch";
+                self.set_request(header.status);
+            }
+            (TDS_TYPE_RPC, PacketDirection::ClientToServer) => {
+                let (procedure, statement) = decode_rpc(body).ok_or(Error::MssqlLogParseFailed)?;
+                self.info.procedure = procedure;
+                self.info.sql = statement.unwrap_or_default();
+                self.info.packet_type = "RPC";
+                self.set_request(header.status);
+            }
+            (TDS_TYPE_TABULAR_RESULT, PacketDirection::ServerToClient) => {
+                self.request_continued = false;
+                // 不是日志中请求的响应, 例如 PRELOGIN 的响应, 或者响应的后续报文
+                // not response of logged request, such as response of PRELOGIN, or following packets of response
+                if !self.wait_response {
+                    return Ok(());
+                }
+                self.parse_tokens(body);
+                self.info.msg_type = LogMessageType::Response;
+                self.wait_response = false;
+            }
+            // 其他报文不生成日志
+            // other packets are not logged
+            (
+                TDS_TYPE_ATTENTION
+                | TDS_TYPE_BULK_LOAD
+                | TDS_TYPE_TRANSACTION_MANAGER
+                | TDS_TYPE_LOGIN7
+                | TDS_TYPE_SSPI
+                | TDS_TYPE_PRELOGIN,
+                _,
+            ) => {}
+            _ => return Err(Error::MssqlLogParseFailed),
+        }
+        Ok(())
+    }
+
+    fn set_request(&mut self, status: u8) {
+        self.info.msg_type = LogMessageType::Request;
+        self.wait_response = true;
+        self.request_continued = status & TDS_STATUS_EOM == 0;
+    }
+
+    // 解析结果集之前的 token, 结果集的 token 无法简单跳过
+    // parse tokens before result set, tokens of result set can not be skipped simply
+    fn parse_tokens(&mut self, data: &[u8]) {
+        let mut offset = 0;
+        let mut done_error = false;
+        while let Some(&token) = data.get(offset) {
+            offset += 1;
+            match token {
+                TOKEN_ERROR | TOKEN_INFO | TOKEN_LOGIN_ACK | TOKEN_ENV_CHANGE | TOKEN_ORDER => {
+                    let Some(len) = data.get(offset..).and_then(try_read_u16_le) else {
+                        break;
+                    };
+                    offset += 2;
+                    let body = &data[offset..(offset + len as usize).min(data.len())];
+                    if token == TOKEN_ERROR && self.info.error_number.is_none() {
+                        if let Some((number, class, message)) = decode_error(body) {
+                            self.set_error(number, class, message);
+                        }
+                    }
+                    offset += len as usize;
+                }
+                TOKEN_RETURN_STATUS => offset += RETURN_STATUS_LEN,
+                TOKEN_DONE | TOKEN_DONE_PROC | TOKEN_DONE_IN_PROC => {
+                    if let Some(status) = data.get(offset..).and_then(try_read_u16_le) {
+                        done_error |= status & DONE_STATUS_ERROR != 0;
+                    }
+                    offset += DONE_LEN;
+                }
+                _ => break,
+            }
+        }
+        // DONE 带有错误但没有 ERROR token
+        // DONE has error without ERROR token
+        if done_error && self.info.error_number.is_none() {
+            self.info.status = L7ResponseStatus::ServerError;
+            self.perf_stats.as_mut().unwrap().inc_resp_err();
+        }
+    }
+
+    fn set_error(&mut self, number: i32, class: u8, message: String) {
+        self.info.error_number = Some(number);
+        self.info.error_message = message;
+        if class <= SEVERITY_USER_ERROR_MAX {
+            self.info.status = L7ResponseStatus::ClientError;
+            self.perf_stats.as_mut().unwrap().inc_req_err();
+        } else {
+            self.info.status = L7ResponseStatus::ServerError;
+            self.perf_stats.as_mut().unwrap().inc_resp_err();
+        }
+    }
+}
+
+// TDS 7.2 之后 SQLBatch 和 RPC 以 ALL_HEADERS 开头: 总长度(4, LE) 和若干个 header,
+// 没有 ALL_HEADERS 时开头为 UCS-2 字符, 读出的总长度远大于数据长度
+// since TDS 7.2, SQLBatch and RPC start with ALL_HEADERS: total length(4, LE) and headers,
+// without ALL_HEADERS it starts with UCS-2 characters, and the total length read is much larger than data
+fn skip_all_headers(data: &[u8]) -> &[u8] {
+    match try_read_u32_le(data) {
+        Some(total) if total as usize >= 4 && total as usize <= data.len() => {
+            &data[total as usize..]
+        }
+        _ => data,
+    }
+}
+
+// 不完整的字符被丢弃, 非法的代理对被替换
+// incomplete character is dropped, and invalid surrogate pair is replaced
+fn ucs2_string(data: &[u8]) -> String {
+    char::decode_utf16(
+        data.chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]])),
+    )
+    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+    .collect()
+}
+
+// 返回存储过程名和 sp_executesql 执行的语句
+// return name of stored procedure and the statement executed by sp_executesql
+fn decode_rpc(data: &[u8]) -> Option<(String, Option<String>)> {
+    let data = skip_all_headers(data);
+    let name_len = try_read_u16_le(data)?;
+    if name_len == PROC_ID_SWITCH {
+        let id = try_read_u16_le(data.get(2..)?)?;
+        let name = PROC_NAMES.get((id as usize).checked_sub(1)?)?;
+        let statement = if id == PROC_ID_SP_EXECUTESQL {
+            data.get(4 + OPTION_FLAGS_LEN..)
+                .and_then(first_nvarchar_param)
+        } else {
+            None
+        };
+        return Some((name.to_string(), statement));
+    }
+    let name_len = name_len as usize;
+    if name_len == 0 || name_len > PROC_NAME_MAX_LEN {
+        return None;
+    }
+    let name = ucs2_string(data.get(2..2 + name_len * 2)?);
+    if name.chars().any(char::is_control) {
+        return None;
+    }
+    Some((name, None))
+}
+
+// 参数: 名称(B_VARCHAR) | status flags(1) | TYPE_INFO | 值, 只解析 NVARCHAR, 值可能被截断
+// parameter: name(B_VARCHAR) | status flags(1) | TYPE_INFO | value, only NVARCHAR is parsed,
+// and the value may be truncated
+fn first_nvarchar_param(data: &[u8]) -> Option<String> {
+    let mut offset = 1 + *data.first()? as usize * 2 + 1;
+    if *data.get(offset)? != TYPE_NVARCHAR {
+        return None;
+    }
+    let max_len = try_read_u16_le(data.get(offset + 1..)?)?;
+    offset += 3 + COLLATION_LEN;
+    let len = if max_len == PLP_MAX_LEN {
+        // 只取第一块
+        // only the first chunk is used
+        if try_read_u64_le(data.get(offset..)?)? == PLP_NULL {
+            return None;
+        }
+        offset += 8;
+        let len = try_read_u32_le(data.get(offset..)?)? as usize;
+        offset += 4;
+        len
+    } else {
+        let len = try_read_u16_le(data.get(offset..)?)?;
+        if len == NVARCHAR_NULL {
+            return None;
+        }
+        offset += 2;
+        len as usize
+    };
+    let value = data.get(offset..)?;
+    Some(ucs2_string(&value[..len.min(value.len())]))
+}
+
+// ERROR: number(4) | state(1) | class(1) | message(US_VARCHAR) | server name | proc name | line number
+// 返回 (错误号, 严重级别, 错误信息)
+// return (error number, severity, error message)
+fn decode_error(data: &[u8]) -> Option<(i32, u8, String)> {
+    let number = try_read_u32_le(data)? as i32;
+    let class = *data.get(5)?;
+    let len = try_read_u16_le(data.get(6..)?)? as usize * 2;
+    let message = data.get(8..)?;
+    Some((
+        number,
+        class,
+        ucs2_string(&message[..len.min(message.len())]),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use super::*;
+
+    use crate::common::l7_protocol_log::L7PerfCache;
+    use crate::flow_generator::L7_RRT_CACHE_CAPACITY;
+    use crate::{common::MetaPacket, utils::test::Capture};
+
+    const FILE_DIR: &str = "resources/test/flow_generator/mssql";
+
+    fn run(name: &str) -> (Vec<MssqlInfo>, L7PerfStats) {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+
+        let mut mssql = MssqlLog::default();
+        let mut infos = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let param = &ParseParam::from((packet as &MetaPacket, log_cache.clone(), false));
+            let payload = packet.get_l4_payload().unwrap();
+            assert_eq!(
+                mssql.check_payload(payload, param),
+                packet.lookup_key.direction == PacketDirection::ClientToServer
+            );
+            for info in mssql.parse_payload(payload, param).unwrap() {
+                if let L7ProtocolInfo::MssqlInfo(info) = info {
+                    infos.push(info);
+                }
+            }
+            mssql.reset();
+        }
+        (infos, mssql.perf_stats.unwrap())
+    }
+
+    #[test]
+    fn check_batch() {
+        let (infos, perf_stats) = run("mssql_batch.pcap");
+        assert_eq!(infos.len(), 4);
+
+        assert_eq!(infos[0].msg_type, LogMessageType::Request);
+        assert_eq!(infos[0].packet_type, "SQLBatch");
+        assert_eq!(infos[0].sql, "SELECT name FROM sys.databases");
+        assert_eq!(infos[1].msg_type, LogMessageType::Response);
+        assert_eq!(infos[1].status, L7ResponseStatus::Ok);
+        assert_eq!(infos[1].error_number, None);
+
+        assert_eq!(infos[2].packet_type, "RPC");
+        assert_eq!(infos[2].procedure, "sp_executesql");
+        assert_eq!(infos[2].sql, "SELECT * FROM users WHERE id = @P1");
+        assert_eq!(infos[3].msg_type, LogMessageType::Response);
+        assert_eq!(infos[3].status, L7ResponseStatus::Ok);
+
+        assert_eq!(perf_stats.request_count, 2);
+        assert_eq!(perf_stats.response_count, 2);
+        assert_eq!(perf_stats.rrt_count, 2);
+    }
+
+    #[test]
+    fn check_error() {
+        let (infos, perf_stats) = run("mssql_error.pcap");
+        assert_eq!(infos.len(), 2);
+
+        assert_eq!(infos[0].sql, "SELECT * FROM nonexist");
+        assert_eq!(infos[1].msg_type, LogMessageType::Response);
+        assert_eq!(infos[1].error_number, Some(208));
+        assert_eq!(infos[1].error_message, "Invalid object name 'nonexist'.");
+        assert_eq!(infos[1].status, L7ResponseStatus::ClientError);
+
+        assert_eq!(perf_stats.err_client_count, 1);
+        assert_eq!(perf_stats.err_server_count, 0);
+    }
+
+    #[test]
+    fn check_rpc_name() {
+        let mut data = vec![0x08, 0x00];
+        data.extend("usp_test".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(decode_rpc(&data), Some(("usp_test".to_owned(), None)));
+        assert_eq!(
+            decode_rpc(&[0xff, 0xff, 0x0c, 0x00]),
+            Some(("sp_execute".to_owned(), None))
+        );
+        // 未知的 id
+        // unknown id
+        assert_eq!(decode_rpc(&[0xff, 0xff, 0x00, 0x00]), None);
+        assert_eq!(decode_rpc(&[0xff, 0xff, 0x10, 0x00]), None);
+        // 名称被截断
+        // name truncated
+        assert_eq!(decode_rpc(&data[..8]), None);
+        assert_eq!(decode_rpc(&[0x00, 0x00]), None);
+    }
+
+    #[test]
+    fn check_request_continued() {
+        let sql = "SELECT 1"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        // 没有 EOM 的第一个报文
+        // the first packet without EOM
+        let mut first = vec![TDS_TYPE_SQL_BATCH, 0, 0, 0, 0, 0, 1, 0];
+        first.extend_from_slice(&sql);
+        first[3] = first.len() as u8;
+
+        let mut mssql = MssqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        mssql
+            .parse(&first, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(mssql.info.sql, "SELECT 1");
+        assert!(mssql.request_continued);
+
+        // 后续报文不生成日志
+        // following packet is not logged
+        mssql.reset();
+        mssql
+            .parse(b"garbage", PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(mssql.info.msg_type, LogMessageType::Other);
+
+        let mut done = vec![TDS_TYPE_TABULAR_RESULT, TDS_STATUS_EOM, 0, 0, 0, 0, 1, 0];
+        done.extend_from_slice(&[TOKEN_DONE, 0x02]);
+        done.resize(TDS_HEADER_LEN + 1 + DONE_LEN, 0);
+        done[3] = done.len() as u8;
+        mssql.parse(&done, PacketDirection::ServerToClient).unwrap();
+        assert_eq!(mssql.info.msg_type, LogMessageType::Response);
+        assert_eq!(mssql.info.status, L7ResponseStatus::ServerError);
+        assert!(!mssql.request_continued);
+    }
+}
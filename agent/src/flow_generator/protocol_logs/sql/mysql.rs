@@ -14,32 +14,61 @@
  * limitations under the License.
  */
 
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    mem,
+    sync::{Arc, OnceLock},
+};
+
 use serde::Serialize;
 
-use super::super::{consts::*, value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
-use super::sql_check::is_mysql;
+use super::super::{
+    consts::*,
+    framing::{Endian, Frame, LengthPrefixed},
+    value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType,
+};
+use super::sql_check::{
+    is_mysql, is_transaction_control, mask_literals, procedure_call, quoted_end, use_database,
+};
 
 use crate::common::flow::L7PerfStats;
 use crate::{
     common::{
+        ebpf::EbpfType,
         enums::IpProtocol,
         flow::L7Protocol,
         flow::PacketDirection,
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
-        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        l7_protocol_log::{
+            L7ProtocolParserInterface, ParseParam, CHECK_SCORE_MAX, CHECK_SCORE_NONE,
+        },
+        L7_LOG_MYSQL_CLIENT_ERROR_CODES, L7_LOG_MYSQL_ERROR_MESSAGE_MAX_LENGTH,
     },
     flow_generator::{
         error::{Error, Result},
-        protocol_logs::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+        protocol_logs::pb_adapter::{
+            ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response,
+        },
     },
     utils::bytes,
 };
+use public::{bitmap::Bitmap, utils::bitmap::parse_u16_range_list_to_bitmap};
+
+// 包头为 3 字节小端的长度和 1 字节的序号, 长度不包含包头
+// packet header is 3 bytes little endian length and 1 byte sequence number, excluded from length
+const PACKET_FRAMING: LengthPrefixed =
+    LengthPrefixed::new(0, 3, Endian::Little).header_len(HEADER_LEN);
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct MysqlInfo {
     msg_type: LogMessageType,
     #[serde(skip)]
     is_tls: bool,
+    // 当前数据库在 l7_log_mysql_ignored_schemas 中, 不发送
+    // current database is in l7_log_mysql_ignored_schemas, not sent
+    #[serde(skip)]
+    ignored_schema: bool,
 
     // Server Greeting
     #[serde(rename = "version", skip_serializing_if = "value_is_default")]
@@ -53,12 +82,49 @@ pub struct MysqlInfo {
     pub command: u8,
     #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
     pub context: String,
+    // context 在 L4 payload 中的 (起始位置, 长度), 用于发送前脱敏原始报文
+    // (start, len) of context in L4 payload, used to redact raw packets before sending
+    #[serde(skip)]
+    pub query_offset: Option<(usize, usize)>,
+    // 多语句查询中语句的数量, context 只保存第一条语句
+    // number of statements in multi-statement query, context holds only the first one
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub statement_count: u32,
+    // COM_STMT_SEND_LONG_DATA 的目标语句和参数
+    // target statement and parameter of COM_STMT_SEND_LONG_DATA
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub statement_id: u32,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub param_id: u16,
+    // CALL 语句调用的存储过程, 包括执行预处理的 CALL 语句
+    // stored procedure invoked by CALL statement, including execution of prepared CALL statement
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub is_procedure_call: bool,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub procedure_name: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub procedure_param_count: u32,
+    // COM_QUERY 是 BEGIN, START TRANSACTION, COMMIT 或 ROLLBACK
+    // COM_QUERY is BEGIN, START TRANSACTION, COMMIT or ROLLBACK
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub is_transaction_control: bool,
     // response
     pub response_code: u8,
     #[serde(skip)]
     pub error_code: Option<i32>,
     #[serde(rename = "sql_affected_rows", skip_serializing_if = "value_is_default")]
     pub affected_rows: u64,
+    #[serde(rename = "sql_returned_rows", skip_serializing_if = "value_is_default")]
+    pub returned_rows: u64,
+    // 多语句查询或存储过程返回多个结果集 (SERVER_MORE_RESULTS_EXISTS)
+    // multi-statement query or stored procedure returns multiple result sets
+    // (SERVER_MORE_RESULTS_EXISTS)
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub is_multi_result: bool,
+    // 响应的 OK/EOF 包中服务端状态带有 SERVER_STATUS_IN_TRANS
+    // server status in OK/EOF packet of response has SERVER_STATUS_IN_TRANS
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub in_transaction: bool,
     #[serde(
         rename = "response_execption",
         skip_serializing_if = "value_is_default"
@@ -93,9 +159,33 @@ impl L7ProtocolInfoInterface for MysqlInfo {
     fn is_tls(&self) -> bool {
         self.is_tls
     }
+
+    fn skip_send(&self) -> bool {
+        self.ignored_schema
+    }
 }
 
 impl MysqlInfo {
+    // 合并请求和响应, 响应可能分为多个消息 (结果集头, 行, OK), 各字段的合并方式:
+    // - 握手字段: 保留第一个非默认值
+    // - 请求字段: 使用请求的值
+    // - 行数: 累加
+    // - is_multi_result: 任意一个为 true 即为 true
+    // - error_code, error_message: 保留第一个非零/非空值
+    // - response_code, status: 使用最后一个响应的值, 出错后不再覆盖
+    // - in_transaction: 使用最后一个响应的值
+    // - context: COM_STATISTICS 响应非空时使用响应的值
+    // ==============================
+    // merge request and response, response may be split into messages (result set header, rows, OK),
+    // fields are merged as below:
+    // - greeting fields: the first non-default value is kept
+    // - request fields: values of the request are used
+    // - row counts: summed up
+    // - is_multi_result: true if any of them is true
+    // - error_code, error_message: the first non-zero/non-empty value is kept
+    // - response_code, status: values of the last response are used, not overwritten after an error
+    // - in_transaction: value of the last response is used
+    // - context: value of the response is used when COM_STATISTICS response is not empty
     pub fn merge(&mut self, other: Self) {
         if self.protocol_version == 0 {
             self.protocol_version = other.protocol_version
@@ -106,17 +196,35 @@ impl MysqlInfo {
         if self.server_thread_id == 0 {
             self.server_thread_id = other.server_thread_id;
         }
+        self.ignored_schema |= other.ignored_schema;
         match other.msg_type {
             LogMessageType::Request => {
                 self.command = other.command;
                 self.context = other.context;
+                self.statement_count = other.statement_count;
+                self.is_procedure_call = other.is_procedure_call;
+                self.procedure_name = other.procedure_name;
+                self.procedure_param_count = other.procedure_param_count;
+                self.is_transaction_control = other.is_transaction_control;
             }
             LogMessageType::Response => {
-                self.response_code = other.response_code;
-                self.affected_rows = other.affected_rows;
-                self.error_message = other.error_message;
-                self.status = other.status;
-                if self.error_code.is_none() {
+                if self.response_code != MYSQL_RESPONSE_CODE_ERR {
+                    self.response_code = other.response_code;
+                    self.status = other.status;
+                }
+                self.affected_rows += other.affected_rows;
+                self.returned_rows += other.returned_rows;
+                self.is_multi_result |= other.is_multi_result;
+                self.in_transaction = other.in_transaction;
+                if self.error_message.is_empty() {
+                    self.error_message = other.error_message;
+                }
+                // COM_STATISTICS 响应的字符串
+                // string in response of COM_STATISTICS
+                if !other.context.is_empty() {
+                    self.context = other.context;
+                }
+                if self.error_code.unwrap_or_default() == 0 {
                     self.error_code = other.error_code;
                 }
             }
@@ -124,6 +232,24 @@ impl MysqlInfo {
         }
     }
 
+    // 合并同一个请求的后续结果集, 行数累加, 出错时使用错误的结果
+    // merge following result set of the same request, rows are summed up,
+    // and the error is taken if it fails
+    fn merge_result(&mut self, other: Self) {
+        self.is_multi_result = true;
+        self.affected_rows += other.affected_rows;
+        self.returned_rows += other.returned_rows;
+        self.in_transaction = other.in_transaction;
+        if other.response_code == MYSQL_RESPONSE_CODE_ERR
+            && self.response_code != MYSQL_RESPONSE_CODE_ERR
+        {
+            self.response_code = other.response_code;
+            self.error_code = other.error_code;
+            self.error_message = other.error_message;
+            self.status = other.status;
+        }
+    }
+
     pub fn get_command_str(&self) -> &'static str {
         let command = [
             "", // command 0 is resp, ignore
@@ -168,6 +294,57 @@ impl MysqlInfo {
 
 impl From<MysqlInfo> for L7ProtocolSendLog {
     fn from(f: MysqlInfo) -> Self {
+        let mut attributes = vec![];
+        if f.statement_count > 1 {
+            attributes.push(KeyVal {
+                key: "statement_count".to_string(),
+                val: f.statement_count.to_string(),
+            });
+        }
+        if f.returned_rows > 0 {
+            attributes.push(KeyVal {
+                key: "returned_rows".to_string(),
+                val: f.returned_rows.to_string(),
+            });
+        }
+        if f.is_multi_result {
+            attributes.push(KeyVal {
+                key: "is_multi_result".to_string(),
+                val: "true".to_string(),
+            });
+        }
+        if f.is_procedure_call {
+            attributes.push(KeyVal {
+                key: "procedure_name".to_string(),
+                val: f.procedure_name,
+            });
+            attributes.push(KeyVal {
+                key: "procedure_param_count".to_string(),
+                val: f.procedure_param_count.to_string(),
+            });
+        }
+        if f.is_transaction_control {
+            attributes.push(KeyVal {
+                key: "is_transaction_control".to_string(),
+                val: "true".to_string(),
+            });
+        }
+        if f.in_transaction {
+            attributes.push(KeyVal {
+                key: "in_transaction".to_string(),
+                val: "true".to_string(),
+            });
+        }
+        if f.command == COM_STMT_SEND_LONG_DATA {
+            attributes.push(KeyVal {
+                key: "statement_id".to_string(),
+                val: f.statement_id.to_string(),
+            });
+            attributes.push(KeyVal {
+                key: "param_id".to_string(),
+                val: f.param_id.to_string(),
+            });
+        }
         let log = L7ProtocolSendLog {
             version: if f.protocol_version == 0 {
                 None
@@ -187,6 +364,11 @@ impl From<MysqlInfo> for L7ProtocolSendLog {
                 ..Default::default()
             },
             ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -198,47 +380,342 @@ impl From<MysqlInfo> for L7ProtocolSendLog {
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct MysqlLog {
     info: MysqlInfo,
+    // 同一个 payload 中第一个消息之后的消息
+    // messages after the first one in the same payload
+    #[serde(skip)]
+    infos: Vec<MysqlInfo>,
     command: u8,
+    // check 成功时保存解码的头部, parse 处理同一个 payload 时直接使用,
+    // 协议识别时首包的头部解码次数从 2 次减少为 1 次
+    // header decoded by successful check, reused by parse on the same payload,
+    // which reduces header decodes of the first packet from 2 to 1 in protocol inference
+    #[serde(skip)]
+    check_cache: Option<CheckCache>,
+    // 客户端在 Handshake Response 中声明了 CLIENT_QUERY_ATTRIBUTES, COM_QUERY 中带有查询属性
+    // client declared CLIENT_QUERY_ATTRIBUTES in Handshake Response, COM_QUERY carries query attributes
+    #[serde(skip)]
+    query_attributes: bool,
+    // 是否记录 SQL 在 L4 payload 中的位置, 由 l7_log_sql_query_offset 配置
+    // whether to record position of sql in L4 payload, configured by l7_log_sql_query_offset
+    #[serde(skip)]
+    record_query_offset: bool,
+    // 是否把 SQL 中的字面量替换为 ?, 由 l7_log_sql_mask_literals 配置
+    // whether to replace literals in sql with ?, configured by l7_log_sql_mask_literals
+    #[serde(skip)]
+    mask_literals: bool,
+    // 客户端错误码, 由 l7_log_mysql_client_error_codes 配置
+    // client error codes, configured by l7_log_mysql_client_error_codes
+    #[serde(skip)]
+    client_error_codes: Option<Arc<Bitmap>>,
+    // 错误信息的最大长度, 为 0 时不限制, 由 l7_log_mysql_error_message_max_length 配置
+    // maximum length of error message, unlimited if 0, configured by
+    // l7_log_mysql_error_message_max_length
+    #[serde(skip)]
+    error_message_max_length: usize,
+    // 按 flow_id 和方向重组 TCP 分段, 下标为 PacketDirection
+    // tcp segments reassembled by flow_id and direction, indexed by PacketDirection
+    #[serde(skip)]
+    reassembly: [Reassembly; 2],
+    // 等待 COM_STMT_PREPARE_OK 的 CALL 语句的存储过程名和参数个数
+    // procedure name and argument count of CALL statement waiting for COM_STMT_PREPARE_OK
+    #[serde(skip)]
+    preparing_procedure: Option<(String, u32)>,
+    // 预处理的 CALL 语句, 键为 statement_id
+    // prepared CALL statements, keyed by statement_id
+    #[serde(skip)]
+    prepared_procedures: HashMap<u32, (String, u32)>,
+    // 当前数据库, 来自 Handshake Response, 以及 COM_INIT_DB 和 USE 语句成功的响应
+    // current database, from Handshake Response, and successful response of COM_INIT_DB
+    // and USE statement
+    #[serde(skip)]
+    database: Option<String>,
+    // 等待响应的 COM_INIT_DB 和 USE 语句切换到的数据库
+    // database switched to by COM_INIT_DB or USE statement waiting for response
+    #[serde(skip)]
+    pending_database: Option<String>,
     #[serde(skip)]
     perf_stats: Option<L7PerfStats>,
 }
 
+// payload 中完整的响应消息
+// complete response message in payload
+#[derive(Debug, PartialEq)]
+struct ResponseMessage {
+    // 用于解析的包体, 第一个 OK/ERR/EOF 包, 与 MysqlHeader::decode 一致
+    // body to parse, the first OK/ERR/EOF packet, same as MysqlHeader::decode
+    body: (usize, usize),
+    // 结果集的行数
+    // rows of result set
+    rows: u64,
+    // 结束包中的服务端状态
+    // server status in the ending packet
+    status_flags: u16,
+}
+
+impl ResponseMessage {
+    fn more_results(&self) -> bool {
+        self.status_flags & SERVER_MORE_RESULTS_EXISTS != 0
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CheckCache {
+    // 报文的时间, 序列号和 payload 长度, 用于判断 parse 处理的是否为 check 的报文
+    // time, sequence numbers of packet and length of payload, used to determine whether
+    // parse handles the packet checked
+    packet: (u64, u64, u32, usize),
+    offset: usize,
+    msg_type: LogMessageType,
+}
+
+impl CheckCache {
+    fn packet_key(payload: &[u8], param: &ParseParam) -> (u64, u64, u32, usize) {
+        (param.time, param.packet_seq, param.tcp_seq, payload.len())
+    }
+}
+
+// 单个方向的 TCP 分段重组缓存, 缓存的数据从 MySQL 包的头部开始,
+// 第一个包完整后交给 parse, 之后不完整的包和现在一样按截断处理
+// tcp reassembly buffer of one direction, buffered data starts at header of a MySQL packet,
+// and is handed over to parse once the first packet is complete, incomplete packets after it
+// are handled as truncated just like before
+#[derive(Clone, Debug, Default)]
+struct Reassembly {
+    flow_id: u64,
+    data: Vec<u8>,
+    // data 第一个字节的序列号
+    // sequence number of the first byte in data
+    seq: u32,
+    // 第一个分段的时间, 用于计算时延
+    // time of the first segment, used to calculate rrt
+    time: u64,
+    // 乱序到达的分段, 键为相对 seq 的偏移
+    // out-of-order segments, keyed by offset relative to seq
+    segments: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Reassembly {
+    // payload 以序号为 number 的不完整的包开始, 并且包的长度允许重组
+    // payload starts with an incomplete packet of sequence number `number`,
+    // and the packet is short enough to be reassembled
+    fn is_incomplete(payload: &[u8], number: u8) -> bool {
+        let framing = PACKET_FRAMING.max_len(MYSQL_REASSEMBLY_MAX_LEN);
+        payload.get(NUMBER_OFFSET) == Some(&number)
+            && matches!(framing.frame(payload), Frame::Partial(Some(_)))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn start(&mut self, payload: &[u8], param: &ParseParam) {
+        *self = Self {
+            flow_id: param.flow_id,
+            data: payload.to_vec(),
+            seq: param.tcp_seq,
+            time: param.time,
+            segments: BTreeMap::new(),
+        };
+    }
+
+    // 追加后续的分段, 第一个包完整或者放弃等待缺失的分段时返回 true
+    // append following segment, return true when the first packet is complete
+    // or the missing segment is no longer waited for
+    fn append(&mut self, payload: &[u8], seq: u32) -> bool {
+        let offset = seq.wrapping_sub(self.seq);
+        // 已经解析的数据的重传
+        // retransmission of data already parsed
+        if (offset as i32) < 0 {
+            return false;
+        }
+        if offset as usize > self.data.len() {
+            // 前面的分段可能被截断, 不会再收到, 乱序分段过多时放弃等待
+            // previous segment may be truncated and will never arrive,
+            // stop waiting when there are too many out-of-order segments
+            if self.segments.len() >= MYSQL_REASSEMBLY_MAX_SEGMENTS
+                || offset as usize + payload.len() > MYSQL_REASSEMBLY_MAX_LEN
+            {
+                return true;
+            }
+            self.segments.insert(offset, payload.to_vec());
+            return false;
+        }
+        self.extend(offset as usize, payload);
+        while let Some((&offset, _)) = self.segments.first_key_value() {
+            if offset as usize > self.data.len() {
+                break;
+            }
+            let (_, segment) = self.segments.pop_first().unwrap();
+            self.extend(offset as usize, &segment);
+        }
+        read_packet(&self.data, 0).is_some() || self.data.len() >= MYSQL_REASSEMBLY_MAX_LEN
+    }
+
+    fn extend(&mut self, offset: usize, payload: &[u8]) {
+        // 和已缓存数据重叠的部分是重传
+        // part overlapping with buffered data is retransmission
+        if let Some(data) = payload.get(self.data.len() - offset..) {
+            self.data.extend_from_slice(data);
+        }
+    }
+
+    // 取出缓存的数据和第一个分段的时间
+    // take buffered data and time of the first segment
+    fn take(&mut self) -> (Vec<u8>, u64) {
+        self.segments.clear();
+        (mem::take(&mut self.data), self.time)
+    }
+}
+
 impl L7ProtocolParserInterface for MysqlLog {
     fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
         if !param.ebpf_type.is_raw_protocol() {
             return false;
         }
         self.info.is_tls = param.is_tls();
-        Self::check(payload, param)
+        self.check(payload, param)
+    }
+
+    // 包头长度, 序号, 命令和 SQL 关键字都需要匹配, 是确定的匹配
+    // header length, sequence number, command and SQL keyword all have to match,
+    // so it is a certain match
+    fn check_payload_score(&mut self, payload: &[u8], param: &ParseParam) -> u8 {
+        if self.check_payload(payload, param) {
+            CHECK_SCORE_MAX
+        } else {
+            CHECK_SCORE_NONE
+        }
     }
 
     fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
-        self.info.is_tls = param.is_tls();
-        if self.perf_stats.is_none() {
-            self.perf_stats = Some(L7PerfStats::default())
+        // eBPF 的数据没有 TCP 序列号, 不重组
+        // no tcp sequence number for ebpf payload, not reassembled
+        if param.ebpf_type != EbpfType::None {
+            return self.parse_message(payload, param);
+        }
+        for reassembly in self.reassembly.iter_mut() {
+            if reassembly.flow_id != param.flow_id {
+                *reassembly = Reassembly {
+                    flow_id: param.flow_id,
+                    ..Default::default()
+                };
+            }
+        }
+
+        // 重传包只用于填补正在重组的数据, 不会产生重复的日志
+        // retransmission only fills data being reassembled, no duplicated log is generated
+        if param.is_retransmission {
+            let reassembly = &mut self.reassembly[param.direction as usize];
+            if reassembly.is_empty() || !reassembly.append(payload, param.tcp_seq) {
+                return Ok(vec![]);
+            }
+            let (data, time) = reassembly.take();
+            let param = ParseParam {
+                time,
+                ..param.clone()
+            };
+            return self.parse_message(&data, &param);
+        }
+
+        // 对端开始发送时, 本端缓存的包不会再有后续分段 (例如被截断), 按原样解析
+        // when the peer starts sending, the buffered packet will have no more segments
+        // (truncated for example), parse it as it is
+        let mut infos = vec![];
+        let peer = param.direction.reversed();
+        if !self.reassembly[peer as usize].is_empty() {
+            let (data, time) = self.reassembly[peer as usize].take();
+            let peer_param = ParseParam {
+                direction: peer,
+                time,
+                ..param.clone()
+            };
+            if let Ok(mut peer_infos) = self.parse_message(&data, &peer_param) {
+                infos.append(&mut peer_infos);
+            }
+        }
+
+        let reassembly = &mut self.reassembly[param.direction as usize];
+        let ret = if !reassembly.is_empty() {
+            if !reassembly.append(payload, param.tcp_seq) {
+                return Ok(infos);
+            }
+            let (data, time) = reassembly.take();
+            let param = ParseParam {
+                time,
+                ..param.clone()
+            };
+            self.parse_message(&data, &param)
+        } else {
+            // 请求的第一个包序号为 0, 响应的第一个包序号为 1
+            // first packet of request has sequence number 0, and 1 for response
+            let incomplete = match param.direction {
+                PacketDirection::ClientToServer => Reassembly::is_incomplete(payload, 0),
+                PacketDirection::ServerToClient => {
+                    self.command != 0 && Reassembly::is_incomplete(payload, 1)
+                }
+            };
+            if incomplete {
+                reassembly.start(payload, param);
+                return Ok(infos);
+            }
+            self.parse_message(payload, param)
         };
-        if self.parse(payload, param.l4_protocol, param.direction)? {
-            // ignore greeting
-            return Ok(vec![]);
+        match ret {
+            Ok(mut message_infos) => {
+                infos.append(&mut message_infos);
+                Ok(infos)
+            }
+            Err(e) if infos.is_empty() => Err(e),
+            Err(_) => Ok(infos),
         }
-        self.info.cal_rrt(param).map(|rrt| {
-            self.info.rrt = rrt;
-            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
-        });
-        Ok(vec![L7ProtocolInfo::MysqlInfo(self.info.clone())])
     }
 
     fn parsable_on_udp(&self) -> bool {
         false
     }
 
+    fn parse_retransmission(&self) -> bool {
+        true
+    }
+
     fn protocol(&self) -> L7Protocol {
         L7Protocol::MySQL
     }
 
+    fn default_ports(&self) -> &[u16] {
+        &[3306]
+    }
+
+    // 握手包由服务端发送, 解析需要服务端方向的数据 (例如协议版本),
+    // 识别只检查客户端的 COM_QUERY 和 COM_STMT_PREPARE, 不依赖此标志
+    // greeting is sent by server, so parsing needs payload of server direction (protocol version
+    // for example), identification only checks COM_QUERY and COM_STMT_PREPARE from client and
+    // does not depend on this flag
+    fn needs_bidirectional(&self) -> bool {
+        true
+    }
+
+    // 每个包解析后都会调用 reset, 一个解析器实例只用于一条流 (get_parser 为每条流创建新实例),
+    // 所以只清除单个包的状态, 等待响应的命令, 协议版本和握手中协商的能力属于流的状态, 需要保留
+    // reset is called after every packet is parsed, and a parser instance is used for only one flow
+    // (get_parser creates a new instance for each flow), so only per-packet states are cleared,
+    // the command waiting for response, protocol version and capabilities negotiated in handshake
+    // are states of the flow and must be kept
     fn reset(&mut self) {
         *self = Self {
             command: self.command,
+            infos: vec![],
+            check_cache: None,
+            query_attributes: self.query_attributes,
+            record_query_offset: self.record_query_offset,
+            mask_literals: self.mask_literals,
+            client_error_codes: self.client_error_codes.take(),
+            error_message_max_length: self.error_message_max_length,
+            reassembly: mem::take(&mut self.reassembly),
+            preparing_procedure: self.preparing_procedure.take(),
+            prepared_procedures: mem::take(&mut self.prepared_procedures),
+            database: self.database.take(),
+            pending_database: self.pending_database.take(),
             info: MysqlInfo {
                 protocol_version: self.info.protocol_version,
                 status: L7ResponseStatus::Ok,
@@ -254,141 +731,437 @@ impl L7ProtocolParserInterface for MysqlLog {
     }
 }
 
-fn mysql_string(payload: &[u8]) -> String {
+// 默认的客户端错误码, 没有解析配置时使用, 只解析一次
+// default client error codes used without parse config, parsed only once
+fn default_client_error_codes() -> &'static Bitmap {
+    static CODES: OnceLock<Bitmap> = OnceLock::new();
+    CODES.get_or_init(|| {
+        parse_u16_range_list_to_bitmap(&L7_LOG_MYSQL_CLIENT_ERROR_CODES.to_string(), false).unwrap()
+    })
+}
+
+// 去掉末尾的 NUL, 控制字符替换为空格, 超过 max_length 时在字符边界截断, 为 0 时不截断
+// trailing NULs are removed, control characters are replaced with spaces, and the message is
+// truncated at char boundary if longer than max_length, not truncated if max_length is 0
+fn error_message(message: &[u8], max_length: usize) -> String {
+    let mut end = message.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    if max_length > 0 {
+        end = end.min(max_length);
+    }
+    let mut message = String::from_utf8_lossy(&message[..end])
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>();
+    // 非法字符替换为 3 字节的 U+FFFD 后可能超过 max_length
+    // may exceed max_length after invalid bytes are replaced with 3-byte U+FFFD
+    if max_length > 0 && message.len() > max_length {
+        let mut end = max_length;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+    }
+    message
+}
+
+// 合法的 UTF-8 直接借用, 只有需要替换非法字符时才分配内存
+// valid UTF-8 is borrowed, memory is allocated only when invalid characters need to be replaced
+fn mysql_string(payload: &[u8]) -> Cow<str> {
+    String::from_utf8_lossy(&payload[mysql_string_start(payload)..])
+}
+
+fn mysql_string_start(payload: &[u8]) -> usize {
     if payload.len() > 2 && payload[0] == 0 && payload[1] == 1 {
         // MYSQL 8.0.26返回字符串前有0x0、0x1，MYSQL 8.0.21版本没有这个问题
         // https://gitlab.yunshan.net/platform/trident/-/merge_requests/2592#note_401425
-        String::from_utf8_lossy(&payload[2..]).into_owned()
+        2
     } else {
-        String::from_utf8_lossy(payload).into_owned()
+        0
+    }
+}
+
+// 按不在字符串和注释中的分号切分 SQL, 返回第一条语句和语句的数量
+// split sql by semicolons outside of string literals and comments,
+// return the first statement and number of statements
+fn split_statements(sql: &str) -> (&str, u32) {
+    let bytes = sql.as_bytes();
+    // 返回 end 最后一个字节的位置, 找不到时为 sql 的结尾
+    // return position of the last byte of end, or end of sql if not found
+    let skip_to = |from: usize, end: &[u8]| {
+        bytes[from..]
+            .windows(end.len())
+            .position(|w| w == end)
+            .map_or(bytes.len(), |p| from + p + end.len() - 1)
+    };
+    let mut first = None;
+    let mut count = 0;
+    let mut add_statement = |start: usize, end: usize| {
+        let statement = sql[start..end].trim();
+        if !statement.is_empty() {
+            first.get_or_insert(statement);
+            count += 1;
+        }
+    };
+
+    let (mut start, mut i) = (0, 0);
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' | b'`' => i = quoted_end(bytes, i),
+            b'#' => i = skip_to(i + 1, b"\n"),
+            b'-' if bytes.get(i + 1) == Some(&b'-')
+                && bytes.get(i + 2).map_or(true, |c| c.is_ascii_whitespace()) =>
+            {
+                i = skip_to(i + 2, b"\n")
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_to(i + 2, b"*/"),
+            b';' => {
+                add_statement(start, i);
+                start = i + 1;
+            }
+            _ => (),
+        }
+        i += 1;
     }
+    add_statement(start, bytes.len());
+    (first.unwrap_or_default(), count)
 }
 
 impl MysqlLog {
-    fn request_string(&mut self, payload: &[u8]) {
-        self.info.context = mysql_string(payload);
+    // 解析完整的 payload 或者重组后的数据
+    // parse a complete payload or reassembled data
+    fn parse_message(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        // 不依赖调用方在上一个包之后调用 reset, 先清除上一个包的状态, 只保留同一个报文的 check 的缓存
+        // not relying on caller to reset after the previous packet, clear states of the previous
+        // packet first, only the check cache of the same packet is kept
+        let check_cache = self
+            .check_cache
+            .take()
+            .filter(|c| c.packet == CheckCache::packet_key(payload, param));
+        self.reset();
+        self.check_cache = check_cache;
+        self.info.is_tls = param.is_tls();
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.record_query_offset = param
+            .parse_config
+            .map(|c| c.l7_log_sql_query_offset)
+            .unwrap_or_default();
+        self.mask_literals = param
+            .parse_config
+            .map(|c| c.l7_log_sql_mask_literals)
+            .unwrap_or_default();
+        self.client_error_codes = param
+            .parse_config
+            .map(|c| c.l7_log_mysql_client_error_codes.clone());
+        self.error_message_max_length = param
+            .parse_config
+            .map(|c| c.l7_log_mysql_error_message_max_length)
+            .unwrap_or(L7_LOG_MYSQL_ERROR_MESSAGE_MAX_LENGTH);
+        // 使用解析前的数据库, USE 语句的请求和响应都属于切换前的数据库
+        // database before parsing is used, both request and response of USE statement
+        // belong to the database before switching
+        let ignored_schema = match (param.parse_config, self.database.as_ref()) {
+            (Some(config), Some(database)) => config
+                .l7_log_mysql_ignored_schemas
+                .iter()
+                .any(|schema| schema.eq_ignore_ascii_case(database)),
+            _ => false,
+        };
+        if self.parse(payload, param.l4_protocol, param.direction)? {
+            // ignore greeting and handshake response
+            return Ok(vec![]);
+        }
+        // COM_STMT_SEND_LONG_DATA 没有响应, 不计算时延
+        // COM_STMT_SEND_LONG_DATA has no response, rrt is not calculated
+        if self.info.msg_type != LogMessageType::Session {
+            let rrt = self.info.cal_rrt(param).map(|rrt| {
+                self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+                rrt
+            });
+            self.info.rrt = rrt.unwrap_or_default();
+        }
+        self.info.ignored_schema = ignored_schema;
+        let mut infos = vec![L7ProtocolInfo::MysqlInfo(self.info.clone())];
+        for mut info in self.infos.drain(..) {
+            info.is_tls = self.info.is_tls;
+            info.ignored_schema = ignored_schema;
+            info.rrt = self.info.rrt;
+            infos.push(L7ProtocolInfo::MysqlInfo(info));
+        }
+        Ok(infos)
     }
 
-    fn greeting(&mut self, payload: &[u8]) -> Result<()> {
-        let mut remain = payload.len();
-        if remain < PROTOCOL_VERSION_LEN {
-            return Err(Error::MysqlLogParseFailed);
+    // offset 为 payload 在 L4 payload 中的位置
+    // offset is the position of payload in L4 payload
+    fn request_string(&mut self, payload: &[u8], offset: usize) {
+        let start = mysql_string_start(payload);
+        let sql = mysql_string(payload);
+        let (first, count) = split_statements(&sql);
+        // 非法的 UTF-8 被替换后与原始字节不再对应, 不记录位置
+        // invalid UTF-8 is replaced and does not match raw bytes, offset is not recorded
+        if self.record_query_offset && !first.is_empty() && matches!(sql, Cow::Borrowed(_)) {
+            let first_start = first.as_ptr() as usize - sql.as_ptr() as usize;
+            self.info.query_offset = Some((offset + start + first_start, first.len()));
+        }
+        // 屏蔽字面量时不保存原始的 SQL
+        // raw sql is never stored when literals are masked
+        self.info.context = match self.info.command {
+            COM_QUERY | COM_STMT_PREPARE if self.mask_literals => mask_literals(first),
+            _ => first.to_owned(),
+        };
+        self.info.statement_count = count;
+        if let Some((name, param_count)) = procedure_call(first) {
+            self.info.is_procedure_call = true;
+            self.info.procedure_name = name.to_owned();
+            self.info.procedure_param_count = param_count;
         }
-        self.info.protocol_version = payload[PROTOCOL_VERSION_OFFSET];
-        remain -= PROTOCOL_VERSION_LEN;
-        let server_version_pos = payload[SERVER_VERSION_OFFSET..]
+        self.info.is_transaction_control =
+            self.info.command == COM_QUERY && is_transaction_control(first);
+        self.pending_database = match self.info.command {
+            COM_INIT_DB if !first.is_empty() => Some(first.to_owned()),
+            COM_QUERY => use_database(first).map(str::to_owned),
+            _ => None,
+        };
+    }
+
+    fn greeting(&mut self, payload: &[u8]) -> Result<()> {
+        let Some(&protocol_version) = payload.get(PROTOCOL_VERSION_OFFSET) else {
+            return Err(Error::MysqlGreetingInvalid);
+        };
+        let server_version = &payload[SERVER_VERSION_OFFSET..];
+        let Some(server_version_pos) = server_version
             .iter()
             .position(|&x| x == SERVER_VERSION_EOF)
-            .unwrap_or_default();
-        if server_version_pos <= 0 {
-            return Err(Error::MysqlLogParseFailed);
-        }
-        self.info.server_version = String::from_utf8_lossy(
-            &payload[SERVER_VERSION_OFFSET..SERVER_VERSION_OFFSET + server_version_pos],
-        )
-        .into_owned();
-        remain -= server_version_pos as usize;
-        if remain < THREAD_ID_LEN + 1 {
-            return Err(Error::MysqlLogParseFailed);
-        }
+            .filter(|&pos| pos > 0)
+        else {
+            return Err(Error::MysqlGreetingInvalid);
+        };
         let thread_id_offset = THREAD_ID_OFFSET_B + server_version_pos + 1;
-        self.info.server_thread_id = bytes::read_u32_le(&payload[thread_id_offset..]);
+        let Some(thread_id) = payload
+            .get(thread_id_offset..)
+            .and_then(bytes::try_read_u32_le)
+        else {
+            return Err(Error::MysqlGreetingInvalid);
+        };
+        self.info.protocol_version = protocol_version;
+        self.info.server_version =
+            String::from_utf8_lossy(&server_version[..server_version_pos]).into_owned();
+        self.info.server_thread_id = thread_id;
         Ok(())
     }
 
-    fn request(&mut self, payload: &[u8]) -> Result<()> {
-        if payload.len() < COMMAND_LEN {
-            return Err(Error::MysqlLogParseFailed);
-        }
-        self.info.command = payload[COMMAND_OFFSET];
-        self.command = payload[COMMAND_OFFSET];
+    // offset 为 payload 在 L4 payload 中的位置
+    // offset is the position of payload in L4 payload
+    fn request(&mut self, payload: &[u8], offset: usize) -> Result<()> {
+        let Some(&command) = payload.get(COMMAND_OFFSET) else {
+            return Err(Error::MysqlPayloadTruncated);
+        };
+        self.info.command = command;
         match self.info.command {
-            COM_QUIT | COM_FIELD_LIST | COM_STMT_EXECUTE | COM_STMT_CLOSE | COM_STMT_FETCH => (),
+            // 没有响应, 不改变等待响应的命令, 以免影响 COM_STMT_EXECUTE 等响应的解析
+            // no response, the command waiting for response is kept to not confuse
+            // response parsing of COM_STMT_EXECUTE and so on
+            COM_STMT_SEND_LONG_DATA => {
+                let (Some(statement_id), Some(param_id)) = (
+                    payload
+                        .get(STMT_ID_OFFSET..)
+                        .and_then(bytes::try_read_u32_le),
+                    payload
+                        .get(LONG_DATA_PARAM_ID_OFFSET..)
+                        .and_then(bytes::try_read_u16_le),
+                ) else {
+                    return Err(Error::MysqlPayloadTruncated);
+                };
+                self.info.statement_id = statement_id;
+                self.info.param_id = param_id;
+                self.info.msg_type = LogMessageType::Session;
+                return Ok(());
+            }
+            COM_QUIT | COM_FIELD_LIST | COM_STMT_FETCH => (),
+            COM_STATISTICS | COM_DEBUG | COM_PING => (),
+            // 执行预处理的 CALL 语句
+            // execute prepared CALL statement
+            COM_STMT_EXECUTE => {
+                let procedure = payload
+                    .get(STMT_ID_OFFSET..)
+                    .and_then(bytes::try_read_u32_le)
+                    .and_then(|id| self.prepared_procedures.get(&id));
+                if let Some((name, param_count)) = procedure {
+                    self.info.is_procedure_call = true;
+                    self.info.procedure_name = name.clone();
+                    self.info.procedure_param_count = *param_count;
+                }
+            }
+            COM_STMT_CLOSE => {
+                if let Some(id) = payload
+                    .get(STMT_ID_OFFSET..)
+                    .and_then(bytes::try_read_u32_le)
+                {
+                    self.prepared_procedures.remove(&id);
+                }
+            }
+            COM_QUERY if self.query_attributes => {
+                let payload = &payload[COMMAND_OFFSET + COMMAND_LEN..];
+                let sql_offset =
+                    skip_query_attributes(payload).ok_or(Error::MysqlPayloadTruncated)?;
+                self.request_string(
+                    &payload[sql_offset..],
+                    offset + COMMAND_OFFSET + COMMAND_LEN + sql_offset,
+                );
+            }
             COM_INIT_DB | COM_QUERY | COM_STMT_PREPARE => {
-                self.request_string(&payload[COMMAND_OFFSET + COMMAND_LEN..]);
+                self.request_string(
+                    &payload[COMMAND_OFFSET + COMMAND_LEN..],
+                    offset + COMMAND_OFFSET + COMMAND_LEN,
+                );
+                if command == COM_STMT_PREPARE {
+                    self.preparing_procedure = self.info.is_procedure_call.then(|| {
+                        (
+                            self.info.procedure_name.clone(),
+                            self.info.procedure_param_count,
+                        )
+                    });
+                }
             }
-            COM_PING => {}
-            _ => return Err(Error::MysqlLogParseFailed),
+            _ => return Err(Error::MysqlUnsupportedCommand(command)),
         }
+        self.info.msg_type = LogMessageType::Request;
+        self.command = command;
         self.perf_stats.as_mut().unwrap().inc_req();
         Ok(())
     }
 
+    // 客户端的 Handshake Response, 记录是否支持查询属性
+    // client Handshake Response, record whether query attributes are supported
+    fn handshake_response(&mut self, payload: &[u8]) -> bool {
+        let Some((HANDSHAKE_RESPONSE_NUMBER, start, end)) = read_packet(payload, 0) else {
+            return false;
+        };
+        let body = &payload[start..end];
+        let filler_end = HANDSHAKE_RESPONSE_FILLER_OFFSET + HANDSHAKE_RESPONSE_FILLER_LEN;
+        let Some(filler) = body.get(HANDSHAKE_RESPONSE_FILLER_OFFSET..filler_end) else {
+            return false;
+        };
+        let capability_flags = bytes::read_u32_le(&body[CAPABILITY_FLAGS_OFFSET..]);
+        if capability_flags & CLIENT_PROTOCOL_41 == 0 || filler.iter().any(|&b| b != 0) {
+            return false;
+        }
+        self.query_attributes = capability_flags & CLIENT_QUERY_ATTRIBUTES != 0;
+        if capability_flags & CLIENT_CONNECT_WITH_DB != 0 {
+            self.database = handshake_database(&body[filler_end..], capability_flags);
+        }
+        true
+    }
+
+    // 长度不足时返回第一个字节的值
+    // return value of the first byte when length is insufficient
     fn decode_compress_int(payload: &[u8]) -> u64 {
-        let remain = payload.len();
-        if remain == 0 {
+        let Some((&value, data)) = payload.split_first() else {
             return 0;
-        }
-        let value = payload[0];
+        };
         match value {
-            INT_FLAGS_2 if remain > INT_BASE_LEN + 2 => {
-                bytes::read_u16_le(&payload[INT_BASE_LEN..]) as u64
-            }
-            INT_FLAGS_3 if remain > INT_BASE_LEN + 3 => {
-                bytes::read_u16_le(&payload[INT_BASE_LEN..]) as u64
-                    | ((payload[INT_BASE_LEN + 2] as u64) << 16)
-            }
-            INT_FLAGS_8 if remain > INT_BASE_LEN + 8 => {
-                bytes::read_u64_le(&payload[INT_BASE_LEN..])
-            }
-            _ => value as u64,
+            INT_FLAGS_2 => bytes::try_read_u16_le(data).map(|v| v as u64),
+            INT_FLAGS_3 => bytes::try_read_u16_le(data)
+                .zip(data.get(2))
+                .map(|(low, &high)| low as u64 | (high as u64) << 16),
+            INT_FLAGS_8 => bytes::try_read_u64_le(data),
+            _ => None,
         }
+        .unwrap_or(value as u64)
     }
 
     fn set_status(&mut self, status_code: u16) {
-        if status_code != 0 {
-            if status_code >= 2000 && status_code <= 2999 {
-                self.info.status = L7ResponseStatus::ClientError;
-            } else {
-                self.info.status = L7ResponseStatus::ServerError;
-            }
-        } else {
+        if status_code == 0 {
             self.info.status = L7ResponseStatus::Ok;
+            return;
+        }
+        // 没有解析配置时使用默认的客户端错误码
+        // use default client error codes without parse config
+        let codes = match self.client_error_codes.as_ref() {
+            Some(codes) => codes.as_ref(),
+            None => default_client_error_codes(),
+        };
+        let is_client_error = codes.get(status_code as usize).unwrap_or_default();
+        if is_client_error {
+            self.info.status = L7ResponseStatus::ClientError;
+        } else {
+            self.info.status = L7ResponseStatus::ServerError;
         }
     }
 
     fn response(&mut self, payload: &[u8]) -> Result<()> {
-        let mut remain = payload.len();
-        if remain < RESPONSE_CODE_LEN {
-            return Err(Error::MysqlLogParseFailed);
+        let Some(&response_code) = payload.get(RESPONSE_CODE_OFFSET) else {
+            return Err(Error::MysqlPayloadTruncated);
+        };
+        self.info.response_code = response_code;
+        let pending_database = self.pending_database.take();
+        if response_code == MYSQL_RESPONSE_CODE_OK && pending_database.is_some() {
+            self.database = pending_database;
         }
-        self.info.response_code = payload[RESPONSE_CODE_OFFSET];
-        remain -= RESPONSE_CODE_LEN;
         match self.info.response_code {
             MYSQL_RESPONSE_CODE_ERR => {
-                if remain > ERROR_CODE_LEN {
-                    let code = bytes::read_u16_le(&payload[ERROR_CODE_OFFSET..]);
+                if let Some(code) = payload
+                    .get(ERROR_CODE_OFFSET..)
+                    .and_then(bytes::try_read_u16_le)
+                {
                     self.info.error_code = Some(code as i32);
                     self.set_status(code);
-                    remain -= ERROR_CODE_LEN;
                 }
                 let error_message_offset =
-                    if remain > SQL_STATE_LEN && payload[SQL_STATE_OFFSET] == SQL_STATE_MARKER {
+                    if payload.get(SQL_STATE_OFFSET) == Some(&SQL_STATE_MARKER) {
                         SQL_STATE_OFFSET + SQL_STATE_LEN
                     } else {
                         SQL_STATE_OFFSET
                     };
-                if error_message_offset < payload.len() {
-                    self.info.error_message =
-                        String::from_utf8_lossy(&payload[error_message_offset..]).into_owned();
+                if let Some(message) = payload.get(error_message_offset..) {
+                    self.info.error_message = error_message(message, self.error_message_max_length);
                 }
                 self.perf_stats.as_mut().unwrap().inc_resp_err();
             }
+            // COM_STATISTICS 的响应是可读的字符串, 不是 OK 包
+            // response of COM_STATISTICS is a human-readable string instead of OK packet
+            _ if self.command == COM_STATISTICS => {
+                self.info.status = L7ResponseStatus::Ok;
+                self.info.context = String::from_utf8_lossy(payload).into_owned();
+            }
             MYSQL_RESPONSE_CODE_OK => {
                 self.info.status = L7ResponseStatus::Ok;
+                // COM_STMT_PREPARE_OK 中没有服务端状态
+                // no server status in COM_STMT_PREPARE_OK
+                if self.command != COM_STMT_PREPARE {
+                    self.info.in_transaction = in_transaction(payload);
+                }
                 if self.command == COM_QUERY {
                     self.info.affected_rows =
                         MysqlLog::decode_compress_int(&payload[AFFECTED_ROWS_OFFSET..]);
                 }
+                // 记录预处理的 CALL 语句, 用于识别之后的 COM_STMT_EXECUTE
+                // record prepared CALL statement to recognize following COM_STMT_EXECUTE
+                if self.command == COM_STMT_PREPARE
+                    && self.prepared_procedures.len() < MYSQL_PREPARED_PROCEDURES_MAX
+                {
+                    let id = payload
+                        .get(STMT_ID_OFFSET..)
+                        .and_then(bytes::try_read_u32_le);
+                    if let (Some(id), Some(procedure)) = (id, self.preparing_procedure.take()) {
+                        self.prepared_procedures.insert(id, procedure);
+                    }
+                }
+            }
+            // 握手中的 Auth Switch Request 同样以 0xfe 开头, 只处理命令的响应
+            // Auth Switch Request in handshake starts with 0xfe as well, only responses of
+            // commands are handled
+            MYSQL_RESPONSE_CODE_EOF if self.command != 0 => {
+                self.info.in_transaction = in_transaction(payload);
             }
             _ => (),
         }
-        self.command = 0;
         self.perf_stats.as_mut().unwrap().inc_resp();
         Ok(())
     }
 
-    fn check(payload: &[u8], param: &ParseParam) -> bool {
+    fn check(&mut self, payload: &[u8], param: &ParseParam) -> bool {
         if param.l4_protocol != IpProtocol::Tcp {
             return false;
         }
@@ -407,12 +1180,29 @@ impl MysqlLog {
         let protocol_version_or_query_type = payload[offset];
         match protocol_version_or_query_type {
             COM_QUERY | COM_STMT_PREPARE => {
-                let context = mysql_string(&payload[offset + 1..]);
-                return context.is_ascii() && is_mysql(&context);
+                let mut sql = &payload[offset + 1..];
+                if protocol_version_or_query_type == COM_QUERY && self.query_attributes {
+                    let Some(sql_offset) = skip_query_attributes(sql) else {
+                        return false;
+                    };
+                    sql = &sql[sql_offset..];
+                }
+                // 先检查字节是否为 ASCII, 避免替换非法字符时分配内存
+                // check ASCII on bytes first to avoid allocation for replacing invalid characters
+                if !sql.is_ascii() || !is_mysql(&mysql_string(sql)) {
+                    return false;
+                }
             }
-            _ => {}
+            _ => return false,
         }
-        false
+        self.check_cache = header
+            .check(param.direction, offset, payload)
+            .map(|msg_type| CheckCache {
+                packet: CheckCache::packet_key(payload, param),
+                offset,
+                msg_type,
+            });
+        true
     }
 
     // return is_greeting?
@@ -425,148 +1215,509 @@ impl MysqlLog {
         if proto != IpProtocol::Tcp {
             return Err(Error::InvalidIpProtocol);
         }
-
-        let mut header = MysqlHeader::default();
-        let offset = header.decode(payload);
-        if offset < 0 {
-            return Err(Error::MysqlLogParseFailed);
+        if direction == PacketDirection::ClientToServer && self.handshake_response(payload) {
+            return Ok(true);
         }
-        let offset = offset as usize;
-        let msg_type = header
-            .check(direction, offset, payload)
-            .ok_or(Error::MysqlLogParseFailed)?;
+
+        let (offset, msg_type) = match self.check_cache.take() {
+            Some(c) => (c.offset, c.msg_type),
+            // COM_STATISTICS 的响应是字符串包, MysqlHeader::decode 只查找 OK/ERR/EOF 包
+            // response of COM_STATISTICS is a string packet, while MysqlHeader::decode
+            // only looks for OK/ERR/EOF packet
+            _ if direction == PacketDirection::ServerToClient && self.command == COM_STATISTICS => {
+                match read_packet(payload, 0) {
+                    Some((1, start, end)) if end > start => (start, LogMessageType::Response),
+                    _ => return Err(Error::MysqlHeaderInvalid),
+                }
+            }
+            _ => {
+                let mut header = MysqlHeader::default();
+                let offset = header.decode(payload);
+                if offset < 0 {
+                    return Err(Error::MysqlHeaderInvalid);
+                }
+                let offset = offset as usize;
+                let msg_type = header
+                    .check(direction, offset, payload)
+                    .ok_or(Error::MysqlHeaderInvalid)?;
+                (offset, msg_type)
+            }
+        };
 
         match msg_type {
-            LogMessageType::Request => self.request(&payload[offset..])?,
-            LogMessageType::Response => self.response(&payload[offset..])?,
+            LogMessageType::Request => {
+                self.request(&payload[offset..packet_end(payload, offset)], offset)?;
+                self.parse_more_requests(payload, packet_end(payload, offset));
+            }
+            LogMessageType::Response => {
+                self.response(&payload[offset..packet_end(payload, offset)])?;
+                self.info.msg_type = msg_type;
+                self.parse_more_responses(payload);
+                self.command = 0;
+            }
             LogMessageType::Other => {
                 self.greeting(&payload[offset..])?;
                 return Ok(true);
             }
-            _ => return Err(Error::MysqlLogParseFailed),
+            _ => return Err(Error::MysqlHeaderInvalid),
         };
-        self.info.msg_type = msg_type;
 
         Ok(false)
     }
-}
-
-#[derive(Debug, Default)]
-pub struct MysqlHeader {
-    length: u32,
-    number: u8,
-}
 
-impl MysqlHeader {
-    pub fn decode(&mut self, payload: &[u8]) -> isize {
-        if payload.len() < 5 {
-            return -1;
-        }
-        let len = bytes::read_u32_le(payload) & 0xffffff;
-        if payload[HEADER_LEN + RESPONSE_CODE_OFFSET] == MYSQL_RESPONSE_CODE_OK
-            || payload[HEADER_LEN + RESPONSE_CODE_OFFSET] == MYSQL_RESPONSE_CODE_ERR
-            || payload[HEADER_LEN + RESPONSE_CODE_OFFSET] == MYSQL_RESPONSE_CODE_EOF
-            || payload[NUMBER_OFFSET] == 0
-        {
-            self.length = len;
-            self.number = payload[NUMBER_OFFSET];
-            return HEADER_LEN as isize;
-        }
-        let offset = len as usize + HEADER_LEN;
-        if offset >= payload.len() {
-            return 0;
+    fn new_info(&self) -> MysqlInfo {
+        MysqlInfo {
+            is_tls: self.info.is_tls,
+            protocol_version: self.info.protocol_version,
+            status: L7ResponseStatus::Ok,
+            ..Default::default()
         }
-        let offset = offset as isize;
-        offset + self.decode(&payload[offset as usize..])
     }
 
-    pub fn check(
-        &self,
-        direction: PacketDirection,
-        offset: usize,
-        payload: &[u8],
-    ) -> Option<LogMessageType> {
-        if offset >= payload.len() || self.length == 0 {
-            return None;
-        }
-
-        match direction {
-            // greeting
-            PacketDirection::ServerToClient if self.number == 0 => {
-                let payload = &payload[offset..];
-                if payload.len() < PROTOCOL_VERSION_LEN {
-                    return None;
-                }
-                let protocol_version = payload[PROTOCOL_VERSION_OFFSET];
-                let index = payload[SERVER_VERSION_OFFSET..]
-                    .iter()
-                    .position(|&x| x == SERVER_VERSION_EOF)?;
-                if index != 0 && protocol_version == PROTOCOL_VERSION {
-                    Some(LogMessageType::Other)
-                } else {
-                    None
-                }
+    // 解析第一个请求之后的完整请求包, 不完整的包留给重组处理
+    // parse complete request packets after the first one, incomplete packet is left for reassembly
+    fn parse_more_requests(&mut self, payload: &[u8], mut offset: usize) {
+        while let Some((number, start, end)) = read_packet(payload, offset) {
+            if number != 0 {
+                break;
             }
-            PacketDirection::ServerToClient => Some(LogMessageType::Response),
-            PacketDirection::ClientToServer if self.number == 0 => Some(LogMessageType::Request),
-            _ => None,
+            let first = mem::replace(&mut self.info, self.new_info());
+            let result = self.request(&payload[start..end], start);
+            let info = mem::replace(&mut self.info, first);
+            if result.is_err() {
+                break;
+            }
+            self.infos.push(info);
+            offset = end;
         }
     }
-}
+
+    // 第一个响应已经解析, 解析同一个 payload 中后续的完整响应,
+    // SERVER_MORE_RESULTS_EXISTS 连接的结果集属于同一个请求, 合并为一个响应
+    // the first response is parsed, parse following complete responses in the same payload,
+    // result sets chained by SERVER_MORE_RESULTS_EXISTS belong to the same request and are
+    // merged into one response
+    fn parse_more_responses(&mut self, payload: &[u8]) {
+        let messages = self.split_responses(payload);
+        let Some((first, messages)) = messages.split_first() else {
+            return;
+        };
+        self.info.returned_rows = first.rows;
+        self.info.is_multi_result = first.more_results();
+        let mut more_results = first.more_results();
+        for message in messages {
+            let first = mem::replace(&mut self.info, self.new_info());
+            // 同一个请求的多个结果集只统计一次响应
+            // result sets of the same request are counted as one response
+            let perf_stats = if more_results {
+                self.perf_stats.replace(L7PerfStats::default())
+            } else {
+                None
+            };
+            let result = self.response(&payload[message.body.0..message.body.1]);
+            if more_results {
+                self.perf_stats = perf_stats;
+            }
+            let mut info = mem::replace(&mut self.info, first);
+            if result.is_err() {
+                break;
+            }
+            info.msg_type = LogMessageType::Response;
+            info.returned_rows = message.rows;
+            if more_results {
+                let last = self.infos.last_mut().unwrap_or(&mut self.info);
+                if info.response_code == MYSQL_RESPONSE_CODE_ERR
+                    && last.response_code != MYSQL_RESPONSE_CODE_ERR
+                {
+                    self.perf_stats.as_mut().unwrap().inc_resp_err();
+                }
+                last.merge_result(info);
+            } else {
+                info.is_multi_result = message.more_results();
+                self.infos.push(info);
+            }
+            more_results = message.more_results();
+        }
+    }
+
+    // 按长度切分 payload 中完整的响应消息: OK/ERR/EOF 包, 结果集或 COM_STMT_PREPARE 的响应
+    // split complete response messages in payload by length: OK/ERR/EOF packet, result set or
+    // response of COM_STMT_PREPARE
+    fn split_responses(&self, payload: &[u8]) -> Vec<ResponseMessage> {
+        let mut messages = vec![];
+        let mut offset = 0;
+        while let Some((number, start, end)) = read_packet(payload, offset) {
+            let body = &payload[start..end];
+            // 序号为 0 的包是 greeting 或请求
+            // packet with sequence number 0 is greeting or request
+            if number == 0 || body.is_empty() {
+                break;
+            }
+            let next = match body[RESPONSE_CODE_OFFSET] {
+                MYSQL_RESPONSE_CODE_OK if self.command == COM_STMT_PREPARE => {
+                    let columns = body
+                        .get(STMT_NUM_COLUMNS_OFFSET..)
+                        .and_then(bytes::try_read_u16_le);
+                    let params = body
+                        .get(STMT_NUM_PARAMS_OFFSET..)
+                        .and_then(bytes::try_read_u16_le);
+                    [params, columns]
+                        .into_iter()
+                        .flatten()
+                        .filter(|&count| count > 0)
+                        .try_fold(end, |next, count| {
+                            skip_definitions(payload, next, count as u64)
+                        })
+                        .map(|next| (next, 0, 0))
+                }
+                MYSQL_RESPONSE_CODE_OK | MYSQL_RESPONSE_CODE_ERR => Some((end, start, 0)),
+                _ if self.command == COM_STATISTICS => Some((end, 0, 0)),
+                _ if is_eof_packet(body) => Some((end, start, 0)),
+                _ => {
+                    // 结果集: 列数, 列定义, [EOF], 行, EOF/ERR
+                    // result set: column count, column definitions, [EOF], rows, EOF/ERR
+                    let columns = Self::decode_compress_int(body);
+                    skip_definitions(payload, end, columns)
+                        .and_then(|next| skip_rows(payload, next))
+                }
+            };
+            // 消息的结束位置, 结束包的起始位置 (COM_STMT_PREPARE 的响应没有结束包), 行数
+            // end of message, start of the ending packet (no ending packet in response of
+            // COM_STMT_PREPARE), and rows
+            let Some((next, last, rows)) = next else {
+                break;
+            };
+            let status_flags = if last > 0 {
+                status_flags(&payload[last..next]).unwrap_or_default()
+            } else {
+                0
+            };
+            messages.push(ResponseMessage {
+                body: first_status_packet(&payload[..next], offset),
+                rows,
+                status_flags,
+            });
+            offset = next;
+        }
+        messages
+    }
+}
+
+// 读取 offset 处完整的包, 返回序号和包体的范围
+// read the complete packet at offset, return sequence number and range of body
+fn read_packet(payload: &[u8], offset: usize) -> Option<(u8, usize, usize)> {
+    let Frame::Complete(packet) = PACKET_FRAMING.frame(payload.get(offset..)?) else {
+        return None;
+    };
+    Some((
+        packet[NUMBER_OFFSET],
+        offset + HEADER_LEN,
+        offset + packet.len(),
+    ))
+}
+
+// offset 处包体的结束位置, 包不完整时为 payload 的结束位置
+// end of body at offset, or end of payload if packet is incomplete
+fn packet_end(payload: &[u8], offset: usize) -> usize {
+    let header = offset
+        .checked_sub(HEADER_LEN)
+        .and_then(|header| payload.get(header..offset));
+    match header.and_then(|header| PACKET_FRAMING.length(header)) {
+        Some(length) => (offset + length as usize).min(payload.len()),
+        None => payload.len(),
+    }
+}
+
+// 读取 Length-Encoded Integer, 返回值和占用的长度
+// read Length-Encoded Integer, return value and its length
+fn read_length_encoded_int(payload: &[u8]) -> Option<(u64, usize)> {
+    let (&value, data) = payload.split_first()?;
+    match value {
+        INT_FLAGS_2 => bytes::try_read_u16_le(data).map(|v| (v as u64, INT_BASE_LEN + 2)),
+        INT_FLAGS_3 => bytes::try_read_u16_le(data)
+            .zip(data.get(2))
+            .map(|(low, &high)| (low as u64 | (high as u64) << 16, INT_BASE_LEN + 3)),
+        INT_FLAGS_8 => bytes::try_read_u64_le(data).map(|v| (v, INT_BASE_LEN + 8)),
+        0..=0xfa => Some((value as u64, INT_BASE_LEN)),
+        _ => None,
+    }
+}
+
+// offset 处 Length-Encoded String 的结束位置
+// end of Length-Encoded String at offset
+fn length_encoded_string_end(payload: &[u8], offset: usize) -> Option<usize> {
+    let (len, int_len) = read_length_encoded_int(payload.get(offset..)?)?;
+    let end = usize::try_from(len).ok()?.checked_add(offset + int_len)?;
+    (end <= payload.len()).then_some(end)
+}
+
+// offset 处二进制协议参数值的结束位置
+// end of parameter value in binary protocol at offset
+fn binary_value_end(payload: &[u8], offset: usize, param_type: u8) -> Option<usize> {
+    let len = match param_type {
+        MYSQL_TYPE_NULL => 0,
+        MYSQL_TYPE_TINY => 1,
+        MYSQL_TYPE_SHORT | MYSQL_TYPE_YEAR => 2,
+        MYSQL_TYPE_LONG | MYSQL_TYPE_INT24 | MYSQL_TYPE_FLOAT => 4,
+        MYSQL_TYPE_LONGLONG | MYSQL_TYPE_DOUBLE => 8,
+        MYSQL_TYPE_TIMESTAMP | MYSQL_TYPE_DATE | MYSQL_TYPE_TIME | MYSQL_TYPE_DATETIME => {
+            1 + *payload.get(offset)? as usize
+        }
+        _ => return length_encoded_string_end(payload, offset),
+    };
+    (offset + len <= payload.len()).then_some(offset + len)
+}
+
+// 跳过 COM_QUERY 中的查询属性, 返回 SQL 的位置
+// skip query attributes in COM_QUERY, return offset of sql
+//
+// parameter_count(lenenc) parameter_set_count(lenenc)
+// [null_bitmap new_params_bind_flag(1) [param_type(2) param_name(lenenc str)]... values...]
+fn skip_query_attributes(payload: &[u8]) -> Option<usize> {
+    let (count, mut offset) = read_length_encoded_int(payload)?;
+    offset += read_length_encoded_int(payload.get(offset..)?)?.1;
+    if count == 0 {
+        return Some(offset);
+    }
+    let count = usize::try_from(count).ok()?;
+    let null_bitmap = payload.get(offset..offset.checked_add(count.checked_add(7)? / 8)?)?;
+    offset += null_bitmap.len();
+    // 没有参数类型时无法跳过参数值
+    // parameter values can not be skipped without parameter types
+    if payload.get(offset) != Some(&1) {
+        return None;
+    }
+    offset += 1;
+    let types_offset = offset;
+    for _ in 0..count {
+        offset = length_encoded_string_end(payload, offset + 2)?;
+    }
+    let mut type_offset = types_offset;
+    for i in 0..count {
+        let param_type = payload[type_offset];
+        type_offset = length_encoded_string_end(payload, type_offset + 2)?;
+        if null_bitmap[i / 8] & (1 << (i % 8)) == 0 {
+            offset = binary_value_end(payload, offset, param_type)?;
+        }
+    }
+    Some(offset)
+}
+
+fn is_eof_packet(body: &[u8]) -> bool {
+    body.first() == Some(&MYSQL_RESPONSE_CODE_EOF) && body.len() < EOF_PACKET_MAX_LEN
+}
+
+// 跳过 count 个定义包和其后的 EOF 包 (如果存在)
+// skip count definition packets and the following EOF packet if exists
+fn skip_definitions(payload: &[u8], mut offset: usize, count: u64) -> Option<usize> {
+    for _ in 0..count {
+        offset = read_packet(payload, offset)?.2;
+    }
+    match read_packet(payload, offset) {
+        Some((_, start, end)) if is_eof_packet(&payload[start..end]) => Some(end),
+        _ => Some(offset),
+    }
+}
+
+// 跳过结果集的行, 直到 EOF/ERR 包 (包含), 返回结束位置, EOF/ERR 包体的起始位置和行数
+// skip rows of result set until EOF/ERR packet (inclusive),
+// return the end, start of EOF/ERR body and number of rows
+fn skip_rows(payload: &[u8], mut offset: usize) -> Option<(usize, usize, u64)> {
+    let mut rows = 0;
+    loop {
+        let (_, start, end) = read_packet(payload, offset)?;
+        match payload[start..end].first() {
+            Some(&MYSQL_RESPONSE_CODE_EOF) | Some(&MYSQL_RESPONSE_CODE_ERR) => {
+                return Some((end, start, rows))
+            }
+            _ => offset = end,
+        }
+        rows += 1;
+    }
+}
+
+// OK/EOF 包中的服务端状态, CLIENT_DEPRECATE_EOF 时以 0xfe 开头的 OK 包结束结果集
+// server status in OK/EOF packet, result set ends with OK packet starting with 0xfe
+// when CLIENT_DEPRECATE_EOF is set
+fn status_flags(body: &[u8]) -> Option<u16> {
+    let offset = match *body.first()? {
+        MYSQL_RESPONSE_CODE_EOF if body.len() == EOF_PACKET_LEN => EOF_STATUS_FLAGS_OFFSET,
+        MYSQL_RESPONSE_CODE_OK | MYSQL_RESPONSE_CODE_EOF => {
+            // affected_rows 和 last_insert_id 之后
+            // after affected_rows and last_insert_id
+            let (_, len) = read_length_encoded_int(body.get(AFFECTED_ROWS_OFFSET..)?)?;
+            let offset = AFFECTED_ROWS_OFFSET + len;
+            let (_, len) = read_length_encoded_int(body.get(offset..)?)?;
+            offset + len
+        }
+        _ => return None,
+    };
+    body.get(offset..).and_then(bytes::try_read_u16_le)
+}
+
+// Handshake Response 中 filler 之后的 username, auth_response 和 database
+// username, auth_response and database after filler in Handshake Response
+fn handshake_database(payload: &[u8], capability_flags: u32) -> Option<String> {
+    let username_end = payload.iter().position(|&b| b == 0)? + 1;
+    let auth_end = if capability_flags & CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA != 0 {
+        length_encoded_string_end(payload, username_end)?
+    } else if capability_flags & CLIENT_SECURE_CONNECTION != 0 {
+        username_end + 1 + *payload.get(username_end)? as usize
+    } else {
+        username_end + payload.get(username_end..)?.iter().position(|&b| b == 0)? + 1
+    };
+    let database = payload.get(auth_end..)?;
+    let database = &database[..database.iter().position(|&b| b == 0)?];
+    (!database.is_empty()).then(|| String::from_utf8_lossy(database).into_owned())
+}
+
+fn in_transaction(body: &[u8]) -> bool {
+    status_flags(body).map_or(false, |flags| flags & SERVER_STATUS_IN_TRANS != 0)
+}
+
+// 消息中第一个 OK/ERR/EOF 包, 不存在时为消息的第一个包
+// the first OK/ERR/EOF packet in message, or the first packet if not exists
+fn first_status_packet(message: &[u8], mut offset: usize) -> (usize, usize) {
+    let first = read_packet(message, offset).map(|(_, start, end)| (start, end));
+    while let Some((_, start, end)) = read_packet(message, offset) {
+        match message[start..end].first() {
+            Some(&MYSQL_RESPONSE_CODE_OK)
+            | Some(&MYSQL_RESPONSE_CODE_ERR)
+            | Some(&MYSQL_RESPONSE_CODE_EOF) => return (start, end),
+            _ => offset = end,
+        }
+    }
+    first.unwrap_or_default()
+}
+
+#[derive(Debug, Default)]
+pub struct MysqlHeader {
+    length: u32,
+    number: u8,
+}
+
+impl MysqlHeader {
+    // 用循环代替递归, 避免大量空包导致栈溢出
+    // loop instead of recursion to avoid stack overflow caused by lots of empty packets
+    pub fn decode(&mut self, mut payload: &[u8]) -> isize {
+        let mut base = 0;
+        loop {
+            if payload.len() < 5 {
+                return base - 1;
+            }
+            let len = PACKET_FRAMING.length(payload).unwrap_or_default() as u32;
+            if payload[HEADER_LEN + RESPONSE_CODE_OFFSET] == MYSQL_RESPONSE_CODE_OK
+                || payload[HEADER_LEN + RESPONSE_CODE_OFFSET] == MYSQL_RESPONSE_CODE_ERR
+                || payload[HEADER_LEN + RESPONSE_CODE_OFFSET] == MYSQL_RESPONSE_CODE_EOF
+                || payload[NUMBER_OFFSET] == 0
+            {
+                self.length = len;
+                self.number = payload[NUMBER_OFFSET];
+                return base + HEADER_LEN as isize;
+            }
+            let offset = len as usize + HEADER_LEN;
+            if offset >= payload.len() {
+                return base;
+            }
+            base += offset as isize;
+            payload = &payload[offset..];
+        }
+    }
+
+    pub fn check(
+        &self,
+        direction: PacketDirection,
+        offset: usize,
+        payload: &[u8],
+    ) -> Option<LogMessageType> {
+        if offset >= payload.len() || self.length == 0 {
+            return None;
+        }
+
+        match direction {
+            // greeting
+            PacketDirection::ServerToClient if self.number == 0 => {
+                let payload = &payload[offset..];
+                if payload.len() < PROTOCOL_VERSION_LEN {
+                    return None;
+                }
+                let protocol_version = payload[PROTOCOL_VERSION_OFFSET];
+                let index = payload[SERVER_VERSION_OFFSET..]
+                    .iter()
+                    .position(|&x| x == SERVER_VERSION_EOF)?;
+                if index != 0 && protocol_version == PROTOCOL_VERSION {
+                    Some(LogMessageType::Other)
+                } else {
+                    None
+                }
+            }
+            PacketDirection::ServerToClient => Some(LogMessageType::Response),
+            PacketDirection::ClientToServer if self.number == 0 => Some(LogMessageType::Request),
+            _ => None,
+        }
+    }
+}
 
 // test log parse
+// 用于 fuzz, 第一个字节为等待响应的命令, 最高位表示是否带有查询属性,
+// 其余字节在两个方向上分别解析
+// for fuzzing, the first byte is the command waiting for response, and the highest bit indicates
+// whether query attributes are carried, the rest is parsed in both directions
+#[doc(hidden)]
+pub fn _fuzz_mysql_parse(data: &[u8]) {
+    let Some((&flags, payload)) = data.split_first() else {
+        return;
+    };
+    let _ = MysqlHeader::default().decode(payload);
+    for direction in [
+        PacketDirection::ClientToServer,
+        PacketDirection::ServerToClient,
+    ] {
+        let mut mysql = MysqlLog {
+            command: flags & 0x7f,
+            query_attributes: flags & 0x80 != 0,
+            record_query_offset: true,
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let _ = mysql.parse(payload, IpProtocol::Tcp, direction);
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::ops::Range;
     use std::path::Path;
     use std::rc::Rc;
-    use std::{cell::RefCell, fs};
+    use std::time::Duration;
 
     use super::*;
 
+    use public::l7_protocol::L7ProtocolEnum;
+
     use crate::{
-        common::{flow::PacketDirection, l7_protocol_log::L7PerfCache, MetaPacket},
+        common::{
+            flow::PacketDirection,
+            l7_protocol_log::{get_parser, L7PerfCache},
+            MetaPacket,
+        },
+        config::{handler::LogParserConfig, YamlConfig},
         flow_generator::L7_RRT_CACHE_CAPACITY,
-        utils::test::Capture,
+        utils::test::{Capture, ProtocolTester},
     };
 
     const FILE_DIR: &str = "resources/test/flow_generator/mysql";
 
-    fn run(name: &str) -> String {
-        let pcap_file = Path::new(FILE_DIR).join(name);
-        let capture = Capture::load_pcap(pcap_file, Some(1400));
-        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
-        let mut packets = capture.as_meta_packets();
-        if packets.is_empty() {
-            return "".to_string();
-        }
-
-        let mut mysql = MysqlLog::default();
-        let mut output: String = String::new();
-        let first_dst_port = packets[0].lookup_key.dst_port;
-        for packet in packets.iter_mut() {
-            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
-                PacketDirection::ClientToServer
-            } else {
-                PacketDirection::ServerToClient
-            };
-            let payload = match packet.get_l4_payload() {
-                Some(p) => p,
-                None => continue,
-            };
-            let is_mysql = mysql.check_payload(
-                payload,
-                &ParseParam::from((packet as &MetaPacket, log_cache.clone(), false)),
-            );
-
-            let _ = mysql.parse_payload(
-                payload,
-                &ParseParam::from((&*packet, log_cache.clone(), false)),
-            );
-            mysql.info.rrt = 0;
-            output.push_str(&format!("{:?} is_mysql: {}\r\n", mysql.info, is_mysql));
-            mysql.reset();
-        }
-        output
+    fn tester() -> ProtocolTester<MysqlLog> {
+        ProtocolTester::new(MysqlLog::default())
+            .parse_len(1400)
+            .step(|mysql, payload, param| {
+                let is_mysql = mysql.check_payload(payload, param);
+                let _ = mysql.parse_payload(payload, param);
+                mysql.info.rrt = 0;
+                format!("{:?} is_mysql: {}\r\n", mysql.info, is_mysql)
+            })
     }
 
     #[test]
@@ -585,22 +1736,18 @@ mod tests {
             ("mysql-table-destroy.pcap", "mysql-table-destroy.result"),
             ("mysql-table-alter.pcap", "mysql-table-alter.result"),
             ("mysql-database.pcap", "mysql-database.result"),
+            (
+                "mysql-query-attributes.pcap",
+                "mysql-query-attributes.result",
+            ),
+            ("mysql-send-long-data.pcap", "mysql-send-long-data.result"),
         ];
 
-        for item in files.iter() {
-            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
-            let output = run(item.0);
-
-            if output != expected {
-                let output_path = Path::new("actual.txt");
-                fs::write(&output_path, &output).unwrap();
-                assert!(
-                    output == expected,
-                    "output different from expected {}, written to {:?}",
-                    item.1,
-                    output_path
-                );
-            }
+        for (pcap, result) in files.iter() {
+            tester().check(
+                Path::new(FILE_DIR).join(pcap),
+                Path::new(FILE_DIR).join(result),
+            );
         }
     }
 
@@ -653,6 +1800,1044 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_and_parse_decode_header_once() {
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("mysql.pcap"), Some(1400));
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+
+        let mut checked = false;
+        for packet in packets.iter_mut() {
+            if packet.lookup_key.dst_port != first_dst_port {
+                continue;
+            }
+            packet.lookup_key.direction = PacketDirection::ClientToServer;
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let mut payload = payload.to_vec();
+            let param = ParseParam::from((&*packet, log_cache.clone(), false));
+
+            let mut mysql = MysqlLog::default();
+            if !mysql.check_payload(&payload, &param) {
+                continue;
+            }
+            // 修改头部的序号, parse 重新解码头部会失败
+            // modify sequence number of header, parse fails if header is decoded again
+            let original = payload.clone();
+            payload[NUMBER_OFFSET] = 1;
+            assert!(mysql.parse_payload(&payload, &param).is_ok());
+            assert_eq!(mysql.info.msg_type, LogMessageType::Request);
+            mysql.reset();
+            assert!(mysql.check_cache.is_none());
+
+            assert!(MysqlLog::default().parse_payload(&payload, &param).is_err());
+
+            // 其他报文不使用 check 的缓存
+            // check cache is not used for other packets
+            let mut mysql = MysqlLog::default();
+            assert!(mysql.check_payload(&original, &param));
+            let other = ParseParam {
+                time: param.time + 1,
+                ..param.clone()
+            };
+            assert!(mysql.parse_payload(&payload, &other).is_err());
+            checked = true;
+            break;
+        }
+        assert!(checked);
+    }
+
+    #[test]
+    fn check_payload_score() {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.lookup_key.direction = PacketDirection::ClientToServer;
+        let param = ParseParam::from((
+            &packet,
+            Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY))),
+            false,
+        ));
+        let mut payload = vec![9, 0, 0, 0, COM_QUERY];
+        payload.extend_from_slice(b"SELECT 1");
+        assert_eq!(
+            MysqlLog::default().check_payload_score(&payload, &param),
+            CHECK_SCORE_MAX
+        );
+        payload[NUMBER_OFFSET] = 1;
+        assert_eq!(
+            MysqlLog::default().check_payload_score(&payload, &param),
+            CHECK_SCORE_NONE
+        );
+    }
+
+    #[test]
+    fn check_multi_response() {
+        ProtocolTester::new(MysqlLog::default())
+            .parse_len(1400)
+            .step(|mysql, payload, param| {
+                let mut lines = String::new();
+                for info in mysql.parse_payload(payload, param).unwrap() {
+                    if let L7ProtocolInfo::MysqlInfo(mut info) = info {
+                        info.rrt = 0;
+                        lines.push_str(&format!("{:?}\r\n", info));
+                    }
+                }
+                lines
+            })
+            .check(
+                Path::new(FILE_DIR).join("mysql-multi-response.pcap"),
+                Path::new(FILE_DIR).join("mysql-multi-response.result"),
+            );
+    }
+
+    #[test]
+    fn check_split_statements() {
+        let cases = [
+            ("SELECT 1", ("SELECT 1", 1)),
+            ("", ("", 0)),
+            (" SELECT 1; ", ("SELECT 1", 1)),
+            ("SELECT 1; SELECT 2;SELECT 3", ("SELECT 1", 3)),
+            (
+                "INSERT INTO t VALUES ('a;b'); SELECT 1",
+                ("INSERT INTO t VALUES ('a;b')", 2),
+            ),
+            (
+                "SELECT 'it''s;', \"x;\\\";\"",
+                ("SELECT 'it''s;', \"x;\\\";\"", 1),
+            ),
+            (
+                "SELECT 'a\\';' FROM t; SELECT 2",
+                ("SELECT 'a\\';' FROM t", 2),
+            ),
+            ("SELECT `a;b` FROM t", ("SELECT `a;b` FROM t", 1)),
+            ("SELECT 1 /* ; */; SELECT 2", ("SELECT 1 /* ; */", 2)),
+            ("SELECT 1 -- ;\n; SELECT 2", ("SELECT 1 -- ;", 2)),
+            ("SELECT 1 # ;\n", ("SELECT 1 # ;", 1)),
+            ("SELECT 1--1; SELECT 2", ("SELECT 1--1", 2)),
+            (
+                "SELECT 'unterminated; SELECT 2",
+                ("SELECT 'unterminated; SELECT 2", 1),
+            ),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(split_statements(sql), expected, "sql: {}", sql);
+        }
+    }
+
+    #[test]
+    fn check_truncated_payload() {
+        let mut mysql = MysqlLog::default();
+        assert!(mysql.greeting(&[PROTOCOL_VERSION]).is_err());
+        assert!(mysql.greeting(&[]).is_err());
+        // 缺少 thread id
+        // thread id is missing
+        assert!(mysql.greeting(&[PROTOCOL_VERSION, b'8', 0, 1, 0]).is_err());
+        assert!(mysql
+            .greeting(&[PROTOCOL_VERSION, b'8', 0, 1, 0, 0, 0])
+            .is_ok());
+        assert_eq!(mysql.info.server_thread_id, 1);
+
+        assert!(matches!(
+            mysql.greeting(&[]),
+            Err(Error::MysqlGreetingInvalid)
+        ));
+
+        mysql.perf_stats = Some(L7PerfStats::default());
+        assert!(matches!(
+            mysql.request(&[], 0),
+            Err(Error::MysqlPayloadTruncated)
+        ));
+        assert!(matches!(
+            mysql.response(&[]),
+            Err(Error::MysqlPayloadTruncated)
+        ));
+        assert!(matches!(
+            mysql.parse(&[1, 0, 0], IpProtocol::Tcp, PacketDirection::ClientToServer),
+            Err(Error::MysqlHeaderInvalid)
+        ));
+        assert!(mysql.response(&[MYSQL_RESPONSE_CODE_ERR, 0x48]).is_ok());
+        assert_eq!(mysql.info.error_code, None);
+
+        assert_eq!(
+            MysqlLog::decode_compress_int(&[INT_FLAGS_8, 1, 2]),
+            INT_FLAGS_8 as u64
+        );
+        assert_eq!(
+            MysqlLog::decode_compress_int(&[INT_FLAGS_3, 1, 2, 3]),
+            0x030201
+        );
+    }
+
+    #[test]
+    fn check_query_offset() {
+        let packet = |body: &[u8]| {
+            let mut payload = (body.len() as u32).to_le_bytes().to_vec();
+            payload[NUMBER_OFFSET] = 0;
+            payload.extend_from_slice(body);
+            payload
+        };
+        let cases = [
+            (packet(b"\x03SELECT 1"), "SELECT 1"),
+            (packet(b"\x03\x00\x01  select 2; select 3"), "select 2"),
+        ];
+        for (payload, context) in cases {
+            let mut mysql = MysqlLog {
+                perf_stats: Some(L7PerfStats::default()),
+                record_query_offset: true,
+                ..Default::default()
+            };
+            mysql
+                .parse(&payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+                .unwrap();
+            assert_eq!(mysql.info.context, context);
+            let (start, len) = mysql.info.query_offset.unwrap();
+            assert_eq!(&payload[start..start + len], context.as_bytes());
+        }
+
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let payload = packet(b"\x03SELECT 1");
+        mysql
+            .parse(&payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(mysql.info.query_offset, None);
+    }
+
+    #[test]
+    fn check_mask_literals() {
+        let packet = |body: &[u8]| {
+            let mut payload = (body.len() as u32).to_le_bytes().to_vec();
+            payload[NUMBER_OFFSET] = 0;
+            payload.extend_from_slice(body);
+            payload
+        };
+        let cases = [
+            (
+                packet(
+                    b"\x03SELECT * FROM users WHERE name = 'admin' AND password = 'se\\'cr''et'",
+                ),
+                "SELECT * FROM users WHERE name = ? AND password = ?",
+            ),
+            (
+                packet(b"\x03UPDATE users SET password = X'736563726574' WHERE id = 42; SELECT 1"),
+                "UPDATE users SET password = ? WHERE id = ?",
+            ),
+            (
+                packet(b"\x16SELECT * FROM users WHERE password = \"secret\" AND id = ?"),
+                "SELECT * FROM users WHERE password = ? AND id = ?",
+            ),
+            // COM_INIT_DB 的数据库名不是 SQL, 不屏蔽
+            // database name of COM_INIT_DB is not sql and not masked
+            (packet(b"\x02db1"), "db1"),
+        ];
+        for (payload, context) in cases {
+            let mut mysql = MysqlLog {
+                perf_stats: Some(L7PerfStats::default()),
+                mask_literals: true,
+                ..Default::default()
+            };
+            mysql
+                .parse(&payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+                .unwrap();
+            assert_eq!(mysql.info.context, context);
+            assert!(!format!("{:?}", mysql.info).contains("secret"));
+        }
+
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let payload = packet(b"\x03SELECT * FROM users WHERE password = 'secret'");
+        mysql
+            .parse(&payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(
+            mysql.info.context,
+            "SELECT * FROM users WHERE password = 'secret'"
+        );
+    }
+
+    #[test]
+    fn check_send_long_data() {
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            command: COM_STMT_EXECUTE,
+            ..Default::default()
+        };
+        let payload = [COM_STMT_SEND_LONG_DATA, 7, 0, 0, 0, 2, 0, b'a', b'b'];
+        assert!(mysql.request(&payload, 0).is_ok());
+        assert_eq!(mysql.info.msg_type, LogMessageType::Session);
+        assert_eq!(mysql.info.statement_id, 7);
+        assert_eq!(mysql.info.param_id, 2);
+        // 等待响应的命令和请求计数不变
+        // command waiting for response and request count are unchanged
+        assert_eq!(mysql.command, COM_STMT_EXECUTE);
+        assert_eq!(mysql.perf_stats.as_ref().unwrap().request_count, 0);
+
+        assert!(mysql.request(&payload[..6], 0).is_err());
+    }
+
+    #[test]
+    fn check_unsupported_command() {
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        // COM_BINLOG_DUMP
+        let result = mysql.request(&[0x12, 0, 0, 0, 0], 0);
+        assert!(
+            matches!(result, Err(Error::MysqlUnsupportedCommand(0x12))),
+            "{:?}",
+            result
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "mysql unsupported command 0x12"
+        );
+    }
+
+    #[test]
+    fn check_admin_commands() {
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let ok = [0x07, 0, 0, 1, 0, 0, 0, 0x02, 0, 0, 0];
+        let eof = [0x05, 0, 0, 1, 0xfe, 0, 0, 0x02, 0];
+        let statistics = b"Uptime: 3600  Threads: 2  Questions: 42  Slow queries: 0";
+        let mut stats_response = vec![statistics.len() as u8, 0, 0, 1];
+        stats_response.extend_from_slice(statistics);
+
+        for (command, response) in [
+            (COM_PING, &ok[..]),
+            (COM_STATISTICS, &stats_response[..]),
+            (COM_DEBUG, &eof[..]),
+        ] {
+            mysql
+                .parse(
+                    &[1, 0, 0, 0, command],
+                    IpProtocol::Tcp,
+                    PacketDirection::ClientToServer,
+                )
+                .unwrap();
+            assert_eq!(mysql.info.msg_type, LogMessageType::Request);
+            let mut req = mysql.info.clone();
+            mysql.reset();
+
+            mysql
+                .parse(response, IpProtocol::Tcp, PacketDirection::ServerToClient)
+                .unwrap();
+            assert_eq!(mysql.info.status, L7ResponseStatus::Ok);
+            assert!(mysql.infos.is_empty());
+            req.merge(mysql.info.clone());
+            mysql.reset();
+
+            let log = L7ProtocolSendLog::from(req);
+            assert_eq!(
+                log.req.req_type,
+                MysqlInfo {
+                    command,
+                    ..Default::default()
+                }
+                .get_command_str()
+            );
+            assert_eq!(log.resp.status, L7ResponseStatus::Ok);
+            if command == COM_STATISTICS {
+                assert_eq!(log.req.resource.as_bytes(), statistics);
+            } else {
+                assert!(log.req.resource.is_empty());
+            }
+        }
+        let stats = mysql.perf_stats.unwrap();
+        assert_eq!((stats.request_count, stats.response_count), (3, 3));
+        assert_eq!(stats.err_server_count + stats.err_client_count, 0);
+    }
+
+    #[test]
+    fn check_procedure_call() {
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let mut parse = |payload: &[u8], direction| {
+            mysql.reset();
+            mysql.parse(payload, IpProtocol::Tcp, direction).unwrap();
+            mysql.info.clone()
+        };
+        let packet = |number: u8, body: &[u8]| {
+            let mut packet = vec![body.len() as u8, 0, 0, number];
+            packet.extend_from_slice(body);
+            packet
+        };
+        let (req, resp) = (
+            PacketDirection::ClientToServer,
+            PacketDirection::ServerToClient,
+        );
+
+        let info = parse(&packet(0, b"\x03CALL shop.add_order(1, 'x')"), req);
+        assert!(info.is_procedure_call);
+        assert_eq!(info.procedure_name, "shop.add_order");
+        assert_eq!(info.procedure_param_count, 2);
+        let log = L7ProtocolSendLog::from(info);
+        let attributes = log.ext_info.unwrap().attributes.unwrap();
+        assert_eq!(attributes[0].key, "procedure_name");
+        assert_eq!(attributes[0].val, "shop.add_order");
+        assert_eq!(attributes[1].key, "procedure_param_count");
+        assert_eq!(attributes[1].val, "2");
+
+        assert!(!parse(&packet(0, b"\x03SELECT 1"), req).is_procedure_call);
+
+        // 预处理的 CALL 语句, statement_id 为 7
+        // prepared CALL statement with statement_id 7
+        parse(&packet(0, b"\x16CALL p(?, ?)"), req);
+        let prepare_ok = [0, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        parse(&packet(1, &prepare_ok), resp);
+        let execute = packet(0, &[COM_STMT_EXECUTE, 7, 0, 0, 0, 0, 1, 0, 0, 0]);
+        let info = parse(&execute, req);
+        assert!(info.is_procedure_call);
+        assert_eq!(info.procedure_name, "p");
+        assert_eq!(info.procedure_param_count, 2);
+
+        parse(&packet(0, &[COM_STMT_CLOSE, 7, 0, 0, 0]), req);
+        assert!(!parse(&execute, req).is_procedure_call);
+    }
+
+    #[test]
+    fn check_transaction() {
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        for (sql, status_flags, is_transaction_control, in_transaction) in [
+            ("BEGIN", 0x0003u16, true, true),
+            ("UPDATE t SET a = 1", 0x0003, false, true),
+            ("COMMIT", 0x0002, true, false),
+        ] {
+            let mut query = vec![sql.len() as u8 + 1, 0, 0, 0, COM_QUERY];
+            query.extend_from_slice(sql.as_bytes());
+            mysql
+                .parse(&query, IpProtocol::Tcp, PacketDirection::ClientToServer)
+                .unwrap();
+            let mut req = mysql.info.clone();
+            assert_eq!(req.is_transaction_control, is_transaction_control);
+            mysql.reset();
+
+            let [low, high] = status_flags.to_le_bytes();
+            let ok = [0x07, 0, 0, 1, 0, 0, 0, low, high, 0, 0];
+            mysql
+                .parse(&ok, IpProtocol::Tcp, PacketDirection::ServerToClient)
+                .unwrap();
+            assert_eq!(mysql.info.in_transaction, in_transaction);
+            req.merge(mysql.info.clone());
+            mysql.reset();
+
+            let log = L7ProtocolSendLog::from(req);
+            let attributes = log
+                .ext_info
+                .and_then(|info| info.attributes)
+                .unwrap_or_default();
+            assert_eq!(
+                attributes
+                    .iter()
+                    .any(|kv| kv.key == "is_transaction_control" && kv.val == "true"),
+                is_transaction_control
+            );
+            assert_eq!(
+                attributes
+                    .iter()
+                    .any(|kv| kv.key == "in_transaction" && kv.val == "true"),
+                in_transaction
+            );
+        }
+    }
+
+    #[test]
+    fn check_ignored_schemas() {
+        let config = LogParserConfig {
+            l7_log_mysql_ignored_schemas: vec!["DeepFlow".to_string()],
+            ..Default::default()
+        };
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+
+        // 握手中连接的数据库为 deepflow, 之后的日志都不发送
+        // database connected in handshake is deepflow, logs after it are not sent
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("mysql.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut mysql = MysqlLog::default();
+        let mut count = 0;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = ParseParam::from((&*packet, log_cache.clone(), false, &config));
+            for info in mysql.parse_payload(payload, &param).unwrap_or_default() {
+                assert!(info.skip_send());
+                count += 1;
+            }
+            mysql.reset();
+        }
+        assert!(count > 0);
+        assert_eq!(mysql.database.as_deref(), Some("deepflow"));
+
+        // 切换数据库的请求和响应属于切换前的数据库, 失败的切换不生效
+        // request and response switching database belong to the database before switching,
+        // failed switching takes no effect
+        let query = |command: u8, sql: &str| {
+            let mut payload = vec![sql.len() as u8 + 1, 0, 0, 0, command];
+            payload.extend_from_slice(sql.as_bytes());
+            payload
+        };
+        let ok = [0x07, 0, 0, 1, 0, 0, 0, 0x02, 0, 0, 0];
+        let err = [
+            0x09, 0, 0, 1, 0xff, 0x19, 0x04, b'#', b'4', b'2', b'0', b'0', b'0',
+        ];
+        let cases = [
+            (query(COM_QUERY, "USE test"), true),
+            (ok.to_vec(), true),
+            (query(COM_INIT_DB, "deepflow"), false),
+            (err.to_vec(), false),
+            (query(COM_QUERY, "SELECT 1"), false),
+            (ok.to_vec(), false),
+            (query(COM_INIT_DB, "DEEPFLOW"), false),
+            (ok.to_vec(), false),
+            (query(COM_QUERY, "SELECT 1"), true),
+        ];
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        for (payload, ignored) in cases {
+            packet.lookup_key.direction = if payload[NUMBER_OFFSET] == 0 {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let param = ParseParam::from((&packet, log_cache.clone(), false, &config));
+            let infos = mysql.parse_payload(&payload, &param).unwrap();
+            assert_eq!(infos.len(), 1);
+            assert_eq!(infos[0].skip_send(), ignored);
+            mysql.reset();
+        }
+    }
+
+    #[test]
+    fn check_skip_query_attributes() {
+        // parameter_count=0 parameter_set_count=1
+        assert_eq!(skip_query_attributes(b"\x00\x01SELECT 1"), Some(2));
+        // 1 个字符串参数和 1 个 NULL 参数
+        // 1 string parameter and 1 NULL parameter
+        let payload = b"\x02\x01\x02\x01\xfe\x00\x01a\x06\x00\x01b\x02xySELECT 1";
+        assert_eq!(skip_query_attributes(payload), Some(payload.len() - 8));
+        // 参数值不完整
+        // incomplete parameter value
+        assert_eq!(
+            skip_query_attributes(b"\x01\x01\x00\x01\x08\x00\x01a\x01"),
+            None
+        );
+        // 没有参数类型
+        // no parameter types
+        assert_eq!(skip_query_attributes(b"\x01\x01\x00\x00\x01"), None);
+    }
+
+    #[test]
+    fn check_mysql_string_borrowed() {
+        assert!(matches!(
+            mysql_string(b"SELECT 1"),
+            Cow::Borrowed("SELECT 1")
+        ));
+        assert!(matches!(
+            mysql_string(b"\x00\x01SELECT 1"),
+            Cow::Borrowed("SELECT 1")
+        ));
+        let lossy = mysql_string(b"SELECT '\xff'");
+        assert!(matches!(lossy, Cow::Owned(_)));
+        assert_eq!(lossy, "SELECT '\u{fffd}'");
+    }
+
+    #[test]
+    fn check_reset_between_payloads() {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        let param = ParseParam::from((
+            &packet,
+            Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY))),
+            false,
+        ));
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let query = [
+            0x09, 0, 0, 0, COM_QUERY, b'S', b'E', b'L', b'E', b'C', b'T', b' ', b'1',
+        ];
+        assert!(mysql.check(&query, &param));
+        assert!(mysql.check_cache.is_some());
+        mysql.reset();
+        assert!(mysql.check_cache.is_none());
+
+        let err = [
+            0x09, 0, 0, 1, 0xff, 0x48, 0x04, b'#', b'4', b'2', b'0', b'0', b'0',
+        ];
+        mysql
+            .parse(&err, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(mysql.info.error_code, Some(1096));
+        mysql.reset();
+
+        // 上一个包的错误不会带到下一个包
+        // error of previous packet is not carried to the next one
+        let ok = [0x07, 0, 0, 1, 0, 1, 0, 0x0a, 0, 0, 0];
+        mysql
+            .parse(&ok, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(mysql.info.error_code, None);
+        assert_eq!(mysql.info.status, L7ResponseStatus::Ok);
+        assert!(mysql.info.error_message.is_empty());
+        assert!(mysql.info.context.is_empty());
+    }
+
+    #[test]
+    fn check_error_message() {
+        let mut err = vec![0, 0, 0, 1, 0xff, 0x48, 0x04];
+        err.extend_from_slice(b"#42000");
+        err.extend_from_slice(b"You have an error\0in your SQL syntax");
+        err.extend_from_slice(&[b'x'; 300]);
+        err.extend_from_slice(&[0, 0]);
+        err[0] = (err.len() - 4) as u8;
+        err[1] = ((err.len() - 4) >> 8) as u8;
+
+        let mut mysql = MysqlLog {
+            perf_stats: Some(L7PerfStats::default()),
+            error_message_max_length: L7_LOG_MYSQL_ERROR_MESSAGE_MAX_LENGTH,
+            ..Default::default()
+        };
+        mysql
+            .parse(&err, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        let message = &mysql.info.error_message;
+        assert_eq!(message.len(), L7_LOG_MYSQL_ERROR_MESSAGE_MAX_LENGTH);
+        assert!(message.starts_with("You have an error in your SQL syntax"));
+        assert!(!message.contains(|c: char| c.is_control()));
+
+        // 不限制长度时只去掉末尾的 NUL
+        // only trailing NULs are removed when length is unlimited
+        assert_eq!(error_message(b"Unknown table\0\0", 0), "Unknown table");
+        assert_eq!(error_message(b"\0\0", 0), "");
+        // 在字符边界截断
+        // truncated at char boundary
+        assert_eq!(error_message("表不存在".as_bytes(), 4), "表");
+        assert_eq!(error_message(b"ab\xff\xfe", 4), "ab");
+    }
+
+    #[test]
+    fn check_greeting_then_request() {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.lookup_key.direction = PacketDirection::ServerToClient;
+        let rrt_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut mysql = MysqlLog::default();
+
+        let greeting = [0x07, 0, 0, 0, PROTOCOL_VERSION, b'8', 0, 1, 0, 0, 0];
+        let param = ParseParam::from((&packet, rrt_cache.clone(), false));
+        assert!(mysql.parse_payload(&greeting, &param).unwrap().is_empty());
+        assert_eq!(mysql.info.server_version, "8");
+
+        // 同一个实例, 中间不调用 reset
+        // same instance, without reset in between
+        packet.lookup_key.direction = PacketDirection::ClientToServer;
+        let query = [
+            0x09, 0, 0, 0, COM_QUERY, b'S', b'E', b'L', b'E', b'C', b'T', b' ', b'1',
+        ];
+        let param = ParseParam::from((&packet, rrt_cache, false));
+        let infos = mysql.parse_payload(&query, &param).unwrap();
+        assert_eq!(infos.len(), 1);
+        let L7ProtocolInfo::MysqlInfo(info) = &infos[0] else {
+            unreachable!();
+        };
+        assert_eq!(info.msg_type, LogMessageType::Request);
+        assert_eq!(info.context, "SELECT 1");
+        // 协议版本属于流的状态, 其他 greeting 字段不会带到请求
+        // protocol version is state of the flow, other greeting fields are not carried to request
+        assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+        assert!(info.server_version.is_empty());
+        assert_eq!(info.server_thread_id, 0);
+        assert_eq!(info.status, L7ResponseStatus::Ok);
+    }
+
+    #[test]
+    fn check_merge_partial_response() {
+        let mut info = MysqlInfo {
+            msg_type: LogMessageType::Request,
+            command: COM_QUERY,
+            context: "SELECT * FROM t".to_string(),
+            ..Default::default()
+        };
+        // 结果集头和行
+        // result set header and rows
+        info.merge(MysqlInfo {
+            msg_type: LogMessageType::Response,
+            returned_rows: 2,
+            error_code: Some(0),
+            ..Default::default()
+        });
+        assert_eq!(info.returned_rows, 2);
+        assert_eq!(info.status, L7ResponseStatus::Ok);
+
+        // 最后的 ERR 包
+        // final ERR packet
+        info.merge(MysqlInfo {
+            msg_type: LogMessageType::Response,
+            response_code: MYSQL_RESPONSE_CODE_ERR,
+            returned_rows: 3,
+            error_code: Some(1317),
+            error_message: "Query execution was interrupted".to_string(),
+            status: L7ResponseStatus::ServerError,
+            ..Default::default()
+        });
+        // 出错后的响应不覆盖错误
+        // response after the error does not overwrite it
+        info.merge(MysqlInfo {
+            msg_type: LogMessageType::Response,
+            response_code: MYSQL_RESPONSE_CODE_OK,
+            error_code: Some(1105),
+            error_message: "unknown".to_string(),
+            ..Default::default()
+        });
+
+        assert_eq!(info.command, COM_QUERY);
+        assert_eq!(info.context, "SELECT * FROM t");
+        assert_eq!(info.returned_rows, 5);
+        assert_eq!(info.response_code, MYSQL_RESPONSE_CODE_ERR);
+        assert_eq!(info.error_code, Some(1317));
+        assert_eq!(info.error_message, "Query execution was interrupted");
+        assert_eq!(info.status, L7ResponseStatus::ServerError);
+    }
+
+    #[test]
+    fn check_needs_bidirectional() {
+        assert!(MysqlLog::default().needs_bidirectional());
+        let parser = get_parser(L7ProtocolEnum::L7Protocol(L7Protocol::MySQL)).unwrap();
+        assert!(parser.needs_bidirectional());
+        let parser = get_parser(L7ProtocolEnum::L7Protocol(L7Protocol::Redis)).unwrap();
+        assert!(!parser.needs_bidirectional());
+    }
+
+    #[test]
+    fn check_malformed_payload() {
+        // 参数数量接近 u64::MAX
+        // parameter count close to u64::MAX
+        let attributes = [0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(skip_query_attributes(&attributes), None);
+
+        // 大量序号不为 0 的非 OK/ERR/EOF 包
+        // lots of packets which are not OK/ERR/EOF and whose sequence number is not 0
+        let payload = [1, 0, 0, 1, 0x03].repeat(1 << 16);
+        let mut header = MysqlHeader::default();
+        assert_eq!(header.decode(&payload), (payload.len() - 5) as isize);
+
+        for data in [
+            &[][..],
+            &[COM_QUERY][..],
+            &[COM_STMT_PREPARE, 0x01, 0, 0, 1, 0][..],
+            &[0x80 | COM_QUERY, 0x05, 0, 0, 0, COM_QUERY, 0xfe, 0xff][..],
+            &payload[..],
+        ] {
+            _fuzz_mysql_parse(data);
+        }
+    }
+
+    #[test]
+    fn check_split_responses() {
+        let ok = [0x07, 0, 0, 1, 0, 1, 0, 0x0a, 0, 0, 0];
+        let err = [
+            0x09, 0, 0, 2, 0xff, 0x48, 0x04, b'#', b'4', b'2', b'0', b'0', b'0',
+        ];
+        let mut payload = [&ok[..], &err[..]].concat();
+        // 不完整的包
+        // incomplete packet
+        payload.extend_from_slice(&[0x07, 0, 0, 3, 0]);
+
+        let mysql = MysqlLog::default();
+        let bodies = |payload: &[u8]| {
+            mysql
+                .split_responses(payload)
+                .into_iter()
+                .map(|m| m.body)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bodies(&payload), vec![(4, 11), (15, 24)]);
+        assert_eq!(bodies(&payload[..20]), vec![(4, 11)]);
+        assert_eq!(bodies(&err[..]), vec![(4, 13)]);
+    }
+
+    #[test]
+    fn check_multi_result() {
+        // 多语句查询: OK (SERVER_MORE_RESULTS_EXISTS), 结果集 (SERVER_MORE_RESULTS_EXISTS), OK
+        // multi-statement query: OK (SERVER_MORE_RESULTS_EXISTS), result set
+        // (SERVER_MORE_RESULTS_EXISTS), OK
+        let ok_more = [0x07, 0, 0, 1, 0, 2, 0, 0x0a, 0, 0, 0];
+        let result_set = [
+            &[0x01, 0, 0, 1, 0x01][..],
+            &[0x05, 0, 0, 2, 0x03, b'd', b'e', b'f', 0][..],
+            &[0x05, 0, 0, 3, 0xfe, 0, 0, 0x02, 0][..],
+            &[0x02, 0, 0, 4, 0x01, b'1'][..],
+            &[0x02, 0, 0, 5, 0x01, b'2'][..],
+            &[0x05, 0, 0, 6, 0xfe, 0, 0, 0x0a, 0][..],
+        ]
+        .concat();
+        let ok = [0x07, 0, 0, 7, 0, 3, 0, 0x02, 0, 0, 0];
+        let payload = [&ok_more[..], &result_set, &ok[..]].concat();
+
+        let messages = MysqlLog::default().split_responses(&payload);
+        assert_eq!(
+            messages
+                .iter()
+                .map(|m| (m.rows, m.more_results()))
+                .collect::<Vec<_>>(),
+            vec![(0, true), (2, true), (0, false)]
+        );
+
+        let mut mysql = MysqlLog {
+            command: COM_QUERY,
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        mysql
+            .parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert!(mysql.infos.is_empty());
+        assert!(mysql.info.is_multi_result);
+        assert_eq!(mysql.info.affected_rows, 5);
+        assert_eq!(mysql.info.returned_rows, 2);
+        assert_eq!(mysql.info.status, L7ResponseStatus::Ok);
+        // 多个结果集只统计一次响应
+        // result sets are counted as one response
+        let perf_stats = mysql.perf_stats.as_ref().unwrap();
+        assert_eq!(perf_stats.response_count, 1);
+        assert_eq!(perf_stats.err_server_count, 0);
+
+        // 后续语句失败时使用错误的结果
+        // error is taken when the following statement fails
+        let err = [
+            0x09, 0, 0, 2, 0xff, 0x48, 0x04, b'#', b'4', b'2', b'0', b'0', b'0',
+        ];
+        let payload = [&ok_more[..], &err[..], &ok[..]].concat();
+        let mut mysql = MysqlLog {
+            command: COM_QUERY,
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        mysql
+            .parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        // ERR 之后的 OK 属于下一个请求
+        // OK after ERR belongs to the next request
+        assert_eq!(mysql.infos.len(), 1);
+        assert!(mysql.info.is_multi_result);
+        assert_eq!(mysql.info.affected_rows, 2);
+        assert_eq!(mysql.info.error_code, Some(1096));
+        assert_eq!(mysql.info.status, L7ResponseStatus::ServerError);
+        let perf_stats = mysql.perf_stats.as_ref().unwrap();
+        assert_eq!(perf_stats.response_count, 2);
+        assert_eq!(perf_stats.err_server_count, 1);
+    }
+
+    #[test]
+    fn check_error_status() {
+        let mut mysql = MysqlLog::default();
+        mysql.perf_stats = Some(L7PerfStats::default());
+        let status_of = |mysql: &mut MysqlLog, code: u16| {
+            let mut err = vec![MYSQL_RESPONSE_CODE_ERR];
+            err.extend_from_slice(&code.to_le_bytes());
+            assert!(mysql.response(&err).is_ok());
+            mysql.info.status
+        };
+
+        // 没有解析配置时使用默认的客户端错误码
+        // default client error codes are used without parse config
+        assert_eq!(status_of(&mut mysql, 1064), L7ResponseStatus::ClientError);
+        assert_eq!(status_of(&mut mysql, 1062), L7ResponseStatus::ClientError);
+        assert_eq!(status_of(&mut mysql, 2013), L7ResponseStatus::ClientError);
+        assert_eq!(status_of(&mut mysql, 1205), L7ResponseStatus::ServerError);
+        assert!(std::ptr::eq(
+            default_client_error_codes(),
+            default_client_error_codes()
+        ));
+
+        let mut config = YamlConfig::default();
+        mysql.client_error_codes = Some(config.get_l7_log_mysql_client_error_codes());
+        assert_eq!(status_of(&mut mysql, 1064), L7ResponseStatus::ClientError);
+        assert_eq!(status_of(&mut mysql, 1205), L7ResponseStatus::ServerError);
+
+        config.l7_log_mysql_client_error_codes = "1205,3000-3999".to_string();
+        mysql.client_error_codes = Some(config.get_l7_log_mysql_client_error_codes());
+        assert_eq!(status_of(&mut mysql, 1205), L7ResponseStatus::ClientError);
+        assert_eq!(status_of(&mut mysql, 3024), L7ResponseStatus::ClientError);
+        assert_eq!(status_of(&mut mysql, 1064), L7ResponseStatus::ServerError);
+
+        // 格式错误时使用默认值
+        // default value is used if the format is invalid
+        config.l7_log_mysql_client_error_codes = "1205,abc".to_string();
+        mysql.client_error_codes = Some(config.get_l7_log_mysql_client_error_codes());
+        assert_eq!(status_of(&mut mysql, 1064), L7ResponseStatus::ClientError);
+        assert_eq!(status_of(&mut mysql, 1205), L7ResponseStatus::ServerError);
+    }
+
+    fn segment_param(
+        cache: &Rc<RefCell<L7PerfCache>>,
+        direction: PacketDirection,
+        seq: u32,
+        time: u64,
+    ) -> ParseParam<'static> {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.lookup_key.direction = direction;
+        packet.lookup_key.timestamp = Duration::from_micros(time);
+        packet.tcp_data.seq = seq;
+        ParseParam::from((&packet, cache.clone(), false))
+    }
+
+    fn split_query(sql: &[u8]) -> (Vec<u8>, [Range<usize>; 3]) {
+        let mut query = vec![sql.len() as u8 + 1, 0, 0, 0, COM_QUERY];
+        query.extend_from_slice(sql);
+        let len = query.len();
+        (query, [0..10, 10..25, 25..len])
+    }
+
+    fn mysql_info(info: &L7ProtocolInfo) -> &MysqlInfo {
+        match info {
+            L7ProtocolInfo::MysqlInfo(info) => info,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn check_reassembly() {
+        let sql = b"SELECT id, name FROM users WHERE id = 1";
+        let (query, segments) = split_query(sql);
+        let ok = [0x07, 0, 0, 1, 0, 1, 0, 0x02, 0, 0, 0];
+
+        // 按序和乱序到达的分段
+        // segments arriving in order and out of order
+        for order in [[0, 1, 2], [0, 2, 1]] {
+            let cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+            let mut mysql = MysqlLog::default();
+            let mut infos = vec![];
+            for (i, &index) in order.iter().enumerate() {
+                let segment = &segments[index];
+                let param = segment_param(
+                    &cache,
+                    PacketDirection::ClientToServer,
+                    1000 + segment.start as u32,
+                    100 + i as u64,
+                );
+                infos = mysql
+                    .parse_payload(&query[segment.clone()], &param)
+                    .unwrap();
+                mysql.reset();
+                if i < order.len() - 1 {
+                    assert!(infos.is_empty(), "order: {:?}", order);
+                }
+            }
+            assert_eq!(infos.len(), 1, "order: {:?}", order);
+            let info = mysql_info(&infos[0]);
+            assert_eq!(info.msg_type, LogMessageType::Request);
+            assert_eq!(info.command, COM_QUERY);
+            assert_eq!(info.context.as_bytes(), sql);
+
+            // 时延从第一个到达的分段开始计算
+            // rrt is calculated from the first segment arrived
+            let param = segment_param(&cache, PacketDirection::ServerToClient, 5000, 200);
+            let infos = mysql.parse_payload(&ok, &param).unwrap();
+            assert_eq!(infos.len(), 1);
+            let info = mysql_info(&infos[0]);
+            assert_eq!(info.msg_type, LogMessageType::Response);
+            assert_eq!(info.affected_rows, 1);
+            assert_eq!(info.rrt, 100);
+        }
+    }
+
+    #[test]
+    fn check_reassembly_truncated() {
+        let sql = b"SELECT id, name FROM users WHERE id = 1";
+        let (query, segments) = split_query(sql);
+        let ok = [0x07, 0, 0, 1, 0, 1, 0, 0x02, 0, 0, 0];
+        let cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut mysql = MysqlLog::default();
+
+        // 后续分段没有到达时, 收到响应后按截断的请求解析
+        // subsequent segments never arrive, request is parsed as truncated when response arrives
+        let param = segment_param(&cache, PacketDirection::ClientToServer, 1000, 100);
+        assert!(mysql
+            .parse_payload(&query[segments[0].clone()], &param)
+            .unwrap()
+            .is_empty());
+        mysql.reset();
+        let param = segment_param(&cache, PacketDirection::ServerToClient, 5000, 200);
+        let infos = mysql.parse_payload(&ok, &param).unwrap();
+        mysql.reset();
+        assert_eq!(infos.len(), 2);
+        let request = mysql_info(&infos[0]);
+        assert_eq!(request.msg_type, LogMessageType::Request);
+        assert_eq!(request.context.as_bytes(), &sql[..segments[0].end - 5]);
+        let response = mysql_info(&infos[1]);
+        assert_eq!(response.msg_type, LogMessageType::Response);
+        assert_eq!(response.rrt, 100);
+
+        // 声明长度超过重组上限的包不重组
+        // packet declared longer than reassembly limit is not reassembled
+        let mut query = query[segments[0].clone()].to_vec();
+        query[..3].copy_from_slice(&[0xff, 0xff, 0xff]);
+        let param = segment_param(&cache, PacketDirection::ClientToServer, 2000, 300);
+        let infos = mysql.parse_payload(&query, &param).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert!(mysql.reassembly.iter().all(|r| r.is_empty()));
+    }
+
+    #[test]
+    fn check_reassembly_retransmission() {
+        let sql = b"SELECT id, name FROM users WHERE id = 1";
+        let (query, segments) = split_query(sql);
+        let cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut mysql = MysqlLog::default();
+        assert!(mysql.parse_retransmission());
+
+        // 乱序到达后被判断为重传的分段填补缺失的数据
+        // segment judged as retransmission after arriving out of order fills the missing data
+        let mut infos = vec![];
+        for (i, index) in [0, 2, 1].into_iter().enumerate() {
+            let segment = &segments[index];
+            let mut param = segment_param(
+                &cache,
+                PacketDirection::ClientToServer,
+                1000 + segment.start as u32,
+                100 + i as u64,
+            );
+            param.is_retransmission = index == 1;
+            infos = mysql
+                .parse_payload(&query[segment.clone()], &param)
+                .unwrap();
+            mysql.reset();
+        }
+        assert_eq!(infos.len(), 1);
+        assert_eq!(mysql_info(&infos[0]).context.as_bytes(), sql);
+
+        // 没有重组时重传包不产生日志
+        // retransmission generates no log when not reassembling
+        let mut param = segment_param(&cache, PacketDirection::ClientToServer, 1000, 200);
+        param.is_retransmission = true;
+        assert!(mysql.parse_payload(&query, &param).unwrap().is_empty());
+        assert!(mysql.reassembly.iter().all(|r| r.is_empty()));
+    }
+
     fn run_perf(pcap: &str) -> L7PerfStats {
         let rrt_cache = Rc::new(RefCell::new(L7PerfCache::new(100)));
         let mut mysql = MysqlLog::default();
@@ -0,0 +1,538 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::sql_check::is_oracle;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::{
+        error::{Error, Result},
+        protocol_logs::{
+            pb_adapter::{L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    utils::bytes::{read_u16_be, read_u32_be},
+};
+
+// TNS header: length(2) | packet checksum(2) | type(1) | reserved(1) | header checksum(2)
+// 协议版本 >= 315 时, 连接建立后的报文长度字段为 4 字节, 没有 packet checksum
+// when protocol version >= 315, the length is 4 bytes without packet checksum after connection established
+const TNS_HEADER_LEN: usize = 8;
+const TNS_TYPE_OFFSET: usize = 4;
+
+const TNS_TYPE_CONNECT: u8 = 1;
+const TNS_TYPE_ACCEPT: u8 = 2;
+const TNS_TYPE_ACK: u8 = 3;
+const TNS_TYPE_REFUSE: u8 = 4;
+const TNS_TYPE_REDIRECT: u8 = 5;
+const TNS_TYPE_DATA: u8 = 6;
+const TNS_TYPE_NULL: u8 = 7;
+const TNS_TYPE_ABORT: u8 = 9;
+const TNS_TYPE_RESEND: u8 = 11;
+const TNS_TYPE_MARKER: u8 = 12;
+const TNS_TYPE_ATTENTION: u8 = 13;
+const TNS_TYPE_CONTROL: u8 = 14;
+
+// Connect: ... | connect data length(2) | connect data offset(2) | ...
+const CONNECT_DATA_LEN_OFFSET: usize = 24;
+const CONNECT_DATA_OFFSET_OFFSET: usize = 26;
+// Refuse: user reason(1) | system reason(1) | data length(2) | data
+const REFUSE_DATA_OFFSET: usize = 12;
+// Data: data flags(2) | TTI message
+const DATA_TTI_OFFSET: usize = 10;
+
+const SERVICE_NAME_KEYS: [&[u8]; 2] = [b"SERVICE_NAME=", b"SID="];
+const REFUSE_ERROR_KEY: &[u8] = b"(ERR=";
+const ORA_ERROR_PREFIX: &[u8] = b"ORA-";
+const ORA_ERROR_CODE_LEN: usize = 5;
+const SQL_MIN_LEN: usize = 6;
+
+// 客户端原因导致的错误码
+// error codes caused by client
+const ORA_UNIQUE_CONSTRAINT: i32 = 1;
+const ORA_INVALID_USERNAME_PASSWORD: i32 = 1017;
+const ORA_INSUFFICIENT_PRIVILEGES: i32 = 1031;
+// ORA-00900 ~ ORA-00999 为 SQL 语法和对象错误
+// ORA-00900 ~ ORA-00999 are sql syntax and object errors
+const ORA_SQL_ERROR_MIN: i32 = 900;
+const ORA_SQL_ERROR_MAX: i32 = 999;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct OracleInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub packet_type: &'static str,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub service_name: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub sql: String,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error_message: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for OracleInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::OracleInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.error_code = other.error_code;
+                self.error_message = other.error_message;
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Oracle,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<OracleInfo> for L7ProtocolSendLog {
+    fn from(f: OracleInfo) -> Self {
+        L7ProtocolSendLog {
+            req: L7Request {
+                req_type: String::from(f.packet_type),
+                domain: f.service_name,
+                resource: f.sql,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.error_code,
+                exception: f.error_message,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OracleLog {
+    info: OracleInfo,
+    // Connect 中的服务名
+    // service name in Connect
+    service_name: String,
+    // 一个请求的响应可能由多个 Data 报文组成, 只用第一个响应报文生成日志
+    // the response of a request may consist of many Data packets, only the first one is logged
+    wait_response: bool,
+
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for OracleLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol()
+            || param.l4_protocol != IpProtocol::Tcp
+            || param.direction != PacketDirection::ClientToServer
+        {
+            return false;
+        }
+        Self::check(payload)
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.info.is_tls = param.is_tls();
+
+        self.parse(payload, param.direction)?;
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_stats.as_mut().unwrap().inc_req(),
+            LogMessageType::Response => self.perf_stats.as_mut().unwrap().inc_resp(),
+            // 报文不需要生成日志
+            // the packet is not logged
+            _ => return Ok(vec![]),
+        }
+        self.info.cal_rrt(param).map(|rrt| {
+            self.info.rrt = rrt;
+            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+        });
+        Ok(vec![L7ProtocolInfo::OracleInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Oracle
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[1521]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = OracleInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl OracleLog {
+    // 头部长度与报文长度一致, 校验和为 0, 且类型为 Connect 或 Data
+    // header length equals to payload length, checksums are zero, and type is Connect or Data
+    fn check(payload: &[u8]) -> bool {
+        let Some((length, packet_type)) = Self::header(payload) else {
+            return false;
+        };
+        if length != payload.len() || read_u16_be(&payload[6..]) != 0 || payload[5] != 0 {
+            return false;
+        }
+        match packet_type {
+            TNS_TYPE_CONNECT => {
+                read_u16_be(&payload[2..]) == 0
+                    && Self::connect_data(payload)
+                        .map(|d| d.starts_with(b"(DESCRIPTION=") || d.starts_with(b"(description="))
+                        .unwrap_or_default()
+            }
+            TNS_TYPE_DATA => payload.len() > DATA_TTI_OFFSET,
+            _ => false,
+        }
+    }
+
+    // return (packet length, packet type)
+    fn header(payload: &[u8]) -> Option<(usize, u8)> {
+        if payload.len() < TNS_HEADER_LEN {
+            return None;
+        }
+        let length = match read_u16_be(payload) as usize {
+            0 => read_u32_be(payload) as usize,
+            l => l,
+        };
+        if length < TNS_HEADER_LEN {
+            return None;
+        }
+        Some((length, payload[TNS_TYPE_OFFSET]))
+    }
+
+    fn connect_data(payload: &[u8]) -> Option<&[u8]> {
+        if payload.len() < CONNECT_DATA_OFFSET_OFFSET + 2 {
+            return None;
+        }
+        let len = read_u16_be(&payload[CONNECT_DATA_LEN_OFFSET..]) as usize;
+        let offset = read_u16_be(&payload[CONNECT_DATA_OFFSET_OFFSET..]) as usize;
+        if offset >= payload.len() {
+            return None;
+        }
+        // connect data 过长时会被截断
+        // connect data may be truncated when it is too long
+        Some(&payload[offset..(offset + len).min(payload.len())])
+    }
+
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
+        let (_, packet_type) = Self::header(payload).ok_or(Error::OracleLogParseFailed)?;
+        match (packet_type, direction) {
+            (TNS_TYPE_CONNECT, PacketDirection::ClientToServer) => {
+                let data = Self::connect_data(payload).ok_or(Error::OracleLogParseFailed)?;
+                self.service_name = SERVICE_NAME_KEYS
+                    .iter()
+                    .find_map(|key| find_value(data, key, b')'))
+                    .map(|s| String::from_utf8_lossy(s).into_owned())
+                    .unwrap_or_default();
+                self.info.packet_type = "Connect";
+                self.set_request();
+            }
+            (TNS_TYPE_DATA, PacketDirection::ClientToServer) => {
+                if let Some(sql) = find_sql(&payload[TNS_HEADER_LEN..]) {
+                    self.info.sql = sql;
+                }
+                self.info.packet_type = "Data";
+                self.set_request();
+            }
+            (TNS_TYPE_ACCEPT, PacketDirection::ServerToClient) => {
+                self.info.packet_type = "Accept";
+                self.set_response();
+            }
+            (TNS_TYPE_REDIRECT, PacketDirection::ServerToClient) => {
+                self.info.packet_type = "Redirect";
+                self.set_response();
+            }
+            // Refuse 报文的数据如: (DESCRIPTION=(TMP=)(VSNNUM=0)(ERR=12514)(ERROR_STACK=...))
+            // data of Refuse packet is like: (DESCRIPTION=(TMP=)(VSNNUM=0)(ERR=12514)(ERROR_STACK=...))
+            (TNS_TYPE_REFUSE, PacketDirection::ServerToClient) => {
+                if payload.len() > REFUSE_DATA_OFFSET {
+                    let data = &payload[REFUSE_DATA_OFFSET..];
+                    self.info.error_code = find_value(data, REFUSE_ERROR_KEY, b')')
+                        .and_then(|s| std::str::from_utf8(s).ok())
+                        .and_then(|s| s.parse().ok());
+                    self.info.error_message = String::from_utf8_lossy(printable(data)).into_owned();
+                }
+                self.info.packet_type = "Refuse";
+                self.info.status = L7ResponseStatus::ClientError;
+                self.perf_stats.as_mut().unwrap().inc_req_err();
+                self.wait_response = true;
+                self.set_response();
+            }
+            (TNS_TYPE_DATA, PacketDirection::ServerToClient) => {
+                let error = find_ora_error(&payload[TNS_HEADER_LEN..]);
+                if error.is_none() && !self.wait_response {
+                    return Ok(());
+                }
+                if let Some((code, message)) = error {
+                    self.info.error_code = Some(code);
+                    self.info.error_message = message;
+                    self.set_status(code);
+                    self.wait_response = true;
+                }
+                self.info.packet_type = "Data";
+                self.set_response();
+            }
+            // 其他控制类报文不生成日志
+            // other control packets are not logged
+            (
+                TNS_TYPE_ACK | TNS_TYPE_NULL | TNS_TYPE_ABORT | TNS_TYPE_RESEND | TNS_TYPE_MARKER
+                | TNS_TYPE_ATTENTION | TNS_TYPE_CONTROL,
+                _,
+            ) => {}
+            _ => return Err(Error::OracleLogParseFailed),
+        }
+        Ok(())
+    }
+
+    fn set_request(&mut self) {
+        self.info.msg_type = LogMessageType::Request;
+        self.info.service_name = self.service_name.clone();
+        self.wait_response = true;
+    }
+
+    fn set_response(&mut self) {
+        if !self.wait_response {
+            return;
+        }
+        self.info.msg_type = LogMessageType::Response;
+        self.wait_response = false;
+    }
+
+    fn set_status(&mut self, code: i32) {
+        match code {
+            ORA_UNIQUE_CONSTRAINT
+            | ORA_INVALID_USERNAME_PASSWORD
+            | ORA_INSUFFICIENT_PRIVILEGES
+            | ORA_SQL_ERROR_MIN..=ORA_SQL_ERROR_MAX => {
+                self.info.status = L7ResponseStatus::ClientError;
+                self.perf_stats.as_mut().unwrap().inc_req_err();
+            }
+            _ => {
+                self.info.status = L7ResponseStatus::ServerError;
+                self.perf_stats.as_mut().unwrap().inc_resp_err();
+            }
+        }
+    }
+}
+
+fn is_printable(b: u8) -> bool {
+    b.is_ascii_graphic() || b == b' ' || b == b'\t' || b == b'\r' || b == b'\n'
+}
+
+// 返回开头的可打印字符
+// returns leading printable characters
+fn printable(data: &[u8]) -> &[u8] {
+    let end = data
+        .iter()
+        .position(|b| !is_printable(*b))
+        .unwrap_or(data.len());
+    &data[..end]
+}
+
+// 在 data 中查找 key, 返回 key 之后到 end 之前的内容
+// find key in data, returns content after key and before end
+fn find_value<'a>(data: &'a [u8], key: &[u8], end: u8) -> Option<&'a [u8]> {
+    let start = data.windows(key.len()).position(|w| w == key)? + key.len();
+    let len = data[start..].iter().position(|b| *b == end)?;
+    Some(&data[start..start + len])
+}
+
+// TTI 报文中 SQL 以长度前缀的字符串存储, 查找以 SQL 关键字开头的可打印字符串
+// sql is stored as length-prefixed string in TTI message, find printable string starting with sql keyword
+fn find_sql(data: &[u8]) -> Option<String> {
+    if data.len() <= DATA_TTI_OFFSET - TNS_HEADER_LEN {
+        return None;
+    }
+    let data = &data[DATA_TTI_OFFSET - TNS_HEADER_LEN..];
+    for (i, b) in data.iter().enumerate() {
+        if !b.is_ascii_alphabetic() && *b != b'/' {
+            continue;
+        }
+        if i > 0 && (data[i - 1].is_ascii_alphabetic() || data[i - 1] == b'/') {
+            continue;
+        }
+        let s = printable(&data[i..]);
+        if s.len() < SQL_MIN_LEN {
+            continue;
+        }
+        let Ok(s) = std::str::from_utf8(s) else {
+            continue;
+        };
+        if is_oracle(s) {
+            return Some(s.to_owned());
+        }
+    }
+    None
+}
+
+// 错误信息如: ORA-00942: table or view does not exist
+// error message is like: ORA-00942: table or view does not exist
+fn find_ora_error(data: &[u8]) -> Option<(i32, String)> {
+    let mut data = data;
+    while let Some(start) = data
+        .windows(ORA_ERROR_PREFIX.len())
+        .position(|w| w == ORA_ERROR_PREFIX)
+    {
+        data = &data[start..];
+        let code = &data[ORA_ERROR_PREFIX.len()..];
+        if code.len() >= ORA_ERROR_CODE_LEN
+            && code[..ORA_ERROR_CODE_LEN].iter().all(u8::is_ascii_digit)
+        {
+            let code = std::str::from_utf8(&code[..ORA_ERROR_CODE_LEN])
+                .unwrap()
+                .parse()
+                .unwrap();
+            let message = printable(data);
+            let message = match message.iter().position(|b| *b == b'\n') {
+                Some(end) => &message[..end],
+                None => message,
+            };
+            return Some((code, String::from_utf8_lossy(message).into_owned()));
+        }
+        data = &data[ORA_ERROR_PREFIX.len()..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::ProtocolTester;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/oracle";
+
+    fn tester() -> ProtocolTester<OracleLog> {
+        ProtocolTester::new(OracleLog::default()).step(|oracle, payload, param| {
+            let is_oracle = oracle.check_payload(payload, param);
+            let mut lines = String::new();
+            for i in oracle.parse_payload(payload, param).unwrap() {
+                let L7ProtocolInfo::OracleInfo(mut i) = i else {
+                    unreachable!()
+                };
+                i.rrt = 0;
+                lines.push_str(&format!("{:?} is_oracle: {}\r\n", i, is_oracle));
+            }
+            lines
+        })
+    }
+
+    #[test]
+    fn check_header() {
+        // 2 字节长度和 4 字节长度 (12c 之后的大 SDU) 的 Data 报文
+        // Data packets with 2-byte length and 4-byte length (large SDU after 12c)
+        let data = [0, 11, 0, 0, TNS_TYPE_DATA, 0, 0, 0, 0, 0, 0x03];
+        assert!(OracleLog::check(&data));
+        let data = [0, 0, 0, 11, TNS_TYPE_DATA, 0, 0, 0, 0, 0, 0x03];
+        assert!(OracleLog::check(&data));
+
+        let invalid: [&[u8]; 5] = [
+            // 长度不一致
+            // length mismatch
+            &[0, 12, 0, 0, TNS_TYPE_DATA, 0, 0, 0, 0, 0, 0x03],
+            // 校验和不为 0
+            // checksum is not zero
+            &[0, 11, 0, 0, TNS_TYPE_DATA, 0, 0, 1, 0, 0, 0x03],
+            // 没有 TTI
+            // no TTI
+            &[0, 10, 0, 0, TNS_TYPE_DATA, 0, 0, 0, 0, 0],
+            &[0, 8, 0, 0, TNS_TYPE_ACK, 0, 0, 0],
+            &[0, 4, 0, 0],
+        ];
+        for payload in invalid {
+            assert!(!OracleLog::check(payload), "{:?}", payload);
+        }
+        assert!(OracleLog::header(&[0, 4, 0, 0, TNS_TYPE_DATA, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn check_find_sql() {
+        let sql = b"SELECT 1 FROM dual";
+        // data flags, TTI function, 长度前缀的 SQL
+        // data flags, TTI function, length-prefixed sql
+        let data = [
+            &[0, 0, 0x03, 0x5e, 0x10, 0x02][..],
+            &[sql.len() as u8][..],
+            &sql[..],
+            &[0, 0, 0x01][..],
+        ]
+        .concat();
+        assert_eq!(find_sql(&data).as_deref(), Some("SELECT 1 FROM dual"));
+        assert_eq!(find_sql(&data[..2]), None);
+        assert_eq!(find_sql(&data[..10]), None);
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        let files = vec![
+            ("oracle_query.pcap", "oracle_query.result"),
+            ("oracle_refuse.pcap", "oracle_refuse.result"),
+        ];
+        for (pcap, result) in files {
+            tester().check(dir.join(pcap), dir.join(result));
+        }
+    }
+}
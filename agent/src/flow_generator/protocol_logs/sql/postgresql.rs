@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use lru::LruCache;
 use public::{
     bytes::{read_u32_be, read_u64_be},
     l7_protocol::L7Protocol,
@@ -28,7 +29,7 @@ use crate::{
     },
     flow_generator::{
         protocol_logs::{
-            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
             L7ResponseStatus,
         },
         AppProtoHead, Error, LogMessageType, Result,
@@ -43,6 +44,10 @@ use super::{
 
 const SSL_REQ: u64 = 34440615471; // 00000008(len) 04d2162f(const 80877103)
 
+// 每个流缓存的预编译语句和 portal 数量
+// number of prepared statements and portals cached per flow
+const PREPARED_CACHE_SIZE: usize = 64;
+
 #[derive(Debug, Default, Clone, Serialize)]
 pub struct PostgreInfo {
     msg_type: LogMessageType,
@@ -55,6 +60,7 @@ pub struct PostgreInfo {
         with request, parse:
             simple query ('Q')
             prepare statment ('P')
+            execute ('E') when no query or prepare statement in the same frame
 
         with response parse
             command complete('C')
@@ -73,6 +79,10 @@ pub struct PostgreInfo {
     pub context: String,
     #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
     pub req_type: char,
+    // 请求来自扩展查询协议 (Parse/Bind/Execute) 而不是简单查询
+    // request comes from extended query protocol (Parse/Bind/Execute) instead of simple query
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub extended_query: bool,
 
     // response
     #[serde(skip)]
@@ -82,11 +92,26 @@ pub struct PostgreInfo {
     pub result: String,
     #[serde(rename = "sql_affected_rows", skip_serializing_if = "value_is_default")]
     pub affected_rows: u64,
+    // SELECT, FETCH, MOVE 返回的行数
+    // number of rows returned by SELECT, FETCH and MOVE
+    #[serde(rename = "sql_returned_rows", skip_serializing_if = "value_is_default")]
+    pub returned_rows: u64,
+    // CommandComplete 中去掉行数的命令标签, 例如 INSERT, CREATE TABLE
+    // command tag of CommandComplete without row count, such as INSERT, CREATE TABLE
+    #[serde(
+        rename = "sql_statement_type",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub statement_type: String,
     #[serde(
         rename = "response_execption",
         skip_serializing_if = "value_is_default"
     )]
     pub error_message: String,
+    // ErrorResponse 中的 message 字段
+    // message field of ErrorResponse
+    #[serde(rename = "error_detail", skip_serializing_if = "value_is_default")]
+    pub error_detail: String,
     pub status: L7ResponseStatus,
 }
 
@@ -101,13 +126,17 @@ impl L7ProtocolInfoInterface for PostgreInfo {
                 LogMessageType::Request => {
                     self.req_type = pg.req_type;
                     self.context = pg.context.clone();
+                    self.extended_query = pg.extended_query;
                 }
                 LogMessageType::Response => {
                     self.resp_type = pg.resp_type;
                     self.result = pg.result;
                     self.error_message = pg.error_message;
+                    self.error_detail = pg.error_detail;
                     self.status = pg.status;
                     self.affected_rows = pg.affected_rows;
+                    self.returned_rows = pg.returned_rows;
+                    self.statement_type = pg.statement_type;
                 }
                 _ => {}
             }
@@ -130,6 +159,31 @@ impl L7ProtocolInfoInterface for PostgreInfo {
 
 impl From<PostgreInfo> for L7ProtocolSendLog {
     fn from(p: PostgreInfo) -> L7ProtocolSendLog {
+        let mut attributes = vec![];
+        if !p.error_detail.is_empty() {
+            attributes.push(KeyVal {
+                key: "error_detail".to_string(),
+                val: p.error_detail,
+            });
+        }
+        if p.extended_query {
+            attributes.push(KeyVal {
+                key: "extended_query".to_string(),
+                val: "true".to_string(),
+            });
+        }
+        if !p.statement_type.is_empty() {
+            attributes.push(KeyVal {
+                key: "statement_type".to_string(),
+                val: p.statement_type,
+            });
+        }
+        if p.returned_rows > 0 {
+            attributes.push(KeyVal {
+                key: "returned_rows".to_string(),
+                val: p.returned_rows.to_string(),
+            });
+        }
         L7ProtocolSendLog {
             req_len: None,
             resp_len: None,
@@ -146,6 +200,11 @@ impl From<PostgreInfo> for L7ProtocolSendLog {
                 ..Default::default()
             },
             ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -160,6 +219,13 @@ pub struct PostgresqlLog {
     perf_stats: Option<L7PerfStats>,
     #[serde(skip)]
     parsed: bool,
+
+    // 扩展查询协议中语句名到 SQL, 以及 portal 到语句名的映射, reset 时保留
+    // statement name to SQL and portal to statement name of extended query protocol, kept when reset
+    #[serde(skip)]
+    statements: LruCache<String, String>,
+    #[serde(skip)]
+    portals: LruCache<String, String>,
 }
 
 impl Default for PostgresqlLog {
@@ -168,6 +234,8 @@ impl Default for PostgresqlLog {
             info: PostgreInfo::default(),
             perf_stats: None,
             parsed: false,
+            statements: LruCache::new(PREPARED_CACHE_SIZE.try_into().unwrap()),
+            portals: LruCache::new(PREPARED_CACHE_SIZE.try_into().unwrap()),
         };
         log.info.ignore = true;
         log
@@ -225,6 +293,10 @@ impl L7ProtocolParserInterface for PostgresqlLog {
         L7Protocol::PostgreSQL
     }
 
+    fn default_ports(&self) -> &[u16] {
+        &[5432]
+    }
+
     fn reset(&mut self) {
         self.info = PostgreInfo::default();
         self.parsed = false;
@@ -303,27 +375,80 @@ impl PostgresqlLog {
             }
             'P' => {
                 self.info.req_type = tag;
+                self.info.extended_query = true;
                 self.info.ignore = false;
 
-                let mut data = data;
-
                 // | statement str, end with 0x0 | query str, end with 0x0 | param |
-                if let Some(idx) = data.iter().position(|x| *x == 0x0) {
-                    // skip statement
-                    data = &data[idx + 1..];
-
-                    // parse query
-                    if let Some(idx) = data.iter().position(|x| *x == 0x0) {
-                        self.info.context = String::from_utf8_lossy(&data[..idx]).to_string();
-                        if is_postgresql(&self.info.context) {
-                            self.perf_stats.as_mut().unwrap().inc_req();
-                            return Ok(());
-                        }
+                let Some((statement, data)) = read_cstring(data) else {
+                    return Err(Error::L7ProtocolUnknown);
+                };
+                let Some((query, _)) = read_cstring(data) else {
+                    return Err(Error::L7ProtocolUnknown);
+                };
+                self.info.context = query;
+                if !is_postgresql(&self.info.context) {
+                    return Err(Error::L7ProtocolUnknown);
+                }
+                self.statements.put(statement, self.info.context.clone());
+                self.perf_stats.as_mut().unwrap().inc_req();
+                Ok(())
+            }
+            'B' => {
+                // | portal str, end with 0x0 | statement str, end with 0x0 | param |
+                let Some((portal, data)) = read_cstring(data) else {
+                    return Err(Error::L7ProtocolUnknown);
+                };
+                let Some((statement, _)) = read_cstring(data) else {
+                    return Err(Error::L7ProtocolUnknown);
+                };
+                self.portals.put(portal, statement);
+                Ok(())
+            }
+            'E' => {
+                // | portal str, end with 0x0 | max rows 4B |
+                let Some((portal, _)) = read_cstring(data) else {
+                    return Err(Error::L7ProtocolUnknown);
+                };
+                // 同一帧中已有 Query 或 Parse 时, 由它们上报请求
+                // the request is reported by Query or Parse in the same frame
+                if !self.info.ignore {
+                    return Ok(());
+                }
+                self.info.req_type = tag;
+                self.info.extended_query = true;
+                self.info.ignore = false;
+                // 语句在抓包之前预编译时找不到 SQL
+                // SQL is not found when the statement is prepared before capture
+                if let Some(query) = self
+                    .portals
+                    .get(&portal)
+                    .and_then(|statement| self.statements.get(statement))
+                {
+                    self.info.context = query.clone();
+                }
+                self.perf_stats.as_mut().unwrap().inc_req();
+                Ok(())
+            }
+            'C' => {
+                // | 'S' or 'P' | statement or portal str, end with 0x0 |
+                let Some((kind, data)) = data.split_first() else {
+                    return Err(Error::L7ProtocolUnknown);
+                };
+                let Some((name, _)) = read_cstring(data) else {
+                    return Err(Error::L7ProtocolUnknown);
+                };
+                match kind {
+                    b'S' => {
+                        self.statements.pop(&name);
+                    }
+                    b'P' => {
+                        self.portals.pop(&name);
                     }
+                    _ => return Err(Error::L7ProtocolUnknown),
                 }
-                Err(Error::L7ProtocolUnknown)
+                Ok(())
             }
-            'B' | 'F' | 'C' | 'D' | 'H' | 'S' | 'X' | 'd' | 'c' | 'f' => Ok(()),
+            'F' | 'D' | 'H' | 'S' | 'X' | 'd' | 'c' | 'f' => Ok(()),
             _ => Err(Error::L7ProtocolUnknown),
         }
     }
@@ -336,32 +461,15 @@ impl PostgresqlLog {
                 self.info.ignore = false;
                 self.info.resp_type = tag;
 
-                // INSERT xxx xxx0x0 where last xxx is row effect.
-                // DELETE xxx0x0
-                // UPDATE xxx0x0
-                // SELECT xxx0x0
-                if let Some(idx) = data.iter().position(|x| *x == 0x20) {
-                    let op = &data[..idx];
-                    data = &data[idx + 1..];
-                    if op.eq("INSERT".as_bytes()) {
-                        if let Some(idx) = data.iter().position(|x| *x == 0x20) {
-                            data = &data[idx + 1..];
-                        } else {
-                            return Ok(());
-                        }
-                    } else {
-                        if !(op.eq("DELETE".as_bytes())
-                            || op.eq("UPDATE".as_bytes())
-                            || op.eq("SELECT".as_bytes()))
-                        {
-                            return Ok(());
-                        }
+                // | command tag str, end with 0x0 |
+                if let Some((command_tag, _)) = read_cstring(data) {
+                    let (statement_type, rows) = parse_command_tag(&command_tag);
+                    match (statement_type, rows) {
+                        ("SELECT" | "FETCH" | "MOVE", Some(rows)) => self.info.returned_rows = rows,
+                        (_, Some(rows)) => self.info.affected_rows = rows,
+                        _ => {}
                     }
-                }
-
-                if let Some(idx) = data.iter().position(|x| *x == 0x0) {
-                    let row_eff = String::from_utf8_lossy(&data[..idx]).to_string();
-                    self.info.affected_rows = row_eff.parse().unwrap_or(0);
+                    self.info.statement_type = statement_type.to_string();
                 }
                 self.perf_stats.as_mut().unwrap().inc_resp();
                 Ok(())
@@ -371,26 +479,31 @@ impl PostgresqlLog {
                 self.info.resp_type = tag;
                 self.info.ignore = false;
                 /*
-                Severity: string end with 0x0
-                Text:     string end with 0x0
-                code:     string end with 0x0
-                message:  string end with 0x0
+                fields, each field is type 1B and string end with 0x0, terminated by 0x0:
+                S  Severity
+                V  Severity (non-localized)
+                C  code, such as `C42601`
+                M  message
                 ...
 
                 */
-                for _ in 0..2 {
-                    if let Some(idx) = data.iter().position(|x| *x == 0) {
-                        data = &data[idx + 1..];
-                    } else {
-                        return Ok(());
+                let mut code = None;
+                while let Some((&field, rest)) = data.split_first() {
+                    if field == 0 {
+                        break;
                     }
-                }
-                // code, such as `C42601`
-                if let Some(idx) = data.iter().position(|x| *x == 0) {
-                    if data[0] != b'C' {
-                        return Err(Error::L7ProtocolUnknown);
+                    let Some((value, rest)) = read_cstring(rest) else {
+                        break;
+                    };
+                    match field {
+                        b'C' => code = Some(value),
+                        b'M' => self.info.error_detail = value,
+                        _ => {}
                     }
-                    self.info.result = String::from_utf8_lossy(&data[1..idx]).to_string();
+                    data = rest;
+                }
+                if let Some(code) = code {
+                    self.info.result = code;
                     let (err_desc, status) = get_code_desc(self.info.result.as_str());
                     self.info.error_message = String::from(err_desc);
                     self.info.status = status;
@@ -435,6 +548,35 @@ fn read_block(payload: &[u8]) -> Option<(char, usize)> {
     Some((tag, len - 4))
 }
 
+/*
+    split command tag of CommandComplete into statement type and row count:
+    INSERT oid rows
+    DELETE rows / UPDATE rows / MERGE rows / COPY rows
+    SELECT rows / FETCH rows / MOVE rows
+    others without row count, such as CREATE TABLE, BEGIN
+*/
+fn parse_command_tag(command_tag: &str) -> (&str, Option<u64>) {
+    let Some((verb, rows)) = command_tag.rsplit_once(' ') else {
+        return (command_tag, None);
+    };
+    let Ok(rows) = rows.parse() else {
+        return (command_tag, None);
+    };
+    match verb.split_once(' ') {
+        Some(("INSERT", oid)) if oid.parse::<u32>().is_ok() => ("INSERT", Some(rows)),
+        _ => (verb, Some(rows)),
+    }
+}
+
+// read string end with 0x0, return the string and the remaining data
+fn read_cstring(data: &[u8]) -> Option<(String, &[u8])> {
+    let idx = data.iter().position(|x| *x == 0x0)?;
+    Some((
+        String::from_utf8_lossy(&data[..idx]).into_owned(),
+        &data[idx + 1..],
+    ))
+}
+
 // strip the latest 0x0 in string
 // if not end with 0x0, presume it is not pg protocol
 fn strip_string_end_with_zero(data: &[u8]) -> Result<String> {
@@ -455,18 +597,22 @@ mod test {
             l7_protocol_log::ParseParam,
             l7_protocol_log::{L7PerfCache, L7ProtocolParserInterface},
         },
-        flow_generator::protocol_logs::PostgreInfo,
+        flow_generator::protocol_logs::{L7ResponseStatus, PostgreInfo},
         flow_generator::{protocol_logs::PostgresqlLog, L7_RRT_CACHE_CAPACITY},
         utils::test::Capture,
     };
 
+    use super::parse_command_tag;
+
     const FILE_DIR: &str = "resources/test/flow_generator/postgre";
 
     #[test]
     fn test_simple_query() {
         let (info, perf) = check_and_parse("simple_query.pcap");
         assert_eq!(info.affected_rows, 1);
+        assert_eq!(info.statement_type, "DELETE");
         assert_eq!(info.req_type, 'Q');
+        assert!(!info.extended_query);
         assert_eq!(info.context.as_str(), "delete  from test;");
         assert_eq!(info.resp_type, 'C');
         assert_eq!(info.resp_type, 'C');
@@ -490,6 +636,7 @@ mod test {
         let (info, perf) = check_and_parse("prepare_stat.pcap");
         assert_eq!(info.affected_rows, 0);
         assert_eq!(info.req_type, 'P');
+        assert!(info.extended_query);
         assert_eq!(
             info.context.as_str(),
             "delete from test where id=$1 returning id"
@@ -511,6 +658,15 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_parse_command_tag() {
+        assert_eq!(parse_command_tag("INSERT 0 7"), ("INSERT", Some(7)));
+        assert_eq!(parse_command_tag("SELECT 42"), ("SELECT", Some(42)));
+        assert_eq!(parse_command_tag("UPDATE 0"), ("UPDATE", Some(0)));
+        assert_eq!(parse_command_tag("CREATE TABLE"), ("CREATE TABLE", None));
+        assert_eq!(parse_command_tag("BEGIN"), ("BEGIN", None));
+    }
+
     #[test]
     fn test_error() {
         let (info, perf) = check_and_parse("error.pcap");
@@ -519,6 +675,10 @@ mod test {
         assert_eq!(info.resp_type, 'E');
         assert_eq!(info.result.as_str(), "42601");
         assert_eq!(info.error_message.as_str(), "syntax_error",);
+        assert_eq!(
+            info.error_detail.as_str(),
+            "syntax error at or near \"asdsdfdsf\""
+        );
 
         assert_eq!(
             perf,
@@ -535,6 +695,65 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_extended_query() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("extended_query.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+
+        let mut parser = PostgresqlLog::default();
+        let mut infos = vec![];
+        for (i, packet) in packets.iter_mut().enumerate() {
+            packet.lookup_key.direction = if i % 2 == 0 {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let param = &ParseParam::from((&*packet, log_cache.clone(), false));
+            let payload = packet.get_l4_payload().unwrap();
+            for info in parser.parse_payload(payload, param).unwrap() {
+                if let L7ProtocolInfo::PostgreInfo(info) = info {
+                    infos.push(info);
+                }
+            }
+            parser.reset();
+        }
+
+        // ParseComplete 的响应被忽略
+        // response of ParseComplete is ignored
+        assert_eq!(infos.len(), 5);
+        assert_eq!(infos[0].req_type, 'P');
+        assert_eq!(infos[0].context, "select name from users where id=$1");
+
+        // Execute 通过 portal 和语句名找到 SQL
+        // Execute finds SQL by portal and statement name
+        assert_eq!(infos[1].req_type, 'E');
+        assert!(infos[1].extended_query);
+        assert_eq!(infos[1].context, "select name from users where id=$1");
+        assert_eq!(infos[2].resp_type, 'C');
+        assert_eq!(infos[2].affected_rows, 0);
+        assert_eq!(infos[2].returned_rows, 1);
+        assert_eq!(infos[2].statement_type, "SELECT");
+
+        // 语句关闭后找不到 SQL
+        // SQL is not found after the statement is closed
+        assert_eq!(infos[3].req_type, 'E');
+        assert_eq!(infos[3].context, "");
+        assert_eq!(infos[4].resp_type, 'E');
+        assert_eq!(infos[4].result, "26000");
+        assert_eq!(infos[4].error_message, "invalid_sql_statement_name");
+        assert_eq!(
+            infos[4].error_detail,
+            "prepared statement \"s1\" does not exist"
+        );
+        assert_eq!(infos[4].status, L7ResponseStatus::ClientError);
+
+        let perf = parser.perf_stats.unwrap();
+        assert_eq!(perf.request_count, 3);
+        assert_eq!(perf.response_count, 2);
+        assert_eq!(perf.err_client_count, 1);
+    }
+
     fn check_and_parse(file_name: &str) -> (PostgreInfo, L7PerfStats) {
         let pcap_file = Path::new(FILE_DIR).join(file_name);
         let capture = Capture::load_pcap(pcap_file, None);
@@ -14,11 +14,13 @@
  * limitations under the License.
  */
 
-use serde::{Serialize, Serializer};
+use serde::Serialize;
 
-use std::{fmt, str};
+use std::{fmt, mem, str};
 
-use super::super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use super::super::{
+    value_is_default, vec_u8_to_string, AppProtoHead, L7ResponseStatus, LogMessageType,
+};
 
 use crate::{
     common::{
@@ -30,11 +32,16 @@ use crate::{
     },
     flow_generator::{
         error::{Error, Result},
-        protocol_logs::pb_adapter::{L7ProtocolSendLog, L7Request, L7Response},
+        protocol_logs::pb_adapter::{
+            ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response,
+        },
     },
 };
 
 const SEPARATOR_SIZE: usize = 2;
+// 缓存跨 TCP 段的不完整命令的最大长度, 超过时按原方式截断解析
+// max length of the cached incomplete command across tcp segments, truncated if exceeded
+const PENDING_SIZE_LIMIT: usize = 1024;
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct RedisInfo {
@@ -67,8 +74,19 @@ pub struct RedisInfo {
         serialize_with = "vec_u8_to_string"
     )]
     pub error: Vec<u8>, // '-'
+    // cluster 模式下 MOVED/ASK 重定向的目标节点, 如 "-MOVED 3999 127.0.0.1:6381" 中的 "127.0.0.1:6381"
+    // target node of MOVED/ASK redirection in cluster mode
+    #[serde(
+        skip_serializing_if = "value_is_default",
+        serialize_with = "vec_u8_to_string"
+    )]
+    pub redirect: Vec<u8>,
     #[serde(rename = "response_status")]
     pub resp_status: L7ResponseStatus,
+    // HELLO 协商的 RESP 协议版本, 0 表示未观察到 HELLO
+    // RESP protocol version negotiated by HELLO, 0 if HELLO is not seen
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub protocol_version: u8,
 
     rrt: u64,
 }
@@ -98,19 +116,14 @@ impl L7ProtocolInfoInterface for RedisInfo {
     }
 }
 
-pub fn vec_u8_to_string<S>(v: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    serializer.serialize_str(&String::from_utf8_lossy(v))
-}
-
 impl RedisInfo {
     pub fn merge(&mut self, other: Self) -> Result<()> {
         self.response = other.response;
         self.status = other.status;
         self.error = other.error;
+        self.redirect = other.redirect;
         self.resp_status = other.resp_status;
+        self.protocol_version = other.protocol_version;
         Ok(())
     }
 }
@@ -147,6 +160,17 @@ impl fmt::Display for RedisInfo {
 
 impl From<RedisInfo> for L7ProtocolSendLog {
     fn from(f: RedisInfo) -> Self {
+        let ext_info = if f.redirect.is_empty() {
+            None
+        } else {
+            Some(ExtendedInfo {
+                attributes: Some(vec![KeyVal {
+                    key: String::from("redirect"),
+                    val: String::from_utf8_lossy(f.redirect.as_slice()).to_string(),
+                }]),
+                ..Default::default()
+            })
+        };
         let log = L7ProtocolSendLog {
             req: L7Request {
                 req_type: String::from_utf8_lossy(f.request_type.as_slice()).to_string(),
@@ -159,6 +183,12 @@ impl From<RedisInfo> for L7ProtocolSendLog {
                 result: String::from_utf8_lossy(f.response.as_slice()).to_string(),
                 ..Default::default()
             },
+            version: if f.protocol_version > 0 {
+                Some(f.protocol_version.to_string())
+            } else {
+                None
+            },
+            ext_info,
             ..Default::default()
         };
         return log;
@@ -168,6 +198,20 @@ impl From<RedisInfo> for L7ProtocolSendLog {
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct RedisLog {
     info: RedisInfo,
+    // pipeline 模式下一个报文中可能包含多个命令或回复
+    // a payload may contain multiple commands or replies in pipeline mode
+    #[serde(skip)]
+    infos: Vec<RedisInfo>,
+    // 被 TCP 分段截断的命令, 等待与下一个请求报文拼接
+    // command truncated by tcp segmentation, waiting to be concatenated with the next request payload
+    #[serde(skip)]
+    pending: Vec<u8>,
+    // HELLO 请求的协议版本 (无参数时为 0), 等待回复确认
+    // protocol version requested by HELLO (0 if no argument), waiting for the reply
+    #[serde(skip)]
+    pending_hello: Option<u8>,
+    #[serde(skip)]
+    protocol_version: u8,
     #[serde(skip)]
     perf_stats: Option<L7PerfStats>,
 }
@@ -188,28 +232,50 @@ impl L7ProtocolParserInterface for RedisLog {
     }
 
     fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
-        self.info.is_tls = param.is_tls();
         if self.perf_stats.is_none() {
             self.perf_stats = Some(L7PerfStats::default())
         };
         self.parse(payload, param.l4_protocol, param.direction)?;
-        self.info.cal_rrt(param).map(|rrt| {
-            self.info.rrt = rrt;
+        // 同一个报文中的命令时间相同, 只计算一次 rrt, 服务端推送不参与计算
+        // commands in the same payload have the same time, rrt is calculated only once,
+        // server pushes are excluded
+        let rrt = self
+            .infos
+            .iter()
+            .find(|info| info.msg_type != LogMessageType::Session)
+            .and_then(|info| info.cal_rrt(param));
+        if let Some(rrt) = rrt {
             self.perf_stats.as_mut().unwrap().update_rrt(rrt);
-        });
-        Ok(vec![L7ProtocolInfo::RedisInfo(self.info.clone())])
+        }
+        for info in self.infos.iter_mut() {
+            info.is_tls = param.is_tls();
+            info.rrt = rrt.unwrap_or_default();
+        }
+        if let Some(last) = self.infos.last() {
+            self.info = last.clone();
+        }
+        Ok(self
+            .infos
+            .drain(..)
+            .map(L7ProtocolInfo::RedisInfo)
+            .collect())
     }
 
     fn protocol(&self) -> L7Protocol {
         L7Protocol::Redis
     }
 
+    fn default_ports(&self) -> &[u16] {
+        &[6379]
+    }
+
     fn parsable_on_udp(&self) -> bool {
         false
     }
 
     fn reset(&mut self) {
         self.info = RedisInfo::default();
+        self.infos.clear();
         self.perf_stats = self.perf_stats.take();
     }
 
@@ -224,33 +290,82 @@ impl RedisLog {
     }
 
     fn fill_request(&mut self, context: Vec<u8>) {
-        self.info.request_type = match (&context).iter().position(|&x| x == b' ') {
+        let request_type = match (&context).iter().position(|&x| x == b' ') {
             Some(i) if i > 0 => Vec::from(&context[..i]),
             _ => context.clone(),
         };
-        self.info.msg_type = LogMessageType::Request;
-        self.info.request = context;
+        // HELLO [protover [AUTH username password] [SETNAME clientname]]
+        if request_type.eq_ignore_ascii_case(b"HELLO") {
+            self.pending_hello = Some(
+                context
+                    .split(|&x| x == b' ')
+                    .nth(1)
+                    .and_then(|v| str::from_utf8(v).ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_default(),
+            );
+        }
+        self.infos.push(RedisInfo {
+            msg_type: LogMessageType::Request,
+            request: context,
+            request_type,
+            protocol_version: self.protocol_version,
+            ..Default::default()
+        });
         self.perf_stats.as_mut().unwrap().inc_req();
     }
 
     fn fill_response(&mut self, context: Vec<u8>, error_response: bool) {
-        self.info.msg_type = LogMessageType::Response;
+        // HELLO 之后的第一个回复, 成功时回复中的 proto 字段为协商的版本
+        // the first reply after HELLO, proto field of the reply is the negotiated version if succeeded
+        if let Some(requested) = self.pending_hello.take() {
+            if !error_response {
+                let version = hello_proto(&context).unwrap_or(requested);
+                if version > 0 {
+                    self.protocol_version = version;
+                }
+            }
+        }
+        let mut info = RedisInfo {
+            msg_type: LogMessageType::Response,
+            protocol_version: self.protocol_version,
+            ..Default::default()
+        };
         self.perf_stats.as_mut().unwrap().inc_resp();
         if context.is_empty() {
+            self.infos.push(info);
             return;
         }
 
-        self.info.resp_status = L7ResponseStatus::Ok;
+        info.resp_status = L7ResponseStatus::Ok;
         match context[0] {
-            b'+' => self.info.status = context,
+            b'+' => info.status = context,
+            // MOVED/ASK 是 cluster 的路由重定向, 不是错误
+            // MOVED/ASK is routing redirection of cluster, not an error
+            b'-' if error_response && redirect_node(&context).is_some() => {
+                info.redirect = redirect_node(&context).unwrap().to_vec();
+                info.response = context;
+            }
             b'-' if error_response => {
-                self.info.error = context;
-                self.info.resp_status = L7ResponseStatus::ServerError;
+                info.error = context;
+                info.resp_status = L7ResponseStatus::ServerError;
                 self.perf_stats.as_mut().unwrap().inc_resp_err();
             }
-            b'-' if !error_response => self.info.response = context,
-            _ => self.info.response = context,
+            b'-' if !error_response => info.response = context,
+            _ => info.response = context,
         }
+        self.infos.push(info);
+    }
+
+    // RESP3 服务端推送, 不对应任何请求, 作为单独的会话上报
+    // server push of RESP3, which does not correspond to any request, reported as a separate session
+    fn fill_push(&mut self, context: Vec<u8>) {
+        self.infos.push(RedisInfo {
+            msg_type: LogMessageType::Session,
+            response: context,
+            protocol_version: self.protocol_version,
+            ..Default::default()
+        });
     }
 
     fn parse(
@@ -263,17 +378,120 @@ impl RedisLog {
             return Err(Error::InvalidIpProtocol);
         }
 
-        let (context, _, error_response) =
-            decode(payload, direction == PacketDirection::ClientToServer)
-                .ok_or(Error::RedisLogParseFailed)?;
         match direction {
-            PacketDirection::ClientToServer => self.fill_request(context),
-            PacketDirection::ServerToClient => self.fill_response(context, error_response),
-        };
+            PacketDirection::ClientToServer => self.parse_request(payload),
+            PacketDirection::ServerToClient => self.parse_response(payload),
+        }
+    }
+
+    fn parse_request(&mut self, payload: &[u8]) -> Result<()> {
+        if !self.pending.is_empty() {
+            let mut data = mem::take(&mut self.pending);
+            data.extend_from_slice(payload);
+            if self.decode_requests(&data) {
+                return Ok(());
+            }
+            // 拼接后仍无法解析, 丢弃缓存的数据, 单独解析当前报文
+            // still unable to decode after concatenation, drop the cached data and decode current payload alone
+        }
+        if self.decode_requests(payload) {
+            Ok(())
+        } else {
+            Err(Error::RedisLogParseFailed)
+        }
+    }
+
+    // 依次解析 pipeline 中的所有完整命令, 末尾不完整的命令缓存到 pending, 过长时截断解析
+    // decode all complete commands in pipeline, the trailing incomplete command is cached into pending,
+    // or decoded as truncated if it is too long
+    fn decode_requests(&mut self, payload: &[u8]) -> bool {
+        let mut offset = 0;
+        while let Some((context, size, _)) = decode_complete(&payload[offset..]) {
+            self.fill_request(context);
+            offset += size;
+        }
+
+        let remain = &payload[offset..];
+        if remain.is_empty() {
+            return offset > 0;
+        }
+        if remain[0] == b'*' && remain.len() <= PENDING_SIZE_LIMIT {
+            self.pending = remain.to_vec();
+            return true;
+        }
+        match decode(remain, true) {
+            Some((context, _, _)) => {
+                self.fill_request(context);
+                true
+            }
+            None => offset > 0,
+        }
+    }
+
+    // 回复不缓存, 第一个回复不完整时按原方式截断解析, 之后不完整的回复直接丢弃
+    // replies are not cached, the first incomplete reply is decoded as truncated, and the following
+    // incomplete replies are dropped
+    fn parse_response(&mut self, payload: &[u8]) -> Result<()> {
+        let mut offset = 0;
+        while let Some((context, size, error_response)) = decode_complete(&payload[offset..]) {
+            if payload[offset] == b'>' {
+                self.fill_push(context);
+            } else {
+                self.fill_response(context, error_response);
+            }
+            offset += size;
+        }
+        if offset == 0 {
+            let (context, _, error_response) =
+                decode(payload, false).ok_or(Error::RedisLogParseFailed)?;
+            if payload[0] == b'>' {
+                self.fill_push(context);
+            } else {
+                self.fill_response(context, error_response);
+            }
+        }
         Ok(())
     }
 }
 
+// 格式为"-MOVED 3999 127.0.0.1:6381"或"-ASK 3999 127.0.0.1:6381", 返回目标节点
+// format is "-MOVED 3999 127.0.0.1:6381" or "-ASK 3999 127.0.0.1:6381", return the target node
+fn redirect_node(context: &[u8]) -> Option<&[u8]> {
+    let mut parts = context.get(1..)?.split(|c| *c == b' ');
+    match parts.next()? {
+        b"MOVED" | b"ASK" => {}
+        _ => return None,
+    }
+    let slot = parts.next()?;
+    if slot.is_empty() || !slot.iter().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match parts.next() {
+        Some(node) if !node.is_empty() && parts.next().is_none() => Some(node),
+        _ => None,
+    }
+}
+
+// HELLO 的回复为 map (RESP3) 或数组 (RESP2), 展开后为 "server redis ... proto :3 ...", 返回 proto 的值
+// reply of HELLO is a map (RESP3) or an array (RESP2), flattened as "server redis ... proto :3 ...",
+// return the value of proto
+fn hello_proto(context: &[u8]) -> Option<u8> {
+    let mut parts = context.split(|&x| x == b' ');
+    parts.find(|&part| part == b"proto")?;
+    let value = parts.next()?;
+    let value = value.strip_prefix(b":").unwrap_or(value);
+    str::from_utf8(value).ok()?.parse().ok()
+}
+
+// 聚合类型的元素个数, map 和 attribute 的每一项包含 key 和 value 两个元素
+// number of elements of aggregate type, each entry of map and attribute contains key and value
+fn aggregate_elements(type_byte: u8, count: isize) -> isize {
+    match type_byte {
+        b'%' | b'|' => count * 2,
+        _ => count,
+    }
+}
+
 // 协议解析：http://redisdoc.com/topic/protocol.html#
 fn find_separator(payload: &[u8]) -> Option<usize> {
     let len = payload.len();
@@ -344,6 +562,7 @@ fn decode_asterisk(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize)> {
 
     // 提取请求参数个数/批量回复个数
     let (next_data_num, sub_offset) = decode_integer(&payload[offset..])?;
+    let next_data_num = aggregate_elements(payload[0], next_data_num);
 
     if next_data_num <= 0 {
         // 无内容的多条批量回复: "*-1\r\n"
@@ -396,6 +615,84 @@ fn decode_str(payload: &[u8], limit: usize) -> Option<(&[u8], usize)> {
     Some((&payload[..separator_pos], separator_pos))
 }
 
+// 严格解析一个完整的消息, 消息不完整时返回 None, 返回的长度包含结尾的分隔符
+// strictly decode a complete message, return None if incomplete, the returned length includes the trailing separator
+fn decode_complete(payload: &[u8]) -> Option<(Vec<u8>, usize, bool)> {
+    match *payload.first()? {
+        b'*' | b'%' | b'~' | b'>' | b'|' => {
+            let (data_num, sub_offset) = decode_integer(&payload[1..])?;
+            let data_num = aggregate_elements(payload[0], data_num);
+            let mut offset = 1 + sub_offset;
+            let mut context = Vec::new();
+            for _ in 0..data_num {
+                let (sub_context, sub_offset, _) = decode_complete(&payload[offset..])?;
+                if !context.is_empty() {
+                    context.push(b' ');
+                }
+                context.extend_from_slice(&sub_context);
+                offset += sub_offset;
+            }
+            // attribute 是附加在回复之前的辅助信息, 跳过后解析真正的回复
+            // attribute is auxiliary data before the reply, skip it and decode the actual reply
+            if payload[0] == b'|' {
+                let (context, sub_offset, error_response) = decode_complete(&payload[offset..])?;
+                return Some((context, offset + sub_offset, error_response));
+            }
+            if data_num <= 0 {
+                return Some((payload[1..offset - SEPARATOR_SIZE].to_vec(), offset, false));
+            }
+            Some((context, offset, false))
+        }
+        b'$' | b'!' | b'=' => {
+            let (data_len, sub_offset) = decode_integer(&payload[1..])?;
+            let offset = 1 + sub_offset;
+            // 与 decode_dollor 一致, $-1 $0 时返回长度
+            // same as decode_dollor, return the length when $-1 or $0
+            let (context, data_end) = if data_len <= 0 {
+                (&payload[1..offset - SEPARATOR_SIZE], offset)
+            } else {
+                let data_end = offset + data_len as usize;
+                (payload.get(offset..data_end)?, data_end)
+            };
+            if data_len < 0 {
+                return Some((context.to_vec(), offset, false));
+            }
+            if payload.get(data_end..data_end + SEPARATOR_SIZE)? != b"\r\n" {
+                return None;
+            }
+            let context = match payload[0] {
+                // blob error 与简单错误一样以 '-' 开头
+                // blob error starts with '-' as simple error
+                b'!' => [b"-", context].concat(),
+                // verbatim string 跳过 "txt:" 格式前缀
+                // verbatim string skips the "txt:" format prefix
+                b'=' if data_len > 4 => context[4..].to_vec(),
+                _ => context.to_vec(),
+            };
+            Some((context, data_end + SEPARATOR_SIZE, payload[0] == b'!'))
+        }
+        // RESP3: null, boolean, double, big number
+        b'_' | b'#' | b',' | b'(' => {
+            let separator_pos = find_separator(payload)?;
+            Some((
+                payload[..separator_pos.min(32)].to_vec(),
+                separator_pos + SEPARATOR_SIZE,
+                false,
+            ))
+        }
+        b'+' | b':' | b'-' => {
+            let separator_pos = find_separator(payload)?;
+            let limit = if payload[0] == b'-' { 256 } else { 32 };
+            Some((
+                payload[..separator_pos.min(limit)].to_vec(),
+                separator_pos + SEPARATOR_SIZE,
+                payload[0] == b'-',
+            ))
+        }
+        _ => None,
+    }
+}
+
 // 函数在入参为"$-1"或"-1"时都返回"-1", 使用第三个参数区分是否为错误回复
 pub fn decode(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize, bool)> {
     if payload.len() < SEPARATOR_SIZE {
@@ -403,14 +700,24 @@ pub fn decode(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize, bool)> {
     }
 
     match payload[0] {
-        // 请求或多条批量回复
-        b'*' => decode_asterisk(payload, strict).map(|(v, s)| (v, s, false)),
-        // 状态回复,整数回复
-        b'+' | b':' => decode_str(payload, 32).map(|(v, s)| (v.to_vec(), s, false)),
+        // 请求或多条批量回复, 以及 RESP3 的 map, set, push 和 attribute
+        // request or multi bulk reply, and map, set, push and attribute of RESP3
+        b'*' | b'%' | b'~' | b'>' | b'|' => {
+            decode_asterisk(payload, strict).map(|(v, s)| (v, s, false))
+        }
+        // 状态回复,整数回复, 以及 RESP3 的 null, boolean, double 和 big number
+        // status reply, integer reply, and null, boolean, double and big number of RESP3
+        b'+' | b':' | b'_' | b'#' | b',' | b'(' => {
+            decode_str(payload, 32).map(|(v, s)| (v.to_vec(), s, false))
+        }
         // 错误回复
         b'-' => decode_str(payload, 256).map(|(v, s)| (v.to_vec(), s, true)),
-        // 批量回复
-        b'$' => decode_dollor(payload, strict).map(|(v, s)| (v.to_vec(), s, false)),
+        // 批量回复, 以及 RESP3 的 verbatim string
+        // bulk reply, and verbatim string of RESP3
+        b'$' | b'=' => decode_dollor(payload, strict).map(|(v, s)| (v.to_vec(), s, false)),
+        // RESP3 的 blob error
+        // blob error of RESP3
+        b'!' => decode_dollor(payload, strict).map(|(v, s)| ([b"-", v].concat(), s, true)),
         _ => None,
     }
 }
@@ -545,6 +852,179 @@ mod tests {
         assert_eq!(e, true);
     }
 
+    #[test]
+    fn check_redirect() {
+        let mut redis = RedisLog::default();
+        redis.perf_stats = Some(L7PerfStats::default());
+        let payload = b"-MOVED 3999 127.0.0.1:6381\r\n";
+        redis
+            .parse(payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(redis.infos[0].resp_status, L7ResponseStatus::Ok);
+        assert_eq!(redis.infos[0].redirect, b"127.0.0.1:6381");
+        assert_eq!(redis.infos[0].response, b"-MOVED 3999 127.0.0.1:6381");
+        assert!(redis.infos[0].error.is_empty());
+
+        let mut redis = RedisLog::default();
+        redis.perf_stats = Some(L7PerfStats::default());
+        let payload = b"-ASK 3999 127.0.0.1:6381\r\n";
+        redis
+            .parse(payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(redis.infos[0].resp_status, L7ResponseStatus::Ok);
+        assert_eq!(redis.infos[0].redirect, b"127.0.0.1:6381");
+        assert_eq!(redis.perf_stats.unwrap().err_server_count, 0);
+
+        let mut redis = RedisLog::default();
+        redis.perf_stats = Some(L7PerfStats::default());
+        let payload = b"-MOVED to somewhere\r\n";
+        redis
+            .parse(payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(redis.infos[0].resp_status, L7ResponseStatus::ServerError);
+        assert!(redis.infos[0].redirect.is_empty());
+    }
+
+    #[test]
+    fn check_pipeline() {
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("redis-pipeline.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let mut redis = RedisLog::default();
+
+        let mut output = String::new();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = &ParseParam::from((packet as &MetaPacket, log_cache.clone(), false));
+            for info in redis.parse_payload(payload, param).unwrap() {
+                if let L7ProtocolInfo::RedisInfo(info) = info {
+                    output.push_str(&format!("{}\r\n", info));
+                }
+            }
+            // 与 FlowLog 一致, 每个报文解析后调用 trait 的 reset
+            // call reset of the trait after each payload, same as FlowLog
+            L7ProtocolParserInterface::reset(&mut redis);
+        }
+
+        let expected =
+            fs::read_to_string(Path::new(FILE_DIR).join("redis-pipeline.result")).unwrap();
+        assert_eq!(output, expected);
+        let perf_stats = redis.perf_stats.unwrap();
+        assert_eq!(perf_stats.request_count, 4);
+        assert_eq!(perf_stats.response_count, 4);
+    }
+
+    #[test]
+    fn test_decode_complete() {
+        let payload = b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n*1\r\n";
+        let (context, n, _) = decode_complete(payload).unwrap();
+        assert_eq!(context, b"GET a");
+        assert_eq!(n, 20);
+        assert_eq!(decode_complete(&payload[n..]), None);
+
+        let payload = b"*2\r\n+OK\r\n:1\r\n";
+        let (context, n, _) = decode_complete(payload).unwrap();
+        assert_eq!(context, b"+OK :1");
+        assert_eq!(n, payload.len());
+
+        let payload = b"$0\r\n\r\n-ERR\r\n";
+        let (context, n, _) = decode_complete(payload).unwrap();
+        assert_eq!(context, b"0");
+        assert_eq!(n, 6);
+        let (context, n, e) = decode_complete(&payload[6..]).unwrap();
+        assert_eq!(context, b"-ERR");
+        assert_eq!(n, 6);
+        assert_eq!(e, true);
+
+        assert_eq!(decode_complete(b"$3\r\nGE"), None);
+        assert_eq!(decode_complete(b"+OK"), None);
+
+        // RESP3
+        let payload = b"%1\r\n+a\r\n,3.14\r\n";
+        let (context, n, _) = decode_complete(payload).unwrap();
+        assert_eq!(context, b"+a ,3.14");
+        assert_eq!(n, payload.len());
+
+        let payload = b"|1\r\n+ttl\r\n:3600\r\n~2\r\n#t\r\n_\r\n";
+        let (context, n, _) = decode_complete(payload).unwrap();
+        assert_eq!(context, b"#t _");
+        assert_eq!(n, payload.len());
+
+        let payload = b"=8\r\ntxt:Some\r\n";
+        let (context, n, _) = decode_complete(payload).unwrap();
+        assert_eq!(context, b"Some");
+        assert_eq!(n, payload.len());
+
+        let payload = b"!9\r\nERR error\r\n";
+        let (context, n, e) = decode_complete(payload).unwrap();
+        assert_eq!(context, b"-ERR error");
+        assert_eq!(n, payload.len());
+        assert_eq!(e, true);
+
+        assert_eq!(decode_complete(b"%1\r\n+a\r\n"), None);
+    }
+
+    #[test]
+    fn check_resp3() {
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("redis-resp3.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let mut redis = RedisLog::default();
+
+        let mut infos = vec![];
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = &ParseParam::from((packet as &MetaPacket, log_cache.clone(), false));
+            for info in redis.parse_payload(payload, param).unwrap() {
+                if let L7ProtocolInfo::RedisInfo(info) = info {
+                    infos.push(info);
+                }
+            }
+            L7ProtocolParserInterface::reset(&mut redis);
+        }
+
+        let expected = [
+            (LogMessageType::Request, "HELLO 3", "", 0),
+            (
+                LogMessageType::Response,
+                "",
+                "server redis version 7.2.4 proto :3 id :5 mode standalone role master modules 0",
+                3,
+            ),
+            (LogMessageType::Request, "HGETALL h", "", 3),
+            (LogMessageType::Response, "", "f1 v1 f2 v2", 3),
+            (LogMessageType::Request, "SUBSCRIBE chan", "", 3),
+            (LogMessageType::Session, "", "subscribe chan :1", 3),
+            (LogMessageType::Session, "", "message chan hello", 3),
+        ];
+        assert_eq!(infos.len(), expected.len());
+        for (info, (msg_type, request, response, version)) in infos.iter().zip(expected) {
+            assert_eq!(info.msg_type, msg_type);
+            assert_eq!(info.request, request.as_bytes());
+            assert_eq!(info.response, response.as_bytes());
+            assert_eq!(info.protocol_version, version);
+        }
+        let perf_stats = redis.perf_stats.unwrap();
+        assert_eq!(perf_stats.request_count, 3);
+        assert_eq!(perf_stats.response_count, 2);
+    }
+
     #[test]
     fn check_perf() {
         let expected = vec![
@@ -594,6 +1074,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_json_binary() {
+        let info = RedisInfo {
+            request: b"GET key".to_vec(),
+            response: vec![0xff, 0xfe, b'a'],
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["request_resource"], "GET key");
+        // 非 UTF-8 的字段按 base64 编码
+        // non UTF-8 field is base64 encoded
+        assert_eq!(json["response_result"], "//5h");
+    }
+
     fn run_perf(pcap: &str) -> L7PerfStats {
         let rrt_cache = Rc::new(RefCell::new(L7PerfCache::new(100)));
         let mut redis = RedisLog::default();
@@ -120,14 +120,40 @@ const MYSQL_START: [&'static str; 14] = [
     "COMMIT", "ROLLBACK", "DESC",
 ];
 
-pub(super) fn is_mysql(sql: &String) -> bool {
-    if let Some(first) = trim_head_comment_and_first_upper(sql.as_str(), 8) {
+pub(super) fn is_mysql(sql: &str) -> bool {
+    if let Some(first) = trim_head_comment_and_first_upper(sql, 8) {
         check_sql(first, &MYSQL_START)
     } else {
         false
     }
 }
 
+// not all of oracle sql start first keyword. only log some necessary sql.
+const ORACLE_START: [&'static str; 9] = [
+    "BEGIN", "DECLARE", "WITH", "MERGE", "COMMIT", "ROLLBACK", "TRUNCATE", "LOCK", "RENAME",
+];
+
+pub(super) fn is_oracle(sql: &str) -> bool {
+    if let Some(first) = trim_head_comment_and_first_upper(sql, 9) {
+        check_sql(first, &ORACLE_START)
+    } else {
+        false
+    }
+}
+
+// not all of sql server sql start first keyword. only log some necessary sql.
+const MSSQL_START: [&'static str; 10] = [
+    "EXEC", "EXECUTE", "WITH", "MERGE", "BEGIN", "COMMIT", "ROLLBACK", "USE", "TRUNCATE", "BULK",
+];
+
+pub(super) fn is_mssql(sql: &str) -> bool {
+    if let Some(first) = trim_head_comment_and_first_upper(sql, 8) {
+        check_sql(first, &MSSQL_START)
+    } else {
+        false
+    }
+}
+
 /*
     strip the sql comment from head and return first word and upper it.
     sql comment include:
@@ -141,7 +167,22 @@ pub(super) fn is_mysql(sql: &String) -> bool {
 
         reference: https://dev.mysql.com/doc/refman/5.6/en/comments.html
 */
-fn trim_head_comment_and_first_upper(mut sql: &str, first_word_max_len: usize) -> Option<String> {
+fn trim_head_comment_and_first_upper(sql: &str, first_word_max_len: usize) -> Option<String> {
+    let sql = trim_head_comment(sql)?;
+    if let Some(idx) = sql.find(|c: char| !c.is_alphabetic()) {
+        if idx <= first_word_max_len && idx != 0 {
+            let (sub_sql, _) = sql.split_at(idx);
+            return Some(sub_sql.to_ascii_uppercase());
+        }
+    } else if sql.len() <= first_word_max_len {
+        // if not have word boundary, assume as single word
+        return Some(sql.to_ascii_uppercase());
+    }
+    None
+}
+
+// strip the sql comment from head, return None if comment is not closed
+fn trim_head_comment(mut sql: &str) -> Option<&str> {
     sql = sql.trim_start();
     // if start with /*, strip all comment block before sql string.
     while sql.starts_with("/*") {
@@ -160,22 +201,318 @@ fn trim_head_comment_and_first_upper(mut sql: &str, first_word_max_len: usize) -
             return None;
         }
     }
+    Some(sql)
+}
 
-    if let Some(idx) = sql.find(|c: char| !c.is_alphabetic()) {
-        if idx <= first_word_max_len && idx != 0 {
-            let (sub_sql, _) = sql.split_at(idx);
-            return Some(sub_sql.to_ascii_uppercase());
+// USE 语句切换到的数据库
+// database switched to by USE statement
+pub(super) fn use_database(sql: &str) -> Option<&str> {
+    let sql = trim_head_comment(sql)?;
+    let (keyword, rest) = (sql.get(..3)?, &sql[3..]);
+    if !keyword.eq_ignore_ascii_case("USE") || !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let database = match rest.strip_prefix('`') {
+        Some(quoted) => &quoted[..quoted.find('`')?],
+        None => rest
+            .split(|c: char| c == ';' || c.is_whitespace())
+            .next()
+            .unwrap_or_default(),
+    };
+    (!database.is_empty()).then_some(database)
+}
+
+// 开始或结束事务的语句: BEGIN, START TRANSACTION, COMMIT, ROLLBACK
+// statement starting or ending a transaction: BEGIN, START TRANSACTION, COMMIT, ROLLBACK
+pub(super) fn is_transaction_control(sql: &str) -> bool {
+    let Some(sql) = trim_head_comment(sql) else {
+        return false;
+    };
+    let mut words = sql
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|w| !w.is_empty());
+    match words.next() {
+        Some(w) if w.eq_ignore_ascii_case("START") => words
+            .next()
+            .map_or(false, |w| w.eq_ignore_ascii_case("TRANSACTION")),
+        Some(w) => ["BEGIN", "COMMIT", "ROLLBACK"]
+            .iter()
+            .any(|k| w.eq_ignore_ascii_case(k)),
+        None => false,
+    }
+}
+
+/*
+    stored procedure invoked by CALL statement, return its name and number of arguments.
+    only the prefix and parentheses are checked without parsing the sql, for example
+    `CALL db.proc(1, 'a,b', f(2))` returns ("db.proc", 3).
+*/
+pub(super) fn procedure_call(sql: &str) -> Option<(&str, u32)> {
+    let sql = trim_head_comment(sql)?;
+    let (keyword, rest) = (sql.get(..4)?, &sql[4..]);
+    if !keyword.eq_ignore_ascii_case("CALL") || !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let end = rest
+        .find(|c: char| c == '(' || c == ';' || c.is_whitespace())
+        .unwrap_or(rest.len());
+    let (name, arguments) = rest.split_at(end);
+    if name.is_empty() {
+        return None;
+    }
+    Some((name, count_arguments(arguments.trim_start())))
+}
+
+// bytes[start] 为引号, 返回匹配的引号的位置, 找不到时为 bytes 的长度,
+// 连续两个引号表示引号本身, 反引号中没有转义字符
+// bytes[start] is a quote, return position of the matching quote or length of bytes if not found,
+// two consecutive quotes stand for the quote itself, no escape character in backquotes
+pub(super) fn quoted_end(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if quote != b'`' => i += 1,
+            c if c == quote && bytes.get(i + 1) == Some(&quote) => i += 1,
+            c if c == quote => return i,
+            _ => (),
         }
-    } else if sql.len() <= first_word_max_len {
-        // if not have word boundary, assume as single word
-        return Some(sql.to_ascii_uppercase());
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn is_identifier_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || !c.is_ascii()
+}
+
+/*
+    把字符串和数字字面量 (包括 X'..', B'..', N'..', 0x.. 和 0b..) 替换为 ?, 保留 SQL 的结构,
+    标识符和注释保持不变, 可执行注释 /*! */ 中的内容按 SQL 处理. 例如
+    `SELECT * FROM t1 WHERE name = 'a\'b' AND id IN (1, 0x1F, X'0A')` 替换为
+    `SELECT * FROM t1 WHERE name = ? AND id IN (?, ?, ?)`.
+
+    replace string and numeric literals (X'..', B'..', N'..', 0x.. and 0b.. included) with ?,
+    structure of the sql is kept, identifiers and comments are unchanged, and contents of executable
+    comments /*! */ are handled as sql. for example
+    `SELECT * FROM t1 WHERE name = 'a\'b' AND id IN (1, 0x1F, X'0A')` is replaced with
+    `SELECT * FROM t1 WHERE name = ? AND id IN (?, ?, ?)`.
+*/
+pub(super) fn mask_literals(sql: &str) -> String {
+    let bytes = sql.as_bytes();
+    let skip_to = |from: usize, end: &[u8]| {
+        bytes[from.min(bytes.len())..]
+            .windows(end.len())
+            .position(|w| w == end)
+            .map_or(bytes.len(), |p| from + p + end.len())
+    };
+    let skip_while = |from: usize, f: fn(u8) -> bool| {
+        bytes[from..]
+            .iter()
+            .position(|&c| !f(c))
+            .map_or(bytes.len(), |p| from + p)
+    };
+
+    let mut masked = String::with_capacity(sql.len());
+    let (mut copied, mut i) = (0, 0);
+    while i < bytes.len() {
+        let next = bytes.get(i + 1).copied();
+        let end = match bytes[i] {
+            b'\'' | b'"' => quoted_end(bytes, i) + 1,
+            b'x' | b'X' | b'b' | b'B' | b'n' | b'N' if next == Some(b'\'') => {
+                quoted_end(bytes, i + 1) + 1
+            }
+            b'0' if matches!(next, Some(b'x' | b'X' | b'b' | b'B')) => {
+                skip_while(i + 2, |c| c.is_ascii_alphanumeric())
+            }
+            c if c.is_ascii_digit()
+                || c == b'.' && matches!(next, Some(c) if c.is_ascii_digit()) =>
+            {
+                let mut end = skip_while(i + 1, |c| c.is_ascii_digit() || c == b'.');
+                if matches!(bytes.get(end), Some(b'e' | b'E')) {
+                    let exponent = match bytes.get(end + 1) {
+                        Some(b'+' | b'-') => end + 2,
+                        _ => end + 1,
+                    };
+                    if matches!(bytes.get(exponent), Some(c) if c.is_ascii_digit()) {
+                        end = skip_while(exponent, |c| c.is_ascii_digit());
+                    }
+                }
+                // 以数字开头的标识符
+                // identifier starting with digits
+                if matches!(bytes.get(end), Some(&c) if is_identifier_byte(c)) {
+                    i = skip_while(end, is_identifier_byte);
+                    continue;
+                }
+                end
+            }
+            b'`' => {
+                i = quoted_end(bytes, i) + 1;
+                continue;
+            }
+            b'/' if next == Some(b'*') && bytes.get(i + 2) == Some(&b'!') => {
+                i = skip_while(i + 3, |c| c.is_ascii_digit());
+                continue;
+            }
+            b'/' if next == Some(b'*') => {
+                i = skip_to(i + 2, b"*/");
+                continue;
+            }
+            b'#' => {
+                i = skip_to(i + 1, b"\n");
+                continue;
+            }
+            b'-' if next == Some(b'-')
+                && !matches!(bytes.get(i + 2), Some(c) if !c.is_ascii_whitespace()) =>
+            {
+                i = skip_to(i + 2, b"\n");
+                continue;
+            }
+            c if is_identifier_byte(c) => {
+                i = skip_while(i, is_identifier_byte);
+                continue;
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        masked.push_str(&sql[copied..i]);
+        masked.push('?');
+        copied = end.min(bytes.len());
+        i = copied;
+    }
+    masked.push_str(&sql[copied..]);
+    masked
+}
+
+// count top-level arguments in parentheses, string literals and nested parentheses are skipped
+fn count_arguments(arguments: &str) -> u32 {
+    let Some(arguments) = arguments.strip_prefix('(') else {
+        return 0;
+    };
+    let bytes = arguments.as_bytes();
+    let (mut depth, mut count, mut empty) = (0, 1, true);
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' | b'`' => {
+                empty = false;
+                i = quoted_end(bytes, i);
+            }
+            b')' if depth == 0 => break,
+            b'(' => {
+                empty = false;
+                depth += 1;
+            }
+            b')' => depth -= 1,
+            b',' if depth == 0 => count += 1,
+            c if c.is_ascii_whitespace() => (),
+            _ => empty = false,
+        }
+        i += 1;
+    }
+    if empty {
+        0
+    } else {
+        count
     }
-    None
 }
 
 #[cfg(test)]
 mod test_sql_check {
-    use crate::flow_generator::protocol_logs::sql::sql_check::trim_head_comment_and_first_upper;
+    use crate::flow_generator::protocol_logs::sql::sql_check::{
+        is_transaction_control, mask_literals, procedure_call, trim_head_comment_and_first_upper,
+        use_database,
+    };
+
+    #[test]
+    fn test_mask_literals() {
+        let cases = [
+            (
+                "SELECT * FROM users WHERE name = 'admin' AND password = 'p@ss''w\\'rd'",
+                "SELECT * FROM users WHERE name = ? AND password = ?",
+            ),
+            (
+                "SELECT * FROM t1 WHERE a IN (1, -2.5, 1e10, .5E-3) AND b = \"x\\\"y\"",
+                "SELECT * FROM t1 WHERE a IN (?, -?, ?, ?) AND b = ?",
+            ),
+            (
+                "INSERT INTO `t 2` (c1) VALUES (X'0A1b', x'', 0x1F, B'01', 0b10, N'名字')",
+                "INSERT INTO `t 2` (c1) VALUES (?, ?, ?, ?, ?, ?)",
+            ),
+            (
+                "/* user 'a' */ SELECT 1abc, t1.c2 FROM t1 -- id = 1\nWHERE id = ?",
+                "/* user 'a' */ SELECT 1abc, t1.c2 FROM t1 -- id = 1\nWHERE id = ?",
+            ),
+            (
+                "SELECT /*!40001 SQL_NO_CACHE 'a' */ 1 # 'b'",
+                "SELECT /*!40001 SQL_NO_CACHE ? */ ? # 'b'",
+            ),
+            ("SELECT 'unterminated", "SELECT ?"),
+            ("SELECT '\\", "SELECT ?"),
+            ("", ""),
+        ];
+        for (sql, masked) in cases {
+            assert_eq!(mask_literals(sql), masked, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn test_use_database() {
+        assert_eq!(use_database("USE db1"), Some("db1"));
+        assert_eq!(use_database("use `my db`;"), Some("my db"));
+        assert_eq!(
+            use_database("/* c */ Use information_schema ;"),
+            Some("information_schema")
+        );
+        assert_eq!(use_database("USE"), None);
+        assert_eq!(use_database("USE `db"), None);
+        assert_eq!(use_database("USER"), None);
+        assert_eq!(use_database("SELECT 1"), None);
+    }
+
+    #[test]
+    fn test_is_transaction_control() {
+        for sql in [
+            "BEGIN",
+            "begin work",
+            "START TRANSACTION READ ONLY",
+            "/* c */ COMMIT;",
+            "rollback",
+            "ROLLBACK TO SAVEPOINT s1",
+        ] {
+            assert!(is_transaction_control(sql), "{}", sql);
+        }
+        for sql in [
+            "",
+            "SELECT 1",
+            "START SLAVE",
+            "COMMITTED",
+            "SET autocommit=0",
+            "/* COMMIT",
+        ] {
+            assert!(!is_transaction_control(sql), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn test_procedure_call() {
+        assert_eq!(procedure_call("CALL p"), Some(("p", 0)));
+        assert_eq!(procedure_call("call p();"), Some(("p", 0)));
+        assert_eq!(procedure_call("CALL p (?, ?)"), Some(("p", 2)));
+        assert_eq!(
+            procedure_call("/* pool */ Call db.proc(1, 'a,b', f(2, 3))"),
+            Some(("db.proc", 3))
+        );
+        assert_eq!(procedure_call("CALL `p`('it\\'s, (')"), Some(("`p`", 1)));
+        assert_eq!(procedure_call("SELECT 1"), None);
+        assert_eq!(procedure_call("CALLS p()"), None);
+        assert_eq!(procedure_call("CALL "), None);
+        assert_eq!(procedure_call("/* not closed CALL p()"), None);
+    }
 
     #[test]
     fn test_trim_head_comment_and_first_upper() {
@@ -0,0 +1,273 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request};
+use super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{
+            L7ProtocolParserInterface, ParseParam, CHECK_SCORE_LOW, CHECK_SCORE_NONE,
+        },
+    },
+    flow_generator::error::{Error, Result},
+};
+
+// 参考：https://github.com/statsd/statsd/blob/master/docs/metric_types.md
+// <name>:<value>|<type>[|@<sample rate>]
+const METRIC_TYPES: [&str; 5] = ["c", "g", "ms", "h", "s"];
+const METRIC_TYPE_SET: &str = "s";
+const SAMPLE_RATE_PREFIX: char = '@';
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct StatsdInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub name: String,
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub metric_type: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for StatsdInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, _: L7ProtocolInfo) -> Result<()> {
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Statsd,
+            msg_type: self.msg_type,
+            rrt: 0,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<StatsdInfo> for L7ProtocolSendLog {
+    fn from(f: StatsdInfo) -> Self {
+        let mut attributes = vec![KeyVal {
+            key: "value".to_string(),
+            val: f.value,
+        }];
+        if let Some(rate) = f.sample_rate {
+            attributes.push(KeyVal {
+                key: "sample_rate".to_string(),
+                val: rate.to_string(),
+            });
+        }
+        L7ProtocolSendLog {
+            req: L7Request {
+                req_type: f.metric_type,
+                resource: f.name.clone(),
+                endpoint: f.name,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes: Some(attributes),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StatsdLog {
+    info: Vec<StatsdInfo>,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for StatsdLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Udp {
+            return false;
+        }
+        let ok = self.parse(payload, param.l4_protocol).is_ok();
+        self.reset();
+        ok
+    }
+
+    // 只检查文本格式, 其他 UDP 文本协议也可能匹配
+    // only text format is checked, other UDP text protocols may match too
+    fn check_payload_score(&mut self, payload: &[u8], param: &ParseParam) -> u8 {
+        if self.check_payload(payload, param) {
+            CHECK_SCORE_LOW
+        } else {
+            CHECK_SCORE_NONE
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.parse(payload, param.l4_protocol)?;
+        // 指标只有单向的上报, 不计算请求/响应和时延
+        // metrics are reported one-way, no request/response or rrt is counted
+        Ok(self
+            .info
+            .drain(..)
+            .map(|mut info| {
+                info.is_tls = param.is_tls();
+                L7ProtocolInfo::StatsdInfo(info)
+            })
+            .collect())
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Statsd
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[8125]
+    }
+
+    fn parsable_on_tcp(&self) -> bool {
+        false
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) {
+        self.info.clear();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl StatsdLog {
+    fn parse(&mut self, payload: &[u8], proto: IpProtocol) -> Result<()> {
+        if proto != IpProtocol::Udp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.info.clear();
+
+        // 一个报文中可能有多行指标, 每行一个
+        // one payload may carry many metrics, one per line
+        let payload = std::str::from_utf8(payload).map_err(|_| Error::StatsdLogParseFailed)?;
+        for line in payload.split('\n') {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+            let info = parse_line(line).ok_or(Error::StatsdLogParseFailed)?;
+            self.info.push(info);
+        }
+
+        if self.info.is_empty() {
+            return Err(Error::StatsdLogParseFailed);
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Option<StatsdInfo> {
+    let (name, rest) = line.split_once(':')?;
+    if name.is_empty() || name.contains(|c: char| c.is_ascii_whitespace() || c == '|') {
+        return None;
+    }
+    let mut fields = rest.split('|');
+    let value = fields.next()?;
+    let metric_type = fields.next()?;
+    if value.is_empty() || !METRIC_TYPES.contains(&metric_type) {
+        return None;
+    }
+    // set 的值可以是任意字符串, 其他类型必须是数字, gauge 可以带 +/- 表示增量
+    // value of set can be any string, other types must be numbers, gauge may be signed as a delta
+    if metric_type != METRIC_TYPE_SET && value.parse::<f64>().is_err() {
+        return None;
+    }
+    let mut sample_rate = None;
+    // 其他扩展字段 (如 DogStatsD 的 #tags) 忽略
+    // other extension fields (e.g. #tags of DogStatsD) are ignored
+    for field in fields {
+        if let Some(rate) = field.strip_prefix(SAMPLE_RATE_PREFIX) {
+            sample_rate = Some(rate.parse::<f64>().ok()?);
+        }
+    }
+    Some(StatsdInfo {
+        msg_type: LogMessageType::Session,
+        name: name.to_owned(),
+        metric_type: metric_type.to_owned(),
+        value: value.to_owned(),
+        sample_rate,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::ProtocolTester;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/statsd";
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        ProtocolTester::new(StatsdLog::default())
+            .check(dir.join("statsd.pcap"), dir.join("statsd.result"));
+    }
+
+    #[test]
+    fn check_line() {
+        let info = parse_line("api.latency:320|ms|@0.1|#env:prod").unwrap();
+        assert_eq!(info.name, "api.latency");
+        assert_eq!(info.metric_type, "ms");
+        assert_eq!(info.value, "320");
+        assert_eq!(info.sample_rate, Some(0.1));
+        assert_eq!(info.msg_type, LogMessageType::Session);
+
+        assert_eq!(parse_line("queue.size:-3|g").unwrap().value, "-3");
+        assert_eq!(parse_line("users.unique:alice|s").unwrap().value, "alice");
+
+        assert!(parse_line("api.requests:1").is_none());
+        assert!(parse_line("api.requests:1|x").is_none());
+        assert!(parse_line("api.requests:abc|c").is_none());
+        assert!(parse_line(":1|c").is_none());
+        assert!(parse_line("GET /index.html:1|c").is_none());
+        assert!(parse_line("api.requests:1|c|@fast").is_none());
+    }
+}
@@ -0,0 +1,410 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
+use super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::error::{Error, Result},
+    utils::bytes::{read_u16_be, read_u32_be},
+};
+
+// 参考：https://www.rfc-editor.org/rfc/rfc8446#section-5.1
+// record: content_type(1) legacy_record_version(2) length(2) fragment
+// handshake: msg_type(1) length(3) body
+const RECORD_HEADER_SIZE: usize = 5;
+const HANDSHAKE_HEADER_SIZE: usize = 4;
+const RANDOM_SIZE: usize = 32;
+// 2^14 加上密文扩展的最大长度
+// 2^14 plus the maximum expansion of ciphertext
+const MAX_RECORD_SIZE: usize = (1 << 14) + 2048;
+
+const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const HANDSHAKE_SERVER_HELLO: u8 = 2;
+
+const EXTENSION_SERVER_NAME: u16 = 0;
+const EXTENSION_ALPN: u16 = 16;
+const EXTENSION_SUPPORTED_VERSIONS: u16 = 43;
+
+const SERVER_NAME_TYPE_HOST_NAME: u8 = 0;
+
+const VERSION_SSL_3_0: u16 = 0x0300;
+const VERSION_TLS_1_0: u16 = 0x0301;
+const VERSION_TLS_1_1: u16 = 0x0302;
+const VERSION_TLS_1_2: u16 = 0x0303;
+const VERSION_TLS_1_3: u16 = 0x0304;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct TlsInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    // ClientHello 中为客户端支持的最高版本, ServerHello 中为服务端选择的版本
+    // the highest version supported by client in ClientHello, the version selected in ServerHello
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: u16,
+
+    // request
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub server_name: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub alpn_protocols: Vec<String>,
+
+    // response
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub cipher_suite: u16,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for TlsInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::TlsInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.version = other.version;
+                self.cipher_suite = other.cipher_suite;
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Tls,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl TlsInfo {
+    pub fn version_str(&self) -> &'static str {
+        match self.version {
+            VERSION_SSL_3_0 => "SSL 3.0",
+            VERSION_TLS_1_0 => "TLS 1.0",
+            VERSION_TLS_1_1 => "TLS 1.1",
+            VERSION_TLS_1_2 => "TLS 1.2",
+            VERSION_TLS_1_3 => "TLS 1.3",
+            _ => "",
+        }
+    }
+}
+
+impl From<TlsInfo> for L7ProtocolSendLog {
+    fn from(f: TlsInfo) -> Self {
+        let mut attributes = vec![];
+        if !f.alpn_protocols.is_empty() {
+            attributes.push(KeyVal {
+                key: "alpn_protocols".to_string(),
+                val: f.alpn_protocols.join(","),
+            });
+        }
+        if f.cipher_suite != 0 {
+            attributes.push(KeyVal {
+                key: "cipher_suite".to_string(),
+                val: format!("0x{:04x}", f.cipher_suite),
+            });
+        }
+        L7ProtocolSendLog {
+            version: match f.version_str() {
+                "" => None,
+                v => Some(v.to_owned()),
+            },
+            req: L7Request {
+                domain: f.server_name.clone(),
+                resource: f.server_name,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+fn read_u8(data: &mut &[u8]) -> Option<u8> {
+    let (&v, remain) = data.split_first()?;
+    *data = remain;
+    Some(v)
+}
+
+fn read_u16(data: &mut &[u8]) -> Option<u16> {
+    let v = read_u16_be(data.get(..2)?);
+    *data = &data[2..];
+    Some(v)
+}
+
+fn read_bytes<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let v = data.get(..len)?;
+    *data = &data[len..];
+    Some(v)
+}
+
+// GREASE 值形如 0x?a?a, 不是真实的版本号
+// GREASE values look like 0x?a?a, which are not real versions
+fn is_grease(v: u16) -> bool {
+    v & 0x0f0f == 0x0a0a && v >> 8 == v & 0xff
+}
+
+// 返回记录中第一个握手消息的类型和内容, 内容可能被截断
+// return type and body of the first handshake message in record, body may be truncated
+fn decode_handshake(payload: &[u8]) -> Option<(u8, &[u8])> {
+    if payload.len() < RECORD_HEADER_SIZE + HANDSHAKE_HEADER_SIZE
+        || payload[0] != CONTENT_TYPE_HANDSHAKE
+    {
+        return None;
+    }
+    match read_u16_be(&payload[1..]) {
+        VERSION_SSL_3_0..=VERSION_TLS_1_3 => (),
+        _ => return None,
+    }
+    let record_len = read_u16_be(&payload[3..]) as usize;
+    if record_len < HANDSHAKE_HEADER_SIZE || record_len > MAX_RECORD_SIZE {
+        return None;
+    }
+    let handshake = &payload[RECORD_HEADER_SIZE..];
+    let body_len = (read_u32_be(handshake) & 0xffffff) as usize;
+    let body = &handshake[HANDSHAKE_HEADER_SIZE..];
+    Some((handshake[0], &body[..body_len.min(body.len())]))
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct TlsLog {
+    info: TlsInfo,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for TlsLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        match decode_handshake(payload) {
+            Some((HANDSHAKE_CLIENT_HELLO, body)) => {
+                let mut info = TlsInfo::default();
+                Self::client_hello(&mut info, body).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        // 握手之后的加密记录不解析
+        // encrypted records after handshake are not parsed
+        if !self.parse(payload)? {
+            return Ok(vec![]);
+        }
+        self.info.is_tls = param.is_tls();
+        self.info.cal_rrt(param).map(|rrt| {
+            self.info.rrt = rrt;
+            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+        });
+        Ok(vec![L7ProtocolInfo::TlsInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Tls
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[443]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = TlsInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl TlsLog {
+    // 返回是否为 ClientHello 或 ServerHello
+    // return whether it is ClientHello or ServerHello
+    fn parse(&mut self, payload: &[u8]) -> Result<bool> {
+        let perf_stats = self.perf_stats.as_mut().unwrap();
+        match decode_handshake(payload) {
+            Some((HANDSHAKE_CLIENT_HELLO, body)) => {
+                Self::client_hello(&mut self.info, body).ok_or(Error::TlsLogParseFailed)?;
+                self.info.msg_type = LogMessageType::Request;
+                perf_stats.inc_req();
+            }
+            Some((HANDSHAKE_SERVER_HELLO, body)) => {
+                Self::server_hello(&mut self.info, body).ok_or(Error::TlsLogParseFailed)?;
+                self.info.msg_type = LogMessageType::Response;
+                self.info.status = L7ResponseStatus::Ok;
+                perf_stats.inc_resp();
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // legacy_version(2) random(32) legacy_session_id<0..32> cipher_suites<2..2^16-2>
+    // legacy_compression_methods<1..2^8-1> extensions<8..2^16-1>
+    fn client_hello(info: &mut TlsInfo, mut body: &[u8]) -> Option<()> {
+        info.version = read_u16(&mut body)?;
+        read_bytes(&mut body, RANDOM_SIZE)?;
+        let len = read_u8(&mut body)? as usize;
+        read_bytes(&mut body, len)?;
+        let len = read_u16(&mut body)? as usize;
+        read_bytes(&mut body, len)?;
+        let len = read_u8(&mut body)? as usize;
+        read_bytes(&mut body, len)?;
+        // 扩展可能被截断, 只解析完整的扩展
+        // extensions may be truncated, only complete ones are parsed
+        let Some(_) = read_u16(&mut body) else {
+            return Some(());
+        };
+        while let (Some(ext_type), Some(len)) = (read_u16(&mut body), read_u16(&mut body)) {
+            let Some(mut data) = read_bytes(&mut body, len as usize) else {
+                break;
+            };
+            match ext_type {
+                EXTENSION_SERVER_NAME => {
+                    read_u16(&mut data)?;
+                    while let Some(name_type) = read_u8(&mut data) {
+                        let len = read_u16(&mut data)? as usize;
+                        let name = read_bytes(&mut data, len)?;
+                        if name_type == SERVER_NAME_TYPE_HOST_NAME {
+                            info.server_name = String::from_utf8_lossy(name).into_owned();
+                            break;
+                        }
+                    }
+                }
+                EXTENSION_ALPN => {
+                    read_u16(&mut data)?;
+                    while let Some(len) = read_u8(&mut data) {
+                        let protocol = read_bytes(&mut data, len as usize)?;
+                        info.alpn_protocols
+                            .push(String::from_utf8_lossy(protocol).into_owned());
+                    }
+                }
+                EXTENSION_SUPPORTED_VERSIONS => {
+                    let len = read_u8(&mut data)? as usize;
+                    let mut versions = read_bytes(&mut data, len)?;
+                    while let Some(version) = read_u16(&mut versions) {
+                        if !is_grease(version) && version > info.version {
+                            info.version = version;
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        Some(())
+    }
+
+    // legacy_version(2) random(32) legacy_session_id_echo<0..32> cipher_suite(2)
+    // legacy_compression_method(1) extensions<6..2^16-1>
+    fn server_hello(info: &mut TlsInfo, mut body: &[u8]) -> Option<()> {
+        info.version = read_u16(&mut body)?;
+        read_bytes(&mut body, RANDOM_SIZE)?;
+        let len = read_u8(&mut body)? as usize;
+        read_bytes(&mut body, len)?;
+        info.cipher_suite = read_u16(&mut body)?;
+        read_u8(&mut body)?;
+        let Some(_) = read_u16(&mut body) else {
+            return Some(());
+        };
+        while let (Some(ext_type), Some(len)) = (read_u16(&mut body), read_u16(&mut body)) {
+            let Some(mut data) = read_bytes(&mut body, len as usize) else {
+                break;
+            };
+            if ext_type == EXTENSION_SUPPORTED_VERSIONS {
+                info.version = read_u16(&mut data)?;
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::ProtocolTester;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/tls";
+
+    fn tester() -> ProtocolTester<TlsLog> {
+        ProtocolTester::new(TlsLog::default()).step(|tls, payload, param| {
+            let is_tls = tls.check_payload(payload, param);
+            let _ = tls.parse_payload(payload, param);
+            tls.info.rrt = 0;
+            format!("{:?} is_tls: {}\r\n", tls.info, is_tls)
+        })
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(dir.join("tls.pcap"), dir.join("tls.result"));
+    }
+
+    #[test]
+    fn check_grease() {
+        assert!(is_grease(0x0a0a));
+        assert!(is_grease(0xfafa));
+        assert!(!is_grease(0x0a1a));
+        assert!(!is_grease(VERSION_TLS_1_3));
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog};
+use super::{AppProtoHead, LogMessageType};
+use crate::{
+    common::{
+        flow::{L7PerfStats, L7Protocol},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::error::{Error, Result},
+};
+
+// 只记录载荷的前 PREVIEW_LEN 字节
+// only the first PREVIEW_LEN bytes of payload are recorded
+const PREVIEW_LEN: usize = 32;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct UnknownInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    pub port_src: u16,
+    pub port_dst: u16,
+    pub payload_len: u32,
+    // 十六进制编码
+    // hex encoded
+    pub preview: String,
+}
+
+impl L7ProtocolInfoInterface for UnknownInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, _: L7ProtocolInfo) -> Result<()> {
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Unrecognized,
+            msg_type: self.msg_type,
+            rrt: 0,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl From<UnknownInfo> for L7ProtocolSendLog {
+    fn from(f: UnknownInfo) -> Self {
+        // 端口已在流日志中, 不重复上报
+        // ports are already in the flow log, not reported again
+        L7ProtocolSendLog {
+            req_len: Some(f.payload_len),
+            ext_info: Some(ExtendedInfo {
+                attributes: Some(vec![KeyVal {
+                    key: "payload_preview".to_string(),
+                    val: f.preview,
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+// 其他协议都识别失败时才使用的兜底解析器, 不会锁定流的协议, 仅在 l7-protocol-enabled 中配置时开启
+// fallback parser used only when all other protocols fail to check, it never locks the protocol of
+// the flow and is enabled only when configured in l7-protocol-enabled
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct UnknownLog {
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for UnknownLog {
+    fn check_payload(&mut self, payload: &[u8], _: &ParseParam) -> bool {
+        !payload.is_empty()
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if payload.is_empty() {
+            return Err(Error::UnknownLogParseFailed);
+        }
+        // 没有性能统计, 仅在需要日志时上报
+        // no perf stats, reported only when log is required
+        if param.perf_only {
+            return Ok(vec![]);
+        }
+        let preview = &payload[..payload.len().min(PREVIEW_LEN)];
+        Ok(vec![L7ProtocolInfo::UnknownInfo(UnknownInfo {
+            msg_type: LogMessageType::Session,
+            is_tls: param.is_tls(),
+            port_src: param.port_src,
+            port_dst: param.port_dst,
+            payload_len: payload.len() as u32,
+            preview: preview
+                .iter()
+                .fold(String::new(), |s, c| s + &format!("{:02x}", c)),
+        })])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Unrecognized
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        true
+    }
+
+    fn parse_default(&self) -> bool {
+        false
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::rc::Rc;
+
+    use super::*;
+
+    use crate::common::{
+        ebpf::EbpfType,
+        enums::IpProtocol,
+        flow::PacketDirection,
+        l7_protocol_log::{L7PerfCache, L7ProtocolParser},
+    };
+    use public::utils::net::MacAddr;
+
+    #[test]
+    fn check_preview() {
+        let param = ParseParam {
+            l4_protocol: IpProtocol::Tcp,
+            ip_src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            ip_dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            port_src: 40000,
+            port_dst: 7777,
+            flow_id: 0,
+            vlan: None,
+            src_mac: MacAddr::ZERO,
+            dst_mac: MacAddr::ZERO,
+            direction: PacketDirection::ClientToServer,
+            ebpf_type: EbpfType::None,
+            ebpf_param: None,
+            packet_seq: 0,
+            tcp_seq: 0,
+            is_retransmission: false,
+            time: 0,
+            perf_only: false,
+            parse_config: None,
+            l7_perf_cache: Rc::new(RefCell::new(L7PerfCache::new(1))),
+        };
+        let mut log = UnknownLog::default();
+        assert!(!log.check_payload(&[], &param));
+
+        let payload = (0..64u8).collect::<Vec<_>>();
+        assert!(log.check_payload(&payload, &param));
+        let infos = log.parse_payload(&payload, &param).unwrap();
+        let L7ProtocolInfo::UnknownInfo(info) = &infos[0] else {
+            unreachable!();
+        };
+        assert_eq!(info.port_src, 40000);
+        assert_eq!(info.port_dst, 7777);
+        assert_eq!(info.payload_len, 64);
+        assert_eq!(info.preview.len(), PREVIEW_LEN * 2);
+        assert!(info.preview.starts_with("000102030405"));
+
+        let infos = log.parse_payload(b"\xab\xcd", &param).unwrap();
+        let L7ProtocolInfo::UnknownInfo(info) = &infos[0] else {
+            unreachable!();
+        };
+        assert_eq!(info.preview, "abcd");
+    }
+
+    #[test]
+    fn check_disabled_by_default() {
+        let parser = L7ProtocolParser::Unrecognized(Default::default());
+        assert!(!parser.parse_default());
+    }
+}
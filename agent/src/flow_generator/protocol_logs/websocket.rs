@@ -0,0 +1,468 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
+use super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::error::{Error, Result},
+    utils::bytes::{read_u16_be, read_u64_be},
+};
+
+// 参考：https://www.rfc-editor.org/rfc/rfc6455#section-5.2
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-------+-+-------------+-------------------------------+
+// |F|R|R|R| opcode|M| Payload len |    Extended payload length    |
+// |I|S|S|S|  (4)  |A|     (7)     |             (16/64)           |
+// |N|V|V|V|       |S|             |   (if payload len==126/127)   |
+// | |1|2|3|       |K|             |                               |
+// +-+-+-+-+-------+-+-------------+ - - - - - - - - - - - - - - - +
+// |     Extended payload length continued, if payload len == 127  |
+// + - - - - - - - - - - - - - - - +-------------------------------+
+// |                               |Masking-key, if MASK set to 1  |
+// +-------------------------------+-------------------------------+
+// | Masking-key (continued)       |          Payload Data         |
+// +-------------------------------- - - - - - - - - - - - - - - - +
+const HEADER_SIZE: usize = 2;
+const MASKING_KEY_SIZE: usize = 4;
+const PAYLOAD_LEN_16BIT: u8 = 126;
+const PAYLOAD_LEN_64BIT: u8 = 127;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+// close code
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_NO_STATUS: u16 = 1005;
+const CLOSE_INTERNAL_ERROR: u16 = 1011;
+const CLOSE_TRY_AGAIN_LATER: u16 = 1013;
+const CLOSE_BAD_GATEWAY: u16 = 1014;
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct WebsocketInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(skip)]
+    pub fin: bool,
+    #[serde(rename = "request_type")]
+    pub opcode: u8,
+
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_length: Option<u64>,
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_length: Option<u64>,
+    // payload 中 FIN 置位的帧数, 即结束的消息数
+    // number of frames with FIN set in payload, which is the number of finished messages
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub message_count: u32,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub close_code: Option<u16>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub close_reason: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for WebsocketInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::WebsocketInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.resp_length = other.resp_length;
+                self.message_count += other.message_count;
+                if other.close_code.is_some() {
+                    self.close_code = other.close_code;
+                    self.close_reason = other.close_reason;
+                }
+                self.status = other.status;
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Websocket,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl WebsocketInfo {
+    pub fn opcode_str(&self) -> &'static str {
+        match self.opcode {
+            OPCODE_CONTINUATION => "Continuation",
+            OPCODE_TEXT => "Text",
+            OPCODE_BINARY => "Binary",
+            OPCODE_CLOSE => "Close",
+            OPCODE_PING => "Ping",
+            OPCODE_PONG => "Pong",
+            _ => "",
+        }
+    }
+}
+
+impl From<WebsocketInfo> for L7ProtocolSendLog {
+    fn from(f: WebsocketInfo) -> Self {
+        let ext_info = if f.message_count > 0 {
+            Some(ExtendedInfo {
+                attributes: Some(vec![KeyVal {
+                    key: "message_count".to_string(),
+                    val: f.message_count.to_string(),
+                }]),
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+        L7ProtocolSendLog {
+            req_len: f.req_length.map(|l| l.min(u32::MAX as u64) as u32),
+            resp_len: f.resp_length.map(|l| l.min(u32::MAX as u64) as u32),
+            req: L7Request {
+                req_type: f.opcode_str().to_owned(),
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.close_code.map(|c| c as i32),
+                exception: f.close_reason,
+                ..Default::default()
+            },
+            ext_info,
+            ..Default::default()
+        }
+    }
+}
+
+struct Frame<'a> {
+    fin: bool,
+    opcode: u8,
+    masking_key: Option<[u8; MASKING_KEY_SIZE]>,
+    payload_length: u64,
+    // 包括头部的帧长度
+    // length of frame including header
+    length: u64,
+    // 帧的载荷, 可能被截断
+    // payload of frame, may be truncated
+    payload: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    fn decode(payload: &'a [u8]) -> Option<Self> {
+        if payload.len() < HEADER_SIZE {
+            return None;
+        }
+        let opcode = payload[0] & 0xf;
+        match opcode {
+            OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY => (),
+            // 控制帧不能分片, 载荷不超过 125 字节
+            // control frames must not be fragmented and payload length is at most 125
+            OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG
+                if payload[0] & 0x80 != 0 && payload[1] & 0x7f < PAYLOAD_LEN_16BIT => {}
+            _ => return None,
+        }
+        let mut offset = HEADER_SIZE;
+        let payload_length = match payload[1] & 0x7f {
+            PAYLOAD_LEN_16BIT => {
+                offset += 2;
+                read_u16_be(payload.get(HEADER_SIZE..offset)?) as u64
+            }
+            // 64 位长度的最高位必须为 0
+            // the most significant bit of 64-bit length must be 0
+            PAYLOAD_LEN_64BIT => {
+                offset += 8;
+                let len = read_u64_be(payload.get(HEADER_SIZE..offset)?);
+                if len & (1 << 63) != 0 {
+                    return None;
+                }
+                len
+            }
+            len => len as u64,
+        };
+        let masking_key = if payload[1] & 0x80 != 0 {
+            let key = payload.get(offset..offset + MASKING_KEY_SIZE)?;
+            offset += MASKING_KEY_SIZE;
+            Some([key[0], key[1], key[2], key[3]])
+        } else {
+            None
+        };
+        let end = payload_length.min((payload.len() - offset) as u64) as usize;
+        Some(Frame {
+            fin: payload[0] & 0x80 != 0,
+            opcode,
+            masking_key,
+            payload_length,
+            length: (offset as u64).checked_add(payload_length)?,
+            payload: &payload[offset..offset + end],
+        })
+    }
+
+    fn unmask(&self) -> Vec<u8> {
+        match self.masking_key {
+            Some(key) => self
+                .payload
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b ^ key[i % MASKING_KEY_SIZE])
+                .collect(),
+            None => self.payload.to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct WebsocketLog {
+    info: WebsocketInfo,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+}
+
+impl L7ProtocolParserInterface for WebsocketLog {
+    // WebSocket 帧没有明显特征, 只由 HTTP 升级交给 WebSocket 解析, 不参与协议识别
+    // WebSocket frames have no obvious feature, so they are only parsed after HTTP upgrade
+    // and not involved in protocol inference
+    fn check_payload(&mut self, _: &[u8], _: &ParseParam) -> bool {
+        false
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.info.is_tls = param.is_tls();
+        self.parse(payload, param.direction)?;
+        self.info.cal_rrt(param).map(|rrt| {
+            self.info.rrt = rrt;
+            self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+        });
+        Ok(vec![L7ProtocolInfo::WebsocketInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Websocket
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = WebsocketInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl WebsocketLog {
+    fn set_status(&mut self, close_code: u16) {
+        let perf_stats = self.perf_stats.as_mut().unwrap();
+        self.info.status = match close_code {
+            CLOSE_NORMAL | CLOSE_GOING_AWAY | CLOSE_NO_STATUS => L7ResponseStatus::Ok,
+            CLOSE_INTERNAL_ERROR | CLOSE_TRY_AGAIN_LATER | CLOSE_BAD_GATEWAY => {
+                perf_stats.inc_resp_err();
+                L7ResponseStatus::ServerError
+            }
+            _ => {
+                perf_stats.inc_req_err();
+                L7ResponseStatus::ClientError
+            }
+        };
+    }
+
+    // 只解析 payload 中的第一个帧, 后续完整的帧只用于统计消息数
+    // only the first frame in payload is parsed, following complete frames are only used to count messages
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
+        let frame = Frame::decode(payload).ok_or(Error::WebsocketLogParseFailed)?;
+        // 客户端发送的帧必须掩码, 服务端发送的帧不能掩码
+        // frames sent by client must be masked, and frames sent by server must not be masked
+        if frame.masking_key.is_some() != (direction == PacketDirection::ClientToServer) {
+            return Err(Error::WebsocketLogParseFailed);
+        }
+        self.info.fin = frame.fin;
+        self.info.opcode = frame.opcode;
+        self.info.message_count = Self::count_messages(payload, &frame);
+        if direction == PacketDirection::ClientToServer {
+            self.info.msg_type = LogMessageType::Request;
+            self.info.req_length = Some(frame.payload_length);
+            self.perf_stats.as_mut().unwrap().inc_req();
+        } else {
+            self.info.msg_type = LogMessageType::Response;
+            self.info.resp_length = Some(frame.payload_length);
+            self.perf_stats.as_mut().unwrap().inc_resp();
+        }
+        // close 帧的载荷为 2 字节的 close code 和原因
+        // payload of close frame is 2-byte close code and reason
+        if frame.opcode == OPCODE_CLOSE && frame.payload.len() >= 2 {
+            let data = frame.unmask();
+            let close_code = read_u16_be(&data);
+            self.info.close_code = Some(close_code);
+            self.info.close_reason = String::from_utf8_lossy(&data[2..]).into_owned();
+            self.set_status(close_code);
+        }
+        Ok(())
+    }
+
+    fn count_messages(payload: &[u8], first: &Frame) -> u32 {
+        let mut count = first.fin as u32;
+        let mut offset = first.length;
+        while offset < payload.len() as u64 {
+            let Some(frame) = Frame::decode(&payload[offset as usize..]) else {
+                break;
+            };
+            offset = match offset.checked_add(frame.length) {
+                Some(next) if next > offset => next,
+                _ => break,
+            };
+            // 不完整的帧不计数
+            // incomplete frame is not counted
+            if offset > payload.len() as u64 {
+                break;
+            }
+            count += frame.fin as u32;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::ProtocolTester;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/websocket";
+
+    fn tester() -> ProtocolTester<WebsocketLog> {
+        ProtocolTester::new(WebsocketLog::default()).step(|websocket, payload, param| {
+            let is_websocket = websocket.parse_payload(payload, param).is_ok();
+            websocket.info.rrt = 0;
+            format!("{:?} is_websocket: {}\r\n", websocket.info, is_websocket)
+        })
+    }
+
+    #[test]
+    fn check_message_count() {
+        let text = [0x81, 0x02, b'h', b'i'];
+        // 分片的消息, 第一个帧 FIN 未置位
+        // fragmented message, FIN of the first frame is not set
+        let first = [0x01, 0x01, b'h'];
+        let last = [0x80, 0x01, b'i'];
+        let close = [0x88, 0x02, 0x03, 0xe8];
+
+        let mut websocket = WebsocketLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let payload = [&text[..], &first[..], &last[..], &close[..]].concat();
+        websocket
+            .parse(&payload, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(websocket.info.opcode, OPCODE_TEXT);
+        assert_eq!(websocket.info.message_count, 3);
+
+        // 最后的帧不完整
+        // the last frame is incomplete
+        websocket.reset();
+        websocket
+            .parse(
+                &payload[..payload.len() - 1],
+                PacketDirection::ServerToClient,
+            )
+            .unwrap();
+        assert_eq!(websocket.info.message_count, 2);
+
+        websocket.reset();
+        websocket
+            .parse(&first, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(websocket.info.message_count, 0);
+    }
+
+    #[test]
+    fn check_invalid_length() {
+        // 64 位长度为 2^64 - 10, 加上 10 字节头部后溢出
+        // 64-bit length is 2^64 - 10, which overflows after adding 10-byte header
+        let mut crafted = vec![0x82, 0x7f];
+        crafted.extend_from_slice(&(u64::MAX - 9).to_be_bytes());
+        crafted.extend_from_slice(b"data");
+        assert!(Frame::decode(&crafted).is_none());
+
+        let text = [0x81, 0x02, b'h', b'i'];
+        let mut websocket = WebsocketLog {
+            perf_stats: Some(L7PerfStats::default()),
+            ..Default::default()
+        };
+        let payload = [&text[..], &crafted[..]].concat();
+        websocket
+            .parse(&payload, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(websocket.info.message_count, 1);
+
+        // 最大的合法长度不会溢出, 帧不完整不计数
+        // the largest valid length does not overflow, the incomplete frame is not counted
+        crafted[2..10].copy_from_slice(&(i64::MAX as u64).to_be_bytes());
+        let frame = Frame::decode(&crafted).unwrap();
+        assert_eq!(frame.length, i64::MAX as u64 + 10);
+        websocket.reset();
+        let payload = [&text[..], &crafted[..]].concat();
+        websocket
+            .parse(&payload, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(websocket.info.message_count, 1);
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(dir.join("websocket.pcap"), dir.join("websocket.result"));
+    }
+}
@@ -0,0 +1,540 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
+use super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::error::{Error, Result},
+    utils::bytes::{read_u32_be, read_u64_be},
+};
+
+// 参考：https://github.com/apache/zookeeper/blob/master/zookeeper-jute/src/main/resources/zookeeper.jute
+// 所有报文以 4 字节长度开头
+// all packets start with 4-byte length
+//
+// ConnectRequest => protocolVersion(4) lastZxidSeen(8) timeOut(4) sessionId(8) passwd(4+16) [readOnly(1)]
+// ConnectResponse => protocolVersion(4) timeOut(4) sessionId(8) passwd(4+16) [readOnly(1)]
+// RequestHeader => xid(4) type(4)
+// ReplyHeader => xid(4) zxid(8) err(4)
+const LEN_SIZE: usize = 4;
+const REQUEST_HEADER_SIZE: usize = 8;
+const REPLY_HEADER_SIZE: usize = 16;
+const PASSWD_SIZE: usize = 16;
+const CONNECT_REQUEST_SIZE: usize = 4 + 8 + 4 + 8 + 4 + PASSWD_SIZE;
+const CONNECT_RESPONSE_SIZE: usize = 4 + 4 + 8 + 4 + PASSWD_SIZE;
+
+// 特殊的 xid
+// special xid
+const XID_NOTIFICATION: i32 = -1;
+const XID_PING: i32 = -2;
+
+const OP_NOTIFICATION: i32 = 0;
+const OP_PING: i32 = 11;
+const OP_CREATE_SESSION: i32 = -10;
+
+const ERR_OK: i32 = 0;
+// -1 到 -99 为系统错误, -100 及以下为 API 错误
+// -1 to -99 are system errors, -100 and below are API errors
+const ERR_API_ERROR: i32 = -100;
+const ERR_SESSION_EXPIRED: i32 = -112;
+const ERR_MIN: i32 = -127;
+
+fn opcode_name(opcode: i32) -> Option<&'static str> {
+    let name = match opcode {
+        OP_NOTIFICATION => "notification",
+        1 => "create",
+        2 => "delete",
+        3 => "exists",
+        4 => "getData",
+        5 => "setData",
+        6 => "getACL",
+        7 => "setACL",
+        8 => "getChildren",
+        9 => "sync",
+        OP_PING => "ping",
+        12 => "getChildren2",
+        13 => "check",
+        14 => "multi",
+        15 => "create2",
+        16 => "reconfig",
+        17 => "checkWatches",
+        18 => "removeWatches",
+        19 => "createContainer",
+        20 => "deleteContainer",
+        21 => "createTTL",
+        22 => "multiRead",
+        100 => "auth",
+        101 => "setWatches",
+        102 => "sasl",
+        103 => "getEphemerals",
+        104 => "getAllChildrenNumber",
+        105 => "setWatches2",
+        106 => "addWatch",
+        107 => "whoAmI",
+        OP_CREATE_SESSION => "createSession",
+        -11 => "closeSession",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// 请求体以 path 开头的操作
+// operations whose request body starts with path
+fn has_path(opcode: i32) -> bool {
+    matches!(opcode, 1..=9 | 12 | 13 | 15 | 17..=21 | 103 | 104 | 106)
+}
+
+fn error_name(err: i32) -> &'static str {
+    match err {
+        ERR_OK => "",
+        -1 => "SystemError",
+        -2 => "RuntimeInconsistency",
+        -3 => "DataInconsistency",
+        -4 => "ConnectionLoss",
+        -5 => "MarshallingError",
+        -6 => "Unimplemented",
+        -7 => "OperationTimeout",
+        -8 => "BadArguments",
+        -13 => "NewConfigNoQuorum",
+        -14 => "ReconfigInProgress",
+        -15 => "UnknownSession",
+        ERR_API_ERROR => "APIError",
+        -101 => "NoNode",
+        -102 => "NoAuth",
+        -103 => "BadVersion",
+        -108 => "NoChildrenForEphemerals",
+        -110 => "NodeExists",
+        -111 => "NotEmpty",
+        ERR_SESSION_EXPIRED => "SessionExpired",
+        -113 => "InvalidCallback",
+        -114 => "InvalidACL",
+        -115 => "AuthFailed",
+        -118 => "SessionMoved",
+        -119 => "NotReadOnly",
+        -120 => "EphemeralOnLocalSession",
+        -121 => "NoWatcher",
+        -122 => "RequestTimeout",
+        -123 => "ReconfigDisabled",
+        -124 => "SessionClosedRequireSasl",
+        -125 => "QuotaExceeded",
+        -127 => "Throttled",
+        _ => "UnknownError",
+    }
+}
+
+fn read_i32(payload: &[u8], offset: usize) -> Option<i32> {
+    Some(read_u32_be(payload.get(offset..offset + 4)?) as i32)
+}
+
+fn read_i64(payload: &[u8], offset: usize) -> Option<i64> {
+    Some(read_u64_be(payload.get(offset..offset + 8)?) as i64)
+}
+
+// ustring => len(4) bytes, 可能被截断
+// ustring => len(4) bytes, may be truncated
+fn read_string(payload: &[u8], offset: usize) -> Option<String> {
+    let len = read_i32(payload, offset)?;
+    if len < 0 {
+        return Some(String::new());
+    }
+    let start = offset + 4;
+    let end = (start + len as usize).min(payload.len());
+    Some(String::from_utf8_lossy(payload.get(start..end)?).into_owned())
+}
+
+// 返回去掉长度字段的报文, strict 时要求长度字段与报文长度一致
+// return payload without the length field, which must match payload length if strict
+fn decode_body(payload: &[u8], strict: bool) -> Option<&[u8]> {
+    let len = read_u32_be(payload.get(..LEN_SIZE)?) as usize;
+    let body = &payload[LEN_SIZE..];
+    if strict && len != body.len() {
+        return None;
+    }
+    Some(&body[..len.min(body.len())])
+}
+
+fn is_connect_request(body: &[u8]) -> bool {
+    if body.len() != CONNECT_REQUEST_SIZE && body.len() != CONNECT_REQUEST_SIZE + 1 {
+        return false;
+    }
+    // protocolVersion 为 0, lastZxidSeen 不为负, timeOut 为正, passwd 长度为 16
+    // protocolVersion is 0, lastZxidSeen is non-negative, timeOut is positive, passwd length is 16
+    read_i32(body, 0) == Some(0)
+        && read_i64(body, 4).unwrap_or(-1) >= 0
+        && read_i32(body, 12).unwrap_or(0) > 0
+        && read_i32(body, 24) == Some(PASSWD_SIZE as i32)
+}
+
+fn is_connect_response(body: &[u8]) -> bool {
+    (body.len() == CONNECT_RESPONSE_SIZE || body.len() == CONNECT_RESPONSE_SIZE + 1)
+        && read_i32(body, 0) == Some(0)
+        && read_i32(body, 16) == Some(PASSWD_SIZE as i32)
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct ZookeeperInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_id", skip_serializing_if = "Option::is_none")]
+    pub xid: Option<i32>,
+    #[serde(rename = "request_type", skip_serializing_if = "Option::is_none")]
+    pub opcode: Option<i32>,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zk_session_id: Option<i64>,
+
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_len: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zxid: Option<i64>,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub err: Option<i32>,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+
+    rrt: u64,
+}
+
+impl L7ProtocolInfoInterface for ZookeeperInfo {
+    fn session_id(&self) -> Option<u32> {
+        self.xid.map(|x| x as u32)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::ZookeeperInfo(other) = other {
+            if other.msg_type == LogMessageType::Response {
+                self.resp_len = other.resp_len;
+                self.zxid = other.zxid;
+                self.err = other.err;
+                self.status = other.status;
+                if other.zk_session_id.is_some() {
+                    self.zk_session_id = other.zk_session_id;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Zookeeper,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl ZookeeperInfo {
+    pub fn opcode_str(&self) -> &'static str {
+        self.opcode.and_then(opcode_name).unwrap_or_default()
+    }
+}
+
+impl From<ZookeeperInfo> for L7ProtocolSendLog {
+    fn from(f: ZookeeperInfo) -> Self {
+        let mut attributes = vec![];
+        if let Some(zxid) = f.zxid {
+            attributes.push(KeyVal {
+                key: "zxid".to_string(),
+                val: zxid.to_string(),
+            });
+        }
+        if let Some(session_id) = f.zk_session_id {
+            attributes.push(KeyVal {
+                key: "session_id".to_string(),
+                val: format!("{:#x}", session_id),
+            });
+        }
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.opcode_str().to_owned(),
+                resource: f.path,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.err,
+                exception: f.err.map(error_name).unwrap_or_default().to_owned(),
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: f.xid.map(|x| x as u32),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct ZookeeperLog {
+    info: ZookeeperInfo,
+    #[serde(skip)]
+    perf_stats: Option<L7PerfStats>,
+    // 最近请求的 (xid, opcode), 响应中没有 opcode, 需要用请求的
+    // (xid, opcode) of recent requests, the response does not contain opcode, so it is taken
+    // from the request
+    #[serde(skip)]
+    requests: Vec<(i32, i32)>,
+    #[serde(skip)]
+    pending_connect: bool,
+}
+
+impl L7ProtocolParserInterface for ZookeeperLog {
+    // 只在会话开始时通过 ConnectRequest 识别
+    // only identified by ConnectRequest at session start
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol()
+            || param.l4_protocol != IpProtocol::Tcp
+            || param.direction != PacketDirection::ClientToServer
+        {
+            return false;
+        }
+        decode_body(payload, true).map_or(false, is_connect_request)
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        if self.perf_stats.is_none() {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        self.parse(payload, param.direction)?;
+        self.info.is_tls = param.is_tls();
+        if self.info.msg_type != LogMessageType::Session {
+            self.info.cal_rrt(param).map(|rrt| {
+                self.info.rrt = rrt;
+                self.perf_stats.as_mut().unwrap().update_rrt(rrt);
+            });
+        }
+        Ok(vec![L7ProtocolInfo::ZookeeperInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Zookeeper
+    }
+
+    fn default_ports(&self) -> &[u16] {
+        &[2181]
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.info = ZookeeperInfo::default();
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+}
+
+impl ZookeeperLog {
+    const MAX_CACHED_REQUESTS: usize = 16;
+
+    fn save_request(&mut self, xid: i32, opcode: i32) {
+        if self.requests.len() >= Self::MAX_CACHED_REQUESTS {
+            self.requests.remove(0);
+        }
+        self.requests.push((xid, opcode));
+    }
+
+    fn take_request(&mut self, xid: i32) -> Option<i32> {
+        let index = self.requests.iter().position(|(x, _)| *x == xid)?;
+        Some(self.requests.remove(index).1)
+    }
+
+    fn set_status(&mut self, err: i32) {
+        let perf_stats = self.perf_stats.as_mut().unwrap();
+        self.info.err = Some(err);
+        self.info.status = match err {
+            ERR_OK => L7ResponseStatus::Ok,
+            // NoNode, NodeExists, BadVersion 等 API 错误由请求导致
+            // API errors such as NoNode, NodeExists and BadVersion are caused by the request
+            ERR_MIN..=ERR_API_ERROR => {
+                perf_stats.inc_req_err();
+                L7ResponseStatus::ClientError
+            }
+            _ => {
+                perf_stats.inc_resp_err();
+                L7ResponseStatus::ServerError
+            }
+        };
+    }
+
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
+        let body = decode_body(payload, false).ok_or(Error::ZookeeperLogParseFailed)?;
+        let parsed = match direction {
+            PacketDirection::ClientToServer => self.request(body),
+            PacketDirection::ServerToClient => self.response(body),
+        };
+        parsed.ok_or(Error::ZookeeperLogParseFailed)?;
+        let len = Some((payload.len() - LEN_SIZE) as u32);
+        match self.info.msg_type {
+            LogMessageType::Request => {
+                self.info.req_len = len;
+                self.perf_stats.as_mut().unwrap().inc_req();
+            }
+            LogMessageType::Response => {
+                self.info.resp_len = len;
+                self.perf_stats.as_mut().unwrap().inc_resp();
+            }
+            _ => self.info.resp_len = len,
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, body: &[u8]) -> Option<()> {
+        self.info.msg_type = LogMessageType::Request;
+        if is_connect_request(body) {
+            self.info.opcode = Some(OP_CREATE_SESSION);
+            self.info.zk_session_id = Some(read_i64(body, 16)?).filter(|id| *id != 0);
+            self.pending_connect = true;
+            return Some(());
+        }
+        if body.len() < REQUEST_HEADER_SIZE {
+            return None;
+        }
+        let xid = read_i32(body, 0)?;
+        let opcode = read_i32(body, 4)?;
+        opcode_name(opcode)?;
+        self.info.xid = Some(xid);
+        self.info.opcode = Some(opcode);
+        if has_path(opcode) {
+            self.info.path = read_string(body, REQUEST_HEADER_SIZE)?;
+        }
+        self.save_request(xid, opcode);
+        Some(())
+    }
+
+    fn response(&mut self, body: &[u8]) -> Option<()> {
+        if self.pending_connect && is_connect_response(body) {
+            self.pending_connect = false;
+            self.info.msg_type = LogMessageType::Response;
+            self.info.opcode = Some(OP_CREATE_SESSION);
+            self.info.zk_session_id = Some(read_i64(body, 8)?);
+            // 会话过期时服务端返回的 timeOut 为 0
+            // timeOut returned by server is 0 when session expired
+            let err = if read_i32(body, 4)? > 0 {
+                ERR_OK
+            } else {
+                ERR_SESSION_EXPIRED
+            };
+            self.set_status(err);
+            return Some(());
+        }
+        if body.len() < REPLY_HEADER_SIZE {
+            return None;
+        }
+        let xid = read_i32(body, 0)?;
+        let zxid = read_i64(body, 4)?;
+        let err = read_i32(body, 12)?;
+        if err > ERR_OK || err < ERR_MIN {
+            return None;
+        }
+        self.info.zxid = Some(zxid);
+        // WatcherEvent => type(4) state(4) path
+        if xid == XID_NOTIFICATION {
+            self.info.msg_type = LogMessageType::Session;
+            self.info.opcode = Some(OP_NOTIFICATION);
+            self.info.path = read_string(body, REPLY_HEADER_SIZE + 8).unwrap_or_default();
+            return Some(());
+        }
+        self.info.msg_type = LogMessageType::Response;
+        self.info.xid = Some(xid);
+        self.info.opcode = match self.take_request(xid) {
+            Some(opcode) => Some(opcode),
+            None if xid == XID_PING => Some(OP_PING),
+            None => None,
+        };
+        self.set_status(err);
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use super::*;
+
+    use crate::common::l7_protocol_log::L7PerfCache;
+    use crate::flow_generator::L7_RRT_CACHE_CAPACITY;
+    use crate::utils::test::{Capture, ProtocolTester};
+
+    const FILE_DIR: &str = "resources/test/flow_generator/zookeeper";
+
+    fn tester() -> ProtocolTester<ZookeeperLog> {
+        ProtocolTester::new(ZookeeperLog::default()).step(|zookeeper, payload, param| {
+            let is_zookeeper = zookeeper.parse_payload(payload, param).is_ok();
+            zookeeper.info.rrt = 0;
+            format!("{:?} is_zookeeper: {}\r\n", zookeeper.info, is_zookeeper)
+        })
+    }
+
+    #[test]
+    fn check() {
+        let dir = Path::new(FILE_DIR);
+        tester().check(dir.join("zookeeper.pcap"), dir.join("zookeeper.result"));
+    }
+
+    #[test]
+    fn check_payload() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("zookeeper.pcap"), None);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        let mut zookeeper = ZookeeperLog::default();
+
+        // 第一个报文为 ConnectRequest, 之后的请求不用于识别
+        // the first packet is ConnectRequest, requests after it are not used for identification
+        for (i, expected) in [(0, true), (2, false)] {
+            packets[i].lookup_key.direction = PacketDirection::ClientToServer;
+            let param = &ParseParam::from((&packets[i], log_cache.clone(), false));
+            let payload = packets[i].get_l4_payload().unwrap();
+            assert_eq!(zookeeper.check_payload(payload, param), expected);
+        }
+    }
+}
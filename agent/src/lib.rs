@@ -39,7 +39,7 @@ mod sender;
 pub mod trident;
 pub mod utils;
 
-// for benchmarks
+// for benchmarks and tools
 #[doc(hidden)]
 pub use {
     common::{
@@ -56,6 +56,7 @@ pub use {
         policy::{Acl as _Acl, Cidr as _Cidr, IpGroupData as _IpGroupData},
         port_range::PortRange as _PortRange,
     },
+    config::ModuleConfig as _ModuleConfig,
     flow_generator::flow_map::{
         _new_flow_map_and_receiver, _new_meta_packet, _reverse_meta_packet,
     },
@@ -65,9 +66,10 @@ pub use {
             _meta_flow_perf_update,
         },
         FlowPerfCounter as _FlowPerfCounter, L7FlowPerf as _L7FlowPerf,
+        L7ProtocolChecker as _L7ProtocolChecker,
     },
-    flow_generator::protocol_logs::LogMessageType as _LogMessageType,
-    flow_generator::HttpLog,
+    flow_generator::protocol_logs::{_fuzz_mysql_parse, LogMessageType as _LogMessageType},
+    flow_generator::{HttpLog, MysqlLog},
     npb_pcap_policy::{
         NpbAction as _NpbAction, NpbTunnelType as _NpbTunnelType, TapSide as _TapSide,
     },
@@ -108,6 +110,20 @@ mod tests {
             ("         +> ", crate::flow_generator::protocol_logs::sql::PostgresqlLog),
             ("         +> ", crate::flow_generator::protocol_logs::rpc::ProtobufRpcWrapLog),
             ("         +> ", crate::flow_generator::protocol_logs::rpc::SofaRpcLog),
+            ("         +> ", crate::flow_generator::protocol_logs::sql::ClickHouseLog),
+            ("         +> ", crate::flow_generator::protocol_logs::sql::OracleLog),
+            ("         +> ", crate::flow_generator::protocol_logs::sql::MssqlLog),
+            ("         +> ", crate::flow_generator::protocol_logs::mq::RocketMqLog),
+            ("         +> ", crate::flow_generator::protocol_logs::coap::CoapLog),
+            ("         +> ", crate::flow_generator::protocol_logs::mq::KafkaLog),
+            ("         +> ", crate::flow_generator::protocol_logs::tls::TlsLog),
+            ("         +> ", crate::flow_generator::protocol_logs::websocket::WebsocketLog),
+            ("         +> ", crate::flow_generator::protocol_logs::rpc::ThriftLog),
+            ("         +> ", crate::flow_generator::protocol_logs::smtp::SmtpLog),
+            ("         +> ", crate::flow_generator::protocol_logs::zookeeper::ZookeeperLog),
+            ("         +> ", crate::flow_generator::protocol_logs::ftp::FtpLog),
+            ("         +> ", crate::flow_generator::protocol_logs::statsd::StatsdLog),
+            ("         +> ", crate::flow_generator::protocol_logs::unknown::UnknownLog),
             ("     -> ", crate::common::l7_protocol_log::L7ProtocolParser),
             ("         +- ", crate::flow_generator::protocol_logs::http::HttpLog),
             ("         +- ", crate::flow_generator::protocol_logs::dns::DnsLog),
@@ -119,6 +135,20 @@ mod tests {
             ("         +- ", crate::flow_generator::protocol_logs::sql::PostgresqlLog),
             ("         +- ", crate::flow_generator::protocol_logs::rpc::DubboLog),
             ("         +- ", crate::flow_generator::protocol_logs::mq::MqttLog),
+            ("         +- ", crate::flow_generator::protocol_logs::mq::NatsLog),
+            ("         +- ", crate::flow_generator::protocol_logs::sql::ClickHouseLog),
+            ("         +- ", crate::flow_generator::protocol_logs::sql::OracleLog),
+            ("         +- ", crate::flow_generator::protocol_logs::sql::MssqlLog),
+            ("         +- ", crate::flow_generator::protocol_logs::mq::RocketMqLog),
+            ("         +- ", crate::flow_generator::protocol_logs::coap::CoapLog),
+            ("         +- ", crate::flow_generator::protocol_logs::tls::TlsLog),
+            ("         +- ", crate::flow_generator::protocol_logs::websocket::WebsocketLog),
+            ("         +- ", crate::flow_generator::protocol_logs::rpc::ThriftLog),
+            ("         +- ", crate::flow_generator::protocol_logs::smtp::SmtpLog),
+            ("         +- ", crate::flow_generator::protocol_logs::zookeeper::ZookeeperLog),
+            ("         +- ", crate::flow_generator::protocol_logs::ftp::FtpLog),
+            ("         +- ", crate::flow_generator::protocol_logs::statsd::StatsdLog),
+            ("         +- ", crate::flow_generator::protocol_logs::unknown::UnknownLog),
             (" 2x ", npb_pcap_policy::PolicyData),
             (" 2x ", crate::common::endpoint::EndpointData),
             (" -> ", packet_sequence_block::PacketSequenceBlock)
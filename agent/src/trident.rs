@@ -41,6 +41,8 @@ use log::{info, warn};
 use regex::Regex;
 use tokio::runtime::{Builder, Runtime};
 
+#[cfg(feature = "wasm")]
+use crate::flow_generator::protocol_logs::plugin::WasmPlugin;
 use crate::flow_generator::protocol_logs::SessionAggregator;
 use crate::{
     collector::Collector,
@@ -50,6 +52,7 @@ use crate::{
     },
     common::{
         enums::TapType,
+        l7_protocol_log::ProtocolParserRegistry,
         tagged_flow::{BoxedTaggedFlow, TaggedFlow},
         tap_types::TapTyper,
         FeatureFlags, DEFAULT_INGESTER_PORT, DEFAULT_LOG_RETENTION, DEFAULT_TRIDENT_CONF_FILE,
@@ -323,6 +326,23 @@ impl Trident {
         Ok(Trident { state, handle })
     }
 
+    #[cfg(feature = "wasm")]
+    fn load_wasm_plugin(path: &str, registry: &mut ProtocolParserRegistry) {
+        let ret = WasmPlugin::load(path).and_then(|plugin| Arc::new(plugin).register(registry));
+        match ret {
+            Ok(_) => info!("wasm plugin {} loaded", path),
+            Err(e) => warn!("load wasm plugin {} failed: {}", path, e),
+        }
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn load_wasm_plugin(path: &str, _: &mut ProtocolParserRegistry) {
+        warn!(
+            "wasm plugin {} is ignored because agent is built without wasm feature",
+            path
+        );
+    }
+
     fn run(
         state: TridentState,
         mut config: Config,
@@ -346,6 +366,14 @@ impl Trident {
             config.agent_mode, ctrl_ip, ctrl_mac
         );
 
+        // 协议解析器的注册表需要在创建 FlowMap 之前安装
+        // registry of protocol parsers should be installed before FlowMap is created
+        let mut registry = ProtocolParserRegistry::default();
+        if let Some(path) = config.wasm_plugin.as_ref() {
+            Self::load_wasm_plugin(path, &mut registry);
+        }
+        ProtocolParserRegistry::install(registry)?;
+
         let exception_handler = ExceptionHandler::default();
         let session = Arc::new(Session::new(
             config.controller_port,
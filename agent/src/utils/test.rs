@@ -16,10 +16,20 @@
 
 use std::path::Path;
 use std::time::Duration;
+#[cfg(test)]
+use std::{cell::RefCell, fs, rc::Rc};
 
 use pcap::{self, PacketHeader};
 
 use crate::common::meta_packet::MetaPacket;
+#[cfg(test)]
+use crate::{
+    common::{
+        flow::PacketDirection,
+        l7_protocol_log::{L7PerfCache, L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::L7_RRT_CACHE_CAPACITY,
+};
 
 pub struct Capture(Vec<(PacketHeader, Vec<u8>)>);
 
@@ -69,3 +79,144 @@ impl From<Capture> for Vec<Vec<u8>> {
         c.0.into_iter().map(|(_, p)| p).collect()
     }
 }
+
+// 协议解析的 golden file 测试
+// 默认按第一个包的目的端口判断方向, 每个包依次调用 check_payload/parse_payload/reset,
+// 每个解析结果输出一行 `{info:?} is_{protocol}: {check}`, 可以通过 step 替换每个包的测试步骤
+// ===============================================================================
+// golden file test of protocol parser
+// by default, direction is inferred from the destination port of the first packet, check_payload,
+// parse_payload and reset are called for each packet, one line `{info:?} is_{protocol}: {check}`
+// is written for each parsed info, the test step of each packet can be replaced by step
+#[cfg(test)]
+pub struct ProtocolTester<P: L7ProtocolParserInterface> {
+    parser: P,
+    parse_len: Option<usize>,
+    server_port: ServerPort,
+    step: Option<ProtocolTestStep<P>>,
+}
+
+// 每个包的测试步骤, 参数为 (解析器, payload, 解析参数), 返回输出, 之后调用解析器的 reset
+// test step of each packet, arguments are (parser, payload, parse param), returns the output,
+// reset of parser is called afterwards
+#[cfg(test)]
+pub type ProtocolTestStep<P> = Box<dyn FnMut(&mut P, &[u8], &ParseParam) -> String>;
+
+// 用于判断包方向的服务端端口
+// server port used to infer packet direction
+#[cfg(test)]
+enum ServerPort {
+    FirstDst,
+    FirstSrc,
+    Fixed(u16),
+}
+
+#[cfg(test)]
+impl<P: L7ProtocolParserInterface> ProtocolTester<P> {
+    pub fn new(parser: P) -> Self {
+        Self {
+            parser,
+            parse_len: None,
+            server_port: ServerPort::FirstDst,
+            step: None,
+        }
+    }
+
+    pub fn parse_len(mut self, parse_len: usize) -> Self {
+        self.parse_len = Some(parse_len);
+        self
+    }
+
+    // 第一个包由服务端发出, 例如 SMTP 和 FTP 的问候语
+    // the first packet is sent by server, such as greeting of SMTP and FTP
+    pub fn server_first(mut self) -> Self {
+        self.server_port = ServerPort::FirstSrc;
+        self
+    }
+
+    pub fn server_port(mut self, port: u16) -> Self {
+        self.server_port = ServerPort::Fixed(port);
+        self
+    }
+
+    pub fn step<F>(mut self, step: F) -> Self
+    where
+        F: FnMut(&mut P, &[u8], &ParseParam) -> String + 'static,
+    {
+        self.step = Some(Box::new(step));
+        self
+    }
+
+    pub fn run<T: AsRef<Path>>(&mut self, pcap: T) -> String {
+        let capture = Capture::load_pcap(pcap, self.parse_len);
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.as_meta_packets();
+        let Some(first) = packets.first() else {
+            return "".to_string();
+        };
+        let server_port = match self.server_port {
+            ServerPort::FirstDst => first.lookup_key.dst_port,
+            ServerPort::FirstSrc => first.lookup_key.src_port,
+            ServerPort::Fixed(port) => port,
+        };
+        let check_name = format!("is_{:?}", self.parser.protocol()).to_lowercase();
+
+        let mut output = String::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == server_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = &ParseParam::from((packet as &MetaPacket, log_cache.clone(), false));
+            let lines = match self.step.as_mut() {
+                Some(step) => step(&mut self.parser, payload, param),
+                None => Self::default_step(&mut self.parser, &check_name, payload, param),
+            };
+            output.push_str(&lines);
+            self.parser.reset();
+        }
+        output
+    }
+
+    fn default_step(
+        parser: &mut P,
+        check_name: &str,
+        payload: &[u8],
+        param: &ParseParam,
+    ) -> String {
+        let checked = parser.check_payload(payload, param);
+        let infos = parser.parse_payload(payload, param).unwrap_or_default();
+        let mut lines = String::new();
+        for info in infos {
+            lines.push_str(&format!(
+                "{} {}: {}\r\n",
+                info.inner_debug(),
+                check_name,
+                checked
+            ));
+        }
+        lines
+    }
+
+    // 与期望结果比较, 不一致时写入 actual.txt
+    // compare with the expected result, written to actual.txt if different
+    pub fn check<T: AsRef<Path>, R: AsRef<Path>>(&mut self, pcap: T, expected: R) {
+        let expected_path = expected.as_ref();
+        let expected = fs::read_to_string(expected_path).unwrap();
+        let output = self.run(pcap);
+        if output != expected {
+            let output_path = Path::new("actual.txt");
+            fs::write(&output_path, &output).unwrap();
+            assert!(
+                output == expected,
+                "output different from expected {:?}, written to {:?}",
+                expected_path,
+                output_path
+            );
+        }
+    }
+}
@@ -152,16 +152,31 @@ const (
 	L7_PROTOCOL_HTTP_2       L7Protocol = 21
 	L7_PROTOCOL_HTTP_1_TLS   L7Protocol = 22
 	L7_PROTOCOL_HTTP_2_TLS   L7Protocol = 23
+	L7_PROTOCOL_COAP         L7Protocol = 24
+	L7_PROTOCOL_WEBSOCKET    L7Protocol = 25
 	L7_PROTOCOL_DUBBO        L7Protocol = 40
 	L7_PROTOCOL_GRPC         L7Protocol = 41
 	L7_PROTOCOL_PROTOBUF_RPC L7Protocol = 42
 	L7_PROTOCOL_SOFARPC      L7Protocol = 43
+	L7_PROTOCOL_THRIFT       L7Protocol = 44
 	L7_PROTOCOL_MYSQL        L7Protocol = 60
 	L7_PROTOCOL_POSTGRE      L7Protocol = 61
+	L7_PROTOCOL_CLICKHOUSE   L7Protocol = 62
+	L7_PROTOCOL_ORACLE       L7Protocol = 63
+	L7_PROTOCOL_MSSQL        L7Protocol = 64
 	L7_PROTOCOL_REDIS        L7Protocol = 80
 	L7_PROTOCOL_KAFKA        L7Protocol = 100
 	L7_PROTOCOL_MQTT         L7Protocol = 101
+	L7_PROTOCOL_NATS         L7Protocol = 102
+	L7_PROTOCOL_ROCKETMQ     L7Protocol = 103
 	L7_PROTOCOL_DNS          L7Protocol = 120
+	L7_PROTOCOL_TLS          L7Protocol = 121
+	L7_PROTOCOL_SMTP         L7Protocol = 122
+	L7_PROTOCOL_ZOOKEEPER    L7Protocol = 123
+	L7_PROTOCOL_FTP          L7Protocol = 124
+	L7_PROTOCOL_STATSD       L7Protocol = 125
+	L7_PROTOCOL_UNRECOGNIZED L7Protocol = 126
+	L7_PROTOCOL_CUSTOM       L7Protocol = 127
 )
 
 // size = 9 * 4B = 36B
@@ -616,12 +631,36 @@ func (p L7Protocol) String() string {
 		formatted = "HTTP1_TLS"
 	case L7_PROTOCOL_HTTP_2_TLS:
 		formatted = "HTTP2_TLS"
+	case L7_PROTOCOL_COAP:
+		formatted = "CoAP"
+	case L7_PROTOCOL_WEBSOCKET:
+		formatted = "WebSocket"
 	case L7_PROTOCOL_DNS:
 		formatted = "DNS"
+	case L7_PROTOCOL_TLS:
+		formatted = "TLS"
+	case L7_PROTOCOL_SMTP:
+		formatted = "SMTP"
+	case L7_PROTOCOL_ZOOKEEPER:
+		formatted = "ZooKeeper"
+	case L7_PROTOCOL_FTP:
+		formatted = "FTP"
+	case L7_PROTOCOL_STATSD:
+		formatted = "StatsD"
+	case L7_PROTOCOL_UNRECOGNIZED:
+		formatted = "Unrecognized"
+	case L7_PROTOCOL_CUSTOM:
+		formatted = "Custom"
 	case L7_PROTOCOL_MYSQL:
 		formatted = "MySQL"
 	case L7_PROTOCOL_POSTGRE:
 		formatted = "PostgreSQL"
+	case L7_PROTOCOL_CLICKHOUSE:
+		formatted = "ClickHouse"
+	case L7_PROTOCOL_ORACLE:
+		formatted = "Oracle"
+	case L7_PROTOCOL_MSSQL:
+		formatted = "MSSQL"
 	case L7_PROTOCOL_REDIS:
 		formatted = "Redis"
 	case L7_PROTOCOL_DUBBO:
@@ -632,10 +671,16 @@ func (p L7Protocol) String() string {
 		formatted = "ProtobufRPC"
 	case L7_PROTOCOL_SOFARPC:
 		formatted = "SofaRPC"
+	case L7_PROTOCOL_THRIFT:
+		formatted = "Thrift"
 	case L7_PROTOCOL_KAFKA:
 		formatted = "Kafka"
 	case L7_PROTOCOL_MQTT:
 		formatted = "MQTT"
+	case L7_PROTOCOL_NATS:
+		formatted = "NATS"
+	case L7_PROTOCOL_ROCKETMQ:
+		formatted = "RocketMQ"
 	case L7_PROTOCOL_OTHER:
 		formatted = "Others"
 	default:
@@ -645,19 +690,34 @@ func (p L7Protocol) String() string {
 }
 
 var L7ProtocolStringMap = map[string]L7Protocol{
-	L7_PROTOCOL_HTTP_1.String():     L7_PROTOCOL_HTTP_1,
-	L7_PROTOCOL_HTTP_2.String():     L7_PROTOCOL_HTTP_2,
-	L7_PROTOCOL_HTTP_1_TLS.String(): L7_PROTOCOL_HTTP_1_TLS,
-	L7_PROTOCOL_HTTP_2_TLS.String(): L7_PROTOCOL_HTTP_2_TLS,
-	L7_PROTOCOL_DNS.String():        L7_PROTOCOL_DNS,
-	L7_PROTOCOL_MYSQL.String():      L7_PROTOCOL_MYSQL,
-	L7_PROTOCOL_REDIS.String():      L7_PROTOCOL_REDIS,
-	L7_PROTOCOL_DUBBO.String():      L7_PROTOCOL_DUBBO,
-	L7_PROTOCOL_GRPC.String():       L7_PROTOCOL_GRPC,
-	L7_PROTOCOL_KAFKA.String():      L7_PROTOCOL_KAFKA,
-	L7_PROTOCOL_MQTT.String():       L7_PROTOCOL_MQTT,
-	L7_PROTOCOL_OTHER.String():      L7_PROTOCOL_OTHER,
-	L7_PROTOCOL_UNKNOWN.String():    L7_PROTOCOL_UNKNOWN,
+	L7_PROTOCOL_HTTP_1.String():       L7_PROTOCOL_HTTP_1,
+	L7_PROTOCOL_HTTP_2.String():       L7_PROTOCOL_HTTP_2,
+	L7_PROTOCOL_HTTP_1_TLS.String():   L7_PROTOCOL_HTTP_1_TLS,
+	L7_PROTOCOL_HTTP_2_TLS.String():   L7_PROTOCOL_HTTP_2_TLS,
+	L7_PROTOCOL_COAP.String():         L7_PROTOCOL_COAP,
+	L7_PROTOCOL_WEBSOCKET.String():    L7_PROTOCOL_WEBSOCKET,
+	L7_PROTOCOL_DNS.String():          L7_PROTOCOL_DNS,
+	L7_PROTOCOL_TLS.String():          L7_PROTOCOL_TLS,
+	L7_PROTOCOL_SMTP.String():         L7_PROTOCOL_SMTP,
+	L7_PROTOCOL_ZOOKEEPER.String():    L7_PROTOCOL_ZOOKEEPER,
+	L7_PROTOCOL_FTP.String():          L7_PROTOCOL_FTP,
+	L7_PROTOCOL_STATSD.String():       L7_PROTOCOL_STATSD,
+	L7_PROTOCOL_UNRECOGNIZED.String(): L7_PROTOCOL_UNRECOGNIZED,
+	L7_PROTOCOL_CUSTOM.String():       L7_PROTOCOL_CUSTOM,
+	L7_PROTOCOL_MYSQL.String():        L7_PROTOCOL_MYSQL,
+	L7_PROTOCOL_CLICKHOUSE.String():   L7_PROTOCOL_CLICKHOUSE,
+	L7_PROTOCOL_ORACLE.String():       L7_PROTOCOL_ORACLE,
+	L7_PROTOCOL_MSSQL.String():        L7_PROTOCOL_MSSQL,
+	L7_PROTOCOL_REDIS.String():        L7_PROTOCOL_REDIS,
+	L7_PROTOCOL_DUBBO.String():        L7_PROTOCOL_DUBBO,
+	L7_PROTOCOL_GRPC.String():         L7_PROTOCOL_GRPC,
+	L7_PROTOCOL_THRIFT.String():       L7_PROTOCOL_THRIFT,
+	L7_PROTOCOL_KAFKA.String():        L7_PROTOCOL_KAFKA,
+	L7_PROTOCOL_MQTT.String():         L7_PROTOCOL_MQTT,
+	L7_PROTOCOL_NATS.String():         L7_PROTOCOL_NATS,
+	L7_PROTOCOL_ROCKETMQ.String():     L7_PROTOCOL_ROCKETMQ,
+	L7_PROTOCOL_OTHER.String():        L7_PROTOCOL_OTHER,
+	L7_PROTOCOL_UNKNOWN.String():      L7_PROTOCOL_UNKNOWN,
 }
 
 func (p *L4Protocol) String() string {